@@ -91,7 +91,7 @@ fn impl_pack_to_struct(
         .collect();
 
     let span = Span::call_site();
-    return quote_spanned! { span =>
+    quote_spanned! { span =>
                             #[automatically_derived]
                             impl<B: xdr::Packer> xdr::PackTo<B> for #name {
                                 fn pack_to(&self, buf: &mut B) {
@@ -101,7 +101,7 @@ fn impl_pack_to_struct(
                                     )*
                                 }
                             }
-    };
+    }
 }
 
 fn impl_pack_to_enum(
@@ -177,7 +177,7 @@ fn impl_pack_to_enum(
         discriminant = quote!((#discriminant)+1);
     }
 
-    if arms.len() == 0 {
+    if arms.is_empty() {
         errors.push(syn::Error::new(
             de.brace_token.span,
             "`#![derive(PackTo)]` cannot derive for empty enum",
@@ -187,7 +187,7 @@ fn impl_pack_to_enum(
     }
 
     let span = de.brace_token.span;
-    return quote_spanned! { span =>
+    quote_spanned! { span =>
                             #[automatically_derived]
                             impl<B: xdr::Packer> xdr::PackTo<B> for #name {
                                 fn pack_to(&self, buf: &mut B) {
@@ -198,7 +198,7 @@ fn impl_pack_to_enum(
                                     }
                                 }
                             }
-    };
+    }
 }
 
 fn discriminant_from_attr(
@@ -286,7 +286,7 @@ fn impl_unpack_from_struct(
         .collect();
 
     let span = Span::call_site();
-    return quote_spanned! { span =>
+    quote_spanned! { span =>
                             #[automatically_derived]
                             impl<B: xdr::Unpacker> xdr::UnpackFrom<B> for #name {
                                 fn unpack_from(buf: &mut B) -> crate::result::Result<Self> {
@@ -297,7 +297,7 @@ fn impl_unpack_from_struct(
                                     })
                                 }
                             }
-    };
+    }
 }
 
 fn impl_unpack_from_enum(
@@ -337,11 +337,8 @@ fn impl_unpack_from_enum(
         let var_name = &variant.ident;
         let span = variant.span();
 
-        let unpack_inner;
-        match &variant.fields {
-            syn::Fields::Unit => {
-                unpack_inner = TokenStream::new();
-            }
+        let unpack_inner = match &variant.fields {
+            syn::Fields::Unit => TokenStream::new(),
             syn::Fields::Unnamed(unnamed) => {
                 if unnamed.unnamed.len() != 1 {
                     errors.push(syn::Error::new(
@@ -352,7 +349,7 @@ fn impl_unpack_from_enum(
                     continue;
                 }
                 let inner_ty = &unnamed.unnamed.first().as_ref().unwrap().ty;
-                unpack_inner = quote_spanned!( span => ( <#inner_ty>::unpack_from(buf)? ) );
+                quote_spanned!( span => ( <#inner_ty>::unpack_from(buf)? ) )
             }
 
             syn::Fields::Named(named) => {
@@ -373,7 +370,7 @@ fn impl_unpack_from_enum(
         discriminant = quote!((#discriminant)+1);
     }
 
-    if arms.len() == 0 {
+    if arms.is_empty() {
         errors.push(syn::Error::new(
             de.brace_token.span,
             "`#![derive(PackTo)]` cannot derive for empty enum",
@@ -383,7 +380,7 @@ fn impl_unpack_from_enum(
     }
 
     let span = de.brace_token.span;
-    return quote_spanned! { span =>
+    quote_spanned! { span =>
                             #[automatically_derived]
                             impl<B: xdr::Unpacker> xdr::UnpackFrom<B> for #name {
                                 fn unpack_from(buf: &mut B) -> crate::result::Result<Self> {
@@ -397,5 +394,5 @@ fn impl_unpack_from_enum(
                                     }
                                 }
                             }
-    };
+    }
 }
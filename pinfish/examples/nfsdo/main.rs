@@ -121,7 +121,7 @@ async fn ls(client: &mut nfs4::client::NfsClient, fh: &nfs4::ops::NfsFh4) -> res
         eof = result.reply.eof;
         for entry in result.reply.iter() {
             cookie = entry.cookie;
-            ls_print_entry(&entry);
+            ls_print_entry(entry);
         }
     }
 
@@ -190,7 +190,7 @@ fn main() -> Result<(), Box<dyn Error>> {
                 Commands::Read(read) => (read.path.as_str(), ""),
             };
 
-            let fh = client.resolve_path(&path).await?;
+            let fh = client.resolve_path(path).await?;
             println!("got fh {:?}", &fh);
 
             match &cmd.cmd {
@@ -0,0 +1,79 @@
+use pinfish::{
+    mount, nfs4,
+    portmap::{self, client::PortmapClient},
+};
+
+use argp::FromArgs;
+use std::error::Error;
+
+#[derive(FromArgs)]
+/// List the RPC services registered on a host
+struct Command {
+    /// host name or IP address
+    #[argp(option, short = 'h', default = "String::from(\"localhost\")")]
+    host: String,
+
+    /// use port mapper version 2, like `rpcinfo -p`
+    #[argp(switch, short = 'p')]
+    portmap: bool,
+}
+
+fn service_name(prog: u32) -> &'static str {
+    match prog {
+        portmap::PMAP_PROG => "portmapper",
+        nfs4::PROG_NFS => "nfs",
+        mount::PROGRAM => "mountd",
+        100021 => "nlockmgr",
+        100024 => "status",
+        _ => "",
+    }
+}
+
+fn protocol_name(prot: u32) -> String {
+    match prot {
+        portmap::IPPROTO_TCP => "tcp".into(),
+        portmap::IPPROTO_UDP => "udp".into(),
+        _ => prot.to_string(),
+    }
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let cmd: Command = argp::from_env();
+
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(async {
+            let client = PortmapClient::connect(&cmd.host).await?;
+
+            if cmd.portmap {
+                println!("   program vers proto   port  service");
+                for mapping in client.dump().await? {
+                    println!(
+                        "{:>10} {:>4} {:>5} {:>6}  {}",
+                        mapping.prog,
+                        mapping.vers,
+                        protocol_name(mapping.prot),
+                        mapping.port,
+                        service_name(mapping.prog)
+                    );
+                }
+            } else {
+                println!("   program version netid     address                service    owner");
+                for entry in client.dump_rpcb().await? {
+                    println!(
+                        "{:>10} {:>7} {:<9} {:<22} {:<10} {}",
+                        entry.prog,
+                        entry.vers,
+                        entry.net_id,
+                        entry.addr,
+                        service_name(entry.prog),
+                        entry.owner
+                    );
+                }
+            }
+
+            Ok(())
+        })
+}
@@ -4,9 +4,12 @@ macro_rules! pub_use{
     ($($name:ident),+) => { $(mod $name; pub use $name::*;)+ }
 }
 
+pub mod memfs;
 pub mod mount;
 pub mod nfs3;
 pub mod nfs4;
+#[cfg(target_os = "linux")]
+pub mod passthrough;
 pub mod portmap;
 pub mod result;
 pub mod rpc;
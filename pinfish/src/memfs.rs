@@ -0,0 +1,1623 @@
+//! In-memory filesystem.
+//!
+//! `MemFs` keeps directories, regular files, symbolic links, hard links
+//! and device nodes in memory and implements `Nfs3Backend`, so it can
+//! be served with `nfs3::server::Nfs3Server` or
+//! `nfs4::server::Nfs4Server` to test clients without a kernel NFS
+//! server.  File handles hold the fileid, which is never reused, so
+//! they stay valid as long as the object exists.
+//!
+//! A filesystem is seeded from a description with one object per line,
+//! the type, the absolute path and optional `key=value` attributes:
+//!
+//! ```text
+//! # comments and blank lines are ignored
+//! dir     /export             mode=0755 uid=1000 gid=1000
+//! file    /export/hello       data="hello world\n" mode=0644
+//! file    /export/zeros       size=4096
+//! symlink /export/link        target=hello
+//! link    /export/hello2      target=/export/hello
+//! chr     /export/null        rdev=1,3
+//! fifo    /export/pipe        mtime=1700000000
+//! ```
+//!
+//! `blk` and `sock` are also accepted.  Missing parent directories are
+//! created with mode 0755, a `dir` line for an existing directory only
+//! changes its attributes.  Objects are owned by root unless `uid` and
+//! `gid` are given and have the current time unless `atime`, `mtime` or
+//! `ctime` are given in seconds.
+use crate::{
+    nfs3::{
+        backend::{allowed_access, default_fsinfo, BackendFuture, DirEntry, DirPage, Nfs3Backend},
+        procs::{CreateHow3, Fsinfo3ResOk, Fsstat3ResOk, MknodData3, StableHow},
+        Cookie3, Count3, FileAttributes, FileId3, FileType3, Filename3, NfsFh3, NfsPath3, NfsTime3,
+        Offset3, SetAttributes, SpecData3, TimeHow, Verifier3, ACCESS3_LOOKUP, ACCESS3_MODIFY,
+        ACCESS3_READ, NFS3ERR_ACCES, NFS3ERR_BADHANDLE, NFS3ERR_BAD_COOKIE, NFS3ERR_EXIST,
+        NFS3ERR_FBIG, NFS3ERR_INVAL, NFS3ERR_ISDIR, NFS3ERR_NOENT, NFS3ERR_NOTDIR,
+        NFS3ERR_NOTEMPTY, NFS3ERR_PERM, NFS3ERR_STALE,
+    },
+    result::{Result, INVALID_DATA},
+    rpc::auth::Identity,
+};
+use bytes::Bytes;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Fileid of the root directory
+const ROOT_ID: FileId3 = 1;
+
+/// Largest file size, writes beyond it fail with NFS3ERR_FBIG
+pub const MAX_FILE_SIZE: u64 = 1 << 30;
+
+/// Cookies of "." and "..", cookies of the other entries follow
+const DOT_COOKIE: Cookie3 = 1;
+const DOTDOT_COOKIE: Cookie3 = 2;
+
+/// Space reported by FSSTAT
+const TOTAL_BYTES: u64 = 1 << 40;
+const TOTAL_FILES: u64 = 1 << 32;
+
+#[derive(Debug)]
+struct Directory {
+    parent: FileId3,
+    entries: BTreeMap<String, FileId3>,
+    /// Changes on every modification, used as the cookie verifier
+    version: u64,
+}
+
+#[derive(Debug)]
+enum Content {
+    File(Vec<u8>),
+    Dir(Directory),
+    Symlink(String),
+    /// Devices, sockets and FIFOs
+    Special,
+}
+
+#[derive(Debug)]
+struct Inode {
+    file_type: FileType3,
+    mode: u32,
+    uid: u32,
+    gid: u32,
+    num_links: u32,
+    rdev: SpecData3,
+    atime: NfsTime3,
+    mtime: NfsTime3,
+    ctime: NfsTime3,
+    content: Content,
+    /// Verifier of an exclusive CREATE, until the attributes are set
+    verifier: Option<Verifier3>,
+}
+
+impl Inode {
+    fn size(&self) -> u64 {
+        match &self.content {
+            Content::File(data) => data.len() as u64,
+            Content::Dir(dir) => 4096 + 32 * dir.entries.len() as u64,
+            Content::Symlink(target) => target.len() as u64,
+            Content::Special => 0,
+        }
+    }
+
+    fn attributes(&self, fileid: FileId3) -> FileAttributes {
+        let size = self.size();
+        FileAttributes {
+            file_type: self.file_type,
+            mode: self.mode,
+            num_links: self.num_links,
+            uid: self.uid,
+            gid: self.gid,
+            size,
+            used: size.div_ceil(4096) * 4096,
+            rdev: self.rdev,
+            fsid: 1,
+            file_id: fileid,
+            atime: self.atime,
+            mtime: self.mtime,
+            ctime: self.ctime,
+        }
+    }
+
+    fn dir(&self) -> Result<&Directory> {
+        match &self.content {
+            Content::Dir(dir) => Ok(dir),
+            _ => Err(NFS3ERR_NOTDIR.into()),
+        }
+    }
+
+    fn dir_mut(&mut self) -> Result<&mut Directory> {
+        match &mut self.content {
+            Content::Dir(dir) => Ok(dir),
+            _ => Err(NFS3ERR_NOTDIR.into()),
+        }
+    }
+
+    /// Returns the data of a regular file
+    fn data_mut(&mut self) -> Result<&mut Vec<u8>> {
+        match &mut self.content {
+            Content::File(data) => Ok(data),
+            Content::Dir(_) => Err(NFS3ERR_ISDIR.into()),
+            _ => Err(NFS3ERR_INVAL.into()),
+        }
+    }
+}
+
+/// Object created by `Inner::create_node`
+struct NewNode {
+    file_type: FileType3,
+    content: Content,
+    rdev: SpecData3,
+    mode: u32,
+}
+
+struct Inner {
+    inodes: HashMap<FileId3, Inode>,
+    next_id: FileId3,
+    /// Last time handed out by `now`
+    clock: NfsTime3,
+}
+
+fn system_time() -> NfsTime3 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    NfsTime3 {
+        seconds: now.as_secs() as u32,
+        nano_seconds: now.subsec_nanos(),
+    }
+}
+
+fn fileid(fh: &NfsFh3) -> Result<FileId3> {
+    let bytes: [u8; 8] = fh
+        .data
+        .as_slice()
+        .try_into()
+        .map_err(|_| NFS3ERR_BADHANDLE)?;
+    Ok(u64::from_be_bytes(bytes))
+}
+
+fn handle(fileid: FileId3) -> NfsFh3 {
+    NfsFh3 {
+        data: fileid.to_be_bytes().to_vec(),
+    }
+}
+
+fn is_owner(cred: &Identity, inode: &Inode) -> bool {
+    cred.uid == 0 || cred.uid == inode.uid
+}
+
+fn in_group(cred: &Identity, gid: u32) -> bool {
+    cred.gid == gid || cred.gids.contains(&gid)
+}
+
+impl Inner {
+    fn new() -> Inner {
+        let now = system_time();
+        let root = Inode {
+            file_type: FileType3::Dir,
+            mode: 0o755,
+            uid: 0,
+            gid: 0,
+            num_links: 2,
+            rdev: SpecData3::default(),
+            atime: now,
+            mtime: now,
+            ctime: now,
+            content: Content::Dir(Directory {
+                parent: ROOT_ID,
+                entries: BTreeMap::new(),
+                version: 1,
+            }),
+            verifier: None,
+        };
+
+        Inner {
+            inodes: HashMap::from([(ROOT_ID, root)]),
+            next_id: ROOT_ID + 1,
+            clock: now,
+        }
+    }
+
+    /// Returns the current time, later than any time returned before so
+    /// every change is visible in ctime
+    fn now(&mut self) -> NfsTime3 {
+        let mut now = system_time();
+        if now <= self.clock {
+            now = self.clock;
+            now.nano_seconds += 1;
+            if now.nano_seconds == 1_000_000_000 {
+                now.seconds += 1;
+                now.nano_seconds = 0;
+            }
+        }
+
+        self.clock = now;
+        now
+    }
+
+    fn inode(&self, fileid: FileId3) -> Result<&Inode> {
+        self.inodes.get(&fileid).ok_or(NFS3ERR_STALE.into())
+    }
+
+    fn inode_mut(&mut self, fileid: FileId3) -> Result<&mut Inode> {
+        self.inodes.get_mut(&fileid).ok_or(NFS3ERR_STALE.into())
+    }
+
+    /// Checks that `cred` is granted all of the ACCESS3_* bits in
+    /// `access` on `fileid`
+    fn check_access(&self, cred: &Identity, fileid: FileId3, access: u32) -> Result<()> {
+        let inode = self.inode(fileid)?;
+        if allowed_access(cred, &inode.attributes(fileid)) & access == access {
+            Ok(())
+        } else {
+            Err(NFS3ERR_ACCES.into())
+        }
+    }
+
+    /// Checks that `cred` may read or write a file, owners always may
+    fn check_io(&self, cred: &Identity, fileid: FileId3, access: u32) -> Result<()> {
+        if cred.uid == self.inode(fileid)?.uid {
+            Ok(())
+        } else {
+            self.check_access(cred, fileid, access)
+        }
+    }
+
+    fn lookup(&self, dir: FileId3, name: &str) -> Result<FileId3> {
+        let dir_inode = self.inode(dir)?.dir()?;
+        match name {
+            "." => Ok(dir),
+            ".." => Ok(dir_inode.parent),
+            _ => dir_inode
+                .entries
+                .get(name)
+                .copied()
+                .ok_or(NFS3ERR_NOENT.into()),
+        }
+    }
+
+    /// Marks `dir` as modified
+    fn touch_dir(&mut self, dir: FileId3) -> Result<()> {
+        let now = self.now();
+        let inode = self.inode_mut(dir)?;
+        inode.mtime = now;
+        inode.ctime = now;
+        inode.dir_mut()?.version += 1;
+        Ok(())
+    }
+
+    /// Checks that `cred` may add or remove entries in `dir`
+    fn check_modify_dir(&self, cred: &Identity, dir: FileId3) -> Result<()> {
+        self.inode(dir)?.dir()?;
+        self.check_access(cred, dir, ACCESS3_MODIFY | ACCESS3_LOOKUP)
+    }
+
+    /// Checks the sticky bit of `dir` before removing or replacing
+    /// `fileid`
+    fn check_sticky(&self, cred: &Identity, dir: FileId3, fileid: FileId3) -> Result<()> {
+        let dir_inode = self.inode(dir)?;
+        if dir_inode.mode & 0o1000 != 0
+            && !is_owner(cred, dir_inode)
+            && !is_owner(cred, self.inode(fileid)?)
+        {
+            Err(NFS3ERR_ACCES.into())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Adds a new object named `name` to `dir`
+    fn create_node(
+        &mut self,
+        cred: &Identity,
+        dir: FileId3,
+        name: &str,
+        node: NewNode,
+        attributes: &SetAttributes,
+    ) -> Result<FileId3> {
+        if self.inode(dir)?.dir()?.entries.contains_key(name) {
+            return Err(NFS3ERR_EXIST.into());
+        }
+
+        let now = self.now();
+        let is_dir = node.file_type == FileType3::Dir;
+        let own = |id: Option<u32>, default: u32| match id {
+            Some(id) if cred.uid == 0 => id,
+            _ => default,
+        };
+        let time = |how: TimeHow| match how {
+            TimeHow::SetToClientTime(time) => time,
+            _ => now,
+        };
+        let mut inode = Inode {
+            file_type: node.file_type,
+            mode: attributes.mode.unwrap_or(node.mode) & 0o7777,
+            uid: own(attributes.uid, cred.uid),
+            gid: own(attributes.gid, cred.gid),
+            num_links: if is_dir { 2 } else { 1 },
+            rdev: node.rdev,
+            atime: time(attributes.atime),
+            mtime: time(attributes.mtime),
+            ctime: now,
+            content: node.content,
+            verifier: None,
+        };
+        if let (Some(size), Content::File(data)) = (attributes.size, &mut inode.content) {
+            if size > MAX_FILE_SIZE {
+                return Err(NFS3ERR_FBIG.into());
+            }
+            data.resize(size as usize, 0);
+        }
+
+        let fileid = self.next_id;
+        self.next_id += 1;
+        self.inodes.insert(fileid, inode);
+        let dir_inode = self.inode_mut(dir)?;
+        dir_inode
+            .dir_mut()?
+            .entries
+            .insert(name.to_string(), fileid);
+        if is_dir {
+            dir_inode.num_links += 1;
+        }
+        self.touch_dir(dir)?;
+
+        Ok(fileid)
+    }
+
+    /// Drops a link to `fileid`, the inode is freed with its last link
+    fn unlink(&mut self, fileid: FileId3) -> Result<()> {
+        let now = self.now();
+        let inode = self.inode_mut(fileid)?;
+        let dead = match inode.file_type {
+            FileType3::Dir => true,
+            _ => {
+                inode.num_links -= 1;
+                inode.ctime = now;
+                inode.num_links == 0
+            }
+        };
+        if dead {
+            self.inodes.remove(&fileid);
+        }
+
+        Ok(())
+    }
+
+    fn setattr(
+        &mut self,
+        cred: &Identity,
+        fileid: FileId3,
+        attributes: &SetAttributes,
+    ) -> Result<()> {
+        let now = self.now();
+        let can_write = self.check_io(cred, fileid, ACCESS3_MODIFY).is_ok();
+        let inode = self.inode_mut(fileid)?;
+        let owner = is_owner(cred, inode);
+
+        if attributes.mode.is_some() && !owner {
+            return Err(NFS3ERR_PERM.into());
+        }
+        if attributes.uid.is_some_and(|uid| uid != inode.uid) && cred.uid != 0 {
+            return Err(NFS3ERR_PERM.into());
+        }
+        if let Some(gid) = attributes.gid {
+            if gid != inode.gid && !(cred.uid == 0 || owner && in_group(cred, gid)) {
+                return Err(NFS3ERR_PERM.into());
+            }
+        }
+        let set_times = [attributes.atime, attributes.mtime];
+        for how in set_times {
+            match how {
+                TimeHow::DontChange => (),
+                TimeHow::SetToServerTime if owner || can_write => (),
+                TimeHow::SetToClientTime(_) if owner => (),
+                _ => return Err(NFS3ERR_ACCES.into()),
+            }
+        }
+        if let Some(size) = attributes.size {
+            if !can_write {
+                return Err(NFS3ERR_ACCES.into());
+            }
+            if size > MAX_FILE_SIZE {
+                return Err(NFS3ERR_FBIG.into());
+            }
+            let data = inode.data_mut()?;
+            if data.len() as u64 != size {
+                data.resize(size as usize, 0);
+                inode.mtime = now;
+            }
+        }
+
+        if let Some(mode) = attributes.mode {
+            inode.mode = mode & 0o7777;
+        }
+        if let Some(uid) = attributes.uid {
+            inode.uid = uid;
+        }
+        if let Some(gid) = attributes.gid {
+            inode.gid = gid;
+        }
+        match attributes.atime {
+            TimeHow::DontChange => (),
+            TimeHow::SetToServerTime => inode.atime = now,
+            TimeHow::SetToClientTime(time) => inode.atime = time,
+        }
+        match attributes.mtime {
+            TimeHow::DontChange => (),
+            TimeHow::SetToServerTime => inode.mtime = now,
+            TimeHow::SetToClientTime(time) => inode.mtime = time,
+        }
+        inode.ctime = now;
+        inode.verifier = None;
+
+        Ok(())
+    }
+
+    fn create(
+        &mut self,
+        cred: &Identity,
+        dir: FileId3,
+        name: &str,
+        how: &CreateHow3,
+    ) -> Result<FileId3> {
+        self.check_modify_dir(cred, dir)?;
+        let existing = self.inode(dir)?.dir()?.entries.get(name).copied();
+        let no_attributes = SetAttributes {
+            atime: TimeHow::SetToServerTime,
+            mtime: TimeHow::SetToServerTime,
+            ..Default::default()
+        };
+        let attributes = match (how, existing) {
+            (CreateHow3::Unchecked(attributes), Some(fileid)) => {
+                if self.inode(fileid)?.file_type != FileType3::Reg {
+                    return Err(NFS3ERR_EXIST.into());
+                }
+                if attributes.size.is_some() {
+                    let size = SetAttributes {
+                        size: attributes.size,
+                        atime: TimeHow::DontChange,
+                        mtime: TimeHow::DontChange,
+                        ..Default::default()
+                    };
+                    self.setattr(cred, fileid, &size)?;
+                }
+                return Ok(fileid);
+            }
+            (CreateHow3::Exclusive(verifier), Some(fileid)) => {
+                return match self.inode(fileid)?.verifier {
+                    Some(existing) if existing == *verifier => Ok(fileid),
+                    _ => Err(NFS3ERR_EXIST.into()),
+                };
+            }
+            (_, Some(_)) => return Err(NFS3ERR_EXIST.into()),
+            (CreateHow3::Unchecked(attributes), None) => attributes,
+            (CreateHow3::Guarded(attributes), None) => attributes,
+            (CreateHow3::Exclusive(_), None) => &no_attributes,
+        };
+
+        let node = NewNode {
+            file_type: FileType3::Reg,
+            content: Content::File(Vec::new()),
+            rdev: SpecData3::default(),
+            mode: 0o644,
+        };
+        let fileid = self.create_node(cred, dir, name, node, attributes)?;
+        if let CreateHow3::Exclusive(verifier) = how {
+            self.inode_mut(fileid)?.verifier = Some(*verifier);
+        }
+
+        Ok(fileid)
+    }
+
+    fn remove(&mut self, cred: &Identity, dir: FileId3, name: &str, rmdir: bool) -> Result<()> {
+        self.check_modify_dir(cred, dir)?;
+        match (name, rmdir) {
+            (".", true) => return Err(NFS3ERR_INVAL.into()),
+            ("..", true) => return Err(NFS3ERR_NOTEMPTY.into()),
+            (".", false) | ("..", false) => return Err(NFS3ERR_ISDIR.into()),
+            _ => (),
+        }
+
+        let fileid = self.lookup(dir, name)?;
+        let inode = self.inode(fileid)?;
+        match (&inode.content, rmdir) {
+            (Content::Dir(victim), true) if !victim.entries.is_empty() => {
+                return Err(NFS3ERR_NOTEMPTY.into())
+            }
+            (Content::Dir(_), false) => return Err(NFS3ERR_ISDIR.into()),
+            (Content::Dir(_), true) => (),
+            (_, true) => return Err(NFS3ERR_NOTDIR.into()),
+            (_, false) => (),
+        }
+        self.check_sticky(cred, dir, fileid)?;
+
+        let dir_inode = self.inode_mut(dir)?;
+        dir_inode.dir_mut()?.entries.remove(name);
+        if rmdir {
+            dir_inode.num_links -= 1;
+        }
+        self.touch_dir(dir)?;
+        self.unlink(fileid)
+    }
+
+    /// Whether `ancestor` is `fileid` or one of its parents
+    fn is_ancestor(&self, ancestor: FileId3, mut fileid: FileId3) -> Result<bool> {
+        loop {
+            if fileid == ancestor {
+                return Ok(true);
+            }
+            if fileid == ROOT_ID {
+                return Ok(false);
+            }
+            fileid = self.inode(fileid)?.dir()?.parent;
+        }
+    }
+
+    fn rename(
+        &mut self,
+        cred: &Identity,
+        from_dir: FileId3,
+        from_name: &str,
+        to_dir: FileId3,
+        to_name: &str,
+    ) -> Result<()> {
+        self.check_modify_dir(cred, from_dir)?;
+        self.check_modify_dir(cred, to_dir)?;
+        if matches!(from_name, "." | "..") {
+            return Err(NFS3ERR_INVAL.into());
+        }
+
+        let fileid = self.lookup(from_dir, from_name)?;
+        self.check_sticky(cred, from_dir, fileid)?;
+        let is_dir = self.inode(fileid)?.file_type == FileType3::Dir;
+        if is_dir && self.is_ancestor(fileid, to_dir)? {
+            return Err(NFS3ERR_INVAL.into());
+        }
+
+        let target = self.inode(to_dir)?.dir()?.entries.get(to_name).copied();
+        if let Some(target) = target {
+            if target == fileid {
+                return Ok(());
+            }
+            self.check_sticky(cred, to_dir, target)?;
+            match (&self.inode(target)?.content, is_dir) {
+                (Content::Dir(victim), true) if !victim.entries.is_empty() => {
+                    return Err(NFS3ERR_NOTEMPTY.into())
+                }
+                (Content::Dir(_), true) => {
+                    self.inode_mut(to_dir)?.num_links -= 1;
+                }
+                (Content::Dir(_), false) => return Err(NFS3ERR_ISDIR.into()),
+                (_, true) => return Err(NFS3ERR_NOTDIR.into()),
+                (_, false) => (),
+            }
+            self.unlink(target)?;
+        }
+
+        let from_inode = self.inode_mut(from_dir)?;
+        from_inode.dir_mut()?.entries.remove(from_name);
+        if is_dir {
+            from_inode.num_links -= 1;
+        }
+        let to_inode = self.inode_mut(to_dir)?;
+        to_inode
+            .dir_mut()?
+            .entries
+            .insert(to_name.to_string(), fileid);
+        if is_dir {
+            to_inode.num_links += 1;
+        }
+
+        let now = self.now();
+        let inode = self.inode_mut(fileid)?;
+        inode.ctime = now;
+        if let Content::Dir(dir) = &mut inode.content {
+            dir.parent = to_dir;
+        }
+        self.touch_dir(from_dir)?;
+        self.touch_dir(to_dir)
+    }
+
+    fn link(&mut self, cred: &Identity, fileid: FileId3, dir: FileId3, name: &str) -> Result<()> {
+        self.check_modify_dir(cred, dir)?;
+        if self.inode(fileid)?.file_type == FileType3::Dir {
+            return Err(NFS3ERR_ISDIR.into());
+        }
+        if self.inode(dir)?.dir()?.entries.contains_key(name) {
+            return Err(NFS3ERR_EXIST.into());
+        }
+
+        let now = self.now();
+        let inode = self.inode_mut(fileid)?;
+        inode.num_links += 1;
+        inode.ctime = now;
+        let dir_inode = self.inode_mut(dir)?;
+        dir_inode
+            .dir_mut()?
+            .entries
+            .insert(name.to_string(), fileid);
+        self.touch_dir(dir)
+    }
+
+    fn readdir(
+        &self,
+        cred: &Identity,
+        dir: FileId3,
+        cookie: Cookie3,
+        verifier: Verifier3,
+    ) -> Result<DirPage> {
+        self.check_access(cred, dir, ACCESS3_READ)?;
+        let dir_inode = self.inode(dir)?.dir()?;
+        if cookie != 0 && verifier != 0 && verifier != dir_inode.version {
+            return Err(NFS3ERR_BAD_COOKIE.into());
+        }
+
+        let entry = |fileid: FileId3, name: &str, cookie: Cookie3| DirEntry {
+            fileid,
+            name: name.to_string(),
+            cookie,
+            handle: Some(handle(fileid)),
+        };
+        let dots = [
+            entry(dir, ".", DOT_COOKIE),
+            entry(dir_inode.parent, "..", DOTDOT_COOKIE),
+        ];
+        let entries = dir_inode
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(i, (name, fileid))| entry(*fileid, name, DOTDOT_COOKIE + 1 + i as u64));
+        let entries = dots
+            .into_iter()
+            .chain(entries)
+            .skip(cookie as usize)
+            .collect();
+
+        Ok(DirPage {
+            entries,
+            verifier: dir_inode.version,
+            eof: true,
+        })
+    }
+
+    /// Follows `path` from the root
+    fn resolve(&self, path: &str) -> Result<FileId3> {
+        path.split('/')
+            .filter(|name| !name.is_empty())
+            .try_fold(ROOT_ID, |dir, name| self.lookup(dir, name))
+    }
+}
+
+/// Splits a description line into words, double quoted parts may
+/// contain spaces and the escapes \n, \t, \\ and \"
+fn split_words(line: &str) -> Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next().ok_or(INVALID_DATA)? {
+                        '"' => break,
+                        '\\' => word.push(match chars.next().ok_or(INVALID_DATA)? {
+                            'n' => '\n',
+                            't' => '\t',
+                            c @ ('\\' | '"') => c,
+                            _ => return Err(INVALID_DATA.into()),
+                        }),
+                        c => word.push(c),
+                    }
+                }
+            }
+            c if c.is_whitespace() => words.extend(word.take()),
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+
+    Ok(words)
+}
+
+fn parse_number(value: &str) -> Result<u32> {
+    value.parse().map_err(|_| INVALID_DATA.into())
+}
+
+fn parse_time(value: &str) -> Result<TimeHow> {
+    Ok(TimeHow::SetToClientTime(NfsTime3 {
+        seconds: parse_number(value)?,
+        nano_seconds: 0,
+    }))
+}
+
+/// Filesystem held in memory
+pub struct MemFs {
+    inner: Mutex<Inner>,
+    verifier: Verifier3,
+}
+
+impl Default for MemFs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemFs {
+    /// Constructs a filesystem with an empty root directory
+    pub fn new() -> MemFs {
+        let now = system_time();
+        MemFs {
+            inner: Mutex::new(Inner::new()),
+            verifier: ((now.seconds as u64) << 32) | now.nano_seconds as u64,
+        }
+    }
+
+    /// Constructs a filesystem seeded from `description`
+    pub fn from_description(description: &str) -> Result<MemFs> {
+        let fs = MemFs::new();
+        fs.seed(description)?;
+        Ok(fs)
+    }
+
+    /// Handle of the root directory
+    pub fn root(&self) -> NfsFh3 {
+        handle(ROOT_ID)
+    }
+
+    /// Returns the handle of the object at absolute `path`
+    pub fn resolve(&self, path: &str) -> Result<NfsFh3> {
+        self.inner.lock().unwrap().resolve(path).map(handle)
+    }
+
+    /// Returns the attributes of `object`
+    pub fn attributes(&self, object: &NfsFh3) -> Result<FileAttributes> {
+        let fileid = fileid(object)?;
+        Ok(self.inner.lock().unwrap().inode(fileid)?.attributes(fileid))
+    }
+
+    /// Returns the data of a regular file
+    pub fn data(&self, file: &NfsFh3) -> Result<Bytes> {
+        let mut inner = self.inner.lock().unwrap();
+        let data = inner.inode_mut(fileid(file)?)?.data_mut()?;
+        Ok(Bytes::copy_from_slice(data))
+    }
+
+    /// Adds the objects in `description`, see the module documentation
+    /// for the format.  Fails with `INVALID_DATA` if a line cannot be
+    /// parsed, objects of the lines before it are kept.
+    pub fn seed(&self, description: &str) -> Result<()> {
+        for line in description.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let words = split_words(line)?;
+            match words.as_slice() {
+                [kind, path, options @ ..] => self.seed_one(kind, path, options)?,
+                _ => return Err(INVALID_DATA.into()),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn seed_one(&self, kind: &str, path: &str, options: &[String]) -> Result<()> {
+        let root = Identity::default();
+        let mut attributes = SetAttributes {
+            atime: TimeHow::SetToServerTime,
+            mtime: TimeHow::SetToServerTime,
+            ..Default::default()
+        };
+        let mut data = None;
+        let mut target = None;
+        let mut rdev = SpecData3::default();
+        let mut ctime = None;
+        for option in options {
+            let (key, value) = option.split_once('=').ok_or(INVALID_DATA)?;
+            match key {
+                "mode" => {
+                    let mode = u32::from_str_radix(value, 8).map_err(|_| INVALID_DATA)?;
+                    attributes.mode = Some(mode);
+                }
+                "uid" => attributes.uid = Some(parse_number(value)?),
+                "gid" => attributes.gid = Some(parse_number(value)?),
+                "size" => attributes.size = Some(parse_number(value)? as u64),
+                "atime" => attributes.atime = parse_time(value)?,
+                "mtime" => attributes.mtime = parse_time(value)?,
+                "ctime" => ctime = Some(parse_time(value)?),
+                "data" => data = Some(value.as_bytes().to_vec()),
+                "target" => target = Some(value.to_string()),
+                "rdev" => {
+                    let (major, minor) = value.split_once(',').ok_or(INVALID_DATA)?;
+                    rdev = SpecData3 {
+                        data1: parse_number(major)?,
+                        data2: parse_number(minor)?,
+                    };
+                }
+                _ => return Err(INVALID_DATA.into()),
+            }
+        }
+
+        let mut inner = self.inner.lock().unwrap();
+        let (dir, name) = match path.rsplit_once('/') {
+            Some((parent, name)) if path.starts_with('/') => (parent, name),
+            _ => return Err(INVALID_DATA.into()),
+        };
+        let mut dir_id = ROOT_ID;
+        for component in dir.split('/').filter(|name| !name.is_empty()) {
+            dir_id = match inner.lookup(dir_id, component) {
+                Ok(fileid) => fileid,
+                Err(_) => {
+                    let node = NewNode {
+                        file_type: FileType3::Dir,
+                        content: Content::Dir(Directory {
+                            parent: dir_id,
+                            entries: BTreeMap::new(),
+                            version: 1,
+                        }),
+                        rdev: SpecData3::default(),
+                        mode: 0o755,
+                    };
+                    let defaults = SetAttributes {
+                        atime: TimeHow::SetToServerTime,
+                        mtime: TimeHow::SetToServerTime,
+                        ..Default::default()
+                    };
+                    inner.create_node(&root, dir_id, component, node, &defaults)?
+                }
+            };
+        }
+
+        let special = |file_type| (file_type, Content::Special, 0o644);
+        let (file_type, content, mode) = match kind {
+            "dir" => (
+                FileType3::Dir,
+                Content::Dir(Directory {
+                    parent: dir_id,
+                    entries: BTreeMap::new(),
+                    version: 1,
+                }),
+                0o755,
+            ),
+            "file" => {
+                let data = data.unwrap_or_default();
+                if attributes.size.is_none() {
+                    attributes.size = Some(data.len() as u64);
+                }
+                (FileType3::Reg, Content::File(data), 0o644)
+            }
+            "symlink" => {
+                let target = target.ok_or(INVALID_DATA)?;
+                (FileType3::Lnk, Content::Symlink(target), 0o777)
+            }
+            "chr" => special(FileType3::Chr),
+            "blk" => special(FileType3::Blk),
+            "fifo" => special(FileType3::Fifo),
+            "sock" => special(FileType3::Sock),
+            "link" => {
+                let target = inner.resolve(&target.ok_or(INVALID_DATA)?)?;
+                return inner.link(&root, target, dir_id, name);
+            }
+            _ => return Err(INVALID_DATA.into()),
+        };
+
+        let existing = match name {
+            "" => Some(dir_id),
+            _ => inner.lookup(dir_id, name).ok(),
+        };
+        let fileid = match existing {
+            Some(fileid) if kind == "dir" && inner.inode(fileid)?.file_type == FileType3::Dir => {
+                inner.setattr(&root, fileid, &attributes)?;
+                fileid
+            }
+            Some(_) => return Err(NFS3ERR_EXIST.into()),
+            None => {
+                let node = NewNode {
+                    file_type,
+                    content,
+                    rdev,
+                    mode,
+                };
+                inner.create_node(&root, dir_id, name, node, &attributes)?
+            }
+        };
+        if let Some(TimeHow::SetToClientTime(ctime)) = ctime {
+            inner.inode_mut(fileid)?.ctime = ctime;
+        }
+
+        Ok(())
+    }
+}
+
+impl Nfs3Backend for MemFs {
+    fn getattr(&self, object: NfsFh3) -> BackendFuture<'_, FileAttributes> {
+        Box::pin(async move { self.attributes(&object) })
+    }
+
+    fn setattr(
+        &self,
+        cred: Identity,
+        object: NfsFh3,
+        attributes: SetAttributes,
+    ) -> BackendFuture<'_, ()> {
+        Box::pin(async move {
+            let mut inner = self.inner.lock().unwrap();
+            inner.setattr(&cred, fileid(&object)?, &attributes)
+        })
+    }
+
+    fn lookup(&self, cred: Identity, dir: NfsFh3, name: Filename3) -> BackendFuture<'_, NfsFh3> {
+        Box::pin(async move {
+            let inner = self.inner.lock().unwrap();
+            let dir = fileid(&dir)?;
+            inner.inode(dir)?.dir()?;
+            inner.check_access(&cred, dir, ACCESS3_LOOKUP)?;
+            inner.lookup(dir, &name).map(handle)
+        })
+    }
+
+    fn readlink(&self, _cred: Identity, symlink: NfsFh3) -> BackendFuture<'_, NfsPath3> {
+        Box::pin(async move {
+            let inner = self.inner.lock().unwrap();
+            match &inner.inode(fileid(&symlink)?)?.content {
+                Content::Symlink(target) => Ok(target.clone()),
+                _ => Err(NFS3ERR_INVAL.into()),
+            }
+        })
+    }
+
+    fn read(
+        &self,
+        cred: Identity,
+        file: NfsFh3,
+        offset: Offset3,
+        count: Count3,
+    ) -> BackendFuture<'_, (Bytes, bool)> {
+        Box::pin(async move {
+            let mut inner = self.inner.lock().unwrap();
+            let fileid = fileid(&file)?;
+            inner.inode_mut(fileid)?.data_mut()?;
+            inner.check_io(&cred, fileid, ACCESS3_READ)?;
+
+            let now = inner.now();
+            let inode = inner.inode_mut(fileid)?;
+            inode.atime = now;
+            let data = inode.data_mut()?;
+            let start = offset.min(data.len() as u64) as usize;
+            let end = start.saturating_add(count as usize).min(data.len());
+            Ok((Bytes::copy_from_slice(&data[start..end]), end == data.len()))
+        })
+    }
+
+    fn write(
+        &self,
+        cred: Identity,
+        file: NfsFh3,
+        offset: Offset3,
+        data: Bytes,
+        _stable: StableHow,
+    ) -> BackendFuture<'_, StableHow> {
+        Box::pin(async move {
+            let mut inner = self.inner.lock().unwrap();
+            let fileid = fileid(&file)?;
+            inner.inode_mut(fileid)?.data_mut()?;
+            inner.check_io(&cred, fileid, ACCESS3_MODIFY)?;
+            let end = offset.saturating_add(data.len() as u64);
+            if end > MAX_FILE_SIZE {
+                return Err(NFS3ERR_FBIG.into());
+            }
+
+            let now = inner.now();
+            let inode = inner.inode_mut(fileid)?;
+            inode.mtime = now;
+            inode.ctime = now;
+            let file_data = inode.data_mut()?;
+            let (start, end) = (offset as usize, end as usize);
+            if file_data.len() < end {
+                file_data.resize(end, 0);
+            }
+            file_data[start..end].copy_from_slice(&data);
+
+            // nothing to lose in a restart
+            Ok(StableHow::FileSync)
+        })
+    }
+
+    fn create(
+        &self,
+        cred: Identity,
+        dir: NfsFh3,
+        name: Filename3,
+        how: CreateHow3,
+    ) -> BackendFuture<'_, NfsFh3> {
+        Box::pin(async move {
+            let mut inner = self.inner.lock().unwrap();
+            inner.create(&cred, fileid(&dir)?, &name, &how).map(handle)
+        })
+    }
+
+    fn mkdir(
+        &self,
+        cred: Identity,
+        dir: NfsFh3,
+        name: Filename3,
+        attributes: SetAttributes,
+    ) -> BackendFuture<'_, NfsFh3> {
+        Box::pin(async move {
+            let mut inner = self.inner.lock().unwrap();
+            let dir = fileid(&dir)?;
+            inner.check_modify_dir(&cred, dir)?;
+            let node = NewNode {
+                file_type: FileType3::Dir,
+                content: Content::Dir(Directory {
+                    parent: dir,
+                    entries: BTreeMap::new(),
+                    version: 1,
+                }),
+                rdev: SpecData3::default(),
+                mode: 0o755,
+            };
+            inner
+                .create_node(&cred, dir, &name, node, &attributes)
+                .map(handle)
+        })
+    }
+
+    fn symlink(
+        &self,
+        cred: Identity,
+        dir: NfsFh3,
+        name: Filename3,
+        target: NfsPath3,
+        attributes: SetAttributes,
+    ) -> BackendFuture<'_, NfsFh3> {
+        Box::pin(async move {
+            let mut inner = self.inner.lock().unwrap();
+            let dir = fileid(&dir)?;
+            inner.check_modify_dir(&cred, dir)?;
+            let node = NewNode {
+                file_type: FileType3::Lnk,
+                content: Content::Symlink(target),
+                rdev: SpecData3::default(),
+                mode: 0o777,
+            };
+            inner
+                .create_node(&cred, dir, &name, node, &attributes)
+                .map(handle)
+        })
+    }
+
+    fn mknod(
+        &self,
+        cred: Identity,
+        dir: NfsFh3,
+        name: Filename3,
+        what: MknodData3,
+    ) -> BackendFuture<'_, NfsFh3> {
+        Box::pin(async move {
+            let mut inner = self.inner.lock().unwrap();
+            let dir = fileid(&dir)?;
+            inner.check_modify_dir(&cred, dir)?;
+            let (file_type, attributes, rdev) = match what {
+                MknodData3::Chr(device) => (FileType3::Chr, device.attributes, device.spec),
+                MknodData3::Blk(device) => (FileType3::Blk, device.attributes, device.spec),
+                MknodData3::Sock(attributes) => (FileType3::Sock, attributes, Default::default()),
+                MknodData3::Fifo(attributes) => (FileType3::Fifo, attributes, Default::default()),
+            };
+            if matches!(file_type, FileType3::Chr | FileType3::Blk) && cred.uid != 0 {
+                return Err(NFS3ERR_PERM.into());
+            }
+
+            let node = NewNode {
+                file_type,
+                content: Content::Special,
+                rdev,
+                mode: 0o644,
+            };
+            inner
+                .create_node(&cred, dir, &name, node, &attributes)
+                .map(handle)
+        })
+    }
+
+    fn remove(&self, cred: Identity, dir: NfsFh3, name: Filename3) -> BackendFuture<'_, ()> {
+        Box::pin(async move {
+            let mut inner = self.inner.lock().unwrap();
+            inner.remove(&cred, fileid(&dir)?, &name, false)
+        })
+    }
+
+    fn rmdir(&self, cred: Identity, dir: NfsFh3, name: Filename3) -> BackendFuture<'_, ()> {
+        Box::pin(async move {
+            let mut inner = self.inner.lock().unwrap();
+            inner.remove(&cred, fileid(&dir)?, &name, true)
+        })
+    }
+
+    fn rename(
+        &self,
+        cred: Identity,
+        from_dir: NfsFh3,
+        from_name: Filename3,
+        to_dir: NfsFh3,
+        to_name: Filename3,
+    ) -> BackendFuture<'_, ()> {
+        Box::pin(async move {
+            let mut inner = self.inner.lock().unwrap();
+            let (from_dir, to_dir) = (fileid(&from_dir)?, fileid(&to_dir)?);
+            inner.rename(&cred, from_dir, &from_name, to_dir, &to_name)
+        })
+    }
+
+    fn link(
+        &self,
+        cred: Identity,
+        file: NfsFh3,
+        dir: NfsFh3,
+        name: Filename3,
+    ) -> BackendFuture<'_, ()> {
+        Box::pin(async move {
+            let mut inner = self.inner.lock().unwrap();
+            inner.link(&cred, fileid(&file)?, fileid(&dir)?, &name)
+        })
+    }
+
+    fn readdir(
+        &self,
+        cred: Identity,
+        dir: NfsFh3,
+        cookie: Cookie3,
+        verifier: Verifier3,
+        _count: Count3,
+    ) -> BackendFuture<'_, DirPage> {
+        Box::pin(async move {
+            let inner = self.inner.lock().unwrap();
+            inner.readdir(&cred, fileid(&dir)?, cookie, verifier)
+        })
+    }
+
+    fn readdirplus(
+        &self,
+        cred: Identity,
+        dir: NfsFh3,
+        cookie: Cookie3,
+        verifier: Verifier3,
+        count: Count3,
+    ) -> BackendFuture<'_, DirPage> {
+        // entries from readdir already carry their handles
+        self.readdir(cred, dir, cookie, verifier, count)
+    }
+
+    fn fsstat(&self, _root: NfsFh3) -> BackendFuture<'_, Fsstat3ResOk> {
+        Box::pin(async move {
+            let inner = self.inner.lock().unwrap();
+            let used: u64 = inner.inodes.values().map(Inode::size).sum();
+            let files = inner.inodes.len() as u64;
+            Ok(Fsstat3ResOk {
+                obj_attributes: None,
+                tbytes: TOTAL_BYTES,
+                fbytes: TOTAL_BYTES.saturating_sub(used),
+                abytes: TOTAL_BYTES.saturating_sub(used),
+                tfiles: TOTAL_FILES,
+                ffiles: TOTAL_FILES - files,
+                afiles: TOTAL_FILES - files,
+                invarsec: 0,
+            })
+        })
+    }
+
+    fn fsinfo(&self, _root: NfsFh3) -> BackendFuture<'_, Fsinfo3ResOk> {
+        Box::pin(async {
+            Ok(Fsinfo3ResOk {
+                maxfilesize: MAX_FILE_SIZE,
+                ..default_fsinfo()
+            })
+        })
+    }
+
+    fn commit(
+        &self,
+        _cred: Identity,
+        file: NfsFh3,
+        _offset: Offset3,
+        _count: Count3,
+    ) -> BackendFuture<'_, ()> {
+        Box::pin(async move { self.attributes(&file).map(|_| ()) })
+    }
+
+    fn write_verifier(&self) -> Verifier3 {
+        self.verifier
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nfs3::{
+        procs::DeviceData3, server::tests::serve, NFS3ERR_EXIST, NFS3ERR_NOT_SYNC, NFS3_OK,
+    };
+    use std::sync::Arc;
+
+    const DESCRIPTION: &str = r#"
+        # test tree
+        dir     /export         mode=0755 uid=1000 gid=1000
+        file    /export/hello   data="hello\n" mode=0644 uid=1000 gid=1000
+        symlink /export/link    target=hello
+        link    /export/hello2  target=/export/hello
+        chr     /export/null    rdev=1,3 mtime=1700000000
+        file    /deep/dir/zeros size=4096
+    "#;
+
+    fn root() -> Identity {
+        Identity::new(0, 0, vec![])
+    }
+
+    fn user() -> Identity {
+        Identity::new(1000, 1000, vec![])
+    }
+
+    fn other() -> Identity {
+        Identity::new(2000, 2000, vec![])
+    }
+
+    #[tokio::test]
+    async fn seed() {
+        let fs = MemFs::from_description(DESCRIPTION).unwrap();
+
+        let hello = fs.resolve("/export/hello").unwrap();
+        assert_eq!(&fs.data(&hello).unwrap()[..], b"hello\n");
+        let attributes = fs.attributes(&hello).unwrap();
+        assert_eq!((attributes.mode, attributes.uid), (0o644, 1000));
+        assert_eq!(attributes.num_links, 2);
+        assert_eq!(fs.resolve("/export/hello2").unwrap(), hello);
+
+        let link = fs.resolve("/export/link").unwrap();
+        assert_eq!(fs.readlink(root(), link).await.unwrap(), "hello");
+        let null = fs.attributes(&fs.resolve("/export/null").unwrap()).unwrap();
+        assert_eq!(null.file_type, FileType3::Chr);
+        assert_eq!((null.rdev.data1, null.rdev.data2), (1, 3));
+        assert_eq!(null.mtime.seconds, 1700000000);
+
+        let deep = fs.attributes(&fs.resolve("/deep/dir").unwrap()).unwrap();
+        assert_eq!((deep.file_type, deep.mode), (FileType3::Dir, 0o755));
+        let zeros = fs.resolve("/deep/dir/zeros").unwrap();
+        assert_eq!(fs.data(&zeros).unwrap(), vec![0; 4096]);
+
+        assert!(fs.resolve("/export/missing").is_err());
+        assert!(MemFs::from_description("file /x data=\"open").is_err());
+        assert!(MemFs::from_description("tape /x").is_err());
+    }
+
+    #[tokio::test]
+    async fn links_and_rename() {
+        let fs = MemFs::from_description(DESCRIPTION).unwrap();
+        let export = fs.resolve("/export").unwrap();
+        let hello = fs.resolve("/export/hello").unwrap();
+
+        fs.remove(user(), export.clone(), "hello".into())
+            .await
+            .unwrap();
+        assert_eq!(fs.attributes(&hello).unwrap().num_links, 1);
+        fs.rename(
+            user(),
+            export.clone(),
+            "hello2".into(),
+            fs.root(),
+            "moved".into(),
+        )
+        .await
+        .unwrap_err();
+        fs.rename(
+            user(),
+            export.clone(),
+            "hello2".into(),
+            export.clone(),
+            "moved".into(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(fs.resolve("/export/moved").unwrap(), hello);
+
+        fs.remove(user(), export.clone(), "moved".into())
+            .await
+            .unwrap();
+        let err = fs.getattr(hello).await.unwrap_err();
+        assert_eq!(err.get(), NFS3ERR_STALE);
+        let err = fs.getattr(NfsFh3 { data: vec![1] }).await.unwrap_err();
+        assert_eq!(err.get(), NFS3ERR_BADHANDLE);
+
+        let err = fs
+            .rmdir(root(), fs.root(), "deep".into())
+            .await
+            .unwrap_err();
+        assert_eq!(err.get(), NFS3ERR_NOTEMPTY);
+    }
+
+    #[tokio::test]
+    async fn permissions() {
+        let fs = MemFs::from_description(DESCRIPTION).unwrap();
+        let export = fs.resolve("/export").unwrap();
+        let hello = fs.resolve("/export/hello").unwrap();
+
+        let access = ACCESS3_READ | ACCESS3_MODIFY;
+        let allowed = fs.access(other(), hello.clone(), access).await.unwrap();
+        assert_eq!(allowed, ACCESS3_READ);
+        let err = fs
+            .write(other(), hello.clone(), 0, Bytes::new(), StableHow::FileSync)
+            .await
+            .unwrap_err();
+        assert_eq!(err.get(), NFS3ERR_ACCES);
+        let err = fs
+            .mkdir(other(), export.clone(), "d".into(), Default::default())
+            .await
+            .unwrap_err();
+        assert_eq!(err.get(), NFS3ERR_ACCES);
+        let chown = SetAttributes {
+            uid: Some(2000),
+            ..Default::default()
+        };
+        let err = fs.setattr(user(), hello.clone(), chown).await.unwrap_err();
+        assert_eq!(err.get(), NFS3ERR_PERM);
+
+        let null = MknodData3::Chr(DeviceData3 {
+            attributes: Default::default(),
+            spec: SpecData3::default(),
+        });
+        let err = fs
+            .mknod(user(), export.clone(), "null2".into(), null)
+            .await
+            .unwrap_err();
+        assert_eq!(err.get(), NFS3ERR_PERM);
+    }
+
+    #[tokio::test]
+    async fn exclusive_create() {
+        let fs = MemFs::new();
+        let how = || CreateHow3::Exclusive(7);
+        let file = fs
+            .create(root(), fs.root(), "f".into(), how())
+            .await
+            .unwrap();
+        // a retransmission succeeds, another verifier does not
+        let again = fs
+            .create(root(), fs.root(), "f".into(), how())
+            .await
+            .unwrap();
+        assert_eq!(again, file);
+        let err = fs
+            .create(root(), fs.root(), "f".into(), CreateHow3::Exclusive(8))
+            .await
+            .unwrap_err();
+        assert_eq!(err.get(), NFS3ERR_EXIST);
+
+        let err = fs
+            .write(
+                root(),
+                file,
+                MAX_FILE_SIZE,
+                Bytes::from("x"),
+                StableHow::Unstable,
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(err.get(), NFS3ERR_FBIG);
+    }
+
+    #[tokio::test]
+    async fn readdir_cookies() {
+        let fs = MemFs::from_description("file /a\nfile /b\nfile /c").unwrap();
+        let page = fs.readdir(root(), fs.root(), 0, 0, 1024).await.unwrap();
+        let names: Vec<_> = page.entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, [".", "..", "a", "b", "c"]);
+        assert!(page.eof);
+
+        let cookie = page.entries[2].cookie;
+        let next = fs
+            .readdir(root(), fs.root(), cookie, page.verifier, 1024)
+            .await
+            .unwrap();
+        assert_eq!(next.entries[0].name, "b");
+
+        fs.remove(root(), fs.root(), "c".into()).await.unwrap();
+        let err = fs
+            .readdir(root(), fs.root(), cookie, page.verifier, 1024)
+            .await
+            .unwrap_err();
+        assert_eq!(err.get(), NFS3ERR_BAD_COOKIE);
+    }
+
+    #[tokio::test]
+    async fn serve_nfs3() {
+        let fs = Arc::new(MemFs::from_description(DESCRIPTION).unwrap());
+        let client = serve(fs.clone());
+
+        let export = fs.resolve("/export").unwrap();
+        let attributes = SetAttributes {
+            mode: Some(0o644),
+            ..Default::default()
+        };
+        let res_ok = client
+            .call_create(&export, "new".into(), CreateHow3::Guarded(attributes))
+            .await
+            .unwrap()
+            .unwrap();
+        let file = res_ok.obj.unwrap();
+        client
+            .call_write(
+                &file,
+                0,
+                5,
+                Bytes::from_static(b"howdy"),
+                StableHow::Unstable,
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(&fs.data(&file).unwrap()[..], b"howdy");
+        let res_ok = client.call_read(&file, 0, 100).await.unwrap().unwrap();
+        assert_eq!(&res_ok.data[..], b"howdy");
+
+        let res_ok = client.call_readdir(&export, 0, 0).await.unwrap().unwrap();
+        let names: Vec<_> = res_ok.reply.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, [".", "..", "hello", "hello2", "link", "new", "null"]);
+    }
+
+    #[tokio::test]
+    async fn nfs3_calls() {
+        let fs = Arc::new(MemFs::from_description(DESCRIPTION).unwrap());
+        let client = serve(fs.clone());
+        client.call_null().await.unwrap();
+
+        let hello = fs.resolve("/export/hello").unwrap();
+        let ctime = fs.attributes(&hello).unwrap().ctime;
+        let mode = SetAttributes {
+            mode: Some(0o600),
+            ..Default::default()
+        };
+        let stale = NfsTime3 {
+            seconds: ctime.seconds + 1,
+            nano_seconds: 0,
+        };
+        let (status, _) = client
+            .call_setattr(&hello, mode.clone(), Some(stale))
+            .await
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(status, NFS3ERR_NOT_SYNC);
+        let res_ok = client
+            .call_setattr(&hello, mode, Some(ctime))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(res_ok.obj_wcc.after.unwrap().mode, 0o600);
+
+        let access = ACCESS3_READ | ACCESS3_MODIFY | ACCESS3_LOOKUP;
+        let res_ok = client.call_access(&hello, access).await.unwrap().unwrap();
+        assert_eq!(res_ok.access, ACCESS3_READ | ACCESS3_MODIFY);
+
+        let export = fs.resolve("/export").unwrap();
+        let device = DeviceData3 {
+            attributes: SetAttributes {
+                mode: Some(0o620),
+                ..Default::default()
+            },
+            spec: SpecData3 { data1: 4, data2: 1 },
+        };
+        let res_ok = client
+            .call_mknod(&export, "tty".into(), MknodData3::Chr(device))
+            .await
+            .unwrap()
+            .unwrap();
+        let attributes = res_ok.attributes.unwrap();
+        assert_eq!(
+            (attributes.file_type, attributes.mode),
+            (FileType3::Chr, 0o620)
+        );
+        assert_eq!((attributes.rdev.data1, attributes.rdev.data2), (4, 1));
+        let fifo = MknodData3::Fifo(Default::default());
+        client
+            .call_mknod(&export, "fifo".into(), fifo)
+            .await
+            .unwrap()
+            .unwrap();
+        let res_ok = client
+            .call_symlink(&export, "l1".into(), "hello".into(), Default::default())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(res_ok.attributes.unwrap().mode, 0o777);
+        let owner = SetAttributes {
+            uid: Some(1000),
+            gid: Some(1000),
+            ..Default::default()
+        };
+        let res_ok = client
+            .call_symlink(&export, "l2".into(), "hello".into(), owner)
+            .await
+            .unwrap()
+            .unwrap();
+        let attributes = res_ok.attributes.unwrap();
+        assert_eq!((attributes.uid, attributes.gid), (1000, 1000));
+
+        let root = fs.root();
+        let (status, _) = client
+            .call_rmdir(&root, "export".into())
+            .await
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(status, NFS3ERR_NOTEMPTY);
+        client
+            .call_mkdir(&root, "empty".into(), Default::default())
+            .await
+            .unwrap()
+            .unwrap();
+        client
+            .call_rmdir(&root, "empty".into())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(fs.resolve("/empty").is_err());
+    }
+
+    #[tokio::test]
+    async fn nfs3_exclusive_create() {
+        let fs = Arc::new(MemFs::from_description(DESCRIPTION).unwrap());
+        let client = serve(fs.clone());
+        let export = fs.resolve("/export").unwrap();
+
+        let attributes = SetAttributes {
+            mode: Some(0o640),
+            uid: Some(1000),
+            ..Default::default()
+        };
+        let res_ok = client
+            .call_create_exclusive(&export, "excl".into(), 99, attributes)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(res_ok.setattr_status, NFS3_OK);
+        let file = res_ok.obj;
+        assert_eq!(fs.resolve("/export/excl").unwrap(), file);
+        let attributes = res_ok.attributes.unwrap();
+        assert_eq!((attributes.mode, attributes.uid), (0o640, 1000));
+        assert_eq!(fs.attributes(&file).unwrap().mode, 0o640);
+
+        let (status, _) = client
+            .call_create_exclusive(&export, "excl".into(), 100, Default::default())
+            .await
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(status, NFS3ERR_EXIST);
+
+        // the file is created even though its size cannot be set
+        let too_big = SetAttributes {
+            size: Some(u64::MAX),
+            ..Default::default()
+        };
+        let res_ok = client
+            .call_create_exclusive(&export, "big".into(), 101, too_big)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(res_ok.setattr_status, NFS3ERR_FBIG);
+        assert_eq!(fs.resolve("/export/big").unwrap(), res_ok.obj);
+
+        let mkdir = SetAttributes {
+            mode: Some(0o700),
+            gid: Some(1000),
+            ..Default::default()
+        };
+        let res_ok = client
+            .call_mkdir(&export, "private".into(), mkdir)
+            .await
+            .unwrap()
+            .unwrap();
+        let attributes = res_ok.attributes.unwrap();
+        assert_eq!((attributes.mode, attributes.gid), (0o700, 1000));
+    }
+}
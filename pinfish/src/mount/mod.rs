@@ -0,0 +1,82 @@
+//! This modules defines the constants and structures for encoding and
+//! decoding NFS MOUNT protocol.
+use crate::{
+    nfs3::NfsFh3,
+    xdr::{self, List},
+};
+use pinfish_macros::{PackTo, UnpackFrom};
+
+pub mod server;
+
+pub const PROGRAM: u32 = 100005;
+pub const VERSION: u32 = 3;
+
+pub const MOUNTPROC3_NULL: u32 = 0;
+pub const MOUNTPROC3_MNT: u32 = 1;
+pub const MOUNTPROC3_DUMP: u32 = 2;
+pub const MOUNTPROC3_UMNT: u32 = 3;
+pub const MOUNTPROC3_UMNTALL: u32 = 4;
+pub const MOUNTPROC3_EXPORT: u32 = 5;
+
+/// Maximum bytes in a path name
+pub const MNTPATHLEN: usize = 1024;
+/// Maximum bytes in a host or group name
+pub const MNTNAMLEN: usize = 255;
+
+#[derive(PackTo, UnpackFrom, Debug)]
+pub struct MountRes3Ok {
+    pub handle: NfsFh3,
+    pub auth_flavors: Vec<u32>,
+}
+
+pub type MountResult = Result<MountRes3Ok, u32>;
+
+/// A mount recorded by the server (`mountbody`)
+#[derive(PackTo, UnpackFrom, Debug, Clone, PartialEq, Eq)]
+pub struct MountBody {
+    pub hostname: String,
+    pub directory: String,
+}
+
+/// MOUNTPROC3_DUMP results
+pub type MountList = List<MountBody>;
+
+/// An exported directory and the groups allowed to mount it
+/// (`exportnode`).  An empty group list means any client may mount it.
+#[derive(PackTo, UnpackFrom, Debug, Clone, PartialEq, Eq)]
+pub struct ExportNode {
+    pub dir: String,
+    pub groups: List<String>,
+}
+
+/// MOUNTPROC3_EXPORT results
+pub type Exports = List<ExportNode>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::xdr::{PackTo as _, UnpackFrom as _};
+    use bytes::BytesMut;
+
+    #[test]
+    fn exports() {
+        let exports = List(vec![
+            ExportNode {
+                dir: "/export".into(),
+                groups: List(vec!["10.0.0.0/8".into(), "client".into()]),
+            },
+            ExportNode {
+                dir: "/public".into(),
+                groups: List(vec![]),
+            },
+        ]);
+        let mut buf = BytesMut::new();
+        exports.pack_to(&mut buf);
+        // "/export" with two groups, "/public" with none, end of list
+        assert_eq!(buf.len(), (4 + 12 + 4 + 16 + 4 + 12 + 4) + (4 + 12 + 4) + 4);
+
+        let mut buf = buf.freeze();
+        assert_eq!(Exports::unpack_from(&mut buf).unwrap(), exports);
+        assert!(buf.is_empty());
+    }
+}
@@ -0,0 +1,389 @@
+//! MOUNT version 3 server.
+//!
+//! `MountServer` maps exported paths to the root file handles of an
+//! NFS server and records which clients mounted them.  Only exported
+//! paths themselves can be mounted, not directories below them.
+use super::{
+    ExportNode, MountBody, MountRes3Ok, MountResult, MNTPATHLEN, MOUNTPROC3_DUMP,
+    MOUNTPROC3_EXPORT, MOUNTPROC3_MNT, MOUNTPROC3_NULL, MOUNTPROC3_UMNT, MOUNTPROC3_UMNTALL,
+    PROGRAM, VERSION,
+};
+use crate::{
+    nfs3::{NfsFh3, MNT3ERR_ACCES, MNT3ERR_NAMETOOLONG, MNT3ERR_NOENT},
+    portmap::{client::PortmapClient, server::Rpcbind},
+    result::{Result, INVALID_DATA, RPC_PROC_UNAVAIL},
+    rpc::{
+        server::{Call, DispatchFuture, Dispatcher, RpcServer},
+        AUTH_SYS,
+    },
+    xdr::{List, PackTo, UnpackFrom},
+};
+use bytes::BytesMut;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+/// Clients allowed to mount an export
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HostPattern {
+    /// Any client, "*"
+    Any,
+    /// Clients in a network given by an address and prefix length,
+    /// "10.0.0.0/8" or a single address such as "192.0.2.1"
+    Network(IpAddr, u8),
+}
+
+impl HostPattern {
+    pub fn matches(&self, addr: IpAddr) -> bool {
+        match *self {
+            HostPattern::Any => true,
+            HostPattern::Network(network, prefix) => match (network, addr.to_canonical()) {
+                (IpAddr::V4(network), IpAddr::V4(addr)) => {
+                    let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+                    u32::from(network) & mask == u32::from(addr) & mask
+                }
+                (IpAddr::V6(network), IpAddr::V6(addr)) => {
+                    let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+                    u128::from(network) & mask == u128::from(addr) & mask
+                }
+                _ => false,
+            },
+        }
+    }
+}
+
+impl FromStr for HostPattern {
+    type Err = crate::result::ErrorCode;
+
+    fn from_str(pattern: &str) -> Result<HostPattern> {
+        if pattern == "*" {
+            return Ok(HostPattern::Any);
+        }
+
+        let (addr, prefix) = match pattern.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (pattern, None),
+        };
+        let addr: IpAddr = addr.parse().map_err(|_| INVALID_DATA)?;
+        let max_prefix = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse().map_err(|_| INVALID_DATA)?,
+            None => max_prefix,
+        };
+        if prefix > max_prefix {
+            return Err(INVALID_DATA.into());
+        }
+
+        Ok(HostPattern::Network(addr, prefix))
+    }
+}
+
+impl fmt::Display for HostPattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HostPattern::Any => write!(f, "*"),
+            HostPattern::Network(addr, prefix) => write!(f, "{}/{}", addr, prefix),
+        }
+    }
+}
+
+/// An exported directory
+#[derive(Debug, Clone)]
+pub struct Export {
+    pub path: String,
+    /// File handle returned by MNT
+    pub root: NfsFh3,
+    /// Clients allowed to mount the export
+    pub hosts: Vec<HostPattern>,
+    /// Auth flavors accepted by the NFS server for this export
+    pub auth_flavors: Vec<u32>,
+}
+
+impl Export {
+    /// Constructs an export of `path` open to any client with AUTH_SYS
+    pub fn new(path: &str, root: NfsFh3) -> Export {
+        Export {
+            path: path.into(),
+            root,
+            hosts: vec![HostPattern::Any],
+            auth_flavors: vec![AUTH_SYS],
+        }
+    }
+
+    /// Whether a client at `peer` may mount the export.  Calls without
+    /// a peer address, e.g. over a Unix domain socket, are only allowed
+    /// by `HostPattern::Any`.
+    pub fn allows(&self, peer: Option<SocketAddr>) -> bool {
+        self.hosts.iter().any(|host| match peer {
+            Some(peer) => host.matches(peer.ip()),
+            None => *host == HostPattern::Any,
+        })
+    }
+}
+
+/// Removes a trailing slash so "/export/" and "/export" are the same
+fn normalize(path: &str) -> &str {
+    match path.strip_suffix('/') {
+        Some(stripped) if !stripped.is_empty() => stripped,
+        _ => path,
+    }
+}
+
+/// Host name recorded for mounts by `peer`, its address since names
+/// are not resolved
+fn host_name(peer: Option<SocketAddr>) -> String {
+    peer.map_or("localhost".into(), |peer| {
+        peer.ip().to_canonical().to_string()
+    })
+}
+
+/// Export table and mount records
+#[derive(Default)]
+pub struct MountServer {
+    exports: Mutex<Vec<Export>>,
+    /// Host name and directory of each mount
+    mounts: Mutex<BTreeSet<(String, String)>>,
+}
+
+impl MountServer {
+    pub fn new() -> MountServer {
+        Default::default()
+    }
+
+    /// Adds `export`, replacing any export of the same path
+    pub fn add_export(&self, mut export: Export) {
+        export.path = normalize(&export.path).into();
+        let mut exports = self.exports.lock().unwrap();
+        exports.retain(|e| e.path != export.path);
+        exports.push(export);
+    }
+
+    /// Removes the export of `path`, returns false if not exported
+    pub fn remove_export(&self, path: &str) -> bool {
+        let path = normalize(path);
+        let mut exports = self.exports.lock().unwrap();
+        let len = exports.len();
+        exports.retain(|e| e.path != path);
+        exports.len() != len
+    }
+
+    pub fn exports(&self) -> Vec<Export> {
+        self.exports.lock().unwrap().clone()
+    }
+
+    /// Returns the recorded mounts
+    pub fn mounts(&self) -> Vec<MountBody> {
+        let mounts = self.mounts.lock().unwrap();
+        mounts
+            .iter()
+            .map(|(hostname, directory)| MountBody {
+                hostname: hostname.clone(),
+                directory: directory.clone(),
+            })
+            .collect()
+    }
+
+    /// Looks up the export of `path` for a client at `peer` and records
+    /// the mount
+    pub fn mount(&self, path: &str, peer: Option<SocketAddr>) -> MountResult {
+        if path.len() > MNTPATHLEN {
+            return Err(MNT3ERR_NAMETOOLONG);
+        }
+
+        let path = normalize(path);
+        let res_ok = {
+            let exports = self.exports.lock().unwrap();
+            let export = exports
+                .iter()
+                .find(|e| e.path == path)
+                .ok_or(MNT3ERR_NOENT)?;
+            if !export.allows(peer) {
+                return Err(MNT3ERR_ACCES);
+            }
+
+            MountRes3Ok {
+                handle: export.root.clone(),
+                auth_flavors: export.auth_flavors.clone(),
+            }
+        };
+
+        let mut mounts = self.mounts.lock().unwrap();
+        mounts.insert((host_name(peer), path.into()));
+        Ok(res_ok)
+    }
+
+    /// Removes the record of a client at `peer` mounting `path`
+    pub fn unmount(&self, path: &str, peer: Option<SocketAddr>) {
+        let mut mounts = self.mounts.lock().unwrap();
+        mounts.remove(&(host_name(peer), normalize(path).into()));
+    }
+
+    /// Removes all mount records of a client at `peer`
+    pub fn unmount_all(&self, peer: Option<SocketAddr>) {
+        let host = host_name(peer);
+        let mut mounts = self.mounts.lock().unwrap();
+        mounts.retain(|(hostname, _)| *hostname != host);
+    }
+
+    /// Registers the mount program with `server`
+    pub fn register_with(self: &Arc<Self>, server: &mut RpcServer) {
+        server.register(PROGRAM, VERSION, Arc::new(MountDispatcher(self.clone())));
+    }
+
+    /// Registers the mount program as reachable at `addr` with a port
+    /// mapper running in the same process
+    pub fn register_rpcbind(&self, rpcbind: &Rpcbind, prot: u32, addr: &SocketAddr) {
+        rpcbind.register(PROGRAM, VERSION, prot, addr);
+    }
+
+    /// Registers the mount program as reachable at `addr` with the port
+    /// mapper reached over `portmap`, e.g. the system rpcbind
+    pub async fn register_portmap(
+        &self,
+        portmap: &PortmapClient,
+        prot: u32,
+        addr: &SocketAddr,
+    ) -> Result<()> {
+        portmap.register(PROGRAM, VERSION, prot, addr).await
+    }
+
+    fn export_list(&self) -> List<ExportNode> {
+        let exports = self.exports.lock().unwrap();
+        List(
+            exports
+                .iter()
+                .map(|e| ExportNode {
+                    dir: e.path.clone(),
+                    groups: List(match e.hosts.as_slice() {
+                        // no groups means anyone
+                        [HostPattern::Any] => vec![],
+                        hosts => hosts.iter().map(|host| host.to_string()).collect(),
+                    }),
+                })
+                .collect(),
+        )
+    }
+}
+
+struct MountDispatcher(Arc<MountServer>);
+
+impl MountDispatcher {
+    fn dispatch_call(&self, mut call: Call, results: &mut BytesMut) -> Result<()> {
+        let server = &self.0;
+        match call.proc {
+            MOUNTPROC3_NULL => (),
+            MOUNTPROC3_MNT => {
+                let path = String::unpack_from(&mut call.args)?;
+                server.mount(&path, call.peer).pack_to(results);
+            }
+            MOUNTPROC3_DUMP => List(server.mounts()).pack_to(results),
+            MOUNTPROC3_UMNT => {
+                let path = String::unpack_from(&mut call.args)?;
+                server.unmount(&path, call.peer);
+            }
+            MOUNTPROC3_UMNTALL => server.unmount_all(call.peer),
+            MOUNTPROC3_EXPORT => server.export_list().pack_to(results),
+            _ => return Err(RPC_PROC_UNAVAIL.into()),
+        }
+
+        Ok(())
+    }
+}
+
+impl Dispatcher for MountDispatcher {
+    fn dispatch(&self, call: Call) -> DispatchFuture {
+        let mut results = BytesMut::new();
+        let result = self.dispatch_call(call, &mut results);
+        Box::pin(async move { result.map(|_| results.freeze()) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nfs3::client::{NfsClient, Program};
+    use crate::rpc::server::tests::serve_duplex;
+
+    fn new_client(mountd: &Arc<MountServer>, peer: Option<SocketAddr>) -> NfsClient {
+        let mut server = RpcServer::new();
+        mountd.register_with(&mut server);
+        let mut client = NfsClient::new("localhost");
+        client.set_rpc_client(Program::Mount, serve_duplex(server, peer));
+        client
+    }
+
+    #[test]
+    fn host_patterns() {
+        let network: HostPattern = "10.1.0.0/16".parse().unwrap();
+        assert!(network.matches("10.1.2.3".parse().unwrap()));
+        assert!(network.matches("::ffff:10.1.2.3".parse().unwrap()));
+        assert!(!network.matches("10.2.0.1".parse().unwrap()));
+        assert_eq!(network.to_string(), "10.1.0.0/16");
+
+        let host: HostPattern = "fd00::1".parse().unwrap();
+        assert!(host.matches("fd00::1".parse().unwrap()));
+        assert!(!host.matches("fd00::2".parse().unwrap()));
+        assert!("0.0.0.0/0"
+            .parse::<HostPattern>()
+            .unwrap()
+            .matches("192.0.2.1".parse().unwrap()));
+        assert!("10.0.0.0/33".parse::<HostPattern>().is_err());
+    }
+
+    #[tokio::test]
+    async fn mount_and_unmount() {
+        let mountd = Arc::new(MountServer::new());
+        mountd.add_export(Export::new("/export/", NfsFh3 { data: vec![1, 2] }));
+        let mut private = Export::new("/private", NfsFh3 { data: vec![3] });
+        private.hosts = vec!["10.0.0.0/8".parse().unwrap()];
+        mountd.add_export(private);
+
+        let client = new_client(&mountd, None);
+        client.call_mount_null().await.unwrap();
+        let exports = client.call_export().await.unwrap();
+        assert_eq!(exports.len(), 2);
+        assert_eq!(exports[0].dir, "/export");
+        assert!(exports[0].groups.0.is_empty());
+        assert_eq!(exports[1].groups.0, vec!["10.0.0.0/8".to_string()]);
+
+        let res_ok = client.call_mount("/export").await.unwrap().unwrap();
+        assert_eq!(res_ok.handle.data, vec![1, 2]);
+        assert_eq!(res_ok.auth_flavors, vec![AUTH_SYS]);
+        assert_eq!(
+            client.call_mount("/missing").await.err().unwrap().get(),
+            MNT3ERR_NOENT
+        );
+        assert_eq!(
+            client.call_mount("/private").await.err().unwrap().get(),
+            MNT3ERR_ACCES
+        );
+
+        let mounts = client.call_mount_dump().await.unwrap();
+        assert_eq!(
+            mounts,
+            vec![MountBody {
+                hostname: "localhost".into(),
+                directory: "/export".into()
+            }]
+        );
+        client.call_umnt("/export").await.unwrap();
+        assert!(mountd.mounts().is_empty());
+    }
+
+    #[tokio::test]
+    async fn host_access() {
+        let mountd = Arc::new(MountServer::new());
+        let mut private = Export::new("/private", NfsFh3 { data: vec![3] });
+        private.hosts = vec!["10.0.0.0/8".parse().unwrap()];
+        mountd.add_export(private);
+
+        let client = new_client(&mountd, Some("10.1.2.3:700".parse().unwrap()));
+        client.call_mount("/private").await.unwrap().unwrap();
+        assert_eq!(mountd.mounts()[0].hostname, "10.1.2.3");
+
+        client.call_umntall().await.unwrap();
+        assert!(mountd.mounts().is_empty());
+    }
+}
@@ -0,0 +1,296 @@
+//! Filesystem backend of the NFSv3 server.
+//!
+//! `Nfs3Backend` has one method per NFSv3 procedure.  Methods work on
+//! file handles and names and return plain results, the server adds the
+//! weak cache consistency data and post-operation attributes to each
+//! reply.  Errors are NFS3ERR_* status codes, any other error code is
+//! replied as NFS3ERR_IO or NFS3ERR_SERVERFAULT.
+use crate::{
+    nfs3::{
+        procs::{CreateHow3, Fsinfo3ResOk, Fsstat3ResOk, MknodData3, Pathconf3ResOk, StableHow},
+        Cookie3, Count3, FileAttributes, FileId3, FileType3, Filename3, NfsFh3, NfsPath3, NfsTime3,
+        Offset3, SetAttributes, Verifier3, ACCESS3_DELETE, ACCESS3_EXECUTE, ACCESS3_EXTEND,
+        ACCESS3_LOOKUP, ACCESS3_MODIFY, ACCESS3_READ, FSF3_CANSETTIME, FSF3_HOMOGENEOUS, FSF3_LINK,
+        FSF3_SYMLINK, NFS3ERR_NOTSUPP,
+    },
+    result::Result,
+    rpc::auth::Identity,
+};
+use bytes::Bytes;
+use std::future::Future;
+use std::pin::Pin;
+
+pub type BackendFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T>> + Send + 'a>>;
+
+/// Largest READ and WRITE advertised by the default `fsinfo`, leaves
+/// room for the headers within the server's packet size limit
+pub const DEFAULT_MAX_TRANSFER: u32 = 512 * 1024;
+
+/// A directory entry returned by `readdir`
+#[derive(Debug, Clone)]
+pub struct DirEntry {
+    pub fileid: FileId3,
+    pub name: Filename3,
+    /// Cookie to continue reading the directory after this entry
+    pub cookie: Cookie3,
+    /// Handle of the entry, only used by READDIRPLUS
+    pub handle: Option<NfsFh3>,
+}
+
+/// Directory entries following a cookie
+#[derive(Debug, Clone, Default)]
+pub struct DirPage {
+    pub entries: Vec<DirEntry>,
+    pub verifier: Verifier3,
+    /// Whether `entries` reach the end of the directory
+    pub eof: bool,
+}
+
+/// Storage served by `Nfs3Server`.  Methods that change the filesystem
+/// take the identity of the caller, AUTH_NONE callers are nobody.
+pub trait Nfs3Backend: Send + Sync {
+    fn null(&self) -> BackendFuture<'_, ()> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn getattr(&self, object: NfsFh3) -> BackendFuture<'_, FileAttributes>;
+
+    /// Changes the attributes of `object`, the ctime guard is checked
+    /// by the server
+    fn setattr(
+        &self,
+        cred: Identity,
+        object: NfsFh3,
+        attributes: SetAttributes,
+    ) -> BackendFuture<'_, ()>;
+
+    fn lookup(&self, cred: Identity, dir: NfsFh3, name: Filename3) -> BackendFuture<'_, NfsFh3>;
+
+    /// Returns which of the ACCESS3_* bits in `access` are granted, by
+    /// default from the mode bits of `object`
+    fn access(&self, cred: Identity, object: NfsFh3, access: u32) -> BackendFuture<'_, u32> {
+        Box::pin(async move {
+            let attributes = self.getattr(object).await?;
+            Ok(access & allowed_access(&cred, &attributes))
+        })
+    }
+
+    fn readlink(&self, cred: Identity, symlink: NfsFh3) -> BackendFuture<'_, NfsPath3>;
+
+    /// Returns up to `count` bytes at `offset` and whether they reach
+    /// the end of the file
+    fn read(
+        &self,
+        cred: Identity,
+        file: NfsFh3,
+        offset: Offset3,
+        count: Count3,
+    ) -> BackendFuture<'_, (Bytes, bool)>;
+
+    /// Writes all of `data` at `offset`, returns how stable the data
+    /// is, at least `stable`
+    fn write(
+        &self,
+        cred: Identity,
+        file: NfsFh3,
+        offset: Offset3,
+        data: Bytes,
+        stable: StableHow,
+    ) -> BackendFuture<'_, StableHow>;
+
+    fn create(
+        &self,
+        cred: Identity,
+        dir: NfsFh3,
+        name: Filename3,
+        how: CreateHow3,
+    ) -> BackendFuture<'_, NfsFh3>;
+
+    fn mkdir(
+        &self,
+        cred: Identity,
+        dir: NfsFh3,
+        name: Filename3,
+        attributes: SetAttributes,
+    ) -> BackendFuture<'_, NfsFh3>;
+
+    fn symlink(
+        &self,
+        cred: Identity,
+        dir: NfsFh3,
+        name: Filename3,
+        target: NfsPath3,
+        attributes: SetAttributes,
+    ) -> BackendFuture<'_, NfsFh3>;
+
+    /// Creates a device, socket or FIFO, not supported by default
+    fn mknod(
+        &self,
+        _cred: Identity,
+        _dir: NfsFh3,
+        _name: Filename3,
+        _what: MknodData3,
+    ) -> BackendFuture<'_, NfsFh3> {
+        Box::pin(async { Err(NFS3ERR_NOTSUPP.into()) })
+    }
+
+    fn remove(&self, cred: Identity, dir: NfsFh3, name: Filename3) -> BackendFuture<'_, ()>;
+
+    fn rmdir(&self, cred: Identity, dir: NfsFh3, name: Filename3) -> BackendFuture<'_, ()>;
+
+    fn rename(
+        &self,
+        cred: Identity,
+        from_dir: NfsFh3,
+        from_name: Filename3,
+        to_dir: NfsFh3,
+        to_name: Filename3,
+    ) -> BackendFuture<'_, ()>;
+
+    /// Creates a hard link to `file`, not supported by default
+    fn link(
+        &self,
+        _cred: Identity,
+        _file: NfsFh3,
+        _dir: NfsFh3,
+        _name: Filename3,
+    ) -> BackendFuture<'_, ()> {
+        Box::pin(async { Err(NFS3ERR_NOTSUPP.into()) })
+    }
+
+    /// Returns the entries after `cookie`, 0 to start from the
+    /// beginning.  `count` is the reply size the client accepts, the
+    /// server drops the entries that do not fit.
+    fn readdir(
+        &self,
+        cred: Identity,
+        dir: NfsFh3,
+        cookie: Cookie3,
+        verifier: Verifier3,
+        count: Count3,
+    ) -> BackendFuture<'_, DirPage>;
+
+    /// Like `readdir` with the handle of each entry, by default looked
+    /// up one by one
+    fn readdirplus(
+        &self,
+        cred: Identity,
+        dir: NfsFh3,
+        cookie: Cookie3,
+        verifier: Verifier3,
+        count: Count3,
+    ) -> BackendFuture<'_, DirPage> {
+        Box::pin(async move {
+            let mut page = self
+                .readdir(cred.clone(), dir.clone(), cookie, verifier, count)
+                .await?;
+            for entry in &mut page.entries {
+                if entry.handle.is_none() {
+                    let handle = self.lookup(cred.clone(), dir.clone(), entry.name.clone());
+                    entry.handle = handle.await.ok();
+                }
+            }
+
+            Ok(page)
+        })
+    }
+
+    /// Returns the space and file counts, `obj_attributes` is filled in
+    /// by the server
+    fn fsstat(&self, root: NfsFh3) -> BackendFuture<'_, Fsstat3ResOk>;
+
+    /// Returns the transfer sizes and capabilities, `obj_attributes` is
+    /// filled in by the server
+    fn fsinfo(&self, _root: NfsFh3) -> BackendFuture<'_, Fsinfo3ResOk> {
+        Box::pin(async { Ok(default_fsinfo()) })
+    }
+
+    /// Returns the POSIX limits, `obj_attributes` is filled in by the
+    /// server
+    fn pathconf(&self, _object: NfsFh3) -> BackendFuture<'_, Pathconf3ResOk> {
+        Box::pin(async {
+            Ok(Pathconf3ResOk {
+                obj_attributes: None,
+                linkmax: u32::MAX,
+                name_max: 255,
+                no_trunc: true,
+                chown_restricted: true,
+                case_insensitive: false,
+                case_preserving: true,
+            })
+        })
+    }
+
+    /// Makes the data written to `count` bytes at `offset`, or to the
+    /// end of the file if `count` is 0, stable
+    fn commit(
+        &self,
+        cred: Identity,
+        file: NfsFh3,
+        offset: Offset3,
+        count: Count3,
+    ) -> BackendFuture<'_, ()>;
+
+    /// Verifier returned by WRITE and COMMIT.  It must change whenever
+    /// unstable writes may have been lost, e.g. after a restart, so
+    /// clients know to send them again.
+    fn write_verifier(&self) -> Verifier3;
+}
+
+/// FSINFO results of the default `Nfs3Backend::fsinfo`, for backends
+/// that only change some of them
+pub fn default_fsinfo() -> Fsinfo3ResOk {
+    Fsinfo3ResOk {
+        obj_attributes: None,
+        rtmax: DEFAULT_MAX_TRANSFER,
+        rtperf: DEFAULT_MAX_TRANSFER,
+        rtmult: 4096,
+        wtmax: DEFAULT_MAX_TRANSFER,
+        wtpref: DEFAULT_MAX_TRANSFER,
+        wtmult: 4096,
+        dtperf: 64 * 1024,
+        maxfilesize: u64::MAX,
+        time_delta: NfsTime3 {
+            seconds: 0,
+            nano_seconds: 1,
+        },
+        properties: FSF3_LINK | FSF3_SYMLINK | FSF3_HOMOGENEOUS | FSF3_CANSETTIME,
+    }
+}
+
+/// Returns the ACCESS3_* bits granted to `cred` by the mode, owner and
+/// group of a file.  Root is granted everything except executing files
+/// without any execute bit.
+pub fn allowed_access(cred: &Identity, attributes: &FileAttributes) -> u32 {
+    let mode = attributes.mode;
+    let is_dir = attributes.file_type == FileType3::Dir;
+    let bits = if cred.uid == 0 {
+        0o6 | if is_dir || mode & 0o111 != 0 { 1 } else { 0 }
+    } else if cred.uid == attributes.uid {
+        mode >> 6
+    } else if cred.gid == attributes.gid || cred.gids.contains(&attributes.gid) {
+        mode >> 3
+    } else {
+        mode
+    } & 0o7;
+
+    let mut access = 0;
+    if bits & 0o4 != 0 {
+        access |= ACCESS3_READ;
+    }
+    if bits & 0o2 != 0 {
+        access |= ACCESS3_MODIFY | ACCESS3_EXTEND;
+        if is_dir {
+            access |= ACCESS3_DELETE;
+        }
+    }
+    if bits & 0o1 != 0 {
+        access |= if is_dir {
+            ACCESS3_LOOKUP
+        } else {
+            ACCESS3_EXECUTE
+        };
+    }
+
+    access
+}
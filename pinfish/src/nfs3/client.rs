@@ -1,33 +1,107 @@
 use crate::{
     mount,
     nfs3::{self, procs, Cookie3, DirOpArgs3, Filename3, NfsFh3, NfsPath3, Verifier3},
-    portmap,
+    portmap::{self, client::PortmapClient},
     result::{Result, NOT_CONNECTED},
-    rpc::{self, RpcClient},
+    rpc::{
+        self,
+        auth::CredentialProvider,
+        pool::{Balance, RpcPool},
+        record::Message,
+        RpcClient,
+    },
     xdr::{PackTo, Packer, UnpackFrom},
 };
 //use core::cell::Cell;
-use bytes::{Buf, Bytes, BytesMut};
-use std::borrow::BorrowMut;
-use tokio::net::TcpStream;
+use bytes::{Bytes, BytesMut};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{lookup_host, TcpStream, UdpSocket};
+
+/// Connects to `host` with Nagle disabled, from a reserved source port
+/// if `reserved` is set
+async fn connect_tcp(host: String, reserved: bool) -> std::io::Result<TcpStream> {
+    let connection = if reserved {
+        rpc::resvport::connect_tcp(host)
+            .await
+            .map_err(std::io::Error::other)?
+    } else {
+        TcpStream::connect(host).await?
+    };
+    connection.set_nodelay(true)?;
+
+    Ok(connection)
+}
+
+/// Room for the RPC and NFS headers around READ data
+const REPLY_OVERHEAD: u32 = 4096;
+
+/// Bytes of directory entries asked for by READDIR and READDIRPLUS,
+/// less if the packet limit set by `call_fsinfo` is smaller
+const DIR_COUNT: u32 = 65536;
+
+/// Returns the READDIR count fitting the packets accepted by `rpc`
+fn dir_count(rpc: &RpcClient) -> u32 {
+    DIR_COUNT.min(rpc.max_packet_size().saturating_sub(REPLY_OVERHEAD))
+}
 
 pub struct NfsClient {
     /// Server address
     server: String,
 
     /// port mapper RPC client
-    portmap: Option<RpcClient>,
+    portmap: Option<PortmapClient>,
 
     /// Mount RPC client
     mount: Option<RpcClient>,
 
-    /// NFSv3 RPC client
-    nfs: Option<RpcClient>,
+    /// NFSv3 RPC connections
+    nfs: Option<RpcPool>,
+
+    /// Number of NFS connections and how calls are spread over them
+    nconnect: NonZeroUsize,
+    balance: Balance,
 
+    /// Ports per program, 0 to look up the mount and NFS ports with
+    /// the port mapper
+    portmap_port: u16,
     mount_port: u16,
     nfs_port: u16,
 
+    /// Transport protocol per program
+    portmap_transport: Transport,
+    mount_transport: Transport,
+    nfs_transport: Transport,
+
+    /// Retransmission policy for UDP connections
+    retransmit: rpc::Retransmit,
+
+    /// Connect to the mount and NFS programs from a reserved port
+    reserved_port: bool,
+
+    /// Reconnect policy for TCP connections, not re-established if `None`
+    reconnect: Option<rpc::Reconnect>,
+
+    /// Default deadline for RPC calls
+    timeout: Option<Duration>,
+
+    /// Limits on outstanding calls per RPC client
+    flow_control: rpc::FlowControl,
+
+    /// RPC-over-TLS parameters for the NFS program, `None` for plain TCP
+    #[cfg(feature = "tls")]
+    tls: Option<rpc::tls::TlsConfig>,
+
     root_fh: std::sync::Mutex<NfsFh3>,
+
+    /// Credentials for portmap and mount calls
+    credentials: Arc<dyn CredentialProvider>,
+
+    /// Credentials for NFS calls, chosen from `credentials` and the
+    /// auth flavors accepted by the mounted export
+    nfs_credentials: std::sync::Mutex<Arc<dyn CredentialProvider>>,
 }
 
 #[derive(Clone, Copy)]
@@ -56,32 +130,238 @@ impl Program {
     }
 }
 
+/// Transport protocol used to reach a program
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Transport {
+    #[default]
+    Tcp,
+    Udp,
+}
+
+impl Transport {
+    /// Protocol number registered with the port mapper
+    pub const fn prot(&self) -> u32 {
+        match self {
+            Transport::Tcp => portmap::IPPROTO_TCP,
+            Transport::Udp => portmap::IPPROTO_UDP,
+        }
+    }
+}
+
 impl NfsClient {
     /// Consructs a new `NfsClient`
     pub fn new(server: &str) -> NfsClient {
+        let credentials: Arc<dyn CredentialProvider> =
+            Arc::new(rpc::auth::SysCredentials::default());
         NfsClient {
             server: server.into(),
             portmap: None,
             mount: None,
             nfs: None,
+            nconnect: NonZeroUsize::MIN,
+            balance: Default::default(),
+            portmap_port: portmap::PORT,
             mount_port: 0,
             nfs_port: 0,
+            portmap_transport: Transport::Tcp,
+            mount_transport: Transport::Tcp,
+            nfs_transport: Transport::Tcp,
+            retransmit: Default::default(),
+            reserved_port: false,
+            reconnect: None,
+            timeout: None,
+            flow_control: Default::default(),
+            #[cfg(feature = "tls")]
+            tls: None,
             root_fh: std::sync::Mutex::new(Default::default()),
+            nfs_credentials: std::sync::Mutex::new(credentials.clone()),
+            credentials,
+        }
+    }
+
+    /// Selects the transport protocol used to reach `program`, TCP by
+    /// default.  Takes effect on the next connect.
+    pub fn set_protocol(&mut self, program: Program, transport: Transport) {
+        match program {
+            Program::Portmap => self.portmap_transport = transport,
+            Program::Mount => self.mount_transport = transport,
+            Program::Nfs => self.nfs_transport = transport,
+        }
+    }
+
+    /// Connects to `program` on `port` instead of the standard port
+    /// mapper port or the port registered with the port mapper, e.g.
+    /// to reach a test server.  Takes effect on the next connect.
+    pub fn set_port(&mut self, program: Program, port: u16) {
+        match program {
+            Program::Portmap => self.portmap_port = port,
+            Program::Mount => self.mount_port = port,
+            Program::Nfs => self.nfs_port = port,
+        }
+    }
+
+    /// Opens `nconnect` connections for the NFS program and spreads
+    /// calls over them according to `balance`.  Takes effect on the
+    /// next connect.
+    pub fn set_nconnect(&mut self, nconnect: NonZeroUsize, balance: Balance) {
+        self.nconnect = nconnect;
+        self.balance = balance;
+    }
+
+    /// Sets the retransmission policy for programs reached over UDP
+    pub fn set_retransmit(&mut self, retransmit: rpc::Retransmit) {
+        self.retransmit = retransmit;
+    }
+
+    /// Connects to the mount and NFS programs from a reserved source
+    /// port (below 1024), as required by exports with the `secure`
+    /// option.  Needs root or CAP_NET_BIND_SERVICE.  Takes effect on
+    /// the next connect.
+    pub fn set_reserved_port(&mut self, reserved_port: bool) {
+        self.reserved_port = reserved_port;
+    }
+
+    /// Enables reconnecting with retransmission of pending calls when
+    /// a TCP connection drops.  Takes effect on the next connect.
+    pub fn set_reconnect(&mut self, reconnect: Option<rpc::Reconnect>) {
+        self.reconnect = reconnect;
+    }
+
+    /// Sets the default deadline for RPC calls, `None` waits
+    /// indefinitely.  Takes effect on the next connect.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Limits the calls waiting for a reply and the READ data they
+    /// expect, per RPC client.  Takes effect on the next connect.
+    pub fn set_flow_control(&mut self, flow_control: rpc::FlowControl) {
+        self.flow_control = flow_control;
+    }
+
+    /// Enables RPC-over-TLS for the NFS program.  Portmap and mount
+    /// stay in the clear.  Takes effect on the next connect.
+    #[cfg(feature = "tls")]
+    pub fn set_tls(&mut self, tls: Option<rpc::tls::TlsConfig>) {
+        self.tls = tls;
+    }
+
+    /// Sets the credentials sent with each call, AUTH_SYS as root by
+    /// default.  NFS calls fall back to AUTH_NONE if the export does
+    /// not accept the flavor of `credentials`.
+    pub fn set_credentials(&mut self, credentials: Arc<dyn CredentialProvider>) {
+        *self.nfs_credentials.lock().unwrap() = credentials.clone();
+        if let Some(portmap) = &mut self.portmap {
+            portmap.set_credentials(credentials.clone());
+        }
+        self.credentials = credentials;
+    }
+
+    fn protocol(&self, program: Program) -> Transport {
+        match program {
+            Program::Portmap => self.portmap_transport,
+            Program::Mount => self.mount_transport,
+            Program::Nfs => self.nfs_transport,
+        }
+    }
+
+    /// Connects a new RPC client to `port` on the server using the
+    /// protocol configured for `program`
+    async fn connect_rpc(&self, program: Program, port: u16) -> Result<RpcClient> {
+        let mut rpc = self.connect_transport(program, port).await?;
+        rpc.set_timeout(self.timeout);
+        rpc.set_flow_control(self.flow_control);
+
+        Ok(rpc)
+    }
+
+    async fn connect_transport(&self, program: Program, port: u16) -> Result<RpcClient> {
+        let host = std::format!("{}:{}", &self.server, port);
+        let reserved = self.reserved_port && !matches!(program, Program::Portmap);
+        #[cfg(feature = "tls")]
+        if let (Program::Nfs, Some(tls)) = (program, &self.tls) {
+            if self.nfs_transport == Transport::Tcp {
+                return self.connect_tls(program, host, reserved, tls.clone()).await;
+            }
+        }
+
+        if self.protocol(program) == Transport::Udp {
+            if reserved {
+                let socket = rpc::resvport::bind_udp(host).await?;
+                return Ok(RpcClient::new_udp(socket, self.retransmit));
+            }
+
+            let addr = match lookup_host(host).await?.next() {
+                Some(addr) => addr,
+                None => return Err(NOT_CONNECTED.into()),
+            };
+            let local: SocketAddr = if addr.is_ipv4() {
+                (Ipv4Addr::UNSPECIFIED, 0).into()
+            } else {
+                (Ipv6Addr::UNSPECIFIED, 0).into()
+            };
+            let socket = UdpSocket::bind(local).await?;
+            socket.connect(addr).await?;
+            Ok(RpcClient::new_udp(socket, self.retransmit))
+        } else if let Some(reconnect) = self.reconnect {
+            let connect = move || connect_tcp(host.clone(), reserved);
+            Ok(RpcClient::new_reconnecting(connect, reconnect).await?)
+        } else {
+            Ok(RpcClient::new(connect_tcp(host, reserved).await?))
+        }
+    }
+
+    /// Connects over TCP and upgrades the connection to TLS, again
+    /// after each reconnect when reconnecting is enabled
+    #[cfg(feature = "tls")]
+    async fn connect_tls(
+        &self,
+        program: Program,
+        host: String,
+        reserved: bool,
+        tls: rpc::tls::TlsConfig,
+    ) -> Result<RpcClient> {
+        let connect = move || {
+            let host = host.clone();
+            let tls = tls.clone();
+            async move {
+                let connection = connect_tcp(host, reserved).await?;
+                rpc::tls::start_tls(connection, program.prog(), program.vers(), &tls)
+                    .await
+                    .map_err(std::io::Error::other)
+            }
+        };
+
+        match self.reconnect {
+            Some(reconnect) => Ok(RpcClient::new_reconnecting(connect, reconnect).await?),
+            None => Ok(RpcClient::new(connect().await?)),
+        }
+    }
+
+    /// Uses `rpc` for `program` instead of connecting to the server,
+    /// e.g. an `RpcClient` over a Unix domain socket or an in-memory
+    /// pipe
+    pub fn set_rpc_client(&mut self, program: Program, rpc: RpcClient) {
+        match program {
+            Program::Portmap => self.portmap = Some(self.new_portmap_client(rpc)),
+            Program::Mount => self.mount = Some(rpc),
+            Program::Nfs => self.nfs = Some(rpc.into()),
         }
     }
 
     /// Connects the portmap client
     async fn connect_portmap(&mut self) -> Result<()> {
-        let host = std::format!("{}:{}", &self.server, portmap::PORT);
-        let connection = TcpStream::connect(host).await?;
-        self.portmap = Some(RpcClient::new(connection));
+        let rpc = self
+            .connect_rpc(Program::Portmap, self.portmap_port)
+            .await?;
+        self.portmap = Some(self.new_portmap_client(rpc));
 
         Ok(())
     }
 
     /// Connects the portmap client if not yet connected
     async fn connect_portmap_if_needed(&mut self) -> Result<()> {
-        if let None = &self.portmap {
+        if self.portmap.is_none() {
             Ok(self.connect_portmap().await?)
         } else {
             Ok(())
@@ -93,58 +373,39 @@ impl NfsClient {
             prog: prog.prog(),
             vers: prog.vers(),
             proc,
-            cred: rpc::OpaqueAuth::new_sys(1, Bytes::from_static(b"blah"), 0, 0, Vec::new()),
+            cred: match prog {
+                Program::Nfs => self.nfs_credentials.lock().unwrap().credential(),
+                _ => self.credentials.credential(),
+            },
             verf: rpc::OpaqueAuth::new_none(),
         }
     }
 
-    /// Constructs a new buffer with placeholder for the RPC frag marker
-    fn new_buf(&self) -> BytesMut {
-        let mut buf = BytesMut::new();
-        buf.pack_uint(0); // placeholder for frag
-
-        buf
-    }
-
+    /// Constructs a new buffer starting with the call header for `proc`
     fn new_buf_with_call_header(&self, xid: u32, prog: Program, proc: u32) -> BytesMut {
-        let mut buf = self.new_buf();
+        let mut buf = BytesMut::new();
         buf.pack_uint(xid);
         self.new_rpc_header(prog, proc).pack_to(&mut buf);
 
         buf
     }
 
-    /// Cosumes `buf` and updates frag size, returns frozen buffer
-    fn finalize(mut buf: BytesMut) -> Bytes {
-        let frag_size = (buf.remaining() - 4) as u32;
-        let frag_size = frag_size | 0x80000000;
-        {
-            let borrow: &mut [u8] = buf.borrow_mut();
-            (&mut borrow[0..4]).pack_uint(frag_size);
-        }
-
-        buf.freeze()
+    fn new_portmap_client(&self, rpc: RpcClient) -> PortmapClient {
+        let mut client = PortmapClient::new(rpc);
+        client.set_credentials(self.credentials.clone());
+        client
     }
 
     async fn call_portmap_get_port(&self, program: Program) -> Result<u32> {
-        let xid = RpcClient::next_xid();
-        let mut buf =
-            self.new_buf_with_call_header(xid, Program::Portmap, portmap::PMAPPROC_GETPORT);
-
-        if let Some(rpc) = &self.portmap {
-            let mapping = portmap::Mapping {
-                prog: program.prog(),
-                vers: program.vers(),
-                prot: portmap::IPPROTO_TCP,
-                port: 0,
-            };
-
-            mapping.pack_to(&mut buf);
-            let buf = Self::finalize(buf);
-
-            let mut response_buf = rpc.call(buf, xid).await?;
-            rpc.check_header(&mut response_buf)?;
-            Ok(u32::unpack_from(&mut response_buf)?)
+        if let Some(client) = &self.portmap {
+            let port = client
+                .getport(
+                    program.prog(),
+                    program.vers(),
+                    self.protocol(program).prot(),
+                )
+                .await?;
+            Ok(port as u32)
         } else {
             Err(NOT_CONNECTED.into())
         }
@@ -152,7 +413,7 @@ impl NfsClient {
 
     async fn portmap_get_port(&mut self, program: Program) -> Result<u32> {
         self.connect_portmap_if_needed().await?;
-        Ok(self.call_portmap_get_port(program).await?)
+        self.call_portmap_get_port(program).await
     }
 
     pub async fn connect_mount(&mut self) -> Result<()> {
@@ -161,9 +422,7 @@ impl NfsClient {
             self.mount_port = port as u16;
         }
 
-        let host = std::format!("{}:{}", &self.server, self.mount_port);
-        let connection = TcpStream::connect(host).await?;
-        self.mount = Some(RpcClient::new(connection));
+        self.mount = Some(self.connect_rpc(Program::Mount, self.mount_port).await?);
 
         Ok(())
     }
@@ -174,24 +433,24 @@ impl NfsClient {
             self.nfs_port = port as u16;
         }
 
-        let host = std::format!("{}:{}", &self.server, self.nfs_port);
-        let connection = TcpStream::connect(host).await?;
-        connection.set_nodelay(true)?;
-        self.nfs = Some(RpcClient::new(connection));
+        let mut clients = Vec::with_capacity(self.nconnect.get());
+        for _ in 0..self.nconnect.get() {
+            clients.push(self.connect_rpc(Program::Nfs, self.nfs_port).await?);
+        }
+        self.nfs = Some(RpcPool::new(clients, self.balance));
 
         Ok(())
     }
 
-    pub fn nfs_close_throttle(&self) {
-        if let Some(rpc) = &self.nfs {
-            rpc.close_throttle();
-        }
+    /// Returns the NFS connection for the next call
+    fn nfs(&self) -> Option<&RpcClient> {
+        self.nfs.as_ref().map(|pool| pool.pick())
     }
 
-    pub fn nfs_open_throttle(&self) {
-        if let Some(rpc) = &self.nfs {
-            rpc.open_throttle();
-        }
+    /// Returns how often and how long NFS calls waited for flow
+    /// control capacity, summed over all connections
+    pub fn nfs_throttle_stats(&self) -> Option<rpc::ThrottleStats> {
+        self.nfs.as_ref().map(|pool| pool.throttle_stats())
     }
 
     pub async fn call_mount(&self, path: &str) -> Result<mount::MountResult> {
@@ -200,13 +459,14 @@ impl NfsClient {
 
         if let Some(rpc) = &self.mount {
             buf.pack_string(path);
-            let buf = Self::finalize(buf);
 
             let mut response_buf = rpc.call(buf, xid).await?;
             rpc.check_header(&mut response_buf)?;
             let res_ok = mount::MountResult::unpack_from(&mut response_buf)??;
 
             self.root_fh.lock().unwrap().data = res_ok.handle.data.clone();
+            *self.nfs_credentials.lock().unwrap() =
+                rpc::auth::select(&self.credentials, &res_ok.auth_flavors)?;
 
             Ok(Ok(res_ok))
         } else {
@@ -214,17 +474,103 @@ impl NfsClient {
         }
     }
 
+    /// Calls the NULL procedure of the mount program
+    pub async fn call_mount_null(&self) -> Result<()> {
+        let xid = RpcClient::next_xid();
+        let buf = self.new_buf_with_call_header(xid, Program::Mount, mount::MOUNTPROC3_NULL);
+
+        if let Some(rpc) = &self.mount {
+            let mut response_buf = rpc.call(buf, xid).await?;
+            rpc.check_header(&mut response_buf)?;
+            Ok(())
+        } else {
+            Err(NOT_CONNECTED.into())
+        }
+    }
+
+    /// Lists the mounts recorded by the server, like `showmount -a`
+    pub async fn call_mount_dump(&self) -> Result<Vec<mount::MountBody>> {
+        let xid = RpcClient::next_xid();
+        let buf = self.new_buf_with_call_header(xid, Program::Mount, mount::MOUNTPROC3_DUMP);
+
+        if let Some(rpc) = &self.mount {
+            let mut response_buf = rpc.call(buf, xid).await?;
+            rpc.check_header(&mut response_buf)?;
+            Ok(mount::MountList::unpack_from(&mut response_buf)?.0)
+        } else {
+            Err(NOT_CONNECTED.into())
+        }
+    }
+
+    /// Removes the server's record of this client mounting `path`
+    pub async fn call_umnt(&self, path: &str) -> Result<()> {
+        let xid = RpcClient::next_xid();
+        let mut buf = self.new_buf_with_call_header(xid, Program::Mount, mount::MOUNTPROC3_UMNT);
+
+        if let Some(rpc) = &self.mount {
+            buf.pack_string(path);
+
+            let mut response_buf = rpc.call(buf, xid).await?;
+            rpc.check_header(&mut response_buf)?;
+            Ok(())
+        } else {
+            Err(NOT_CONNECTED.into())
+        }
+    }
+
+    /// Removes all of the server's mount records for this client
+    pub async fn call_umntall(&self) -> Result<()> {
+        let xid = RpcClient::next_xid();
+        let buf = self.new_buf_with_call_header(xid, Program::Mount, mount::MOUNTPROC3_UMNTALL);
+
+        if let Some(rpc) = &self.mount {
+            let mut response_buf = rpc.call(buf, xid).await?;
+            rpc.check_header(&mut response_buf)?;
+            Ok(())
+        } else {
+            Err(NOT_CONNECTED.into())
+        }
+    }
+
+    /// Lists the exported directories, like `showmount -e`
+    pub async fn call_export(&self) -> Result<Vec<mount::ExportNode>> {
+        let xid = RpcClient::next_xid();
+        let buf = self.new_buf_with_call_header(xid, Program::Mount, mount::MOUNTPROC3_EXPORT);
+
+        if let Some(rpc) = &self.mount {
+            let mut response_buf = rpc.call(buf, xid).await?;
+            rpc.check_header(&mut response_buf)?;
+            Ok(mount::Exports::unpack_from(&mut response_buf)?.0)
+        } else {
+            Err(NOT_CONNECTED.into())
+        }
+    }
+
+    /// Calls the NULL procedure of the NFS program, e.g. to check that
+    /// the server is up
+    pub async fn call_null(&self) -> Result<()> {
+        let xid = RpcClient::next_xid();
+        let buf = self.new_buf_with_call_header(xid, Program::Nfs, nfs3::NFSPROC3_NULL);
+
+        if let Some(rpc) = self.nfs() {
+            let mut response_buf = rpc.call(buf, xid).await?;
+            rpc.check_header(&mut response_buf)?;
+            Ok(())
+        } else {
+            Err(NOT_CONNECTED.into())
+        }
+    }
+
     pub async fn call_lookup(&self, dir: &NfsFh3, name: Filename3) -> Result<procs::LookupResult> {
         let xid = RpcClient::next_xid();
         let mut buf = self.new_buf_with_call_header(xid, Program::Nfs, nfs3::NFSPROC3_LOOKUP);
 
-        if let Some(rpc) = &self.nfs {
+        if let Some(rpc) = self.nfs() {
             let dir = dir.clone();
             let lookup = procs::Lookup3Args {
                 what: DirOpArgs3 { dir, name },
             };
             lookup.pack_to(&mut buf);
-            let buf = Self::finalize(buf);
 
             let mut response_buf = rpc.call(buf, xid).await?;
             rpc.check_header(&mut response_buf)?;
@@ -234,22 +580,24 @@ impl NfsClient {
         }
     }
 
-    pub async fn call_mkdir(&self, dir: &NfsFh3, name: Filename3) -> Result<procs::MkdirResult> {
+    /// Creates directory `name` with `attributes`, the server picks
+    /// the attributes left unset
+    pub async fn call_mkdir(
+        &self,
+        dir: &NfsFh3,
+        name: Filename3,
+        attributes: nfs3::SetAttributes,
+    ) -> Result<procs::MkdirResult> {
         let xid = RpcClient::next_xid();
         let mut buf = self.new_buf_with_call_header(xid, Program::Nfs, nfs3::NFSPROC3_MKDIR);
 
-        if let Some(rpc) = &self.nfs {
-            let attributes = nfs3::SetAttributes {
-                mode: Some(0o755),
-                ..Default::default()
-            };
+        if let Some(rpc) = self.nfs() {
             let dir = dir.clone();
             let mkdir = procs::Mkdir3Args {
                 mkdir_where: DirOpArgs3 { dir, name },
                 attributes,
             };
             mkdir.pack_to(&mut buf);
-            let buf = Self::finalize(buf);
 
             let mut response_buf = rpc.call(buf, xid).await?;
             rpc.check_header(&mut response_buf)?;
@@ -259,32 +607,25 @@ impl NfsClient {
         }
     }
 
+    /// Creates regular file `name`.  `how` is UNCHECKED or GUARDED
+    /// with the initial attributes, or EXCLUSIVE with a verifier, see
+    /// `call_create_exclusive`.
     pub async fn call_create(
         &self,
         dir: &NfsFh3,
         name: Filename3,
-        guarded: bool,
+        how: procs::CreateHow3,
     ) -> Result<procs::CreateResult> {
         let xid = RpcClient::next_xid();
         let mut buf = self.new_buf_with_call_header(xid, Program::Nfs, nfs3::NFSPROC3_CREATE);
 
-        if let Some(rpc) = &self.nfs {
-            let attributes = nfs3::SetAttributes {
-                mode: Some(0o644),
-                ..Default::default()
-            };
-            let how = if guarded {
-                procs::CreateHow3::Guarded(attributes)
-            } else {
-                procs::CreateHow3::Unchecked(attributes)
-            };
+        if let Some(rpc) = self.nfs() {
             let dir = dir.clone();
             let create = procs::Create3Args {
                 create_where: DirOpArgs3 { dir, name },
                 how,
             };
             create.pack_to(&mut buf);
-            let buf = Self::finalize(buf);
 
             let mut response_buf = rpc.call(buf, xid).await?;
             rpc.check_header(&mut response_buf)?;
@@ -294,6 +635,57 @@ impl NfsClient {
         }
     }
 
+    /// Creates regular file `name` with EXCLUSIVE semantics, so a
+    /// retransmitted CREATE with the same `verifier` succeeds while
+    /// another client's fails with NFS3ERR_EXIST.  The server may keep
+    /// the verifier in the file's times, so `attributes` are then set
+    /// with SETATTR as RFC 1813 requires, times left unchanged are set
+    /// to the server time.  Once the CREATE succeeded the handle of the
+    /// new file is returned along with the status of the SETATTR.
+    pub async fn call_create_exclusive(
+        &self,
+        dir: &NfsFh3,
+        name: Filename3,
+        verifier: Verifier3,
+        mut attributes: nfs3::SetAttributes,
+    ) -> Result<procs::ExclusiveCreateResult> {
+        let how = procs::CreateHow3::Exclusive(verifier);
+        let res_ok = match self.call_create(dir, name.clone(), how).await? {
+            Ok(res_ok) => res_ok,
+            Err(res_fail) => return Ok(Err(res_fail)),
+        };
+
+        let obj = match res_ok.obj {
+            Some(object) => object,
+            None => match self.call_lookup(dir, name).await? {
+                Ok(lookup) => lookup.object,
+                Err((status, res_fail)) => {
+                    let dir_wcc = nfs3::WccData {
+                        before: None,
+                        after: res_fail.dir_attributes,
+                    };
+                    return Ok(Err((status, procs::Create3ResFail { dir_wcc })));
+                }
+            },
+        };
+
+        for time in [&mut attributes.atime, &mut attributes.mtime] {
+            if let nfs3::TimeHow::DontChange = time {
+                *time = nfs3::TimeHow::SetToServerTime;
+            }
+        }
+        let (setattr_status, attributes) = match self.call_setattr(&obj, attributes, None).await? {
+            Ok(setattr) => (nfs3::NFS3_OK, setattr.obj_wcc.after),
+            Err((status, _)) => (status, res_ok.attributes),
+        };
+        Ok(Ok(procs::ExclusiveCreate3ResOk {
+            obj,
+            attributes,
+            wcc_data: res_ok.wcc_data,
+            setattr_status,
+        }))
+    }
+
     pub async fn call_rename(
         &self,
         from_dir: &NfsFh3,
@@ -304,7 +696,7 @@ impl NfsClient {
         let xid = RpcClient::next_xid();
         let mut buf = self.new_buf_with_call_header(xid, Program::Nfs, nfs3::NFSPROC3_RENAME);
 
-        if let Some(rpc) = &self.nfs {
+        if let Some(rpc) = self.nfs() {
             let from_dir = from_dir.clone();
             let to_dir = to_dir.clone();
             let rename = procs::Rename3Args {
@@ -318,7 +710,6 @@ impl NfsClient {
                 },
             };
             rename.pack_to(&mut buf);
-            let buf = Self::finalize(buf);
 
             let mut response_buf = rpc.call(buf, xid).await?;
             rpc.check_header(&mut response_buf)?;
@@ -328,27 +719,25 @@ impl NfsClient {
         }
     }
 
+    /// Creates symbolic link `name` to `data` with `attributes`, the
+    /// server picks the attributes left unset
     pub async fn call_symlink(
         &self,
         dir: &NfsFh3,
         name: Filename3,
         data: NfsPath3,
+        attributes: nfs3::SetAttributes,
     ) -> Result<procs::SymLinkResult> {
         let xid = RpcClient::next_xid();
         let mut buf = self.new_buf_with_call_header(xid, Program::Nfs, nfs3::NFSPROC3_SYMLINK);
 
-        if let Some(rpc) = &self.nfs {
-            let attributes = nfs3::SetAttributes {
-                mode: Some(0o755),
-                ..Default::default()
-            };
+        if let Some(rpc) = self.nfs() {
             let dir = dir.clone();
             let symlink = procs::SymLink3Args {
                 symlink_where: DirOpArgs3 { dir, name },
                 data: procs::SymLinkData3 { attributes, data },
             };
             symlink.pack_to(&mut buf);
-            let buf = Self::finalize(buf);
 
             let mut response_buf = rpc.call(buf, xid).await?;
             rpc.check_header(&mut response_buf)?;
@@ -358,15 +747,41 @@ impl NfsClient {
         }
     }
 
+    /// Creates a device, socket or FIFO, `what` carries its type,
+    /// attributes and for devices the major and minor numbers
+    pub async fn call_mknod(
+        &self,
+        dir: &NfsFh3,
+        name: Filename3,
+        what: procs::MknodData3,
+    ) -> Result<procs::MknodResult> {
+        let xid = RpcClient::next_xid();
+        let mut buf = self.new_buf_with_call_header(xid, Program::Nfs, nfs3::NFSPROC3_MKNOD);
+
+        if let Some(rpc) = self.nfs() {
+            let dir = dir.clone();
+            let mknod = procs::Mknod3Args {
+                mknod_where: DirOpArgs3 { dir, name },
+                what,
+            };
+            mknod.pack_to(&mut buf);
+
+            let mut response_buf = rpc.call(buf, xid).await?;
+            rpc.check_header(&mut response_buf)?;
+            Ok(procs::MknodResult::unpack_from(&mut response_buf)?)
+        } else {
+            Err(NOT_CONNECTED.into())
+        }
+    }
+
     pub async fn call_getattr(&self, object: &NfsFh3) -> Result<procs::GetAttrResult> {
         let xid = RpcClient::next_xid();
         let mut buf = self.new_buf_with_call_header(xid, Program::Nfs, nfs3::NFSPROC3_GETATTR);
 
-        if let Some(rpc) = &self.nfs {
+        if let Some(rpc) = self.nfs() {
             let object = object.clone();
             let getattr = procs::GetAttr3Args { object };
             getattr.pack_to(&mut buf);
-            let buf = Self::finalize(buf);
 
             let mut response_buf = rpc.call(buf, xid).await?;
             rpc.check_header(&mut response_buf)?;
@@ -376,15 +791,62 @@ impl NfsClient {
         }
     }
 
+    /// Changes the attributes of `object`.  With a `guard` the server
+    /// only applies them if the ctime of `object` still equals it and
+    /// fails with NFS3ERR_NOT_SYNC otherwise.
+    pub async fn call_setattr(
+        &self,
+        object: &NfsFh3,
+        new_attributes: nfs3::SetAttributes,
+        guard: Option<nfs3::NfsTime3>,
+    ) -> Result<procs::SetAttrResult> {
+        let xid = RpcClient::next_xid();
+        let mut buf = self.new_buf_with_call_header(xid, Program::Nfs, nfs3::NFSPROC3_SETATTR);
+
+        if let Some(rpc) = self.nfs() {
+            let object = object.clone();
+            let setattr = procs::SetAttr3Args {
+                object,
+                new_attributes,
+                guard,
+            };
+            setattr.pack_to(&mut buf);
+
+            let mut response_buf = rpc.call(buf, xid).await?;
+            rpc.check_header(&mut response_buf)?;
+            Ok(procs::SetAttrResult::unpack_from(&mut response_buf)?)
+        } else {
+            Err(NOT_CONNECTED.into())
+        }
+    }
+
+    /// Asks which of the ACCESS3_* bits in `access` the server grants
+    /// on `object`
+    pub async fn call_access(&self, object: &NfsFh3, access: u32) -> Result<procs::AccessResult> {
+        let xid = RpcClient::next_xid();
+        let mut buf = self.new_buf_with_call_header(xid, Program::Nfs, nfs3::NFSPROC3_ACCESS);
+
+        if let Some(rpc) = self.nfs() {
+            let object = object.clone();
+            let access = procs::Access3Args { object, access };
+            access.pack_to(&mut buf);
+
+            let mut response_buf = rpc.call(buf, xid).await?;
+            rpc.check_header(&mut response_buf)?;
+            Ok(procs::AccessResult::unpack_from(&mut response_buf)?)
+        } else {
+            Err(NOT_CONNECTED.into())
+        }
+    }
+
     pub async fn call_fsstat(&self, root: &NfsFh3) -> Result<procs::FsstatResult> {
         let xid = RpcClient::next_xid();
         let mut buf = self.new_buf_with_call_header(xid, Program::Nfs, nfs3::NFSPROC3_FSSTAT);
 
-        if let Some(rpc) = &self.nfs {
+        if let Some(rpc) = self.nfs() {
             let root = root.clone();
             let fsstat = procs::Fsstat3Args { root };
             fsstat.pack_to(&mut buf);
-            let buf = Self::finalize(buf);
 
             let mut response_buf = rpc.call(buf, xid).await?;
             rpc.check_header(&mut response_buf)?;
@@ -394,19 +856,28 @@ impl NfsClient {
         }
     }
 
+    /// Calls FSINFO on `root`.  On success the largest reply accepted
+    /// from the server is sized after the returned `rtmax` and `wtmax`.
     pub async fn call_fsinfo(&self, root: &NfsFh3) -> Result<procs::FsinfoResult> {
         let xid = RpcClient::next_xid();
         let mut buf = self.new_buf_with_call_header(xid, Program::Nfs, nfs3::NFSPROC3_FSINFO);
 
-        if let Some(rpc) = &self.nfs {
+        if let Some(rpc) = self.nfs() {
             let root = root.clone();
             let fsinfo = procs::Fsinfo3Args { root };
             fsinfo.pack_to(&mut buf);
-            let buf = Self::finalize(buf);
 
             let mut response_buf = rpc.call(buf, xid).await?;
             rpc.check_header(&mut response_buf)?;
-            Ok(procs::FsinfoResult::unpack_from(&mut response_buf)?)
+            let result = procs::FsinfoResult::unpack_from(&mut response_buf)?;
+            if let Ok(fsinfo) = &result {
+                // fit the largest READ or WRITE data plus headers
+                let max_data = std::cmp::max(fsinfo.rtmax, fsinfo.wtmax);
+                if let Some(pool) = &self.nfs {
+                    pool.set_max_packet_size(max_data.saturating_add(REPLY_OVERHEAD));
+                }
+            }
+            Ok(result)
         } else {
             Err(NOT_CONNECTED.into())
         }
@@ -416,11 +887,10 @@ impl NfsClient {
         let xid = RpcClient::next_xid();
         let mut buf = self.new_buf_with_call_header(xid, Program::Nfs, nfs3::NFSPROC3_PATHCONF);
 
-        if let Some(rpc) = &self.nfs {
+        if let Some(rpc) = self.nfs() {
             let root = root.clone();
             let pathconf = procs::Pathconf3Args { root };
             pathconf.pack_to(&mut buf);
-            let buf = Self::finalize(buf);
 
             let mut response_buf = rpc.call(buf, xid).await?;
             rpc.check_header(&mut response_buf)?;
@@ -434,11 +904,10 @@ impl NfsClient {
         let xid = RpcClient::next_xid();
         let mut buf = self.new_buf_with_call_header(xid, Program::Nfs, nfs3::NFSPROC3_READLINK);
 
-        if let Some(rpc) = &self.nfs {
+        if let Some(rpc) = self.nfs() {
             let symlink = symlink.clone();
             let readlink = procs::ReadLink3Args { symlink };
             readlink.pack_to(&mut buf);
-            let buf = Self::finalize(buf);
 
             let mut response_buf = rpc.call(buf, xid).await?;
             rpc.check_header(&mut response_buf)?;
@@ -457,7 +926,7 @@ impl NfsClient {
         let xid = RpcClient::next_xid();
         let mut buf = self.new_buf_with_call_header(xid, Program::Nfs, nfs3::NFSPROC3_READ);
 
-        if let Some(rpc) = &self.nfs {
+        if let Some(rpc) = self.nfs() {
             let file = file.clone();
             let getattr = procs::Read3Args {
                 file,
@@ -465,9 +934,10 @@ impl NfsClient {
                 count,
             };
             getattr.pack_to(&mut buf);
-            let buf = Self::finalize(buf);
 
-            let mut response_buf = rpc.call(buf, xid).await?;
+            let mut response_buf = rpc
+                .call_sized(buf, xid, count.saturating_add(REPLY_OVERHEAD))
+                .await?;
             rpc.check_header(&mut response_buf)?;
             Ok(procs::ReadResult::unpack_from(&mut response_buf)?)
         } else {
@@ -475,30 +945,30 @@ impl NfsClient {
         }
     }
 
+    /// Writes `data` at `offset`.  UNSTABLE data is only durable after
+    /// a COMMIT returning the verifier of the reply, DATA_SYNC and
+    /// FILE_SYNC are durable when the reply arrives.
     pub async fn call_write(
         &self,
         file: &NfsFh3,
         offset: u64,
         count: u32,
         data: Bytes,
+        stable: procs::StableHow,
     ) -> Result<procs::WriteResult> {
         let xid = RpcClient::next_xid();
         let mut buf = self.new_buf_with_call_header(xid, Program::Nfs, nfs3::NFSPROC3_WRITE);
 
-        if let Some(rpc) = &self.nfs {
-            let stable = procs::StableHow::DataSync;
-            let file = file.clone();
-            let write = procs::Write3Args {
-                file,
-                offset,
-                count,
-                stable,
-                data,
-            };
-            write.pack_to(&mut buf);
-            let buf = Self::finalize(buf);
+        if let Some(rpc) = self.nfs() {
+            // packed as Write3Args, with the data appended without copying
+            file.pack_to(&mut buf);
+            offset.pack_to(&mut buf);
+            count.pack_to(&mut buf);
+            stable.pack_to(&mut buf);
+            let mut message = Message::from(buf);
+            message.push_opaque(data);
 
-            let mut response_buf = rpc.call(buf, xid).await?;
+            let mut response_buf = rpc.call(message, xid).await?;
             rpc.check_header(&mut response_buf)?;
             Ok(procs::WriteResult::unpack_from(&mut response_buf)?)
         } else {
@@ -515,7 +985,7 @@ impl NfsClient {
         let xid = RpcClient::next_xid();
         let mut buf = self.new_buf_with_call_header(xid, Program::Nfs, nfs3::NFSPROC3_COMMIT);
 
-        if let Some(rpc) = &self.nfs {
+        if let Some(rpc) = self.nfs() {
             let file = file.clone();
             let commit = procs::Commit3Args {
                 file,
@@ -523,7 +993,6 @@ impl NfsClient {
                 count,
             };
             commit.pack_to(&mut buf);
-            let buf = Self::finalize(buf);
 
             let mut response_buf = rpc.call(buf, xid).await?;
             rpc.check_header(&mut response_buf)?;
@@ -537,13 +1006,12 @@ impl NfsClient {
         let xid = RpcClient::next_xid();
         let mut buf = self.new_buf_with_call_header(xid, Program::Nfs, nfs3::NFSPROC3_REMOVE);
 
-        if let Some(rpc) = &self.nfs {
+        if let Some(rpc) = self.nfs() {
             let dir = dir.clone();
             let remove = procs::Remove3Args {
                 object: DirOpArgs3 { dir, name },
             };
             remove.pack_to(&mut buf);
-            let buf = Self::finalize(buf);
 
             let mut response_buf = rpc.call(buf, xid).await?;
             rpc.check_header(&mut response_buf)?;
@@ -553,6 +1021,25 @@ impl NfsClient {
         }
     }
 
+    pub async fn call_rmdir(&self, dir: &NfsFh3, name: Filename3) -> Result<procs::RmdirResult> {
+        let xid = RpcClient::next_xid();
+        let mut buf = self.new_buf_with_call_header(xid, Program::Nfs, nfs3::NFSPROC3_RMDIR);
+
+        if let Some(rpc) = self.nfs() {
+            let dir = dir.clone();
+            let rmdir = procs::Rmdir3Args {
+                object: DirOpArgs3 { dir, name },
+            };
+            rmdir.pack_to(&mut buf);
+
+            let mut response_buf = rpc.call(buf, xid).await?;
+            rpc.check_header(&mut response_buf)?;
+            Ok(procs::RmdirResult::unpack_from(&mut response_buf)?)
+        } else {
+            Err(NOT_CONNECTED.into())
+        }
+    }
+
     pub async fn call_link(
         &self,
         file: &NfsFh3,
@@ -562,7 +1049,7 @@ impl NfsClient {
         let xid = RpcClient::next_xid();
         let mut buf = self.new_buf_with_call_header(xid, Program::Nfs, nfs3::NFSPROC3_LINK);
 
-        if let Some(rpc) = &self.nfs {
+        if let Some(rpc) = self.nfs() {
             let file = file.clone();
             let link_dir = link_dir.clone();
             let link = procs::Link3Args {
@@ -573,7 +1060,6 @@ impl NfsClient {
                 },
             };
             link.pack_to(&mut buf);
-            let buf = Self::finalize(buf);
 
             let mut response_buf = rpc.call(buf, xid).await?;
             rpc.check_header(&mut response_buf)?;
@@ -592,19 +1078,20 @@ impl NfsClient {
         let xid = RpcClient::next_xid();
         let mut buf = self.new_buf_with_call_header(xid, Program::Nfs, nfs3::NFSPROC3_READDIR);
 
-        if let Some(rpc) = &self.nfs {
+        if let Some(rpc) = self.nfs() {
             let dir = dir.clone();
             let readdir = procs::Readdir3Args {
                 dir,
                 cookie,
                 verifier,
-                count: 65536,
+                count: dir_count(rpc),
             };
 
             readdir.pack_to(&mut buf);
-            let buf = Self::finalize(buf);
 
-            let mut response_buf = rpc.call(buf, xid).await?;
+            let mut response_buf = rpc
+                .call_sized(buf, xid, readdir.count.saturating_add(REPLY_OVERHEAD))
+                .await?;
             rpc.check_header(&mut response_buf)?;
             Ok(procs::ReaddirResult::unpack_from(&mut response_buf)?)
         } else {
@@ -621,20 +1108,25 @@ impl NfsClient {
         let xid = RpcClient::next_xid();
         let mut buf = self.new_buf_with_call_header(xid, Program::Nfs, nfs3::NFSPROC3_READDIRPLUS);
 
-        if let Some(rpc) = &self.nfs {
+        if let Some(rpc) = self.nfs() {
             let dir = dir.clone();
             let readdirplus = procs::ReaddirPlus3Args {
                 dir,
                 cookie,
                 verifier,
                 dircount: 8192,
-                maxcount: 65536,
+                maxcount: dir_count(rpc),
             };
 
             readdirplus.pack_to(&mut buf);
-            let buf = Self::finalize(buf);
 
-            let mut response_buf = rpc.call(buf, xid).await?;
+            let mut response_buf = rpc
+                .call_sized(
+                    buf,
+                    xid,
+                    readdirplus.maxcount.saturating_add(REPLY_OVERHEAD),
+                )
+                .await?;
             rpc.check_header(&mut response_buf)?;
             Ok(procs::ReaddirPlusResult::unpack_from(&mut response_buf)?)
         } else {
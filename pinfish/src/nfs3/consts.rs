@@ -70,6 +70,9 @@ pub const ACCESS3_EXTEND: u32 = 0x0008;
 pub const ACCESS3_DELETE: u32 = 0x0010;
 pub const ACCESS3_EXECUTE: u32 = 0x0020;
 
+/// Largest file handle in bytes
+pub const NFS3_FHSIZE: usize = 64;
+
 /// RPC program number for NFS
 pub const PROG_NFS: u32 = 100003;
 
@@ -1,8 +1,11 @@
 //! Definitions for encoding/decoding NFSv3 calls and replies.
+pub mod backend;
 pub mod client;
 mod consts;
 pub mod procs;
+pub mod server;
 mod types;
+pub mod writeback;
 
 pub use consts::*;
 pub use types::*;
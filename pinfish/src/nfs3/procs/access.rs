@@ -4,7 +4,7 @@ use crate::{
 };
 use pinfish_macros::{PackTo, UnpackFrom};
 
-#[derive(PackTo, Debug)]
+#[derive(PackTo, UnpackFrom, Debug)]
 pub struct Access3Args {
     pub object: NfsFh3,
     pub access: u32,
@@ -4,7 +4,7 @@ use crate::{
 };
 use pinfish_macros::{PackTo, UnpackFrom};
 
-#[derive(PackTo, Debug)]
+#[derive(PackTo, UnpackFrom, Debug)]
 pub struct Commit3Args {
     pub file: NfsFh3,
     pub offset: Offset3,
@@ -19,7 +19,7 @@ pub struct Commit3ResOk {
 
 #[derive(PackTo, UnpackFrom, Debug)]
 pub struct Commit3ResFail {
-    file_wcc: WccData,
+    pub file_wcc: WccData,
 }
 
 pub type CommitResult = Result<Commit3ResOk, (u32, Commit3ResFail)>;
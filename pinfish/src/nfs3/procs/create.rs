@@ -1,10 +1,10 @@
 use crate::{
-    nfs3::{DirOpArgs3, PostOpAttributes, PostOpFh3, SetAttributes, Verifier3, WccData},
+    nfs3::{DirOpArgs3, NfsFh3, PostOpAttributes, PostOpFh3, SetAttributes, Verifier3, WccData},
     xdr,
 };
 use pinfish_macros::{PackTo, UnpackFrom};
 
-#[derive(PackTo, Debug)]
+#[derive(PackTo, UnpackFrom, Debug)]
 pub enum CreateHow3 {
     /// Create the file without checking for existence of a duplicate
     /// file in the same directory
@@ -16,7 +16,7 @@ pub enum CreateHow3 {
     Exclusive(Verifier3),
 }
 
-#[derive(PackTo, Debug)]
+#[derive(PackTo, UnpackFrom, Debug)]
 pub struct Create3Args {
     pub create_where: DirOpArgs3,
     pub how: CreateHow3,
@@ -35,3 +35,17 @@ pub struct Create3ResFail {
 }
 
 pub type CreateResult = Result<Create3ResOk, (u32, Create3ResFail)>;
+
+/// A file created by `NfsClient::call_create_exclusive`, which sets its
+/// attributes with SETATTR after the CREATE
+#[derive(Debug)]
+pub struct ExclusiveCreate3ResOk {
+    pub obj: NfsFh3,
+    /// After the SETATTR, or after the CREATE if the SETATTR failed
+    pub attributes: PostOpAttributes,
+    pub wcc_data: WccData,
+    /// Status of the SETATTR, the file exists either way
+    pub setattr_status: u32,
+}
+
+pub type ExclusiveCreateResult = Result<ExclusiveCreate3ResOk, (u32, Create3ResFail)>;
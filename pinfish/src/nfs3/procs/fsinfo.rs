@@ -1,10 +1,10 @@
 use crate::{
-    nfs3::{NfsFh3, PostOpAttributes, NfsTime3, Size3},
+    nfs3::{NfsFh3, NfsTime3, PostOpAttributes, Size3},
     xdr::{self},
 };
 use pinfish_macros::{PackTo, UnpackFrom};
 
-#[derive(PackTo, Debug)]
+#[derive(PackTo, UnpackFrom, Debug)]
 pub struct Fsinfo3Args {
     pub root: NfsFh3,
 }
@@ -4,7 +4,7 @@ use crate::{
 };
 use pinfish_macros::{PackTo, UnpackFrom};
 
-#[derive(PackTo, Debug)]
+#[derive(PackTo, UnpackFrom, Debug)]
 pub struct Fsstat3Args {
     pub root: NfsFh3,
 }
@@ -5,7 +5,7 @@ use crate::{
 };
 use pinfish_macros::{PackTo, UnpackFrom};
 
-#[derive(PackTo, Debug)]
+#[derive(PackTo, UnpackFrom, Debug)]
 pub struct GetAttr3Args {
     pub object: NfsFh3,
 }
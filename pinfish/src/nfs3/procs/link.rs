@@ -4,7 +4,7 @@ use crate::{
 };
 use pinfish_macros::{PackTo, UnpackFrom};
 
-#[derive(PackTo, Debug)]
+#[derive(PackTo, UnpackFrom, Debug)]
 pub struct Link3Args {
     pub file: NfsFh3,
     pub link: DirOpArgs3,
@@ -1,16 +1,16 @@
 use crate::{
-    nfs3::{DirOpArgs3, PostOpAttributes, PostOpFh3, SetAttributes, WccData, SpecData3},
+    nfs3::{DirOpArgs3, PostOpAttributes, PostOpFh3, SetAttributes, SpecData3, WccData},
     xdr,
 };
 use pinfish_macros::{PackTo, UnpackFrom};
 
-#[derive(PackTo, Debug)]
+#[derive(PackTo, UnpackFrom, Debug)]
 pub struct DeviceData3 {
     pub attributes: SetAttributes,
     pub spec: SpecData3,
 }
 
-#[derive(PackTo, Debug)]
+#[derive(PackTo, UnpackFrom, Debug)]
 pub enum MknodData3 {
     #[xdr(4)]
     Chr(DeviceData3),
@@ -22,7 +22,7 @@ pub enum MknodData3 {
     Fifo(SetAttributes),
 }
 
-#[derive(PackTo, Debug)]
+#[derive(PackTo, UnpackFrom, Debug)]
 pub struct Mknod3Args {
     pub mknod_where: DirOpArgs3,
     pub what: MknodData3,
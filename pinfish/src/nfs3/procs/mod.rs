@@ -1,6 +1,6 @@
 use crate::{
     result::{ErrorCode, Result},
-    xdr::{UnpackFrom, Unpacker},
+    xdr::{PackTo, Packer, UnpackFrom, Unpacker},
 };
 
 pub_use!(lookup, create, getattr, setattr, access, readlink, read, write);
@@ -33,3 +33,30 @@ impl<T: core::fmt::Debug + UnpackFrom<B>, B: Unpacker> UnpackFrom<B>
         }
     }
 }
+
+impl<T: PackTo<B>, E: PackTo<B>, B: Packer> PackTo<B> for core::result::Result<T, (u32, E)> {
+    fn pack_to(&self, buf: &mut B) {
+        match self {
+            Ok(res) => {
+                buf.pack_uint(0);
+                res.pack_to(buf);
+            }
+            Err((status, res)) => {
+                buf.pack_uint(*status);
+                res.pack_to(buf);
+            }
+        }
+    }
+}
+
+impl<T: PackTo<B>, B: Packer> PackTo<B> for core::result::Result<T, ErrorCode> {
+    fn pack_to(&self, buf: &mut B) {
+        match self {
+            Ok(res) => {
+                buf.pack_uint(0);
+                res.pack_to(buf);
+            }
+            Err(status) => buf.pack_uint(status.get()),
+        }
+    }
+}
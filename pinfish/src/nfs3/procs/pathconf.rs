@@ -1,10 +1,10 @@
 use crate::{
-    nfs3::{NfsFh3, PostOpAttributes, },
+    nfs3::{NfsFh3, PostOpAttributes},
     xdr::{self},
 };
 use pinfish_macros::{PackTo, UnpackFrom};
 
-#[derive(PackTo, Debug)]
+#[derive(PackTo, UnpackFrom, Debug)]
 pub struct Pathconf3Args {
     pub root: NfsFh3,
 }
@@ -1,10 +1,10 @@
 use crate::{
-    nfs3::{Count3, NfsFh3, Cookie3, PostOpAttributes, Verifier3, FileId3, Filename3},
+    nfs3::{Cookie3, Count3, FileId3, Filename3, NfsFh3, PostOpAttributes, Verifier3},
     xdr::{self},
 };
 use pinfish_macros::{PackTo, UnpackFrom};
 
-#[derive(PackTo, Debug)]
+#[derive(PackTo, UnpackFrom, Debug)]
 pub struct Readdir3Args {
     pub dir: NfsFh3,
     pub cookie: Cookie3,
@@ -26,7 +26,6 @@ pub struct DirList3 {
     pub eof: bool,
 }
 
-
 #[derive(PackTo, UnpackFrom, Debug)]
 pub struct Readdir3ResOk {
     pub dir_attributes: PostOpAttributes,
@@ -51,7 +50,7 @@ impl<'a> Iterator for Entry3Iter<'a> {
 
     fn next(&mut self) -> Option<Self::Item> {
         if let Some(entry) = self.next {
-            self.next = entry.next_entry.as_ref().map(|e| &**e);
+            self.next = entry.next_entry.as_deref();
             Some(entry)
         } else {
             None
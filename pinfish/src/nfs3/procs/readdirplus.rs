@@ -1,10 +1,10 @@
 use crate::{
-    nfs3::{Count3, NfsFh3, Cookie3, PostOpAttributes, Verifier3, FileId3, Filename3, PostOpFh3},
+    nfs3::{Cookie3, Count3, FileId3, Filename3, NfsFh3, PostOpAttributes, PostOpFh3, Verifier3},
     xdr::{self},
 };
 use pinfish_macros::{PackTo, UnpackFrom};
 
-#[derive(PackTo, Debug)]
+#[derive(PackTo, UnpackFrom, Debug)]
 pub struct ReaddirPlus3Args {
     pub dir: NfsFh3,
     pub cookie: Cookie3,
@@ -20,8 +20,8 @@ pub struct EntryPlus3 {
     pub fileid: FileId3,
     pub name: Filename3,
     pub cookie: Cookie3,
-    name_attibutes: PostOpAttributes,
-    name_handle: PostOpFh3,
+    pub name_attributes: PostOpAttributes,
+    pub name_handle: PostOpFh3,
     pub next_entry: Option<Box<EntryPlus3>>,
 }
 
@@ -31,7 +31,6 @@ pub struct DirListPlus3 {
     pub eof: bool,
 }
 
-
 #[derive(PackTo, UnpackFrom, Debug)]
 pub struct ReaddirPlus3ResOk {
     pub dir_attributes: PostOpAttributes,
@@ -56,7 +55,7 @@ impl<'a> Iterator for EntryPlus3Iter<'a> {
 
     fn next(&mut self) -> Option<Self::Item> {
         if let Some(entry) = self.next {
-            self.next = entry.next_entry.as_ref().map(|e| &**e);
+            self.next = entry.next_entry.as_deref();
             Some(entry)
         } else {
             None
@@ -4,8 +4,7 @@ use crate::{
 };
 use pinfish_macros::{PackTo, UnpackFrom};
 
-
-#[derive(PackTo, Debug)]
+#[derive(PackTo, UnpackFrom, Debug)]
 pub struct Rename3Args {
     pub from: DirOpArgs3,
     pub to: DirOpArgs3,
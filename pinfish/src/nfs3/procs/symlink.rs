@@ -1,16 +1,16 @@
 use crate::{
-    nfs3::{DirOpArgs3, PostOpAttributes, PostOpFh3, SetAttributes, WccData, NfsPath3},
+    nfs3::{DirOpArgs3, NfsPath3, PostOpAttributes, PostOpFh3, SetAttributes, WccData},
     xdr,
 };
 use pinfish_macros::{PackTo, UnpackFrom};
 
-#[derive(PackTo, Debug)]
+#[derive(PackTo, UnpackFrom, Debug)]
 pub struct SymLinkData3 {
     pub attributes: SetAttributes,
     pub data: NfsPath3,
 }
 
-#[derive(PackTo, Debug)]
+#[derive(PackTo, UnpackFrom, Debug)]
 pub struct SymLink3Args {
     pub symlink_where: DirOpArgs3,
     pub data: SymLinkData3,
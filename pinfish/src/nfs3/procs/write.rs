@@ -4,14 +4,14 @@ use crate::{
 };
 use pinfish_macros::{PackTo, UnpackFrom};
 
-#[derive(PackTo, Debug, UnpackFrom)]
+#[derive(PackTo, Debug, UnpackFrom, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum StableHow {
     Unstable,
     DataSync,
     FileSync,
 }
 
-#[derive(PackTo, Debug)]
+#[derive(PackTo, UnpackFrom, Debug)]
 pub struct Write3Args {
     pub file: NfsFh3,
     pub offset: Offset3,
@@ -30,7 +30,7 @@ pub struct Write3ResOk {
 
 #[derive(PackTo, UnpackFrom, Debug)]
 pub struct Write3ResFail {
-    file_wcc: WccData,
+    pub file_wcc: WccData,
 }
 
 pub type WriteResult = Result<Write3ResOk, (u32, Write3ResFail)>;
@@ -0,0 +1,1238 @@
+//! NFSv3 server.
+//!
+//! `Nfs3Server` decodes NFSv3 calls, runs them against an `Nfs3Backend`
+//! and encodes the replies.  The server reads the attributes of the
+//! objects involved before and after each operation to fill in the
+//! weak cache consistency data and post-operation attributes, so
+//! backends only implement the operations themselves.
+use crate::{
+    nfs3::{
+        backend::{DirEntry, DirPage, Nfs3Backend, DEFAULT_MAX_TRANSFER},
+        procs::*,
+        DirOpArgs3, FileAttributes, NfsFh3, PostOpAttributes, PreOpAttributes, WccAttributes,
+        WccData, NFS3ERR_BADHANDLE, NFS3ERR_EXIST, NFS3ERR_INVAL, NFS3ERR_IO, NFS3ERR_NAMETOOLONG,
+        NFS3ERR_NOT_SYNC, NFS3ERR_SERVERFAULT, NFS3ERR_TOOSMALL, NFS3_FHSIZE, NFSPROC3_ACCESS,
+        NFSPROC3_COMMIT, NFSPROC3_CREATE, NFSPROC3_FSINFO, NFSPROC3_FSSTAT, NFSPROC3_GETATTR,
+        NFSPROC3_LINK, NFSPROC3_LOOKUP, NFSPROC3_MKDIR, NFSPROC3_MKNOD, NFSPROC3_NULL,
+        NFSPROC3_PATHCONF, NFSPROC3_READ, NFSPROC3_READDIR, NFSPROC3_READDIRPLUS,
+        NFSPROC3_READLINK, NFSPROC3_REMOVE, NFSPROC3_RENAME, NFSPROC3_RMDIR, NFSPROC3_SETATTR,
+        NFSPROC3_SYMLINK, NFSPROC3_WRITE, PROG_NFS,
+    },
+    result::{ErrorCode, Result, INTERNAL_ERROR, RPC_PROC_UNAVAIL, UNCATEGORIZED_IO_ERROR},
+    rpc::{
+        auth::Identity,
+        server::{Call, DispatchFuture, Dispatcher, RpcServer},
+    },
+    xdr::{PackTo, UnpackFrom},
+};
+use bytes::BytesMut;
+use std::sync::Arc;
+
+/// NFS protocol version served
+pub const VERSION: u32 = 3;
+
+/// Longest file name accepted, also by NFSv4
+pub(crate) const NAME_MAX: usize = 255;
+
+/// uid and gid of callers without AUTH_SYS credentials
+pub(crate) const NOBODY: u32 = 65534;
+
+/// XDR size of `fattr3`
+const ATTRIBUTES_SIZE: usize = 84;
+
+/// Maps a backend error to an NFSv3 status, NFSv4 uses the same values
+pub(crate) fn status(err: ErrorCode) -> u32 {
+    match err.get() {
+        n if n < INTERNAL_ERROR => n,
+        UNCATEGORIZED_IO_ERROR => NFS3ERR_IO,
+        _ => NFS3ERR_SERVERFAULT,
+    }
+}
+
+fn check_fh(fh: &NfsFh3) -> Result<()> {
+    if fh.data.is_empty() || fh.data.len() > NFS3_FHSIZE {
+        Err(NFS3ERR_BADHANDLE.into())
+    } else {
+        Ok(())
+    }
+}
+
+/// Checks the length of a component name and that it is a single
+/// component, NFSv4 uses the same status values
+pub(crate) fn check_name(name: &str) -> Result<()> {
+    if name.len() > NAME_MAX {
+        Err(NFS3ERR_NAMETOOLONG.into())
+    } else if name.is_empty() || name.contains(['/', '\0']) {
+        Err(NFS3ERR_INVAL.into())
+    } else {
+        Ok(())
+    }
+}
+
+/// Checks the name of an object to be created
+fn check_new_name(name: &str) -> Result<()> {
+    check_name(name)?;
+    if name == "." || name == ".." {
+        Err(NFS3ERR_EXIST.into())
+    } else {
+        Ok(())
+    }
+}
+
+/// XDR size of a string or opaque of `len` bytes
+fn opaque_size(len: usize) -> usize {
+    4 + len.div_ceil(4) * 4
+}
+
+/// XDR size of the `entry3` part of an entry: value follows, fileid,
+/// name and cookie
+fn entry_size(entry: &DirEntry) -> usize {
+    4 + 8 + opaque_size(entry.name.len()) + 8
+}
+
+/// XDR size of post-operation attributes
+fn post_op_size(attributes: &PostOpAttributes) -> usize {
+    4 + attributes.as_ref().map_or(0, |_| ATTRIBUTES_SIZE)
+}
+
+fn wcc_attributes(attributes: &FileAttributes) -> WccAttributes {
+    WccAttributes {
+        size: attributes.size,
+        mtime: attributes.mtime,
+        ctime: attributes.ctime,
+    }
+}
+
+/// Largest READ of a backend, the rtmax of its FSINFO fetched by the
+/// first READ, also used by NFSv4
+#[derive(Default)]
+pub(crate) struct ReadLimit(tokio::sync::OnceCell<u32>);
+
+impl ReadLimit {
+    /// Cuts `count` to the rtmax of `backend`.  Short reads are
+    /// allowed, reading at most what the backend advertises keeps the
+    /// reply within the packet size limit.
+    pub(crate) async fn clamp(&self, backend: &dyn Nfs3Backend, file: &NfsFh3, count: u32) -> u32 {
+        let fsinfo = || async {
+            let fsinfo = backend.fsinfo(file.clone()).await?;
+            Ok::<_, ErrorCode>(fsinfo.rtmax)
+        };
+        match self.0.get_or_try_init(fsinfo).await {
+            Ok(rtmax) => count.min(*rtmax),
+            Err(_) => count.min(DEFAULT_MAX_TRANSFER),
+        }
+    }
+}
+
+/// Serves NFSv3 from a backend
+pub struct Nfs3Server {
+    backend: Arc<dyn Nfs3Backend>,
+    read_limit: ReadLimit,
+}
+
+impl Nfs3Server {
+    pub fn new(backend: Arc<dyn Nfs3Backend>) -> Nfs3Server {
+        Nfs3Server {
+            backend,
+            read_limit: Default::default(),
+        }
+    }
+
+    pub fn backend(&self) -> &Arc<dyn Nfs3Backend> {
+        &self.backend
+    }
+
+    /// Registers NFS version 3 with `server`
+    pub fn register_with(self: &Arc<Self>, server: &mut RpcServer) {
+        server.register(PROG_NFS, VERSION, Arc::new(Nfs3Dispatcher(self.clone())));
+    }
+
+    async fn post_op(&self, object: &NfsFh3) -> PostOpAttributes {
+        self.backend.getattr(object.clone()).await.ok()
+    }
+
+    async fn pre_op(&self, object: &NfsFh3) -> PreOpAttributes {
+        self.post_op(object).await.as_ref().map(wcc_attributes)
+    }
+
+    async fn wcc(&self, object: &NfsFh3, before: PreOpAttributes) -> WccData {
+        WccData {
+            before,
+            after: self.post_op(object).await,
+        }
+    }
+
+    async fn getattr(&self, args: GetAttr3Args) -> GetAttrResult {
+        check_fh(&args.object)?;
+        match self.backend.getattr(args.object).await {
+            Ok(attributes) => Ok(GetAttr3ResOk { attributes }),
+            Err(err) => Err(status(err).into()),
+        }
+    }
+
+    async fn setattr(&self, cred: Identity, args: SetAttr3Args) -> SetAttrResult {
+        let object = args.object;
+        if let Err(err) = check_fh(&object) {
+            let obj_wcc = WccData {
+                before: None,
+                after: None,
+            };
+            return Err((status(err), SetAttr3ResFail { obj_wcc }));
+        }
+
+        let before = match self.backend.getattr(object.clone()).await {
+            Ok(attributes) => attributes,
+            Err(err) => {
+                let obj_wcc = WccData {
+                    before: None,
+                    after: None,
+                };
+                return Err((status(err), SetAttr3ResFail { obj_wcc }));
+            }
+        };
+        if args.guard.is_some_and(|ctime| ctime != before.ctime) {
+            let obj_wcc = WccData {
+                before: Some(wcc_attributes(&before)),
+                after: Some(before),
+            };
+            return Err((NFS3ERR_NOT_SYNC, SetAttr3ResFail { obj_wcc }));
+        }
+
+        let result = self
+            .backend
+            .setattr(cred, object.clone(), args.new_attributes)
+            .await;
+        let obj_wcc = self.wcc(&object, Some(wcc_attributes(&before))).await;
+        match result {
+            Ok(()) => Ok(SetAttr3ResOk { obj_wcc }),
+            Err(err) => Err((status(err), SetAttr3ResFail { obj_wcc })),
+        }
+    }
+
+    async fn lookup(&self, cred: Identity, args: Lookup3Args) -> LookupResult {
+        let DirOpArgs3 { dir, name } = args.what;
+        let result = match check_fh(&dir).and_then(|_| check_name(&name)) {
+            Ok(()) => self.backend.lookup(cred, dir.clone(), name).await,
+            Err(err) => Err(err),
+        };
+
+        let dir_attributes = self.post_op(&dir).await;
+        match result {
+            Ok(object) => Ok(Lookup3ResOk {
+                obj_attributes: self.post_op(&object).await,
+                object,
+                dir_attributes,
+            }),
+            Err(err) => Err((status(err), Lookup3ResFail { dir_attributes })),
+        }
+    }
+
+    async fn access(&self, cred: Identity, args: Access3Args) -> AccessResult {
+        let result = match check_fh(&args.object) {
+            Ok(()) => {
+                let access = self.backend.access(cred, args.object.clone(), args.access);
+                access.await
+            }
+            Err(err) => Err(err),
+        };
+
+        let obj_attributes = self.post_op(&args.object).await;
+        match result {
+            Ok(access) => Ok(Access3ResOk {
+                obj_attributes,
+                access,
+            }),
+            Err(err) => Err((
+                status(err),
+                Access3ResFail {
+                    dir_attributes: obj_attributes,
+                },
+            )),
+        }
+    }
+
+    async fn readlink(&self, cred: Identity, args: ReadLink3Args) -> ReadLinkResult {
+        let result = match check_fh(&args.symlink) {
+            Ok(()) => self.backend.readlink(cred, args.symlink.clone()).await,
+            Err(err) => Err(err),
+        };
+
+        let symlink_attributes = self.post_op(&args.symlink).await;
+        match result {
+            Ok(data) => Ok(ReadLink3ResOk {
+                symlink_attributes,
+                data,
+            }),
+            Err(err) => Err((status(err), ReadLink3ResFail { symlink_attributes })),
+        }
+    }
+
+    async fn read(&self, cred: Identity, args: Read3Args) -> ReadResult {
+        let count = self
+            .read_limit
+            .clamp(self.backend.as_ref(), &args.file, args.count)
+            .await;
+        let result = match check_fh(&args.file) {
+            Ok(()) => {
+                let read = self
+                    .backend
+                    .read(cred, args.file.clone(), args.offset, count);
+                read.await
+            }
+            Err(err) => Err(err),
+        };
+
+        let file_attributes = self.post_op(&args.file).await;
+        match result {
+            Ok((mut data, mut eof)) => {
+                if data.len() > count as usize {
+                    data.truncate(count as usize);
+                    eof = false;
+                }
+                Ok(Read3ResOk {
+                    file_attributes,
+                    count: data.len() as u32,
+                    eof,
+                    data,
+                })
+            }
+            Err(err) => Err((status(err), Read3ResFail { file_attributes })),
+        }
+    }
+
+    async fn write(&self, cred: Identity, args: Write3Args) -> WriteResult {
+        let mut data = args.data;
+        let count = args.count as usize;
+        let checked = check_fh(&args.file).and_then(|_| {
+            if data.len() < count {
+                Err(NFS3ERR_INVAL.into())
+            } else {
+                data.truncate(count);
+                Ok(())
+            }
+        });
+
+        let before = self.pre_op(&args.file).await;
+        let result = match checked {
+            Ok(()) => {
+                let write =
+                    self.backend
+                        .write(cred, args.file.clone(), args.offset, data, args.stable);
+                write.await
+            }
+            Err(err) => Err(err),
+        };
+
+        let file_wcc = self.wcc(&args.file, before).await;
+        match result {
+            Ok(committed) => Ok(Write3ResOk {
+                file_wcc,
+                count: count as u32,
+                committed,
+                verifier: self.backend.write_verifier(),
+            }),
+            Err(err) => Err((status(err), Write3ResFail { file_wcc })),
+        }
+    }
+
+    /// Creates `name` in `dir` with `create` and returns the handle and
+    /// attributes of the new object along with the wcc data of `dir`
+    async fn create_in<F>(
+        &self,
+        dir: &NfsFh3,
+        name: &str,
+        create: F,
+    ) -> (Result<(NfsFh3, PostOpAttributes)>, WccData)
+    where
+        F: std::future::Future<Output = Result<NfsFh3>>,
+    {
+        let before = self.pre_op(dir).await;
+        let result = match check_fh(dir).and_then(|_| check_new_name(name)) {
+            Ok(()) => create.await,
+            Err(err) => Err(err),
+        };
+
+        let result = match result {
+            Ok(object) => Ok((object.clone(), self.post_op(&object).await)),
+            Err(err) => Err(err),
+        };
+        (result, self.wcc(dir, before).await)
+    }
+
+    async fn create(&self, cred: Identity, args: Create3Args) -> CreateResult {
+        let DirOpArgs3 { dir, name } = args.create_where;
+        let create = self
+            .backend
+            .create(cred, dir.clone(), name.clone(), args.how);
+        match self.create_in(&dir, &name, create).await {
+            (Ok((object, attributes)), wcc_data) => Ok(Create3ResOk {
+                obj: Some(object),
+                attributes,
+                wcc_data,
+            }),
+            (Err(err), dir_wcc) => Err((status(err), Create3ResFail { dir_wcc })),
+        }
+    }
+
+    async fn mkdir(&self, cred: Identity, args: Mkdir3Args) -> MkdirResult {
+        let DirOpArgs3 { dir, name } = args.mkdir_where;
+        let mkdir = self
+            .backend
+            .mkdir(cred, dir.clone(), name.clone(), args.attributes);
+        match self.create_in(&dir, &name, mkdir).await {
+            (Ok((object, attributes)), wcc_data) => Ok(Mkdir3ResOk {
+                obj: Some(object),
+                attributes,
+                wcc_data,
+            }),
+            (Err(err), dir_wcc) => Err((status(err), Mkdir3ResFail { dir_wcc })),
+        }
+    }
+
+    async fn symlink(&self, cred: Identity, args: SymLink3Args) -> SymLinkResult {
+        let DirOpArgs3 { dir, name } = args.symlink_where;
+        let SymLinkData3 { attributes, data } = args.data;
+        let symlink = self
+            .backend
+            .symlink(cred, dir.clone(), name.clone(), data, attributes);
+        match self.create_in(&dir, &name, symlink).await {
+            (Ok((object, attributes)), wcc_data) => Ok(SymLink3ResOk {
+                obj: Some(object),
+                attributes,
+                wcc_data,
+            }),
+            (Err(err), dir_wcc) => Err((status(err), SymLink3ResFail { dir_wcc })),
+        }
+    }
+
+    async fn mknod(&self, cred: Identity, args: Mknod3Args) -> MknodResult {
+        let DirOpArgs3 { dir, name } = args.mknod_where;
+        let mknod = self
+            .backend
+            .mknod(cred, dir.clone(), name.clone(), args.what);
+        match self.create_in(&dir, &name, mknod).await {
+            (Ok((object, attributes)), wcc_data) => Ok(Mknod3ResOk {
+                obj: Some(object),
+                attributes,
+                wcc_data,
+            }),
+            (Err(err), dir_wcc) => Err((status(err), Mknod3ResFail { dir_wcc })),
+        }
+    }
+
+    /// Removes `name` from `dir` with `remove`, returns the wcc data of
+    /// `dir`
+    async fn remove_from<F>(&self, dir: &NfsFh3, name: &str, remove: F) -> (Result<()>, WccData)
+    where
+        F: std::future::Future<Output = Result<()>>,
+    {
+        let before = self.pre_op(dir).await;
+        let result = match check_fh(dir).and_then(|_| check_name(name)) {
+            Ok(()) => remove.await,
+            Err(err) => Err(err),
+        };
+
+        (result, self.wcc(dir, before).await)
+    }
+
+    async fn remove(&self, cred: Identity, args: Remove3Args) -> RemoveResult {
+        let DirOpArgs3 { dir, name } = args.object;
+        let remove = self.backend.remove(cred, dir.clone(), name.clone());
+        match self.remove_from(&dir, &name, remove).await {
+            (Ok(()), wcc_data) => Ok(Remove3ResOk { wcc_data }),
+            (Err(err), dir_wcc) => Err((status(err), Remove3ResFail { dir_wcc })),
+        }
+    }
+
+    async fn rmdir(&self, cred: Identity, args: Rmdir3Args) -> RmdirResult {
+        let DirOpArgs3 { dir, name } = args.object;
+        let rmdir = self.backend.rmdir(cred, dir.clone(), name.clone());
+        match self.remove_from(&dir, &name, rmdir).await {
+            (Ok(()), wcc_data) => Ok(Rmdir3ResOk { wcc_data }),
+            (Err(err), dir_wcc) => Err((status(err), Rmdir3ResFail { dir_wcc })),
+        }
+    }
+
+    async fn rename(&self, cred: Identity, args: Rename3Args) -> RenameResult {
+        let (from, to) = (args.from, args.to);
+        let from_before = self.pre_op(&from.dir).await;
+        let to_before = self.pre_op(&to.dir).await;
+        let checked = check_fh(&from.dir)
+            .and_then(|_| check_fh(&to.dir))
+            .and_then(|_| check_name(&from.name))
+            .and_then(|_| check_new_name(&to.name));
+        let result = match checked {
+            Ok(()) => {
+                let rename =
+                    self.backend
+                        .rename(cred, from.dir.clone(), from.name, to.dir.clone(), to.name);
+                rename.await
+            }
+            Err(err) => Err(err),
+        };
+
+        let fromdir_wcc = self.wcc(&from.dir, from_before).await;
+        let todir_wcc = self.wcc(&to.dir, to_before).await;
+        match result {
+            Ok(()) => Ok(Rename3ResOk {
+                fromdir_wcc,
+                todir_wcc,
+            }),
+            Err(err) => Err((
+                status(err),
+                Rename3ResFail {
+                    fromdir_wcc,
+                    todir_wcc,
+                },
+            )),
+        }
+    }
+
+    async fn link(&self, cred: Identity, args: Link3Args) -> LinkResult {
+        let DirOpArgs3 { dir, name } = args.link;
+        let before = self.pre_op(&dir).await;
+        let checked = check_fh(&args.file)
+            .and_then(|_| check_fh(&dir))
+            .and_then(|_| check_new_name(&name));
+        let result = match checked {
+            Ok(()) => {
+                let link = self
+                    .backend
+                    .link(cred, args.file.clone(), dir.clone(), name);
+                link.await
+            }
+            Err(err) => Err(err),
+        };
+
+        let attributes = self.post_op(&args.file).await;
+        let linkdir_wcc = self.wcc(&dir, before).await;
+        match result {
+            Ok(()) => Ok(Link3ResOk {
+                attributes,
+                linkdir_wcc,
+            }),
+            Err(err) => Err((
+                status(err),
+                Link3ResFail {
+                    attributes,
+                    linkdir_wcc,
+                },
+            )),
+        }
+    }
+
+    async fn readdir(&self, cred: Identity, args: Readdir3Args) -> ReaddirResult {
+        let result = match check_fh(&args.dir) {
+            Ok(()) => {
+                let readdir = self.backend.readdir(
+                    cred,
+                    args.dir.clone(),
+                    args.cookie,
+                    args.verifier,
+                    args.count,
+                );
+                readdir.await
+            }
+            Err(err) => Err(err),
+        };
+
+        let dir_attributes = self.post_op(&args.dir).await;
+        let page = match result {
+            Ok(page) => page,
+            Err(err) => return Err((status(err), Readdir3ResFail { dir_attributes })),
+        };
+
+        // status, attributes, verifier, end of list and eof
+        let mut size = 4 + post_op_size(&dir_attributes) + 8 + 4 + 4;
+        let fits = page
+            .entries
+            .iter()
+            .take_while(|entry| {
+                size += entry_size(entry);
+                size <= args.count as usize
+            })
+            .count();
+        if fits == 0 && !page.entries.is_empty() {
+            return Err((NFS3ERR_TOOSMALL, Readdir3ResFail { dir_attributes }));
+        }
+
+        let DirPage {
+            mut entries,
+            verifier,
+            eof,
+        } = page;
+        let eof = eof && fits == entries.len();
+        entries.truncate(fits);
+
+        let mut next = None;
+        for entry in entries.into_iter().rev() {
+            next = Some(Box::new(Entry3 {
+                fileid: entry.fileid,
+                name: entry.name,
+                cookie: entry.cookie,
+                next_entry: next,
+            }));
+        }
+
+        Ok(Readdir3ResOk {
+            dir_attributes,
+            verifier,
+            reply: DirList3 {
+                entries: next.map(|entry| *entry),
+                eof,
+            },
+        })
+    }
+
+    async fn readdirplus(&self, cred: Identity, args: ReaddirPlus3Args) -> ReaddirPlusResult {
+        let result = match check_fh(&args.dir) {
+            Ok(()) => {
+                let readdirplus = self.backend.readdirplus(
+                    cred,
+                    args.dir.clone(),
+                    args.cookie,
+                    args.verifier,
+                    args.dircount,
+                );
+                readdirplus.await
+            }
+            Err(err) => Err(err),
+        };
+
+        let dir_attributes = self.post_op(&args.dir).await;
+        let page = match result {
+            Ok(page) => page,
+            Err(err) => return Err((status(err), ReaddirPlus3ResFail { dir_attributes })),
+        };
+
+        let mut size = 4 + post_op_size(&dir_attributes) + 8 + 4 + 4;
+        let mut dircount = 0;
+        let mut entries = Vec::new();
+        for entry in &page.entries {
+            let name_attributes = match &entry.handle {
+                Some(handle) => self.post_op(handle).await,
+                None => None,
+            };
+            let handle_size = 4 + entry
+                .handle
+                .as_ref()
+                .map_or(0, |fh| opaque_size(fh.data.len()));
+            dircount += entry_size(entry);
+            size += entry_size(entry) + post_op_size(&name_attributes) + handle_size;
+            if size > args.maxcount as usize || dircount > args.dircount as usize {
+                break;
+            }
+
+            entries.push((entry, name_attributes));
+        }
+        if entries.is_empty() && !page.entries.is_empty() {
+            return Err((NFS3ERR_TOOSMALL, ReaddirPlus3ResFail { dir_attributes }));
+        }
+
+        let eof = page.eof && entries.len() == page.entries.len();
+        let mut next = None;
+        for (entry, name_attributes) in entries.into_iter().rev() {
+            next = Some(Box::new(EntryPlus3 {
+                fileid: entry.fileid,
+                name: entry.name.clone(),
+                cookie: entry.cookie,
+                name_attributes,
+                name_handle: entry.handle.clone(),
+                next_entry: next,
+            }));
+        }
+
+        Ok(ReaddirPlus3ResOk {
+            dir_attributes,
+            verifier: page.verifier,
+            reply: DirListPlus3 {
+                entries: next.map(|entry| *entry),
+                eof,
+            },
+        })
+    }
+
+    async fn fsstat(&self, args: Fsstat3Args) -> FsstatResult {
+        let result = match check_fh(&args.root) {
+            Ok(()) => self.backend.fsstat(args.root.clone()).await,
+            Err(err) => Err(err),
+        };
+
+        let obj_attributes = self.post_op(&args.root).await;
+        match result {
+            Ok(res_ok) => Ok(Fsstat3ResOk {
+                obj_attributes,
+                ..res_ok
+            }),
+            Err(err) => Err((
+                status(err),
+                Fsstat3ResFail {
+                    dir_attributes: obj_attributes,
+                },
+            )),
+        }
+    }
+
+    async fn fsinfo(&self, args: Fsinfo3Args) -> FsinfoResult {
+        let result = match check_fh(&args.root) {
+            Ok(()) => self.backend.fsinfo(args.root.clone()).await,
+            Err(err) => Err(err),
+        };
+
+        let obj_attributes = self.post_op(&args.root).await;
+        match result {
+            Ok(res_ok) => Ok(Fsinfo3ResOk {
+                obj_attributes,
+                ..res_ok
+            }),
+            Err(err) => Err((
+                status(err),
+                Fsinfo3ResFail {
+                    dir_attributes: obj_attributes,
+                },
+            )),
+        }
+    }
+
+    async fn pathconf(&self, args: Pathconf3Args) -> PathconfResult {
+        let result = match check_fh(&args.root) {
+            Ok(()) => self.backend.pathconf(args.root.clone()).await,
+            Err(err) => Err(err),
+        };
+
+        let obj_attributes = self.post_op(&args.root).await;
+        match result {
+            Ok(res_ok) => Ok(Pathconf3ResOk {
+                obj_attributes,
+                ..res_ok
+            }),
+            Err(err) => Err((
+                status(err),
+                Pathconf3ResFail {
+                    dir_attributes: obj_attributes,
+                },
+            )),
+        }
+    }
+
+    async fn commit(&self, cred: Identity, args: Commit3Args) -> CommitResult {
+        let before = self.pre_op(&args.file).await;
+        let result = match check_fh(&args.file) {
+            Ok(()) => {
+                let commit = self
+                    .backend
+                    .commit(cred, args.file.clone(), args.offset, args.count);
+                commit.await
+            }
+            Err(err) => Err(err),
+        };
+
+        let file_wcc = self.wcc(&args.file, before).await;
+        match result {
+            Ok(()) => Ok(Commit3ResOk {
+                file_wcc,
+                verifier: self.backend.write_verifier(),
+            }),
+            Err(err) => Err((status(err), Commit3ResFail { file_wcc })),
+        }
+    }
+
+    async fn dispatch_call(&self, mut call: Call) -> Result<bytes::Bytes> {
+        let cred = call
+            .identity()
+            .unwrap_or_else(|| Identity::new(NOBODY, NOBODY, vec![]));
+        let args = &mut call.args;
+        let mut results = BytesMut::new();
+        let results_buf = &mut results;
+        match call.proc {
+            NFSPROC3_NULL => self.backend.null().await?,
+            NFSPROC3_GETATTR => {
+                let args = GetAttr3Args::unpack_from(args)?;
+                self.getattr(args).await.pack_to(results_buf);
+            }
+            NFSPROC3_SETATTR => {
+                let args = SetAttr3Args::unpack_from(args)?;
+                self.setattr(cred, args).await.pack_to(results_buf);
+            }
+            NFSPROC3_LOOKUP => {
+                let args = Lookup3Args::unpack_from(args)?;
+                self.lookup(cred, args).await.pack_to(results_buf);
+            }
+            NFSPROC3_ACCESS => {
+                let args = Access3Args::unpack_from(args)?;
+                self.access(cred, args).await.pack_to(results_buf);
+            }
+            NFSPROC3_READLINK => {
+                let args = ReadLink3Args::unpack_from(args)?;
+                self.readlink(cred, args).await.pack_to(results_buf);
+            }
+            NFSPROC3_READ => {
+                let args = Read3Args::unpack_from(args)?;
+                self.read(cred, args).await.pack_to(results_buf);
+            }
+            NFSPROC3_WRITE => {
+                let args = Write3Args::unpack_from(args)?;
+                self.write(cred, args).await.pack_to(results_buf);
+            }
+            NFSPROC3_CREATE => {
+                let args = Create3Args::unpack_from(args)?;
+                self.create(cred, args).await.pack_to(results_buf);
+            }
+            NFSPROC3_MKDIR => {
+                let args = Mkdir3Args::unpack_from(args)?;
+                self.mkdir(cred, args).await.pack_to(results_buf);
+            }
+            NFSPROC3_SYMLINK => {
+                let args = SymLink3Args::unpack_from(args)?;
+                self.symlink(cred, args).await.pack_to(results_buf);
+            }
+            NFSPROC3_MKNOD => {
+                let args = Mknod3Args::unpack_from(args)?;
+                self.mknod(cred, args).await.pack_to(results_buf);
+            }
+            NFSPROC3_REMOVE => {
+                let args = Remove3Args::unpack_from(args)?;
+                self.remove(cred, args).await.pack_to(results_buf);
+            }
+            NFSPROC3_RMDIR => {
+                let args = Rmdir3Args::unpack_from(args)?;
+                self.rmdir(cred, args).await.pack_to(results_buf);
+            }
+            NFSPROC3_RENAME => {
+                let args = Rename3Args::unpack_from(args)?;
+                self.rename(cred, args).await.pack_to(results_buf);
+            }
+            NFSPROC3_LINK => {
+                let args = Link3Args::unpack_from(args)?;
+                self.link(cred, args).await.pack_to(results_buf);
+            }
+            NFSPROC3_READDIR => {
+                let args = Readdir3Args::unpack_from(args)?;
+                self.readdir(cred, args).await.pack_to(results_buf);
+            }
+            NFSPROC3_READDIRPLUS => {
+                let args = ReaddirPlus3Args::unpack_from(args)?;
+                self.readdirplus(cred, args).await.pack_to(results_buf);
+            }
+            NFSPROC3_FSSTAT => {
+                let args = Fsstat3Args::unpack_from(args)?;
+                self.fsstat(args).await.pack_to(results_buf);
+            }
+            NFSPROC3_FSINFO => {
+                let args = Fsinfo3Args::unpack_from(args)?;
+                self.fsinfo(args).await.pack_to(results_buf);
+            }
+            NFSPROC3_PATHCONF => {
+                let args = Pathconf3Args::unpack_from(args)?;
+                self.pathconf(args).await.pack_to(results_buf);
+            }
+            NFSPROC3_COMMIT => {
+                let args = Commit3Args::unpack_from(args)?;
+                self.commit(cred, args).await.pack_to(results_buf);
+            }
+            _ => return Err(RPC_PROC_UNAVAIL.into()),
+        }
+
+        Ok(results.freeze())
+    }
+}
+
+struct Nfs3Dispatcher(Arc<Nfs3Server>);
+
+impl Dispatcher for Nfs3Dispatcher {
+    fn dispatch(&self, call: Call) -> DispatchFuture {
+        let server = self.0.clone();
+        Box::pin(async move { server.dispatch_call(call).await })
+    }
+
+    fn cacheable(&self, proc: u32) -> bool {
+        matches!(
+            proc,
+            NFSPROC3_SETATTR
+                | NFSPROC3_WRITE
+                | NFSPROC3_CREATE
+                | NFSPROC3_MKDIR
+                | NFSPROC3_SYMLINK
+                | NFSPROC3_MKNOD
+                | NFSPROC3_REMOVE
+                | NFSPROC3_RMDIR
+                | NFSPROC3_RENAME
+                | NFSPROC3_LINK
+        )
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::nfs3::{
+        backend::BackendFuture,
+        client::{NfsClient, Program},
+        Cookie3, Count3, FileType3, Filename3, NfsPath3, NfsTime3, Offset3, SetAttributes,
+        SpecData3, Verifier3, NFS3ERR_NOENT, NFS3ERR_NOTSUPP,
+    };
+    use crate::rpc::server::tests::serve_duplex;
+    use bytes::Bytes;
+    use std::sync::Mutex;
+
+    pub(crate) const ROOT: u8 = 1;
+    pub(crate) const FILE: u8 = 2;
+
+    /// A root directory holding one file named "file".  Writes are
+    /// FILE_SYNC, or with `unstable` UNSTABLE and lost by `restart`
+    /// until committed.
+    pub(crate) struct OneFile {
+        pub(crate) data: Mutex<Vec<u8>>,
+        mtime: Mutex<u32>,
+        pub(crate) unstable: bool,
+        pub(crate) disk: Mutex<Disk>,
+    }
+
+    /// What survives a restart of `OneFile`, and the calls it served
+    #[derive(Default)]
+    pub(crate) struct Disk {
+        pub(crate) data: Vec<u8>,
+        pub(crate) verifier: Verifier3,
+        pub(crate) writes: usize,
+        pub(crate) commits: usize,
+        /// Restart after every commit, changing only the verifier
+        pub(crate) restart_on_commit: bool,
+    }
+
+    impl OneFile {
+        pub(crate) fn new(data: &[u8]) -> OneFile {
+            OneFile {
+                data: Mutex::new(data.to_vec()),
+                mtime: Mutex::new(1),
+                unstable: false,
+                disk: Mutex::new(Disk {
+                    data: data.to_vec(),
+                    verifier: 42,
+                    ..Default::default()
+                }),
+            }
+        }
+
+        /// Loses the data written since the last commit and changes
+        /// the write verifier
+        pub(crate) fn restart(&self) {
+            let mut disk = self.disk.lock().unwrap();
+            *self.data.lock().unwrap() = disk.data.clone();
+            disk.verifier += 1;
+        }
+
+        fn attributes(&self, fh: &NfsFh3) -> Result<FileAttributes> {
+            let (file_type, size) = match fh.data[..] {
+                [ROOT] => (FileType3::Dir, 4096),
+                [FILE] => (FileType3::Reg, self.data.lock().unwrap().len() as u64),
+                _ => return Err(NFS3ERR_BADHANDLE.into()),
+            };
+            let time = NfsTime3 {
+                seconds: *self.mtime.lock().unwrap(),
+                nano_seconds: 0,
+            };
+            Ok(FileAttributes {
+                file_type,
+                mode: 0o644,
+                num_links: 1,
+                uid: 0,
+                gid: 0,
+                size,
+                used: size,
+                rdev: SpecData3::default(),
+                fsid: 1,
+                file_id: fh.data[0] as u64,
+                atime: time,
+                mtime: time,
+                ctime: time,
+            })
+        }
+    }
+
+    pub(crate) fn unsupported<T>() -> BackendFuture<'static, T> {
+        Box::pin(async { Err(NFS3ERR_NOTSUPP.into()) })
+    }
+
+    impl Nfs3Backend for OneFile {
+        fn getattr(&self, object: NfsFh3) -> BackendFuture<'_, FileAttributes> {
+            Box::pin(async move { self.attributes(&object) })
+        }
+
+        fn setattr(&self, _: Identity, _: NfsFh3, _: SetAttributes) -> BackendFuture<'_, ()> {
+            unsupported()
+        }
+
+        fn lookup(&self, _: Identity, _: NfsFh3, name: Filename3) -> BackendFuture<'_, NfsFh3> {
+            Box::pin(async move {
+                match name.as_str() {
+                    "file" => Ok(NfsFh3 { data: vec![FILE] }),
+                    _ => Err(NFS3ERR_NOENT.into()),
+                }
+            })
+        }
+
+        fn readlink(&self, _: Identity, _: NfsFh3) -> BackendFuture<'_, NfsPath3> {
+            unsupported()
+        }
+
+        fn read(
+            &self,
+            _: Identity,
+            _: NfsFh3,
+            offset: Offset3,
+            count: Count3,
+        ) -> BackendFuture<'_, (Bytes, bool)> {
+            Box::pin(async move {
+                let data = self.data.lock().unwrap();
+                let start = (offset as usize).min(data.len());
+                let end = (start + count as usize).min(data.len());
+                Ok((Bytes::copy_from_slice(&data[start..end]), end == data.len()))
+            })
+        }
+
+        fn write(
+            &self,
+            _: Identity,
+            _: NfsFh3,
+            offset: Offset3,
+            data: Bytes,
+            _: StableHow,
+        ) -> BackendFuture<'_, StableHow> {
+            Box::pin(async move {
+                let contents = {
+                    let mut file = self.data.lock().unwrap();
+                    let end = offset as usize + data.len();
+                    if file.len() < end {
+                        file.resize(end, 0);
+                    }
+                    file[offset as usize..end].copy_from_slice(&data);
+                    file.clone()
+                };
+                *self.mtime.lock().unwrap() += 1;
+                let mut disk = self.disk.lock().unwrap();
+                disk.writes += 1;
+                if self.unstable {
+                    Ok(StableHow::Unstable)
+                } else {
+                    disk.data = contents;
+                    Ok(StableHow::FileSync)
+                }
+            })
+        }
+
+        fn create(
+            &self,
+            _: Identity,
+            _: NfsFh3,
+            _: Filename3,
+            _: CreateHow3,
+        ) -> BackendFuture<'_, NfsFh3> {
+            unsupported()
+        }
+
+        fn mkdir(
+            &self,
+            _: Identity,
+            _: NfsFh3,
+            _: Filename3,
+            _: SetAttributes,
+        ) -> BackendFuture<'_, NfsFh3> {
+            unsupported()
+        }
+
+        fn symlink(
+            &self,
+            _: Identity,
+            _: NfsFh3,
+            _: Filename3,
+            _: NfsPath3,
+            _: SetAttributes,
+        ) -> BackendFuture<'_, NfsFh3> {
+            unsupported()
+        }
+
+        fn remove(&self, _: Identity, _: NfsFh3, _: Filename3) -> BackendFuture<'_, ()> {
+            unsupported()
+        }
+
+        fn rmdir(&self, _: Identity, _: NfsFh3, _: Filename3) -> BackendFuture<'_, ()> {
+            unsupported()
+        }
+
+        fn rename(
+            &self,
+            _: Identity,
+            _: NfsFh3,
+            _: Filename3,
+            _: NfsFh3,
+            _: Filename3,
+        ) -> BackendFuture<'_, ()> {
+            unsupported()
+        }
+
+        fn readdir(
+            &self,
+            _: Identity,
+            _: NfsFh3,
+            cookie: Cookie3,
+            _: Verifier3,
+            _: Count3,
+        ) -> BackendFuture<'_, DirPage> {
+            Box::pin(async move {
+                let names = [(ROOT, "."), (ROOT, ".."), (FILE, "file")];
+                let entries = (cookie as usize..names.len())
+                    .map(|i| DirEntry {
+                        fileid: names[i].0 as u64,
+                        name: names[i].1.to_string(),
+                        cookie: i as u64 + 1,
+                        handle: None,
+                    })
+                    .collect();
+                Ok(DirPage {
+                    entries,
+                    verifier: 7,
+                    eof: true,
+                })
+            })
+        }
+
+        fn fsstat(&self, _: NfsFh3) -> BackendFuture<'_, Fsstat3ResOk> {
+            unsupported()
+        }
+
+        fn commit(&self, _: Identity, _: NfsFh3, _: Offset3, _: Count3) -> BackendFuture<'_, ()> {
+            Box::pin(async move {
+                let contents = self.data.lock().unwrap().clone();
+                let restart = {
+                    let mut disk = self.disk.lock().unwrap();
+                    disk.commits += 1;
+                    disk.data = contents;
+                    disk.restart_on_commit
+                };
+                if restart {
+                    self.restart();
+                }
+                Ok(())
+            })
+        }
+
+        fn write_verifier(&self) -> Verifier3 {
+            self.disk.lock().unwrap().verifier
+        }
+    }
+
+    /// Serves `backend` over an in-memory stream, returns a client of
+    /// it
+    pub(crate) fn serve(backend: Arc<dyn Nfs3Backend>) -> NfsClient {
+        let nfsd = Arc::new(Nfs3Server::new(backend));
+        let mut server = RpcServer::new();
+        nfsd.register_with(&mut server);
+        let mut client = NfsClient::new("localhost");
+        client.set_rpc_client(Program::Nfs, serve_duplex(server, None));
+        client
+    }
+
+    fn new_client(data: &[u8]) -> NfsClient {
+        serve(Arc::new(OneFile::new(data)))
+    }
+
+    #[tokio::test]
+    async fn lookup_read_write() {
+        let client = new_client(b"hello");
+        let root = NfsFh3 { data: vec![ROOT] };
+
+        let res_ok = client
+            .call_lookup(&root, "file".into())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(res_ok.object.data, vec![FILE]);
+        assert_eq!(res_ok.obj_attributes.unwrap().size, 5);
+        assert_eq!(res_ok.dir_attributes.unwrap().file_type, FileType3::Dir);
+        let (status, res_fail) = client
+            .call_lookup(&root, "x".into())
+            .await
+            .unwrap()
+            .err()
+            .unwrap();
+        assert_eq!(status, NFS3ERR_NOENT);
+        assert!(res_fail.dir_attributes.is_some());
+        let (status, _) = client
+            .call_lookup(&root, "a/b".into())
+            .await
+            .unwrap()
+            .err()
+            .unwrap();
+        assert_eq!(status, NFS3ERR_INVAL);
+        let empty = NfsFh3 { data: vec![] };
+        let err = client.call_getattr(&empty).await.unwrap().err().unwrap();
+        assert_eq!(err.get(), NFS3ERR_BADHANDLE);
+
+        let file = res_ok.object;
+        let res_ok = client
+            .call_write(
+                &file,
+                3,
+                4,
+                Bytes::from_static(b"p me"),
+                StableHow::DataSync,
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(res_ok.count, 4);
+        assert_eq!(res_ok.committed, StableHow::FileSync);
+        assert_eq!(res_ok.verifier, 42);
+        let before = res_ok.file_wcc.before.unwrap();
+        let after = res_ok.file_wcc.after.unwrap();
+        assert_eq!((before.size, after.size), (5, 7));
+        assert!(after.mtime > before.mtime);
+
+        let res_ok = client.call_read(&file, 0, 100).await.unwrap().unwrap();
+        assert_eq!(&res_ok.data[..], b"help me");
+        assert!(res_ok.eof);
+        let res_ok = client.call_read(&file, 1, 3).await.unwrap().unwrap();
+        assert_eq!(&res_ok.data[..], b"elp");
+        assert!(!res_ok.eof);
+
+        let res_ok = client.call_commit(&file, 0, 0).await.unwrap().unwrap();
+        assert_eq!(res_ok.verifier, 42);
+    }
+
+    #[tokio::test]
+    async fn read_clamped() {
+        let size = DEFAULT_MAX_TRANSFER as usize + 4096;
+        let client = new_client(&vec![7; size]);
+        let file = NfsFh3 { data: vec![FILE] };
+
+        let res_ok = client.call_read(&file, 0, u32::MAX).await.unwrap().unwrap();
+        assert_eq!(res_ok.count, DEFAULT_MAX_TRANSFER);
+        assert_eq!(res_ok.data.len(), DEFAULT_MAX_TRANSFER as usize);
+        assert!(!res_ok.eof);
+        let res_ok = client
+            .call_read(&file, res_ok.count as u64, u32::MAX)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(res_ok.count, 4096);
+        assert!(res_ok.eof);
+    }
+
+    #[tokio::test]
+    async fn readdir() {
+        let client = new_client(b"hello");
+        let root = NfsFh3 { data: vec![ROOT] };
+
+        let res_ok = client.call_readdir(&root, 0, 0).await.unwrap().unwrap();
+        let names: Vec<_> = res_ok.reply.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, [".", "..", "file"]);
+        assert!(res_ok.reply.eof);
+        assert_eq!(res_ok.verifier, 7);
+
+        let res_ok = client.call_readdirplus(&root, 2, 7).await.unwrap().unwrap();
+        let entry = res_ok.reply.entries.unwrap();
+        assert_eq!(entry.name, "file");
+        assert_eq!(entry.name_handle.unwrap().data, vec![FILE]);
+        assert_eq!(entry.name_attributes.unwrap().size, 5);
+    }
+}
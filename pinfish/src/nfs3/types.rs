@@ -15,7 +15,7 @@ pub type Count3 = u32;
 pub type Mode3 = u32;
 pub type Offset3 = u64;
 
-#[derive(PackTo, Debug, UnpackFrom, Copy, Clone)]
+#[derive(PackTo, Debug, UnpackFrom, Copy, Clone, PartialEq, Eq)]
 pub enum FileType3 {
     Reg = 1,
     Dir = 2,
@@ -26,13 +26,13 @@ pub enum FileType3 {
     Fifo = 7,
 }
 
-#[derive(PackTo, UnpackFrom, Debug)]
+#[derive(PackTo, UnpackFrom, Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub struct SpecData3 {
     pub data1: u32,
     pub data2: u32,
 }
 
-#[derive(PackTo, UnpackFrom, Debug, Clone, Default)]
+#[derive(PackTo, UnpackFrom, Debug, Clone, Default, PartialEq, Eq, Hash)]
 pub struct NfsFh3 {
     pub data: Vec<u8>, // should be opaque<NFS3_FHSIZE>
 }
@@ -40,13 +40,13 @@ pub struct NfsFh3 {
 /// The NfsTime3 gives the number of seconds and nano seconds since
 /// midnight or zero hour January 1, 1970 Coordinated Universal Time
 /// (UTC).
-#[derive(PackTo, UnpackFrom, Debug)]
+#[derive(PackTo, UnpackFrom, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
 pub struct NfsTime3 {
     pub seconds: u32,
     pub nano_seconds: u32,
 }
 
-#[derive(PackTo, UnpackFrom, Debug)]
+#[derive(PackTo, UnpackFrom, Debug, Clone)]
 pub struct FileAttributes {
     pub file_type: FileType3,
     pub mode: Mode3,
@@ -63,20 +63,15 @@ pub struct FileAttributes {
     pub ctime: NfsTime3,
 }
 
-#[derive(PackTo, UnpackFrom, Debug)]
+#[derive(PackTo, UnpackFrom, Debug, Clone, Copy, Default)]
 pub enum TimeHow {
     DontChange,
+    #[default]
     SetToServerTime,
     SetToClientTime(NfsTime3),
 }
 
-impl Default for TimeHow {
-    fn default() -> Self {
-        TimeHow::SetToServerTime
-    }
-}
-
-#[derive(PackTo, UnpackFrom, Debug, Default)]
+#[derive(PackTo, UnpackFrom, Debug, Clone, Default)]
 pub struct SetAttributes {
     pub mode: Option<Mode3>,
     pub uid: Option<Uid3>,
@@ -87,7 +82,7 @@ pub struct SetAttributes {
 }
 
 /// Subset of pre-operation attributes used for weak cache consistency
-#[derive(PackTo, UnpackFrom, Debug)]
+#[derive(PackTo, UnpackFrom, Debug, Clone, Copy)]
 pub struct WccAttributes {
     pub size: Size3,
     pub mtime: NfsTime3,
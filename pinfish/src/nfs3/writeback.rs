@@ -0,0 +1,322 @@
+//! Write-back of UNSTABLE writes over `NfsClient`.
+//!
+//! Data written UNSTABLE is only durable once a COMMIT returns the
+//! verifier of the WRITE replies, a different verifier means the server
+//! restarted and may have lost it.  `WriteBack` keeps the data of each
+//! file until it is committed, commits once enough of it piles up and
+//! sends it again, in the original order, whenever the verifier changes.
+use crate::{
+    nfs3::{client::NfsClient, procs::StableHow, NfsFh3, Offset3, Verifier3, NFS3ERR_IO},
+    result::{ErrorCode, Result, WRITE_VERIFIER_UNSTABLE},
+};
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::RwLock;
+
+/// Uncommitted bytes of a file that trigger a COMMIT by default
+pub const DEFAULT_MAX_UNCOMMITTED: u64 = 16 * 1024 * 1024;
+
+/// Times the uncommitted writes of a file are sent again before a
+/// commit gives up
+const MAX_RESENDS: usize = 3;
+
+/// Data written at `offset` and the verifier of the reply
+struct Pending {
+    offset: Offset3,
+    data: Bytes,
+    verifier: Verifier3,
+}
+
+#[derive(Default)]
+struct Uncommitted {
+    /// In the order the writes completed, later ones win on overlap
+    writes: Vec<Pending>,
+    bytes: u64,
+}
+
+impl Uncommitted {
+    /// Keeps the writes that are not durable yet, returns whether any
+    /// reply has a verifier different from the kept writes
+    fn record(&mut self, sent: Vec<(Pending, StableHow)>) -> bool {
+        let mut changed = false;
+        for (write, committed) in sent {
+            changed |= self
+                .writes
+                .iter()
+                .any(|kept| kept.verifier != write.verifier);
+            // stable data after uncommitted writes is kept as well, so
+            // that sending those again does not overwrite it
+            if committed == StableHow::Unstable || !self.writes.is_empty() {
+                self.bytes += write.data.len() as u64;
+                self.writes.push(write);
+            }
+        }
+
+        changed
+    }
+}
+
+/// Write-back state of one file
+#[derive(Default)]
+struct FileState {
+    /// Shared by writes, exclusive while committing and sending again
+    gate: RwLock<()>,
+    uncommitted: Mutex<Uncommitted>,
+}
+
+/// Sends writes UNSTABLE and keeps their data until it is committed
+pub struct WriteBack {
+    client: Arc<NfsClient>,
+    max_uncommitted: u64,
+    files: Mutex<HashMap<NfsFh3, Arc<FileState>>>,
+}
+
+impl WriteBack {
+    pub fn new(client: Arc<NfsClient>) -> WriteBack {
+        WriteBack {
+            client,
+            max_uncommitted: DEFAULT_MAX_UNCOMMITTED,
+            files: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn client(&self) -> &Arc<NfsClient> {
+        &self.client
+    }
+
+    /// Sets the uncommitted bytes of a file that trigger a COMMIT, 0 to
+    /// commit only in `commit` and `flush`
+    pub fn set_max_uncommitted(&mut self, max_uncommitted: u64) {
+        self.max_uncommitted = max_uncommitted;
+    }
+
+    /// Returns the bytes written to `file` that are not committed yet
+    pub fn uncommitted(&self, file: &NfsFh3) -> u64 {
+        match self.files.lock().unwrap().get(file) {
+            Some(state) => state.uncommitted.lock().unwrap().bytes,
+            None => 0,
+        }
+    }
+
+    /// Writes `data` at `offset`.  The file is committed when its
+    /// uncommitted data exceeds the limit or the server restarted since
+    /// the kept writes were sent.
+    pub async fn write(&self, file: &NfsFh3, offset: Offset3, data: Bytes) -> Result<()> {
+        let state = self.state(file);
+        let (changed, bytes) = {
+            let _gate = state.gate.read().await;
+            let sent = self.send(file, offset, data).await?;
+            let mut uncommitted = state.uncommitted.lock().unwrap();
+            (uncommitted.record(sent), uncommitted.bytes)
+        };
+
+        if changed || (self.max_uncommitted > 0 && bytes > self.max_uncommitted) {
+            self.commit(file).await
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Commits the data written to `file`, sending it again while the
+    /// COMMIT verifier differs from the one of the writes
+    pub async fn commit(&self, file: &NfsFh3) -> Result<()> {
+        let state = self.state(file);
+        let _gate = state.gate.write().await;
+        let mut resends = 0;
+        loop {
+            if state.uncommitted.lock().unwrap().writes.is_empty() {
+                break;
+            }
+            let res_ok = self
+                .client
+                .call_commit(file, 0, 0)
+                .await?
+                .map_err(|(status, _)| ErrorCode::from(status))?;
+            let writes: Vec<(Offset3, Bytes)> = {
+                let mut uncommitted = state.uncommitted.lock().unwrap();
+                let writes = &uncommitted.writes;
+                if writes.iter().all(|write| write.verifier == res_ok.verifier) {
+                    *uncommitted = Uncommitted::default();
+                    break;
+                }
+                writes.iter().map(|w| (w.offset, w.data.clone())).collect()
+            };
+            if resends == MAX_RESENDS {
+                return Err(WRITE_VERIFIER_UNSTABLE.into());
+            }
+            resends += 1;
+
+            // the kept writes are only replaced once all are sent again
+            let mut resent = Vec::with_capacity(writes.len());
+            for (offset, data) in writes {
+                resent.extend(self.send(file, offset, data).await?);
+            }
+            let mut uncommitted = state.uncommitted.lock().unwrap();
+            *uncommitted = Uncommitted::default();
+            uncommitted.record(resent);
+        }
+
+        // forget the file unless another call is using it
+        let mut files = self.files.lock().unwrap();
+        if Arc::strong_count(&state) == 2 {
+            files.remove(file);
+        }
+
+        Ok(())
+    }
+
+    /// Commits all files with uncommitted data
+    pub async fn flush(&self) -> Result<()> {
+        let files: Vec<NfsFh3> = self.files.lock().unwrap().keys().cloned().collect();
+        for file in files {
+            self.commit(&file).await?;
+        }
+
+        Ok(())
+    }
+
+    fn state(&self, file: &NfsFh3) -> Arc<FileState> {
+        let mut files = self.files.lock().unwrap();
+        files.entry(file.clone()).or_default().clone()
+    }
+
+    /// Writes `data` UNSTABLE, continuing after short writes, returns
+    /// the part written by each reply and how stable it is
+    async fn send(
+        &self,
+        file: &NfsFh3,
+        offset: Offset3,
+        data: Bytes,
+    ) -> Result<Vec<(Pending, StableHow)>> {
+        let mut sent = Vec::new();
+        let mut done = 0;
+        while done < data.len() {
+            let part = data.slice(done..);
+            let res_ok = self
+                .client
+                .call_write(
+                    file,
+                    offset + done as u64,
+                    part.len() as u32,
+                    part.clone(),
+                    StableHow::Unstable,
+                )
+                .await?
+                .map_err(|(status, _)| ErrorCode::from(status))?;
+            let count = (res_ok.count as usize).min(part.len());
+            if count == 0 {
+                return Err(NFS3ERR_IO.into());
+            }
+            let write = Pending {
+                offset: offset + done as u64,
+                data: part.slice(..count),
+                verifier: res_ok.verifier,
+            };
+            sent.push((write, res_ok.committed));
+            done += count;
+        }
+
+        Ok(sent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nfs3::server::tests::{serve, OneFile, FILE};
+
+    fn file() -> NfsFh3 {
+        NfsFh3 { data: vec![FILE] }
+    }
+
+    /// A file written UNSTABLE and its client
+    fn volatile() -> (Arc<OneFile>, Arc<NfsClient>) {
+        let mut backend = OneFile::new(b"");
+        backend.unstable = true;
+        let backend = Arc::new(backend);
+        let client = Arc::new(serve(backend.clone()));
+        (backend, client)
+    }
+
+    #[tokio::test]
+    async fn commit_batches() {
+        let (backend, client) = volatile();
+        let mut writeback = WriteBack::new(client);
+        writeback.set_max_uncommitted(10);
+
+        for i in 0..2 {
+            let data = Bytes::from_static(b"abcd");
+            writeback.write(&file(), i * 4, data).await.unwrap();
+        }
+        assert_eq!(writeback.uncommitted(&file()), 8);
+        assert_eq!(backend.disk.lock().unwrap().commits, 0);
+        let data = Bytes::from_static(b"efgh");
+        writeback.write(&file(), 8, data).await.unwrap();
+        assert_eq!(writeback.uncommitted(&file()), 0);
+        assert_eq!(backend.disk.lock().unwrap().commits, 1);
+
+        let data = Bytes::from_static(b"ij");
+        writeback.write(&file(), 12, data).await.unwrap();
+        assert_eq!(writeback.uncommitted(&file()), 2);
+        writeback.flush().await.unwrap();
+        writeback.flush().await.unwrap();
+        let disk = backend.disk.lock().unwrap();
+        assert_eq!(disk.commits, 2);
+        assert_eq!(disk.writes, 4);
+        assert_eq!(disk.data, b"abcdabcdefghij");
+        assert_eq!(writeback.uncommitted(&file()), 0);
+    }
+
+    #[tokio::test]
+    async fn resend_after_restart() {
+        let (backend, client) = volatile();
+        let writeback = WriteBack::new(client);
+
+        // overlapping writes are sent again in their original order
+        let data = Bytes::from_static(b"aaaa");
+        writeback.write(&file(), 0, data).await.unwrap();
+        let data = Bytes::from_static(b"bb");
+        writeback.write(&file(), 2, data).await.unwrap();
+        backend.restart();
+        assert!(backend.data.lock().unwrap().is_empty());
+        writeback.commit(&file()).await.unwrap();
+        {
+            let disk = backend.disk.lock().unwrap();
+            assert_eq!(disk.data, b"aabb");
+            assert_eq!(disk.writes, 4);
+            assert_eq!(disk.commits, 2);
+        }
+
+        // a write reply with a new verifier commits at once
+        let data = Bytes::from_static(b"cc");
+        writeback.write(&file(), 4, data).await.unwrap();
+        backend.restart();
+        let data = Bytes::from_static(b"dd");
+        writeback.write(&file(), 6, data).await.unwrap();
+        assert_eq!(writeback.uncommitted(&file()), 0);
+        let disk = backend.disk.lock().unwrap();
+        assert_eq!(disk.data, b"aabbccdd");
+        // both kept writes are sent again, "dd" included
+        assert_eq!(disk.writes, 8);
+    }
+
+    #[tokio::test]
+    async fn verifier_unstable() {
+        let (backend, client) = volatile();
+        let writeback = WriteBack::new(client);
+
+        let data = Bytes::from_static(b"abcd");
+        writeback.write(&file(), 0, data).await.unwrap();
+        backend.disk.lock().unwrap().restart_on_commit = true;
+        let err = writeback.commit(&file()).await.unwrap_err();
+        assert_eq!(err.get(), WRITE_VERIFIER_UNSTABLE);
+        assert_eq!(writeback.uncommitted(&file()), 4);
+        assert_eq!(backend.disk.lock().unwrap().commits, MAX_RESENDS + 1);
+
+        backend.disk.lock().unwrap().restart_on_commit = false;
+        writeback.commit(&file()).await.unwrap();
+        assert_eq!(writeback.uncommitted(&file()), 0);
+        assert_eq!(backend.disk.lock().unwrap().data, b"abcd");
+    }
+}
@@ -64,6 +64,12 @@ impl Bitmap4 {
     }
 }
 
+impl Default for Bitmap4 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// File types (RFC 7531)
 #[derive(PackTo, Debug, UnpackFrom, Copy, Clone)]
 pub enum NfsType4 {
@@ -137,6 +143,12 @@ impl FileAttributes {
     }
 }
 
+impl Default for FileAttributes {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<B: Packer> PackTo<B> for FileAttributes {
     fn pack_to(&self, buf: &mut B) {
         let bm = self.calculate_bitmap();
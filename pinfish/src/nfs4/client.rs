@@ -9,13 +9,20 @@ use crate::{
         sequence::{ClientSequence, ClientSequencer},
     },
     result::{Result, INVALID_DATA, NOT_CONNECTED},
-    rpc::{self, RpcClient},
+    rpc::{
+        self,
+        auth::CredentialProvider,
+        pool::{Balance, RpcPool},
+        RpcClient,
+    },
     xdr::{PackTo, Packer, UnpackFrom},
 };
-use bytes::{Buf, Bytes, BytesMut};
+use bytes::{Bytes, BytesMut};
 use core::cell::Cell;
-use std::borrow::BorrowMut;
 use std::collections::btree_map::BTreeMap;
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+use std::time::Duration;
 use tokio::net::TcpStream;
 
 #[derive(Default)]
@@ -33,12 +40,39 @@ pub enum ClientFsNode {
     Dir(ClientFsDirNode),
 }
 
+/// Room for the RPC and COMPOUND headers around READ data
+const REPLY_OVERHEAD: u32 = 4096;
+
 pub struct NfsClient {
     /// Server address in host:port format
     server: String,
 
-    /// Active connection
-    rpc: Option<RpcClient>,
+    /// Active connections
+    rpc: Option<RpcPool>,
+
+    /// Number of connections opened by `connect` and how calls are
+    /// spread over them
+    nconnect: NonZeroUsize,
+    balance: Balance,
+
+    /// Reconnect policy, connections are not re-established if `None`
+    reconnect: Option<rpc::Reconnect>,
+
+    /// Default deadline for RPC calls
+    timeout: Option<Duration>,
+
+    /// Limits on outstanding calls
+    flow_control: rpc::FlowControl,
+
+    /// Credentials sent with each call
+    credentials: Arc<dyn CredentialProvider>,
+
+    /// Handles backchannel calls from the server
+    callbacks: Option<Arc<dyn rpc::CallbackDispatcher>>,
+
+    /// RPC-over-TLS parameters, `None` for plain TCP
+    #[cfg(feature = "tls")]
+    tls: Option<rpc::tls::TlsConfig>,
 
     /// Client ID returned from EXCHANGE_ID
     pub client_id: Cell<ClientId4>,
@@ -62,6 +96,15 @@ impl NfsClient {
         NfsClient {
             server: server.into(),
             rpc: None,
+            nconnect: NonZeroUsize::MIN,
+            balance: Default::default(),
+            reconnect: None,
+            timeout: None,
+            flow_control: Default::default(),
+            credentials: Arc::new(rpc::auth::SysCredentials::default()),
+            callbacks: None,
+            #[cfg(feature = "tls")]
+            tls: None,
             client_id: Cell::new(0),
             sequence_id: Cell::new(0),
             session_id: Cell::new(Default::default()),
@@ -70,58 +113,156 @@ impl NfsClient {
         }
     }
 
+    /// Enables reconnecting with retransmission of pending calls when
+    /// the connection drops.  Takes effect on the next connect.
+    pub fn set_reconnect(&mut self, reconnect: Option<rpc::Reconnect>) {
+        self.reconnect = reconnect;
+    }
+
+    /// Sets the default deadline for RPC calls, `None` waits
+    /// indefinitely.  Takes effect on the next connect.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Limits the calls waiting for a reply and the READ data they
+    /// expect.  Takes effect on the next connect.
+    pub fn set_flow_control(&mut self, flow_control: rpc::FlowControl) {
+        self.flow_control = flow_control;
+    }
+
+    /// Sets the credentials sent with each call, AUTH_SYS as root by
+    /// default
+    pub fn set_credentials(&mut self, credentials: Arc<dyn CredentialProvider>) {
+        self.credentials = credentials;
+    }
+
+    /// Opens `nconnect` connections to the server and spreads calls
+    /// over them according to `balance`.  All connections are bound to
+    /// the session by `create_session_call`.  Takes effect on the next
+    /// connect.
+    pub fn set_nconnect(&mut self, nconnect: NonZeroUsize, balance: Balance) {
+        self.nconnect = nconnect;
+        self.balance = balance;
+    }
+
+    /// Enables RPC-over-TLS.  Takes effect on the next connect.
+    #[cfg(feature = "tls")]
+    pub fn set_tls(&mut self, tls: Option<rpc::tls::TlsConfig>) {
+        self.tls = tls;
+    }
+
+    /// Registers `dispatcher` for backchannel calls (CB_SEQUENCE,
+    /// CB_RECALL, ...) the server sends over the connection.  Takes
+    /// effect on the next connect.
+    pub fn set_callback_dispatcher(
+        &mut self,
+        dispatcher: Option<Arc<dyn rpc::CallbackDispatcher>>,
+    ) {
+        self.callbacks = dispatcher;
+    }
+
     /// Connects the client
     pub async fn connect(&mut self) -> Result<()> {
-        let connection = TcpStream::connect(&self.server).await?;
-        self.rpc = Some(RpcClient::new(connection));
+        let mut clients = Vec::with_capacity(self.nconnect.get());
+        for _ in 0..self.nconnect.get() {
+            clients.push(self.connect_one().await?);
+        }
+        self.attach(clients);
 
         Ok(())
     }
 
+    async fn connect_one(&self) -> Result<RpcClient> {
+        #[cfg(feature = "tls")]
+        if let Some(tls) = self.tls.clone() {
+            return self.connect_tls(tls).await;
+        }
+
+        Ok(match self.reconnect {
+            None => RpcClient::new(TcpStream::connect(&self.server).await?),
+            Some(reconnect) => {
+                let server = self.server.clone();
+                let connect = move || TcpStream::connect(server.clone());
+                RpcClient::new_reconnecting(connect, reconnect).await?
+            }
+        })
+    }
+
+    /// Connects and upgrades the connection to TLS, again after each
+    /// reconnect when reconnecting is enabled
+    #[cfg(feature = "tls")]
+    async fn connect_tls(&self, tls: rpc::tls::TlsConfig) -> Result<RpcClient> {
+        let server = self.server.clone();
+        let connect = move || {
+            let server = server.clone();
+            let tls = tls.clone();
+            async move {
+                let connection = TcpStream::connect(server).await?;
+                rpc::tls::start_tls(connection, nfs4::PROG_NFS, 4, &tls)
+                    .await
+                    .map_err(std::io::Error::other)
+            }
+        };
+
+        Ok(match self.reconnect {
+            None => RpcClient::new(connect().await?),
+            Some(reconnect) => RpcClient::new_reconnecting(connect, reconnect).await?,
+        })
+    }
+
+    /// Applies the client settings to newly connected `clients` and
+    /// starts using them
+    fn attach(&mut self, clients: Vec<RpcClient>) {
+        let mut pool = RpcPool::new(clients, self.balance);
+        pool.set_timeout(self.timeout);
+        pool.set_flow_control(self.flow_control);
+        pool.set_callback_dispatcher(self.callbacks.clone());
+        self.rpc = Some(pool);
+    }
+
+    /// Uses `rpc` instead of connecting to the server, e.g. an
+    /// `RpcClient` over a Unix domain socket or an in-memory pipe
+    pub fn set_rpc_client(&mut self, rpc: RpcClient) {
+        rpc.set_callback_dispatcher(self.callbacks.clone());
+        self.rpc = Some(rpc.into());
+    }
+
+    /// Returns the connection for the next call
+    fn rpc(&self) -> Option<&RpcClient> {
+        self.rpc.as_ref().map(|pool| pool.pick())
+    }
+
+    /// Returns how often and how long calls waited for flow control
+    /// capacity, summed over all connections
+    pub fn throttle_stats(&self) -> Option<rpc::ThrottleStats> {
+        self.rpc.as_ref().map(|pool| pool.throttle_stats())
+    }
+
     fn new_rpc_header(&self, proc: u32) -> rpc::CallHeader {
         rpc::CallHeader {
             prog: nfs4::PROG_NFS,
             vers: 4,
             proc,
-            cred: rpc::OpaqueAuth::new_sys(1, Bytes::from_static(b"blah"), 0, 0, Vec::new()),
+            cred: self.credentials.credential(),
             verf: rpc::OpaqueAuth::new_none(),
         }
     }
 
-    /// Constructs a new buffer with placeholder for the RPC frag marker
-    fn new_buf(&self) -> BytesMut {
-        let mut buf = BytesMut::new();
-        buf.pack_uint(0); // placeholder for frag
-
-        buf
-    }
-
+    /// Constructs a new buffer starting with the call header for `proc`
     fn new_buf_with_call_header(&self, xid: u32, proc: u32) -> BytesMut {
-        let mut buf = self.new_buf();
+        let mut buf = BytesMut::new();
         buf.pack_uint(xid);
         self.new_rpc_header(proc).pack_to(&mut buf);
 
         buf
     }
 
-    /// Cosumes `buf` and updates frag size, returns frozen buffer
-    fn finalize(mut buf: BytesMut) -> Bytes {
-        let frag_size = (buf.remaining() - 4) as u32;
-        let frag_size = frag_size | 0x80000000;
-        {
-            let borrow: &mut [u8] = buf.borrow_mut();
-            (&mut borrow[0..4]).pack_uint(frag_size);
-        }
-
-        buf.freeze()
-    }
-
     /// Make a NULL RPC call
     pub async fn null_call(&self) -> Result<Bytes> {
         let xid = RpcClient::next_xid();
         let buf = self.new_buf_with_call_header(xid, nfs4::PROC_NULL);
-        if let Some(rpc) = &self.rpc {
-            let buf = Self::finalize(buf);
+        if let Some(rpc) = self.rpc() {
             Ok(rpc.call(buf, xid).await?)
         } else {
             Err(NOT_CONNECTED.into())
@@ -132,7 +273,7 @@ impl NfsClient {
     pub async fn exchange_id_call(&self) -> Result<()> {
         let xid = RpcClient::next_xid();
         let mut buf = self.new_buf_with_call_header(xid, nfs4::PROC_COMPOUND);
-        if let Some(rpc) = &self.rpc {
+        if let Some(rpc) = self.rpc() {
             let mut compound = nfs4::ops::Compound::new();
             compound
                 .arg_array
@@ -150,7 +291,6 @@ impl NfsClient {
 
             compound.pack_to(&mut buf);
 
-            let buf = Self::finalize(buf);
             let mut response_buf = rpc.call(buf, xid).await?;
             rpc.check_header(&mut response_buf)?;
             let resp = nfs4::ops::CompoundResult::unpack_from(&mut response_buf)?;
@@ -171,11 +311,12 @@ impl NfsClient {
         }
     }
 
-    /// Make a CREATE_SESSION call and process the result
+    /// Make a CREATE_SESSION call and process the result, replies
+    /// are then limited to the negotiated `max_response_size`
     pub async fn create_session_call(&self) -> Result<()> {
         let xid = RpcClient::next_xid();
         let mut buf = self.new_buf_with_call_header(xid, nfs4::PROC_COMPOUND);
-        if let Some(rpc) = &self.rpc {
+        if let Some(rpc) = self.rpc() {
             let mut compound = nfs4::ops::Compound::new();
             compound.arg_array.push(nfs4::ops::ArgOp4::CreateSession(
                 nfs4::ops::CreateSession4Args {
@@ -207,7 +348,6 @@ impl NfsClient {
 
             compound.pack_to(&mut buf);
 
-            let buf = Self::finalize(buf);
             let mut response_buf = rpc.call(buf, xid).await?;
             rpc.check_header(&mut response_buf)?;
             let resp = nfs4::ops::CompoundResult::unpack_from(&mut response_buf)?;
@@ -218,6 +358,12 @@ impl NfsClient {
                 let reply = reply.as_ref()?;
 
                 self.session_id.set(reply.session_id);
+                if let Some(pool) = &self.rpc {
+                    pool.set_max_packet_size(reply.fore_chan_attrs.max_response_size);
+                    if pool.clients().len() > 1 {
+                        self.bind_connections(pool).await?;
+                    }
+                }
 
                 Ok(())
             } else {
@@ -228,6 +374,48 @@ impl NfsClient {
         }
     }
 
+    /// Binds every connection in `pool` to the session with
+    /// BIND_CONN_TO_SESSION, including the backchannel when a callback
+    /// dispatcher is registered
+    async fn bind_connections(&self, pool: &RpcPool) -> Result<()> {
+        let dir = if self.callbacks.is_some() {
+            nfs4::ops::CDFC4_FORE_OR_BOTH
+        } else {
+            nfs4::ops::CDFC4_FORE
+        };
+
+        for rpc in pool.clients() {
+            let xid = RpcClient::next_xid();
+            let mut buf = self.new_buf_with_call_header(xid, nfs4::PROC_COMPOUND);
+            let mut compound = nfs4::ops::Compound::new();
+            compound
+                .arg_array
+                .push(nfs4::ops::ArgOp4::BindConnToSession(
+                    nfs4::ops::BindConnToSession4Args {
+                        session_id: self.session_id.get(),
+                        dir,
+                        use_conn_in_rdma_mode: false,
+                    },
+                ));
+            compound.pack_to(&mut buf);
+
+            let mut response_buf = rpc.call(buf, xid).await?;
+            rpc.check_header(&mut response_buf)?;
+            let resp = nfs4::ops::CompoundResult::unpack_from(&mut response_buf)?;
+            if resp.status != nfs4::NFS4_OK {
+                return Err(resp.status.into());
+            }
+            match resp.result_array.first() {
+                Some(nfs4::ops::ResultOp4::BindConnToSession(reply)) => {
+                    reply.as_ref()?;
+                }
+                _ => return Err(INVALID_DATA.into()),
+            }
+        }
+
+        Ok(())
+    }
+
     fn new_sequence_op(&self, sequence: &ClientSequence, cache_this: bool) -> nfs4::ops::ArgOp4 {
         nfs4::ops::ArgOp4::Sequence(nfs4::ops::Sequence4Args {
             session_id: self.session_id.get(),
@@ -243,7 +431,7 @@ impl NfsClient {
         let xid = RpcClient::next_xid();
         let mut buf = self.new_buf_with_call_header(xid, nfs4::PROC_COMPOUND);
 
-        if let Some(rpc) = &self.rpc {
+        if let Some(rpc) = self.rpc() {
             let mut compound = nfs4::ops::Compound::new();
             let sequence = self.seq.get_seq().await;
             compound
@@ -255,7 +443,6 @@ impl NfsClient {
 
             compound.pack_to(&mut buf);
 
-            let buf = Self::finalize(buf);
             let mut response_buf = rpc.call(buf, xid).await?;
             rpc.check_header(&mut response_buf)?;
             let resp = nfs4::ops::CompoundResult::unpack_from(&mut response_buf)?;
@@ -282,7 +469,7 @@ impl NfsClient {
         let xid = RpcClient::next_xid();
         let mut buf = self.new_buf_with_call_header(xid, nfs4::PROC_COMPOUND);
 
-        if let Some(rpc) = &self.rpc {
+        if let Some(rpc) = self.rpc() {
             let mut compound = nfs4::ops::Compound::new();
             let sequence = self.seq.get_seq().await;
             compound
@@ -293,7 +480,6 @@ impl NfsClient {
 
             compound.pack_to(&mut buf);
 
-            let buf = Self::finalize(buf);
             let mut response_buf = rpc.call(buf, xid).await?;
             rpc.check_header(&mut response_buf)?;
             let resp = nfs4::ops::CompoundResult::unpack_from(&mut response_buf)?;
@@ -318,7 +504,7 @@ impl NfsClient {
         let xid = RpcClient::next_xid();
         let mut buf = self.new_buf_with_call_header(xid, nfs4::PROC_COMPOUND);
 
-        if let Some(rpc) = &self.rpc {
+        if let Some(rpc) = self.rpc() {
             let mut compound = nfs4::ops::Compound::new();
             let sequence = self.seq.get_seq().await;
             compound
@@ -338,7 +524,6 @@ impl NfsClient {
 
             compound.pack_to(&mut buf);
 
-            let buf = Self::finalize(buf);
             let mut response_buf = rpc.call(buf, xid).await?;
             rpc.check_header(&mut response_buf)?;
             let resp = nfs4::ops::CompoundResult::unpack_from(&mut response_buf)?;
@@ -363,7 +548,7 @@ impl NfsClient {
         let xid = RpcClient::next_xid();
         let mut buf = self.new_buf_with_call_header(xid, nfs4::PROC_COMPOUND);
 
-        if let Some(rpc) = &self.rpc {
+        if let Some(rpc) = self.rpc() {
             let mut compound = nfs4::ops::Compound::new();
             let mut attributes = nfs4::attr::FileAttributes::new();
             attributes.mode = Some(0o775);
@@ -387,7 +572,6 @@ impl NfsClient {
 
             compound.pack_to(&mut buf);
 
-            let buf = Self::finalize(buf);
             let mut response_buf = rpc.call(buf, xid).await?;
             rpc.check_header(&mut response_buf)?;
             let resp = nfs4::ops::CompoundResult::unpack_from(&mut response_buf)?;
@@ -412,7 +596,7 @@ impl NfsClient {
         let xid = RpcClient::next_xid();
         let mut buf = self.new_buf_with_call_header(xid, nfs4::PROC_COMPOUND);
 
-        if let Some(rpc) = &self.rpc {
+        if let Some(rpc) = self.rpc() {
             let mut compound = nfs4::ops::Compound::new();
             let sequence = self.seq.get_seq().await;
             compound
@@ -431,7 +615,6 @@ impl NfsClient {
 
             compound.pack_to(&mut buf);
 
-            let buf = Self::finalize(buf);
             let mut response_buf = rpc.call(buf, xid).await?;
             rpc.check_header(&mut response_buf)?;
             let resp = nfs4::ops::CompoundResult::unpack_from(&mut response_buf)?;
@@ -465,7 +648,7 @@ impl NfsClient {
         attr_request.set(attr::MODE);
         attr_request.set(attr::OWNER);
 
-        if let Some(rpc) = &self.rpc {
+        if let Some(rpc) = self.rpc() {
             let mut compound = nfs4::ops::Compound::new();
             let sequence = self.seq.get_seq().await;
             compound
@@ -488,7 +671,6 @@ impl NfsClient {
 
             compound.pack_to(&mut buf);
 
-            let buf = Self::finalize(buf);
             let mut response_buf = rpc.call(buf, xid).await?;
             rpc.check_header(&mut response_buf)?;
             let mut resp = nfs4::ops::CompoundResult::unpack_from(&mut response_buf)?;
@@ -518,7 +700,7 @@ impl NfsClient {
         let xid = RpcClient::next_xid();
         let mut buf = self.new_buf_with_call_header(xid, nfs4::PROC_COMPOUND);
 
-        if let Some(rpc) = &self.rpc {
+        if let Some(rpc) = self.rpc() {
             let mut compound = nfs4::ops::Compound::new();
             let sequence = self.seq.get_seq().await;
             compound
@@ -545,7 +727,6 @@ impl NfsClient {
 
             compound.pack_to(&mut buf);
 
-            let buf = Self::finalize(buf);
             let mut response_buf = rpc.call(buf, xid).await?;
             rpc.check_header(&mut response_buf)?;
             let mut resp = nfs4::ops::CompoundResult::unpack_from(&mut response_buf)?;
@@ -574,7 +755,7 @@ impl NfsClient {
         let xid = RpcClient::next_xid();
         let mut buf = self.new_buf_with_call_header(xid, nfs4::PROC_COMPOUND);
 
-        if let Some(rpc) = &self.rpc {
+        if let Some(rpc) = self.rpc() {
             let mut compound = nfs4::ops::Compound::new();
             let sequence = self.seq.get_seq().await;
             compound
@@ -595,8 +776,9 @@ impl NfsClient {
 
             compound.pack_to(&mut buf);
 
-            let buf = Self::finalize(buf);
-            let mut response_buf = rpc.call(buf, xid).await?;
+            let mut response_buf = rpc
+                .call_sized(buf, xid, count.saturating_add(REPLY_OVERHEAD))
+                .await?;
             rpc.check_header(&mut response_buf)?;
             let mut resp = nfs4::ops::CompoundResult::unpack_from(&mut response_buf)?;
             if resp.status != nfs4::NFS4_OK {
@@ -616,7 +798,7 @@ impl NfsClient {
     /// Returns the root FH memory or from server.
     pub async fn get_root(&self) -> Result<NfsFh4> {
         let root = self.get_root_fh();
-        if root.len() > 0 {
+        if !root.is_empty() {
             Ok(root)
         } else {
             Ok(self.send_putrootfh().await?)
@@ -2,6 +2,7 @@
 pub mod client;
 pub mod ops;
 pub mod sequence;
+pub mod server;
 pub const PROG_NFS: u32 = 100003;
 pub mod attr;
 
@@ -9,3 +10,39 @@ pub const PROC_NULL: u32 = 0;
 pub const PROC_COMPOUND: u32 = 1;
 
 pub const NFS4_OK: u32 = 0;
+pub const NFS4ERR_PERM: u32 = 1;
+pub const NFS4ERR_NOENT: u32 = 2;
+pub const NFS4ERR_IO: u32 = 5;
+pub const NFS4ERR_ACCESS: u32 = 13;
+pub const NFS4ERR_EXIST: u32 = 17;
+pub const NFS4ERR_NOTDIR: u32 = 20;
+pub const NFS4ERR_ISDIR: u32 = 21;
+pub const NFS4ERR_INVAL: u32 = 22;
+pub const NFS4ERR_NAMETOOLONG: u32 = 63;
+pub const NFS4ERR_NOTEMPTY: u32 = 66;
+pub const NFS4ERR_STALE: u32 = 70;
+pub const NFS4ERR_BADHANDLE: u32 = 10001;
+pub const NFS4ERR_BAD_COOKIE: u32 = 10003;
+pub const NFS4ERR_NOTSUPP: u32 = 10004;
+pub const NFS4ERR_TOOSMALL: u32 = 10005;
+pub const NFS4ERR_SERVERFAULT: u32 = 10006;
+pub const NFS4ERR_DELAY: u32 = 10008;
+pub const NFS4ERR_NOFILEHANDLE: u32 = 10020;
+pub const NFS4ERR_MINOR_VERS_MISMATCH: u32 = 10021;
+pub const NFS4ERR_STALE_CLIENTID: u32 = 10022;
+pub const NFS4ERR_BAD_STATEID: u32 = 10025;
+pub const NFS4ERR_SYMLINK: u32 = 10029;
+pub const NFS4ERR_BADOWNER: u32 = 10039;
+pub const NFS4ERR_BADNAME: u32 = 10041;
+pub const NFS4ERR_OP_ILLEGAL: u32 = 10044;
+pub const NFS4ERR_BADSESSION: u32 = 10052;
+pub const NFS4ERR_BADSLOT: u32 = 10053;
+pub const NFS4ERR_SEQ_MISORDERED: u32 = 10063;
+pub const NFS4ERR_SEQUENCE_POS: u32 = 10064;
+pub const NFS4ERR_RETRY_UNCACHED_REP: u32 = 10068;
+pub const NFS4ERR_TOO_MANY_OPS: u32 = 10070;
+pub const NFS4ERR_OP_NOT_IN_SESSION: u32 = 10071;
+pub const NFS4ERR_WRONG_TYPE: u32 = 10083;
+
+/// Largest file handle
+pub const NFS4_FHSIZE: usize = 128;
@@ -0,0 +1,29 @@
+use super::SessionId4;
+use pinfish_macros::{PackTo, UnpackFrom};
+use crate::xdr;
+
+/// channel_dir_from_client4
+pub const CDFC4_FORE: u32 = 0x1;
+pub const CDFC4_BACK: u32 = 0x2;
+pub const CDFC4_FORE_OR_BOTH: u32 = 0x3;
+pub const CDFC4_BACK_OR_BOTH: u32 = 0x7;
+
+/// channel_dir_from_server4
+pub const CDFS4_FORE: u32 = 0x1;
+pub const CDFS4_BACK: u32 = 0x2;
+pub const CDFS4_BOTH: u32 = 0x3;
+
+/// 18.34 -- BIND_CONN_TO_SESSION4args
+#[derive(PackTo, UnpackFrom, Debug)]
+pub struct BindConnToSession4Args {
+    pub session_id: SessionId4,
+    pub dir: u32,
+    pub use_conn_in_rdma_mode: bool,
+}
+
+#[derive(PackTo, UnpackFrom, Debug)]
+pub struct BindConnToSession4ResOk {
+    pub session_id: SessionId4,
+    pub dir: u32,
+    pub use_conn_in_rdma_mode: bool,
+}
@@ -33,7 +33,7 @@ pub struct Create4Args {
 
 #[derive(PackTo, UnpackFrom, Debug)]
 pub struct Create4ResOk {
-    change_info: ChangeInfo4,
-    attr_set: Bitmap4,
+    pub change_info: ChangeInfo4,
+    pub attr_set: Bitmap4,
 }
 
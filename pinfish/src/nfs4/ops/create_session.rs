@@ -21,7 +21,7 @@ pub struct CreateSession4Args {
     pub sec_params: Vec<CallbackSecParams4>,
 }
 
-#[derive(PackTo, UnpackFrom, Debug)]
+#[derive(PackTo, UnpackFrom, Debug, Clone)]
 pub struct CreateSession4ResOk {
     pub session_id: SessionId4,
     pub sequence: SequenceId4,
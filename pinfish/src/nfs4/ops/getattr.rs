@@ -0,0 +1,14 @@
+use super::{Bitmap4, FileAttributes};
+use pinfish_macros::{PackTo, UnpackFrom};
+use crate::xdr;
+
+/// GETATTR of the current FH
+#[derive(PackTo, UnpackFrom, Debug)]
+pub struct GetAttr4Args {
+    pub attr_request: Bitmap4,
+}
+
+#[derive(PackTo, UnpackFrom, Debug)]
+pub struct GetAttr4ResOk {
+    pub obj_attributes: FileAttributes,
+}
@@ -1,7 +1,7 @@
 /// NFS4 Operations
 use crate::{
     result::Result,
-    xdr::{self, UnpackFrom, Unpacker, VecPackUnpack},
+    xdr::{self, PackTo, Packer, UnpackFrom, Unpacker, VecPackUnpack},
 };
 use pinfish_macros::{PackTo, UnpackFrom, VecPackUnpack};
 
@@ -15,6 +15,7 @@ const OP_COMMIT: u32 = 5;
 const OP_CREATE: u32 = 6;
 const OP_DELEGPURGE: u32 = 7;
 const OP_DELEGRETURN: u32 = 8;
+const OP_GETATTR: u32 = 9;
 const OP_GETFH: u32 = 10;
 const OP_LOOKUP: u32 = 15;
 const OP_OPEN: u32 = 18;
@@ -23,15 +24,17 @@ const OP_READ: u32 = 25;
 const OP_READDIR: u32 = 26;
 const OP_REMOVE: u32 = 28;
 const OP_PUTROOTFH: u32 = 24;
+const OP_WRITE: u32 = 38;
+const OP_BIND_CONN_TO_SESSION: u32 = 41;
 const OP_EXCHANGE_ID: u32 = 42;
 const OP_CREATE_SESSION: u32 = 43;
 const OP_SEQUENCE: u32 = 53;
 const OP_RECLAIM_COMPLETE: u32 = 58;
 const OP_ILLEGAL: u32 = 10044;
 
-const NFS4_SESSION_ID_SIZE: usize = 16;
+pub const NFS4_SESSION_ID_SIZE: usize = 16;
 // const NFS4_VERIFIER_SIZE: usize = 8;
-const NFS4_OTHER_SIZE: usize = 12;
+pub const NFS4_OTHER_SIZE: usize = 12;
 
 pub const EXCHGID4_FLAG_SUPP_MOVED_REFER: u32 = 0x00000001;
 pub const EXCHGID4_FLAG_SUPP_MOVED_MIGR: u32 = 0x00000002;
@@ -56,6 +59,9 @@ pub const OPEN4_SHARE_DENY_READ: u32 = 0x00000001;
 pub const OPEN4_SHARE_DENY_WRITE: u32 = 0x00000002;
 pub const OPEN4_SHARE_DENY_BOTH: u32 = 0x00000003;
 
+pub const OPEN4_RESULT_CONFIRM: u32 = 0x00000002;
+pub const OPEN4_RESULT_LOCKTYPE_POSIX: u32 = 0x00000004;
+
 // --------------
 
 #[derive(PackTo, UnpackFrom, Debug, VecPackUnpack)]
@@ -78,6 +84,9 @@ pub enum ArgOp4 {
     #[xdr(OP_DELEGRETURN)] // 8
     DelegReturn(DelegReturn4Args),
 
+    #[xdr(OP_GETATTR)] // 9
+    GetAttr(GetAttr4Args),
+
     #[xdr(OP_GETFH)] // 10
     GetFh,
 
@@ -102,6 +111,12 @@ pub enum ArgOp4 {
     #[xdr(OP_REMOVE)] // 28
     Remove(Remove4Args),
 
+    #[xdr(OP_WRITE)] // 38
+    Write(Write4Args),
+
+    #[xdr(OP_BIND_CONN_TO_SESSION)] // 41
+    BindConnToSession(BindConnToSession4Args),
+
     #[xdr(OP_EXCHANGE_ID)] // 42
     ExchangeId(ExchangeId4Args),
 
@@ -119,7 +134,7 @@ pub enum ArgOp4 {
 }
 
 /// NFS4 COMPOUND args.
-#[derive(PackTo, Debug)]
+#[derive(PackTo, UnpackFrom, Debug)]
 pub struct Compound {
     pub tag: String,
     pub minor_version: u32,
@@ -137,7 +152,13 @@ impl Compound {
     }
 }
 
-#[derive(UnpackFrom, Debug, VecPackUnpack)]
+impl Default for Compound {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(PackTo, UnpackFrom, Debug, VecPackUnpack)]
 pub enum ResultOp4 {
     #[xdr(OP_ACCESS)] // 3
     Access(core::result::Result<Access4ResOk, u32>),
@@ -157,6 +178,9 @@ pub enum ResultOp4 {
     #[xdr(OP_DELEGRETURN)] // 8
     DelegReturn(core::result::Result<(), u32>),
 
+    #[xdr(OP_GETATTR)] // 9
+    GetAttr(core::result::Result<GetAttr4ResOk, u32>),
+
     #[xdr(OP_GETFH)] // 10
     GetFh(core::result::Result<GetFh4ResOk, u32>),
 
@@ -181,6 +205,12 @@ pub enum ResultOp4 {
     #[xdr(OP_REMOVE)] // 28
     Remove(core::result::Result<Remove4ResOk, u32>),
 
+    #[xdr(OP_WRITE)] // 38
+    Write(core::result::Result<Write4ResOk, u32>),
+
+    #[xdr(OP_BIND_CONN_TO_SESSION)] // 41
+    BindConnToSession(core::result::Result<BindConnToSession4ResOk, u32>),
+
     #[xdr(OP_EXCHANGE_ID)] // 42
     ExchangeId(core::result::Result<ExchangeId4ResOk, u32>),
 
@@ -198,7 +228,7 @@ pub enum ResultOp4 {
 }
 
 /// NFS4 COMPOUND result.
-#[derive(UnpackFrom, Debug)]
+#[derive(PackTo, UnpackFrom, Debug)]
 pub struct CompoundResult {
     pub status: u32,
     pub tag: String,
@@ -217,7 +247,25 @@ impl<T: core::fmt::Debug + UnpackFrom<B>, B: Unpacker> UnpackFrom<B>
     }
 }
 
-pub_use!(exchange_id, lookup, sequence, create_session);
+impl<T: PackTo<B>, B: Packer> PackTo<B> for core::result::Result<T, u32> {
+    fn pack_to(&self, buf: &mut B) {
+        match self {
+            Ok(res) => {
+                buf.pack_uint(0);
+                res.pack_to(buf);
+            }
+            Err(status) => buf.pack_uint(*status),
+        }
+    }
+}
+
+pub_use!(
+    exchange_id,
+    lookup,
+    sequence,
+    create_session,
+    bind_conn_to_session
+);
 pub_use!(
     create,
     remove,
@@ -228,4 +276,4 @@ pub_use!(
     deleg_purge,
     deleg_return
 );
-pub_use!(reclaim_complete, getfh, readdir, open, read);
+pub_use!(reclaim_complete, getfh, getattr, readdir, open, read, write);
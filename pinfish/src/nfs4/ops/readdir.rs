@@ -36,7 +36,7 @@ impl<'a> Iterator for Entry4Iter<'a> {
 
     fn next(&mut self) -> Option<Self::Item> {
         if let Some(entry) = self.next {
-            self.next = entry.next_entry.as_ref().map(|e| &**e);
+            self.next = entry.next_entry.as_deref();
             Some(entry)
         } else {
             None
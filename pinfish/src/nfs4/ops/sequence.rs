@@ -4,7 +4,7 @@ use crate::xdr;
 
 
 /// channel_attrs4
-#[derive(PackTo, UnpackFrom, Debug)]
+#[derive(PackTo, UnpackFrom, Debug, Clone)]
 pub struct ChannelAttrs4 {
     pub header_pad_size: Count4,
     pub max_request_size: Count4,
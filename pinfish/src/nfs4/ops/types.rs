@@ -50,15 +50,15 @@ pub enum CallbackSecParams4 {
 /// device numbers for block/char special devices
 #[derive(PackTo, UnpackFrom, Debug)]
 pub struct SpecData4 {
-    major: u32,
-    minor: u32,
+    pub major: u32,
+    pub minor: u32,
 }
 
 #[derive(PackTo, UnpackFrom, Debug, Clone)]
 pub struct ChangeInfo4 {
-    atomic: bool,
-    before: ChangeId4,
-    after: ChangeId4,
+    pub atomic: bool,
+    pub before: ChangeId4,
+    pub after: ChangeId4,
 }
 
 #[derive(PackTo, UnpackFrom, Debug)]
@@ -67,10 +67,10 @@ pub struct OpenOwner4 {
     pub owner: bytes::Bytes,
 }
 
-#[derive(PackTo, UnpackFrom, Debug, Clone)]
+#[derive(PackTo, UnpackFrom, Debug, Clone, PartialEq, Eq)]
 pub struct StateId4 {
-    sequence_id: u32,
-    other: [u8; NFS4_OTHER_SIZE],
+    pub sequence_id: u32,
+    pub other: [u8; NFS4_OTHER_SIZE],
 }
 
 #[derive(PackTo, UnpackFrom, Debug, Clone)]
@@ -0,0 +1,25 @@
+use super::{StateId4, Verifier4};
+use pinfish_macros::{PackTo, UnpackFrom};
+use crate::xdr;
+
+#[derive(PackTo, UnpackFrom, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum StableHow4 {
+    Unstable,
+    DataSync,
+    FileSync,
+}
+
+#[derive(PackTo, UnpackFrom, Debug)]
+pub struct Write4Args {
+    pub state_id: StateId4,
+    pub offset: u64,
+    pub stable: StableHow4,
+    pub data: bytes::Bytes,
+}
+
+#[derive(PackTo, UnpackFrom, Debug, Clone)]
+pub struct Write4ResOk {
+    pub count: u32,
+    pub committed: StableHow4,
+    pub verifier: Verifier4,
+}
@@ -102,7 +102,7 @@ impl ClientSequencer {
         ClientSequencer {
             sem: Semaphore::new(size),
             inner: Mutex::new(ClientSequencerInner {
-                busy: vec![0; (size + 63) / 64],
+                busy: vec![0; size.div_ceil(64)],
                 sequences: vec![0; size],
             }),
         }
@@ -134,7 +134,7 @@ impl ClientSequencer {
 
         ClientSequence {
             info: SequenceInfo { slot, sequence },
-            owner: &self,
+            owner: self,
             _permit: permit,
         }
     }
@@ -0,0 +1,1284 @@
+//! NFSv4.1 server.
+//!
+//! `Nfs4Server` runs the COMPOUND operations defined in `ops` against
+//! an `Nfs3Backend`, so the backends written for `nfs3::server` can be
+//! served over NFSv4.1 as well.  Client IDs, sessions with a reply
+//! cache per slot and open stateids are kept in memory.  Delegations,
+//! locks, callbacks and state recovery are not supported.
+//!
+//! Client IDs, sessions and opens never expire, there are no leases.
+//! DESTROY_SESSION and DESTROY_CLIENTID are not implemented, state is
+//! only dropped when a client reboots and calls EXCHANGE_ID with a new
+//! verifier.
+use crate::{
+    nfs3::{
+        self,
+        backend::Nfs3Backend,
+        procs::{CreateHow3, DeviceData3, MknodData3, StableHow},
+        server::{check_name as check_nfs3_name, status, ReadLimit, NOBODY},
+        FileType3, NfsFh3, SetAttributes, SpecData3, TimeHow, ACCESS3_DELETE, ACCESS3_EXECUTE,
+        ACCESS3_EXTEND, ACCESS3_LOOKUP, ACCESS3_MODIFY, ACCESS3_READ,
+    },
+    nfs4::{
+        attr::{self, Bitmap4, FileAttributes, NfsType4},
+        ops::*,
+        *,
+    },
+    result::{Result, NFS4ERR_COMPLETE_ALREADY, RPC_PROC_UNAVAIL},
+    rpc::{
+        auth::Identity,
+        server::{Call, DispatchFuture, Dispatcher, RpcServer},
+        DEFAULT_MAX_PACKET_SIZE,
+    },
+    xdr::{PackTo, UnpackFrom},
+};
+use bytes::{Bytes, BytesMut};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// NFS protocol version served
+pub const VERSION: u32 = 4;
+
+/// NFSv4 minor version served
+pub const MINOR_VERSION: u32 = 1;
+
+/// Most slots granted to a session
+const MAX_SLOTS: u32 = 64;
+
+/// Most operations in a COMPOUND
+const MAX_OPERATIONS: u32 = 16;
+
+/// fh_expire_type of handles valid for the lifetime of the object
+const FH4_PERSISTENT: u32 = 0;
+
+const ACCESS_ALL: u32 = ACCESS3_READ
+    | ACCESS3_LOOKUP
+    | ACCESS3_MODIFY
+    | ACCESS3_EXTEND
+    | ACCESS3_DELETE
+    | ACCESS3_EXECUTE;
+
+type OpResult<T> = core::result::Result<T, u32>;
+
+/// Attributes returned by GETATTR and READDIR
+fn supported_attributes() -> Bitmap4 {
+    let mut supported = Bitmap4::new();
+    for attribute in [
+        attr::SUPPORTED_ATTRS,
+        attr::TYPE,
+        attr::FH_EXPIRE_TYPE,
+        attr::CHANGE,
+        attr::SIZE,
+        attr::MODE,
+        attr::OWNER,
+        attr::OWNER_GROUP,
+    ] {
+        supported.set(attribute);
+    }
+
+    supported
+}
+
+/// Change attribute derived from ctime, which changes with every
+/// modification
+fn change_id(attributes: &nfs3::FileAttributes) -> ChangeId4 {
+    ((attributes.ctime.seconds as u64) << 32) | attributes.ctime.nano_seconds as u64
+}
+
+fn nfs_type(file_type: FileType3) -> NfsType4 {
+    match file_type {
+        FileType3::Reg => NfsType4::Reg,
+        FileType3::Dir => NfsType4::Dir,
+        FileType3::Blk => NfsType4::Blk,
+        FileType3::Chr => NfsType4::Chr,
+        FileType3::Lnk => NfsType4::Lnk,
+        FileType3::Sock => NfsType4::Sock,
+        FileType3::Fifo => NfsType4::Fifo,
+    }
+}
+
+/// Returns the attributes in `request` from backend `attributes`
+fn encode_attributes(attributes: &nfs3::FileAttributes, request: &Bitmap4) -> FileAttributes {
+    let mut encoded = FileAttributes::new();
+    if request.is_set(attr::SUPPORTED_ATTRS) {
+        encoded.supported_attrs = Some(supported_attributes());
+    }
+    if request.is_set(attr::TYPE) {
+        encoded.obj_type = Some(nfs_type(attributes.file_type));
+    }
+    if request.is_set(attr::FH_EXPIRE_TYPE) {
+        encoded.fh_expire_type = Some(FH4_PERSISTENT);
+    }
+    if request.is_set(attr::CHANGE) {
+        encoded.change = Some(change_id(attributes));
+    }
+    if request.is_set(attr::SIZE) {
+        encoded.size = Some(attributes.size);
+    }
+    if request.is_set(attr::MODE) {
+        encoded.mode = Some(attributes.mode & 0o7777);
+    }
+    if request.is_set(attr::OWNER) {
+        encoded.owner = Some(attributes.uid.to_string());
+    }
+    if request.is_set(attr::OWNER_GROUP) {
+        encoded.owner_group = Some(attributes.gid.to_string());
+    }
+
+    encoded
+}
+
+/// Converts the attributes of CREATE and OPEN, returns them with the
+/// bitmap of the attributes set.  Owners are numeric ids.
+fn decode_attributes(attributes: &FileAttributes) -> OpResult<(SetAttributes, Bitmap4)> {
+    let id = |name: &String| name.parse::<u32>().map_err(|_| NFS4ERR_BADOWNER);
+    let decoded = SetAttributes {
+        mode: attributes.mode,
+        uid: attributes.owner.as_ref().map(id).transpose()?,
+        gid: attributes.owner_group.as_ref().map(id).transpose()?,
+        size: attributes.size,
+        atime: TimeHow::DontChange,
+        mtime: TimeHow::DontChange,
+    };
+    let attr_set = attributes.calculate_bitmap();
+    for read_only in [
+        attr::SUPPORTED_ATTRS,
+        attr::TYPE,
+        attr::FH_EXPIRE_TYPE,
+        attr::CHANGE,
+    ] {
+        if attr_set.is_set(read_only) {
+            return Err(NFS4ERR_INVAL);
+        }
+    }
+
+    Ok((decoded, attr_set))
+}
+
+/// Checks a component name like NFSv3, except that "." and ".." and
+/// names with a '/' or NUL are bad names
+fn check_name(name: &str) -> OpResult<()> {
+    if name == "." || name == ".." || name.contains(['/', '\0']) {
+        Err(NFS4ERR_BADNAME)
+    } else {
+        check_nfs3_name(name).map_err(status)
+    }
+}
+
+/// Whether `state_id` is the anonymous stateid, or the current stateid
+/// which is treated the same since it is not tracked
+fn is_special(state_id: &StateId4) -> bool {
+    state_id.other == [0; NFS4_OTHER_SIZE]
+}
+
+fn stable_how(stable: StableHow4) -> StableHow {
+    match stable {
+        StableHow4::Unstable => StableHow::Unstable,
+        StableHow4::DataSync => StableHow::DataSync,
+        StableHow4::FileSync => StableHow::FileSync,
+    }
+}
+
+fn stable_how4(stable: StableHow) -> StableHow4 {
+    match stable {
+        StableHow::Unstable => StableHow4::Unstable,
+        StableHow::DataSync => StableHow4::DataSync,
+        StableHow::FileSync => StableHow4::FileSync,
+    }
+}
+
+/// Result of `op` failing with `status` without being run
+fn failed(op: &ArgOp4, status: u32) -> ResultOp4 {
+    match op {
+        ArgOp4::Access(_) => ResultOp4::Access(Err(status)),
+        ArgOp4::Close(_) => ResultOp4::Close(Err(status)),
+        ArgOp4::Commit(_) => ResultOp4::Commit(Err(status)),
+        ArgOp4::Create(_) => ResultOp4::Create(Err(status)),
+        ArgOp4::DelegPurge(_) => ResultOp4::DelegPurge(Err(status)),
+        ArgOp4::DelegReturn(_) => ResultOp4::DelegReturn(Err(status)),
+        ArgOp4::GetAttr(_) => ResultOp4::GetAttr(Err(status)),
+        ArgOp4::GetFh => ResultOp4::GetFh(Err(status)),
+        ArgOp4::Lookup(_) => ResultOp4::Lookup(Err(status)),
+        ArgOp4::Open(_) => ResultOp4::Open(Err(status)),
+        ArgOp4::PutFh(_) => ResultOp4::PutFh(Err(status)),
+        ArgOp4::PutRootFh => ResultOp4::PutRootFh(Err(status)),
+        ArgOp4::Read(_) => ResultOp4::Read(Err(status)),
+        ArgOp4::ReadDir(_) => ResultOp4::ReadDir(Err(status)),
+        ArgOp4::Remove(_) => ResultOp4::Remove(Err(status)),
+        ArgOp4::Write(_) => ResultOp4::Write(Err(status)),
+        ArgOp4::BindConnToSession(_) => ResultOp4::BindConnToSession(Err(status)),
+        ArgOp4::ExchangeId(_) => ResultOp4::ExchangeId(Err(status)),
+        ArgOp4::CreateSession(_) => ResultOp4::CreateSession(Err(status)),
+        ArgOp4::Sequence(_) => ResultOp4::Sequence(Err(status)),
+        ArgOp4::ReclaimComplete(_) => ResultOp4::ReclaimComplete(Err(status)),
+        ArgOp4::Illegal => ResultOp4::Illegal(Err(status)),
+    }
+}
+
+struct Client {
+    owner: Vec<u8>,
+    verifier: Verifier4,
+    /// Sequence expected by the next CREATE_SESSION
+    sequence_id: SequenceId4,
+    /// Reply to the last CREATE_SESSION, replayed for a retransmission
+    last_session: Option<CreateSession4ResOk>,
+    confirmed: bool,
+    reclaim_complete: bool,
+}
+
+#[derive(Default)]
+struct Slot {
+    sequence_id: SequenceId4,
+    /// Reply to the last request, `None` while it is in progress
+    reply: Option<Bytes>,
+}
+
+struct Session {
+    client_id: ClientId4,
+    slots: Vec<Slot>,
+    max_operations: u32,
+}
+
+#[derive(Default)]
+struct State {
+    next_client_id: ClientId4,
+    clients: HashMap<ClientId4, Client>,
+    next_session: u64,
+    sessions: HashMap<SessionId4, Session>,
+    next_open: u64,
+    /// Files of the open stateids
+    opens: HashMap<[u8; NFS4_OTHER_SIZE], (ClientId4, NfsFh3)>,
+}
+
+impl State {
+    /// Drops the sessions and opens of `client_id`
+    fn expire(&mut self, client_id: ClientId4) {
+        self.clients.remove(&client_id);
+        self.sessions
+            .retain(|_, session| session.client_id != client_id);
+        self.opens.retain(|_, (owner, _)| *owner != client_id);
+    }
+}
+
+/// Progress of one COMPOUND
+struct Request {
+    cred: Identity,
+    current: Option<NfsFh3>,
+    /// Session and slot of the SEQUENCE operation
+    slot: Option<(SessionId4, SlotId4)>,
+}
+
+impl Request {
+    fn current(&self) -> OpResult<NfsFh3> {
+        self.current.clone().ok_or(NFS4ERR_NOFILEHANDLE)
+    }
+}
+
+/// Outcome of SEQUENCE
+enum Sequenced {
+    New(Sequence4ResOk),
+    /// Retransmission of the last request on the slot
+    Replay(Bytes),
+}
+
+/// Serves NFSv4.1 from a backend
+pub struct Nfs4Server {
+    backend: Arc<dyn Nfs3Backend>,
+    root: NfsFh3,
+    state: Mutex<State>,
+    read_limit: ReadLimit,
+}
+
+impl Nfs4Server {
+    /// Constructs a server with `root` as the handle of PUTROOTFH
+    pub fn new(backend: Arc<dyn Nfs3Backend>, root: NfsFh3) -> Nfs4Server {
+        Nfs4Server {
+            backend,
+            root,
+            state: Default::default(),
+            read_limit: Default::default(),
+        }
+    }
+
+    pub fn backend(&self) -> &Arc<dyn Nfs3Backend> {
+        &self.backend
+    }
+
+    /// Registers NFS version 4 with `server`
+    pub fn register_with(self: &Arc<Self>, server: &mut RpcServer) {
+        server.register(PROG_NFS, VERSION, Arc::new(Nfs4Dispatcher(self.clone())));
+    }
+
+    async fn getattr(&self, object: &NfsFh3) -> OpResult<nfs3::FileAttributes> {
+        self.backend.getattr(object.clone()).await.map_err(status)
+    }
+
+    /// Returns the change attribute of `dir`, 0 if it is unknown
+    async fn change(&self, dir: &NfsFh3) -> ChangeId4 {
+        self.getattr(dir)
+            .await
+            .map_or(0, |attributes| change_id(&attributes))
+    }
+
+    fn exchange_id(&self, args: ExchangeId4Args) -> OpResult<ExchangeId4ResOk> {
+        let ClientOwner4 { verifier, owner_id } = args.client_owner;
+        let mut state = self.state.lock().unwrap();
+        let existing = state
+            .clients
+            .iter()
+            .find(|(_, client)| client.owner == owner_id)
+            .map(|(client_id, client)| (*client_id, client.verifier));
+        let client_id = match existing {
+            Some((client_id, existing)) if existing == verifier => client_id,
+            rebooted => {
+                if let Some((client_id, _)) = rebooted {
+                    state.expire(client_id);
+                }
+                state.next_client_id += 1;
+                let client_id = state.next_client_id;
+                let client = Client {
+                    owner: owner_id,
+                    verifier,
+                    sequence_id: 1,
+                    last_session: None,
+                    confirmed: false,
+                    reclaim_complete: false,
+                };
+                state.clients.insert(client_id, client);
+                client_id
+            }
+        };
+
+        let client = &state.clients[&client_id];
+        let mut flags = EXCHGID4_FLAG_USE_NON_PNFS;
+        if client.confirmed {
+            flags |= EXCHGID4_FLAG_CONFIRMED_R;
+        }
+        Ok(ExchangeId4ResOk {
+            client_id,
+            sequence_id: client.sequence_id,
+            flags,
+            state_protect: StateProtect4R::None,
+            server_owner: ServerOwner4 {
+                minor_id: 0,
+                major_id: b"pinfish".to_vec(),
+            },
+            server_scope: b"pinfish".to_vec(),
+            server_impl_id: None,
+        })
+    }
+
+    fn create_session(&self, args: CreateSession4Args) -> OpResult<CreateSession4ResOk> {
+        let mut state = self.state.lock().unwrap();
+        let client = state
+            .clients
+            .get_mut(&args.client_id)
+            .ok_or(NFS4ERR_STALE_CLIENTID)?;
+        // see RFC 8881 section 18.36.4
+        if args.sequence == client.sequence_id.wrapping_sub(1) {
+            if let Some(reply) = &client.last_session {
+                return Ok(reply.clone());
+            }
+        }
+        if args.sequence != client.sequence_id {
+            return Err(NFS4ERR_SEQ_MISORDERED);
+        }
+        client.sequence_id = client.sequence_id.wrapping_add(1);
+        client.confirmed = true;
+
+        let requested = args.fore_chan_attrs;
+        let max_size = DEFAULT_MAX_PACKET_SIZE;
+        let fore_chan_attrs = ChannelAttrs4 {
+            header_pad_size: 0,
+            max_request_size: requested.max_request_size.min(max_size),
+            max_response_size: requested.max_response_size.min(max_size),
+            max_response_size_cached: requested.max_response_size_cached.min(max_size),
+            max_operation: requested.max_operation.clamp(1, MAX_OPERATIONS),
+            max_requests: requested.max_requests.clamp(1, MAX_SLOTS),
+            rdma_ird: None,
+        };
+
+        state.next_session += 1;
+        let mut session_id = [0; NFS4_SESSION_ID_SIZE];
+        session_id[..8].copy_from_slice(&args.client_id.to_be_bytes());
+        session_id[8..].copy_from_slice(&state.next_session.to_be_bytes());
+        let session = Session {
+            client_id: args.client_id,
+            slots: (0..fore_chan_attrs.max_requests)
+                .map(|_| Slot::default())
+                .collect(),
+            max_operations: fore_chan_attrs.max_operation,
+        };
+        state.sessions.insert(session_id, session);
+
+        let reply = CreateSession4ResOk {
+            session_id,
+            sequence: args.sequence,
+            flags: 0,
+            fore_chan_attrs,
+            back_chan_attrs: args.back_chan_attrs,
+        };
+        if let Some(client) = state.clients.get_mut(&args.client_id) {
+            client.last_session = Some(reply.clone());
+        }
+        Ok(reply)
+    }
+
+    fn bind_conn_to_session(
+        &self,
+        args: BindConnToSession4Args,
+    ) -> OpResult<BindConnToSession4ResOk> {
+        let state = self.state.lock().unwrap();
+        if !state.sessions.contains_key(&args.session_id) {
+            return Err(NFS4ERR_BADSESSION);
+        }
+
+        // no backchannel
+        Ok(BindConnToSession4ResOk {
+            session_id: args.session_id,
+            dir: CDFS4_FORE,
+            use_conn_in_rdma_mode: false,
+        })
+    }
+
+    fn sequence(&self, args: &Sequence4Args, operations: usize) -> OpResult<Sequenced> {
+        let mut state = self.state.lock().unwrap();
+        let session = state
+            .sessions
+            .get_mut(&args.session_id)
+            .ok_or(NFS4ERR_BADSESSION)?;
+        let highest_slot_id = session.slots.len() as u32 - 1;
+        let max_operations = session.max_operations;
+        let slot = session
+            .slots
+            .get_mut(args.slot_id as usize)
+            .ok_or(NFS4ERR_BADSLOT)?;
+
+        if args.sequence_id == slot.sequence_id {
+            return match &slot.reply {
+                Some(reply) => Ok(Sequenced::Replay(reply.clone())),
+                None if slot.sequence_id == 0 => Err(NFS4ERR_SEQ_MISORDERED),
+                None => Err(NFS4ERR_DELAY),
+            };
+        }
+        if args.sequence_id != slot.sequence_id.wrapping_add(1) {
+            return Err(NFS4ERR_SEQ_MISORDERED);
+        }
+        if operations > max_operations as usize {
+            return Err(NFS4ERR_TOO_MANY_OPS);
+        }
+        slot.sequence_id = args.sequence_id;
+        slot.reply = None;
+
+        Ok(Sequenced::New(Sequence4ResOk {
+            session_id: args.session_id,
+            sequence_id: args.sequence_id,
+            slot_id: args.slot_id,
+            highest_slot_id,
+            target_highest_slot_id: highest_slot_id,
+            status_flags: 0,
+        }))
+    }
+
+    fn reclaim_complete(&self, request: &Request) -> OpResult<()> {
+        let mut state = self.state.lock().unwrap();
+        let (session_id, _) = request.slot.ok_or(NFS4ERR_OP_NOT_IN_SESSION)?;
+        let client_id = state
+            .sessions
+            .get(&session_id)
+            .ok_or(NFS4ERR_BADSESSION)?
+            .client_id;
+        let client = state
+            .clients
+            .get_mut(&client_id)
+            .ok_or(NFS4ERR_STALE_CLIENTID)?;
+        if client.reclaim_complete {
+            return Err(NFS4ERR_COMPLETE_ALREADY);
+        }
+
+        client.reclaim_complete = true;
+        Ok(())
+    }
+
+    /// Checks that `state_id` may be used for I/O to `file`
+    fn check_state_id(&self, state_id: &StateId4, file: &NfsFh3) -> OpResult<()> {
+        if is_special(state_id) {
+            return Ok(());
+        }
+
+        let state = self.state.lock().unwrap();
+        match state.opens.get(&state_id.other) {
+            Some((_, open)) if open == file => Ok(()),
+            _ => Err(NFS4ERR_BAD_STATEID),
+        }
+    }
+
+    async fn lookup(&self, request: &mut Request, args: Lookup4Args) -> OpResult<()> {
+        let dir = request.current()?;
+        check_name(&args.objname)?;
+        if self.getattr(&dir).await?.file_type == FileType3::Lnk {
+            return Err(NFS4ERR_SYMLINK);
+        }
+
+        let lookup = self.backend.lookup(request.cred.clone(), dir, args.objname);
+        request.current = Some(lookup.await.map_err(status)?);
+        Ok(())
+    }
+
+    async fn access(&self, request: &Request, args: Access4Args) -> OpResult<Access4ResOk> {
+        let supported = args.access & ACCESS_ALL;
+        let access = self
+            .backend
+            .access(request.cred.clone(), request.current()?, supported);
+
+        Ok(Access4ResOk {
+            supported,
+            access: access.await.map_err(status)?,
+        })
+    }
+
+    async fn create(&self, request: &mut Request, args: Create4Args) -> OpResult<Create4ResOk> {
+        let dir = request.current()?;
+        check_name(&args.component)?;
+        let (attributes, attr_set) = decode_attributes(&args.attributes)?;
+        let cred = request.cred.clone();
+        let (name, before) = (args.component, self.change(&dir).await);
+        let device = |spec: SpecData4| DeviceData3 {
+            attributes: attributes.clone(),
+            spec: SpecData3 {
+                data1: spec.major,
+                data2: spec.minor,
+            },
+        };
+        let backend = &self.backend;
+        let object = match args.objtype {
+            CreateType4::Directory => backend.mkdir(cred, dir.clone(), name, attributes).await,
+            CreateType4::Link(target) => {
+                let symlink = backend.symlink(cred, dir.clone(), name, target, attributes);
+                symlink.await
+            }
+            CreateType4::Block(spec) => {
+                let what = MknodData3::Blk(device(spec));
+                backend.mknod(cred, dir.clone(), name, what).await
+            }
+            CreateType4::Char(spec) => {
+                let what = MknodData3::Chr(device(spec));
+                backend.mknod(cred, dir.clone(), name, what).await
+            }
+            CreateType4::Socket => {
+                let what = MknodData3::Sock(attributes);
+                backend.mknod(cred, dir.clone(), name, what).await
+            }
+            CreateType4::Fifo => {
+                let what = MknodData3::Fifo(attributes);
+                backend.mknod(cred, dir.clone(), name, what).await
+            }
+        };
+
+        request.current = Some(object.map_err(status)?);
+        Ok(Create4ResOk {
+            change_info: ChangeInfo4 {
+                atomic: false,
+                before,
+                after: self.change(&dir).await,
+            },
+            attr_set,
+        })
+    }
+
+    async fn remove(&self, request: &Request, args: Remove4Args) -> OpResult<Remove4ResOk> {
+        let dir = request.current()?;
+        check_name(&args.target)?;
+        let cred = request.cred.clone();
+        let before = self.change(&dir).await;
+        let lookup = self
+            .backend
+            .lookup(cred.clone(), dir.clone(), args.target.clone());
+        let object = lookup.await.map_err(status)?;
+        let remove = match self.getattr(&object).await?.file_type {
+            FileType3::Dir => self.backend.rmdir(cred, dir.clone(), args.target),
+            _ => self.backend.remove(cred, dir.clone(), args.target),
+        };
+        remove.await.map_err(status)?;
+
+        Ok(Remove4ResOk {
+            change_info: ChangeInfo4 {
+                atomic: false,
+                before,
+                after: self.change(&dir).await,
+            },
+        })
+    }
+
+    async fn open(&self, request: &mut Request, args: Open4Args) -> OpResult<Open4ResOk> {
+        let current = request.current()?;
+        let cred = request.cred.clone();
+        let mut change_info = ChangeInfo4 {
+            atomic: false,
+            before: 0,
+            after: 0,
+        };
+        let mut attr_set = Bitmap4::new();
+        let mut created = false;
+        let file = match (args.claim, args.how) {
+            (OpenClaim4::Null(name), how) => {
+                check_name(&name)?;
+                change_info.before = self.change(&current).await;
+                let how = match how {
+                    OpenFlag4::NoCreate => None,
+                    OpenFlag4::Create(CreateHow4::Unchecked(attributes)) => {
+                        let (attributes, set) = decode_attributes(&attributes)?;
+                        attr_set = set;
+                        let lookup =
+                            self.backend
+                                .lookup(cred.clone(), current.clone(), name.clone());
+                        created = lookup.await.is_err();
+                        Some(CreateHow3::Unchecked(attributes))
+                    }
+                    OpenFlag4::Create(CreateHow4::Guarded(attributes)) => {
+                        let (attributes, set) = decode_attributes(&attributes)?;
+                        attr_set = set;
+                        created = true;
+                        Some(CreateHow3::Guarded(attributes))
+                    }
+                    OpenFlag4::Create(CreateHow4::Exclusive(verifier)) => {
+                        created = true;
+                        Some(CreateHow3::Exclusive(verifier))
+                    }
+                };
+                let file = match how {
+                    None => self.backend.lookup(cred.clone(), current.clone(), name),
+                    Some(how) => self
+                        .backend
+                        .create(cred.clone(), current.clone(), name, how),
+                };
+                let file = file.await.map_err(status)?;
+                change_info.after = self.change(&current).await;
+                file
+            }
+            (OpenClaim4::FileHandle, OpenFlag4::NoCreate) => current,
+            (OpenClaim4::FileHandle, OpenFlag4::Create(_)) => return Err(NFS4ERR_INVAL),
+        };
+
+        match self.getattr(&file).await?.file_type {
+            FileType3::Reg => (),
+            FileType3::Dir => return Err(NFS4ERR_ISDIR),
+            FileType3::Lnk => return Err(NFS4ERR_SYMLINK),
+            _ => return Err(NFS4ERR_WRONG_TYPE),
+        }
+        if !created {
+            let mut wanted = 0;
+            if args.share_access & OPEN4_SHARE_ACCESS_READ != 0 {
+                wanted |= ACCESS3_READ;
+            }
+            if args.share_access & OPEN4_SHARE_ACCESS_WRITE != 0 {
+                wanted |= ACCESS3_MODIFY;
+            }
+            let access = self.backend.access(cred, file.clone(), wanted);
+            if access.await.map_err(status)? != wanted {
+                return Err(NFS4ERR_ACCESS);
+            }
+        }
+
+        let mut state = self.state.lock().unwrap();
+        let client_id = args.owner.client_id;
+        if !state.clients.contains_key(&client_id) {
+            return Err(NFS4ERR_STALE_CLIENTID);
+        }
+        state.next_open += 1;
+        let mut other = [0; NFS4_OTHER_SIZE];
+        other[4..].copy_from_slice(&state.next_open.to_be_bytes());
+        state.opens.insert(other, (client_id, file.clone()));
+        request.current = Some(file);
+
+        Ok(Open4ResOk {
+            state_id: StateId4 {
+                sequence_id: 1,
+                other,
+            },
+            change_info,
+            result_flags: OPEN4_RESULT_LOCKTYPE_POSIX,
+            attr_set,
+            delegation: OpenDelegation4::None,
+        })
+    }
+
+    fn close(&self, args: Close4Args) -> OpResult<Close4ResOk> {
+        let mut state = self.state.lock().unwrap();
+        state
+            .opens
+            .remove(&args.state_id.other)
+            .ok_or(NFS4ERR_BAD_STATEID)?;
+
+        // the invalid special stateid, see RFC 8881 section 18.2.4
+        Ok(Close4ResOk {
+            state_id: StateId4 {
+                sequence_id: u32::MAX,
+                other: [0; NFS4_OTHER_SIZE],
+            },
+        })
+    }
+
+    async fn read(&self, request: &Request, args: Read4Args) -> OpResult<Read4ResOk> {
+        let file = request.current()?;
+        self.check_state_id(&args.state_id, &file)?;
+        let count = self
+            .read_limit
+            .clamp(self.backend.as_ref(), &file, args.count)
+            .await;
+        let read = self
+            .backend
+            .read(request.cred.clone(), file, args.offset, count);
+        let (mut data, mut eof) = read.await.map_err(status)?;
+        if data.len() > count as usize {
+            data.truncate(count as usize);
+            eof = false;
+        }
+
+        Ok(Read4ResOk { eof, data })
+    }
+
+    async fn write(&self, request: &Request, args: Write4Args) -> OpResult<Write4ResOk> {
+        let file = request.current()?;
+        self.check_state_id(&args.state_id, &file)?;
+        let count = args.data.len() as u32;
+        let write = self.backend.write(
+            request.cred.clone(),
+            file,
+            args.offset,
+            args.data,
+            stable_how(args.stable),
+        );
+
+        Ok(Write4ResOk {
+            count,
+            committed: stable_how4(write.await.map_err(status)?),
+            verifier: self.backend.write_verifier(),
+        })
+    }
+
+    async fn commit(&self, request: &Request, args: Commit4Args) -> OpResult<Commit4ResOk> {
+        let commit = self.backend.commit(
+            request.cred.clone(),
+            request.current()?,
+            args.offset,
+            args.count,
+        );
+        commit.await.map_err(status)?;
+
+        Ok(Commit4ResOk {
+            verifier: self.backend.write_verifier(),
+        })
+    }
+
+    async fn readdir(&self, request: &Request, args: ReadDir4Args) -> OpResult<ReadDir4ResOk> {
+        let dir = request.current()?;
+        let readdir = self.backend.readdirplus(
+            request.cred.clone(),
+            dir.clone(),
+            args.cookie,
+            args.verifier,
+            args.dir_count,
+        );
+        let page = readdir.await.map_err(status)?;
+
+        // cookie verifier, no more entries and eof
+        let mut size = 8 + 4 + 4;
+        let mut entries = Vec::new();
+        let mut all = true;
+        for entry in page.entries {
+            if entry.name == "." || entry.name == ".." {
+                continue;
+            }
+
+            let handle = match entry.handle {
+                Some(handle) => handle,
+                None => {
+                    let lookup =
+                        self.backend
+                            .lookup(request.cred.clone(), dir.clone(), entry.name.clone());
+                    match lookup.await {
+                        Ok(handle) => handle,
+                        Err(_) => continue,
+                    }
+                }
+            };
+            // skip entries removed since
+            let Ok(attributes) = self.getattr(&handle).await else {
+                continue;
+            };
+
+            let attrs = encode_attributes(&attributes, &args.attr_request);
+            let mut encoded = BytesMut::new();
+            entry.cookie.pack_to(&mut encoded);
+            entry.name.pack_to(&mut encoded);
+            attrs.pack_to(&mut encoded);
+            size += 4 + encoded.len();
+            if size > args.max_count as usize {
+                all = false;
+                break;
+            }
+
+            entries.push((entry.cookie, entry.name, attrs));
+        }
+        if entries.is_empty() && !all {
+            return Err(NFS4ERR_TOOSMALL);
+        }
+
+        let mut next = None;
+        for (cookie, name, attrs) in entries.into_iter().rev() {
+            next = Some(Box::new(Entry4 {
+                cookie,
+                name,
+                attrs,
+                next_entry: next,
+            }));
+        }
+
+        Ok(ReadDir4ResOk {
+            cookie_verf: page.verifier,
+            reply: DirList4 {
+                entries: next.map(|entry| *entry),
+                eof: page.eof && all,
+            },
+        })
+    }
+
+    /// Runs one operation, updating the current filehandle of `request`
+    async fn operation(&self, request: &mut Request, op: ArgOp4) -> ResultOp4 {
+        match op {
+            ArgOp4::Access(args) => ResultOp4::Access(self.access(request, args).await),
+            ArgOp4::Close(args) => ResultOp4::Close(self.close(args)),
+            ArgOp4::Commit(args) => ResultOp4::Commit(self.commit(request, args).await),
+            ArgOp4::Create(args) => ResultOp4::Create(self.create(request, args).await),
+            ArgOp4::DelegPurge(_) => ResultOp4::DelegPurge(Err(NFS4ERR_NOTSUPP)),
+            // no delegations are handed out
+            ArgOp4::DelegReturn(_) => ResultOp4::DelegReturn(Err(NFS4ERR_BAD_STATEID)),
+            ArgOp4::GetAttr(args) => ResultOp4::GetAttr(match request.current() {
+                Ok(object) => self.getattr(&object).await.map(|attributes| GetAttr4ResOk {
+                    obj_attributes: encode_attributes(&attributes, &args.attr_request),
+                }),
+                Err(err) => Err(err),
+            }),
+            ArgOp4::GetFh => ResultOp4::GetFh(request.current().map(|object| GetFh4ResOk {
+                object: object.data,
+            })),
+            ArgOp4::Lookup(args) => ResultOp4::Lookup(self.lookup(request, args).await),
+            ArgOp4::Open(args) => ResultOp4::Open(self.open(request, args).await),
+            ArgOp4::PutFh(args) => ResultOp4::PutFh(
+                if args.object.is_empty() || args.object.len() > NFS4_FHSIZE {
+                    Err(NFS4ERR_BADHANDLE)
+                } else {
+                    request.current = Some(NfsFh3 { data: args.object });
+                    Ok(())
+                },
+            ),
+            ArgOp4::PutRootFh => {
+                request.current = Some(self.root.clone());
+                ResultOp4::PutRootFh(Ok(()))
+            }
+            ArgOp4::Read(args) => ResultOp4::Read(self.read(request, args).await),
+            ArgOp4::ReadDir(args) => ResultOp4::ReadDir(self.readdir(request, args).await),
+            ArgOp4::Remove(args) => ResultOp4::Remove(self.remove(request, args).await),
+            ArgOp4::Write(args) => ResultOp4::Write(self.write(request, args).await),
+            ArgOp4::BindConnToSession(args) => {
+                ResultOp4::BindConnToSession(self.bind_conn_to_session(args))
+            }
+            ArgOp4::ExchangeId(args) => ResultOp4::ExchangeId(self.exchange_id(args)),
+            ArgOp4::CreateSession(args) => ResultOp4::CreateSession(self.create_session(args)),
+            ArgOp4::Sequence(_) => ResultOp4::Sequence(Err(NFS4ERR_SEQUENCE_POS)),
+            ArgOp4::ReclaimComplete(_) => {
+                ResultOp4::ReclaimComplete(self.reclaim_complete(request))
+            }
+            ArgOp4::Illegal => ResultOp4::Illegal(Err(NFS4ERR_OP_ILLEGAL)),
+        }
+    }
+
+    /// Runs the operations of `compound` until one fails
+    async fn compound(&self, cred: Identity, compound: Compound) -> Result<Bytes> {
+        let mut result = CompoundResult {
+            status: NFS4_OK,
+            tag: compound.tag,
+            result_array: Vec::new(),
+        };
+        let mut results = BytesMut::new();
+        if compound.minor_version != MINOR_VERSION {
+            result.status = NFS4ERR_MINOR_VERS_MISMATCH;
+            result.pack_to(&mut results);
+            return Ok(results.freeze());
+        }
+
+        let mut request = Request {
+            cred,
+            current: None,
+            slot: None,
+        };
+        let operations = compound.arg_array.len();
+        for (i, op) in compound.arg_array.into_iter().enumerate() {
+            let op_result = match op {
+                ArgOp4::Sequence(args) if i == 0 => match self.sequence(&args, operations) {
+                    Ok(Sequenced::Replay(reply)) => return Ok(reply),
+                    Ok(Sequenced::New(res_ok)) => {
+                        request.slot = Some((args.session_id, args.slot_id));
+                        ResultOp4::Sequence(Ok(res_ok))
+                    }
+                    Err(err) => ResultOp4::Sequence(Err(err)),
+                },
+                ArgOp4::ExchangeId(_)
+                | ArgOp4::CreateSession(_)
+                | ArgOp4::BindConnToSession(_)
+                | ArgOp4::Illegal
+                    if i == 0 =>
+                {
+                    self.operation(&mut request, op).await
+                }
+                op if request.slot.is_none() => failed(&op, NFS4ERR_OP_NOT_IN_SESSION),
+                op => self.operation(&mut request, op).await,
+            };
+
+            let mut status = BytesMut::new();
+            op_result.pack_to(&mut status);
+            result.result_array.push(op_result);
+            // status follows the operation number
+            let op_status = u32::from_be_bytes(status[4..8].try_into()?);
+            if op_status != NFS4_OK {
+                result.status = op_status;
+                break;
+            }
+        }
+
+        result.pack_to(&mut results);
+        let results = results.freeze();
+        if let Some((session_id, slot_id)) = request.slot {
+            let mut state = self.state.lock().unwrap();
+            let slot = state
+                .sessions
+                .get_mut(&session_id)
+                .and_then(|session| session.slots.get_mut(slot_id as usize));
+            if let Some(slot) = slot {
+                slot.reply = Some(results.clone());
+            }
+        }
+
+        Ok(results)
+    }
+
+    async fn dispatch_call(&self, mut call: Call) -> Result<Bytes> {
+        match call.proc {
+            PROC_NULL => Ok(Bytes::new()),
+            PROC_COMPOUND => {
+                let cred = call
+                    .identity()
+                    .unwrap_or_else(|| Identity::new(NOBODY, NOBODY, vec![]));
+                let compound = Compound::unpack_from(&mut call.args)?;
+                self.compound(cred, compound).await
+            }
+            _ => Err(RPC_PROC_UNAVAIL.into()),
+        }
+    }
+}
+
+struct Nfs4Dispatcher(Arc<Nfs4Server>);
+
+impl Dispatcher for Nfs4Dispatcher {
+    fn dispatch(&self, call: Call) -> DispatchFuture {
+        let server = self.0.clone();
+        Box::pin(async move { server.dispatch_call(call).await })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memfs::MemFs;
+    use crate::nfs3::backend::DEFAULT_MAX_TRANSFER;
+    use crate::nfs4::client::NfsClient;
+    use crate::rpc::server::tests::serve_duplex;
+
+    const DESCRIPTION: &str = r#"
+        file /hello data="hello world" mode=0644
+        file /secret data="s" mode=0600 uid=1000
+        symlink /link target=hello
+    "#;
+
+    fn new_server() -> Arc<Nfs4Server> {
+        let fs = MemFs::from_description(DESCRIPTION).unwrap();
+        let root = fs.root();
+        Arc::new(Nfs4Server::new(Arc::new(fs), root))
+    }
+
+    async fn new_client(nfsd: &Arc<Nfs4Server>) -> NfsClient {
+        let mut server = RpcServer::new();
+        nfsd.register_with(&mut server);
+        let mut client = NfsClient::new("localhost");
+        client.set_rpc_client(serve_duplex(server, None));
+        client.exchange_id_call().await.unwrap();
+        client.create_session_call().await.unwrap();
+        client.send_reclaim_complete().await.unwrap();
+        client
+    }
+
+    async fn run(nfsd: &Nfs4Server, arg_array: Vec<ArgOp4>) -> CompoundResult {
+        let mut compound = Compound::new();
+        compound.arg_array = arg_array;
+        let cred = Identity::new(0, 0, vec![]);
+        let mut reply = nfsd.compound(cred, compound).await.unwrap();
+        CompoundResult::unpack_from(&mut reply).unwrap()
+    }
+
+    /// Returns the session of the client and the last sequence of slot 0
+    fn session(nfsd: &Nfs4Server) -> (SessionId4, SequenceId4) {
+        let state = nfsd.state.lock().unwrap();
+        let (session_id, session) = state.sessions.iter().next().unwrap();
+        (*session_id, session.slots[0].sequence_id)
+    }
+
+    fn sequence(session_id: SessionId4, sequence_id: SequenceId4) -> ArgOp4 {
+        ArgOp4::Sequence(Sequence4Args {
+            session_id,
+            sequence_id,
+            slot_id: 0,
+            highest_slot_id: 0,
+            cache_this: false,
+        })
+    }
+
+    #[tokio::test]
+    async fn files() {
+        let nfsd = new_server();
+        let client = new_client(&nfsd).await;
+        let err = client.send_reclaim_complete().await.unwrap_err();
+        assert_eq!(err.get(), NFS4ERR_COMPLETE_ALREADY);
+
+        let root = client.get_root().await.unwrap();
+        let hello = client.send_lookup(&root, "hello").await.unwrap();
+        let open = client
+            .open_by_id(&hello, OPEN4_SHARE_ACCESS_READ, OPEN4_SHARE_DENY_NONE)
+            .await
+            .unwrap();
+        let res_ok = client.read(&hello, &open.state_id, 6, 100).await.unwrap();
+        assert_eq!(&res_ok.data[..], b"world");
+        assert!(res_ok.eof);
+        let err = client.send_lookup(&root, "missing").await.unwrap_err();
+        assert_eq!(err.get(), NFS4ERR_NOENT);
+        let link = client.send_lookup(&root, "link").await.unwrap();
+        let err = client
+            .open_by_id(&link, OPEN4_SHARE_ACCESS_READ, OPEN4_SHARE_DENY_NONE)
+            .await
+            .unwrap_err();
+        assert_eq!(err.get(), NFS4ERR_SYMLINK);
+
+        let dir = client.mkdir(&root, "dir").await.unwrap();
+        let err = client.mkdir(&root, "dir").await.unwrap_err();
+        assert_eq!(err.get(), NFS4ERR_EXIST);
+        let err = client
+            .open_by_id(&dir, OPEN4_SHARE_ACCESS_READ, OPEN4_SHARE_DENY_NONE)
+            .await
+            .unwrap_err();
+        assert_eq!(err.get(), NFS4ERR_ISDIR);
+
+        let res_ok = client.readdir(&root, 0, 0).await.unwrap();
+        assert!(res_ok.reply.eof);
+        let entries: Vec<_> = res_ok.reply.iter().collect();
+        let names: Vec<_> = entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["dir", "hello", "link", "secret"]);
+        assert!(matches!(entries[0].attrs.obj_type, Some(NfsType4::Dir)));
+        assert_eq!(entries[1].attrs.size, Some(11));
+        assert_eq!(entries[3].attrs.mode, Some(0o600));
+        assert_eq!(entries[3].attrs.owner.as_deref(), Some("1000"));
+
+        client.remove(&root, "dir").await.unwrap();
+        let err = client.send_lookup(&root, "dir").await.unwrap_err();
+        assert_eq!(err.get(), NFS4ERR_NOENT);
+
+        // reads are cut at the rtmax of the backend
+        let data = Bytes::from(vec![7; DEFAULT_MAX_TRANSFER as usize + 1]);
+        let file = NfsFh3 {
+            data: hello.clone(),
+        };
+        let cred = Identity::new(0, 0, vec![]);
+        let write = nfsd
+            .backend()
+            .write(cred, file, 0, data, StableHow::FileSync);
+        write.await.unwrap();
+        let res_ok = client
+            .read(&hello, &open.state_id, 0, u32::MAX)
+            .await
+            .unwrap();
+        assert_eq!(res_ok.data.len(), DEFAULT_MAX_TRANSFER as usize);
+        assert!(!res_ok.eof);
+    }
+
+    #[tokio::test]
+    async fn open_write_close() {
+        let nfsd = new_server();
+        let _client = new_client(&nfsd).await;
+        let (session_id, last) = session(&nfsd);
+        let client_id = nfsd.state.lock().unwrap().sessions[&session_id].client_id;
+        let fs = nfsd.backend().clone();
+
+        let mut attributes = FileAttributes::new();
+        attributes.mode = Some(0o640);
+        let open = ArgOp4::Open(Open4Args {
+            seqid: 0,
+            share_access: OPEN4_SHARE_ACCESS_BOTH,
+            share_deny: OPEN4_SHARE_DENY_NONE,
+            owner: OpenOwner4 {
+                client_id,
+                owner: "test".into(),
+            },
+            how: OpenFlag4::Create(CreateHow4::Guarded(attributes)),
+            claim: OpenClaim4::Null("new".into()),
+        });
+        let ops = vec![
+            sequence(session_id, last + 1),
+            ArgOp4::PutRootFh,
+            open,
+            ArgOp4::GetFh,
+        ];
+        let mut res = run(&nfsd, ops).await;
+        assert_eq!(res.status, NFS4_OK);
+        let ResultOp4::GetFh(Ok(fh)) = res.result_array.pop().unwrap() else {
+            panic!("no filehandle");
+        };
+        let ResultOp4::Open(Ok(open)) = res.result_array.pop().unwrap() else {
+            panic!("no open result");
+        };
+        assert!(open.change_info.after > open.change_info.before);
+        assert!(open.attr_set.is_set(attr::MODE));
+        let file = NfsFh3 { data: fh.object };
+        assert_eq!(fs.getattr(file.clone()).await.unwrap().mode, 0o640);
+
+        let write = ArgOp4::Write(Write4Args {
+            state_id: open.state_id.clone(),
+            offset: 0,
+            stable: StableHow4::Unstable,
+            data: Bytes::from_static(b"data"),
+        });
+        let put = || {
+            ArgOp4::PutFh(PutFh4Args {
+                object: file.data.clone(),
+            })
+        };
+        let res = run(&nfsd, vec![sequence(session_id, last + 2), put(), write]).await;
+        assert_eq!(res.status, NFS4_OK);
+        let ResultOp4::Write(Ok(res_ok)) = &res.result_array[2] else {
+            panic!("no write result");
+        };
+        assert_eq!(res_ok.count, 4);
+        assert_eq!(res_ok.verifier, fs.write_verifier());
+
+        let close = ArgOp4::Close(Close4Args {
+            seqid: 0,
+            state_id: open.state_id.clone(),
+        });
+        let res = run(&nfsd, vec![sequence(session_id, last + 3), put(), close]).await;
+        assert_eq!(res.status, NFS4_OK);
+        let read = ArgOp4::Read(Read4Args {
+            state_id: open.state_id,
+            offset: 0,
+            count: 10,
+        });
+        let res = run(&nfsd, vec![sequence(session_id, last + 4), put(), read]).await;
+        assert_eq!(res.status, NFS4ERR_BAD_STATEID);
+    }
+
+    #[tokio::test]
+    async fn sessions() {
+        let nfsd = new_server();
+        let _client = new_client(&nfsd).await;
+        let (session_id, last) = session(&nfsd);
+
+        let res = run(&nfsd, vec![ArgOp4::PutRootFh, ArgOp4::GetFh]).await;
+        assert_eq!(res.status, NFS4ERR_OP_NOT_IN_SESSION);
+        assert!(matches!(
+            res.result_array[..],
+            [ResultOp4::PutRootFh(Err(NFS4ERR_OP_NOT_IN_SESSION))]
+        ));
+
+        let res = run(
+            &nfsd,
+            vec![sequence(session_id, last + 1), ArgOp4::PutRootFh],
+        )
+        .await;
+        assert_eq!(res.status, NFS4_OK);
+        // a retransmission gets the cached reply, not the new request
+        let ops = vec![sequence(session_id, last + 1), ArgOp4::GetFh];
+        let replay = run(&nfsd, ops).await;
+        assert_eq!(replay.status, NFS4_OK);
+        assert!(matches!(
+            replay.result_array[1],
+            ResultOp4::PutRootFh(Ok(()))
+        ));
+        let res = run(&nfsd, vec![sequence(session_id, last + 3)]).await;
+        assert_eq!(res.status, NFS4ERR_SEQ_MISORDERED);
+
+        let ops = vec![
+            sequence(session_id, last + 2),
+            sequence(session_id, last + 3),
+        ];
+        let res = run(&nfsd, ops).await;
+        assert_eq!(res.status, NFS4ERR_SEQUENCE_POS);
+        let res = run(&nfsd, vec![sequence([9; NFS4_SESSION_ID_SIZE], 1)]).await;
+        assert_eq!(res.status, NFS4ERR_BADSESSION);
+        let ops = vec![sequence(session_id, last + 3), ArgOp4::GetFh];
+        let res = run(&nfsd, ops).await;
+        assert_eq!(res.status, NFS4ERR_NOFILEHANDLE);
+
+        let mut compound = Compound::new();
+        compound.minor_version = 0;
+        let cred = Identity::new(0, 0, vec![]);
+        let mut reply = nfsd.compound(cred, compound).await.unwrap();
+        let res = CompoundResult::unpack_from(&mut reply).unwrap();
+        assert_eq!(res.status, NFS4ERR_MINOR_VERS_MISMATCH);
+    }
+
+    #[tokio::test]
+    async fn create_session_replay() {
+        let nfsd = new_server();
+        let _client = new_client(&nfsd).await;
+        let (session_id, _) = session(&nfsd);
+        let (client_id, next) = {
+            let state = nfsd.state.lock().unwrap();
+            let (client_id, client) = state.clients.iter().next().unwrap();
+            (*client_id, client.sequence_id)
+        };
+        let attrs = ChannelAttrs4 {
+            header_pad_size: 0,
+            max_request_size: 0x1000,
+            max_response_size: 0x1000,
+            max_response_size_cached: 0,
+            max_operation: 2,
+            max_requests: 1,
+            rdma_ird: None,
+        };
+        let create = |sequence| {
+            ArgOp4::CreateSession(CreateSession4Args {
+                client_id,
+                sequence,
+                flags: 0,
+                fore_chan_attrs: attrs.clone(),
+                back_chan_attrs: attrs.clone(),
+                cb_program: 0,
+                sec_params: vec![],
+            })
+        };
+
+        // the retransmission gets the session created before
+        let res = run(&nfsd, vec![create(next - 1)]).await;
+        match &res.result_array[..] {
+            [ResultOp4::CreateSession(Ok(res_ok))] => {
+                assert_eq!(res_ok.session_id, session_id);
+                assert_eq!(res_ok.sequence, next - 1);
+            }
+            results => panic!("unexpected {:?}", results),
+        }
+        assert_eq!(nfsd.state.lock().unwrap().sessions.len(), 1);
+
+        let res = run(&nfsd, vec![create(next + 1)]).await;
+        assert_eq!(res.status, NFS4ERR_SEQ_MISORDERED);
+        let res = run(&nfsd, vec![create(next)]).await;
+        assert_eq!(res.status, NFS4_OK);
+        assert_eq!(nfsd.state.lock().unwrap().sessions.len(), 2);
+    }
+}
@@ -0,0 +1,1189 @@
+//! Backend exporting a local directory.
+//!
+//! `PassthroughFs` implements `Nfs3Backend` over a directory of the
+//! local Linux filesystem, so it can be served with
+//! `nfs3::server::Nfs3Server` or `nfs4::server::Nfs4Server` as a
+//! userspace NFS gateway.  File handles are the handles returned by
+//! `name_to_handle_at`, which encode the inode and generation number,
+//! and are opened again with `open_by_handle_at`, so they survive
+//! server restarts.  This needs a filesystem that supports exporting
+//! (ext4, xfs, btrfs, tmpfs...) and CAP_DAC_READ_SEARCH.
+//!
+//! Calls are run on the blocking thread pool.  When the server runs
+//! as root, each call switches the fsuid, fsgid and supplementary
+//! groups of its thread to the caller, so the kernel checks
+//! permissions and new objects get the caller as owner.  Callers with
+//! uid 0 keep root's privileges.
+//!
+//! Like knfsd with `no_subtree_check`, handles of any object on the
+//! exported filesystem are accepted, not only the ones below the
+//! exported directory.  Mount points below the exported directory are
+//! not crossed, looking them up fails with NFS3ERR_XDEV.  Symbolic
+//! links only take an owner, their mode and times are left alone.
+use crate::{
+    nfs3::{
+        backend::{BackendFuture, DirEntry, DirPage, Nfs3Backend, DEFAULT_MAX_TRANSFER},
+        procs::{CreateHow3, Fsstat3ResOk, MknodData3, StableHow},
+        Cookie3, Count3, FileAttributes, FileType3, Filename3, NfsFh3, NfsPath3, NfsTime3, Offset3,
+        SetAttributes, SpecData3, TimeHow, Verifier3, NFS3ERR_ACCES, NFS3ERR_BADHANDLE,
+        NFS3ERR_DQUOT, NFS3ERR_EXIST, NFS3ERR_FBIG, NFS3ERR_INVAL, NFS3ERR_IO, NFS3ERR_ISDIR,
+        NFS3ERR_JUKEBOX, NFS3ERR_MLINK, NFS3ERR_NAMETOOLONG, NFS3ERR_NODEV, NFS3ERR_NOENT,
+        NFS3ERR_NOSPC, NFS3ERR_NOTDIR, NFS3ERR_NOTEMPTY, NFS3ERR_NOTSUPP, NFS3ERR_NXIO,
+        NFS3ERR_PERM, NFS3ERR_ROFS, NFS3ERR_STALE, NFS3ERR_XDEV, NFS3_FHSIZE,
+    },
+    result::{ErrorCode, Result, INTERNAL_ERROR},
+    rpc::auth::Identity,
+};
+use bytes::Bytes;
+use std::ffi::{CStr, CString};
+use std::fs::File;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Largest handle from `name_to_handle_at`
+const MAX_HANDLE_SZ: usize = libc::MAX_HANDLE_SZ as usize;
+
+/// Bytes of the handle type in front of the handle
+const HANDLE_TYPE_SIZE: usize = 4;
+
+/// Maps an errno to the NFS3ERR_ code, which NFSv4 shares
+fn nfs_error(err: io::Error) -> ErrorCode {
+    match err.raw_os_error().unwrap_or(0) {
+        libc::EPERM => NFS3ERR_PERM,
+        libc::ENOENT => NFS3ERR_NOENT,
+        libc::ENXIO => NFS3ERR_NXIO,
+        libc::EACCES => NFS3ERR_ACCES,
+        libc::EEXIST => NFS3ERR_EXIST,
+        libc::EXDEV => NFS3ERR_XDEV,
+        libc::ENODEV => NFS3ERR_NODEV,
+        libc::ENOTDIR => NFS3ERR_NOTDIR,
+        libc::EISDIR => NFS3ERR_ISDIR,
+        libc::EINVAL => NFS3ERR_INVAL,
+        libc::EFBIG => NFS3ERR_FBIG,
+        libc::ENOSPC => NFS3ERR_NOSPC,
+        libc::EROFS => NFS3ERR_ROFS,
+        libc::EMLINK => NFS3ERR_MLINK,
+        libc::ENAMETOOLONG => NFS3ERR_NAMETOOLONG,
+        libc::ENOTEMPTY => NFS3ERR_NOTEMPTY,
+        libc::EDQUOT => NFS3ERR_DQUOT,
+        libc::ESTALE => NFS3ERR_STALE,
+        libc::EOPNOTSUPP => NFS3ERR_NOTSUPP,
+        libc::EAGAIN => NFS3ERR_JUKEBOX,
+        _ => NFS3ERR_IO,
+    }
+    .into()
+}
+
+fn last_error() -> ErrorCode {
+    nfs_error(io::Error::last_os_error())
+}
+
+/// Returns the result of a libc call, failing with errno if it is
+/// negative
+fn check<T: Default + PartialOrd>(ret: T) -> Result<T> {
+    if ret < T::default() {
+        Err(last_error())
+    } else {
+        Ok(ret)
+    }
+}
+
+fn c_name(name: &str) -> Result<CString> {
+    CString::new(name).map_err(|_| NFS3ERR_INVAL.into())
+}
+
+/// Path reopening `fd` through procfs, which also works for `O_PATH`
+/// descriptors
+fn proc_path(fd: &OwnedFd) -> CString {
+    CString::new(format!("/proc/self/fd/{}", fd.as_raw_fd())).unwrap()
+}
+
+/// Opens `fd` again with `flags`, checking permissions as the current
+/// fsuid
+fn reopen(fd: &OwnedFd, flags: libc::c_int) -> Result<File> {
+    let path = proc_path(fd);
+    let fd = check(unsafe { libc::open(path.as_ptr(), flags | libc::O_CLOEXEC) })?;
+    Ok(unsafe { File::from_raw_fd(fd) })
+}
+
+/// `struct file_handle` with room for the largest handle
+#[repr(C)]
+struct RawHandle {
+    handle_bytes: libc::c_uint,
+    handle_type: libc::c_int,
+    f_handle: [u8; MAX_HANDLE_SZ],
+}
+
+/// Returns the stat of `name` in `dirfd`, or of `dirfd` itself if
+/// `name` is empty, without following symbolic links
+fn stat_at(dirfd: RawFd, name: &CStr) -> Result<libc::stat> {
+    let mut st: libc::stat = unsafe { std::mem::zeroed() };
+    let flags = libc::AT_EMPTY_PATH | libc::AT_SYMLINK_NOFOLLOW;
+    check(unsafe { libc::fstatat(dirfd, name.as_ptr(), &mut st, flags) })?;
+    Ok(st)
+}
+
+fn file_type(mode: libc::mode_t) -> FileType3 {
+    match mode & libc::S_IFMT {
+        libc::S_IFDIR => FileType3::Dir,
+        libc::S_IFBLK => FileType3::Blk,
+        libc::S_IFCHR => FileType3::Chr,
+        libc::S_IFLNK => FileType3::Lnk,
+        libc::S_IFSOCK => FileType3::Sock,
+        libc::S_IFIFO => FileType3::Fifo,
+        _ => FileType3::Reg,
+    }
+}
+
+fn nfs_time(seconds: i64, nano_seconds: i64) -> NfsTime3 {
+    NfsTime3 {
+        seconds: seconds as u32,
+        nano_seconds: nano_seconds as u32,
+    }
+}
+
+fn attributes(st: &libc::stat) -> FileAttributes {
+    FileAttributes {
+        file_type: file_type(st.st_mode),
+        mode: st.st_mode & 0o7777,
+        num_links: st.st_nlink as u32,
+        uid: st.st_uid,
+        gid: st.st_gid,
+        size: st.st_size as u64,
+        used: st.st_blocks as u64 * 512,
+        rdev: SpecData3 {
+            data1: libc::major(st.st_rdev),
+            data2: libc::minor(st.st_rdev),
+        },
+        fsid: st.st_dev,
+        file_id: st.st_ino,
+        atime: nfs_time(st.st_atime, st.st_atime_nsec),
+        mtime: nfs_time(st.st_mtime, st.st_mtime_nsec),
+        ctime: nfs_time(st.st_ctime, st.st_ctime_nsec),
+    }
+}
+
+fn timespec(how: &TimeHow) -> libc::timespec {
+    let (tv_sec, tv_nsec) = match how {
+        TimeHow::DontChange => (0, libc::UTIME_OMIT),
+        TimeHow::SetToServerTime => (0, libc::UTIME_NOW),
+        TimeHow::SetToClientTime(time) => (time.seconds as i64, time.nano_seconds as i64),
+    };
+    libc::timespec { tv_sec, tv_nsec }
+}
+
+/// Splits an exclusive create verifier into the atime and mtime it is
+/// stored in, as knfsd does
+fn verifier_times(verifier: Verifier3) -> (TimeHow, TimeHow) {
+    let time = |seconds| {
+        TimeHow::SetToClientTime(NfsTime3 {
+            seconds,
+            nano_seconds: 0,
+        })
+    };
+    (time((verifier >> 32) as u32), time(verifier as u32))
+}
+
+/// Switches the filesystem ids of the current thread to a caller, the
+/// server's ids are restored when dropped
+struct Caller<'a> {
+    export: &'a Export,
+}
+
+impl Drop for Caller<'_> {
+    fn drop(&mut self) {
+        let export = self.export;
+        unsafe {
+            libc::setfsuid(0);
+            libc::setfsgid(export.gid);
+            libc::syscall(
+                libc::SYS_setgroups,
+                export.groups.len(),
+                export.groups.as_ptr(),
+            );
+        }
+    }
+}
+
+struct Export {
+    /// Exported directory, also the `mount_fd` of `open_by_handle_at`
+    root_fd: OwnedFd,
+    root: NfsFh3,
+    /// Device of the exported filesystem
+    dev: libc::dev_t,
+    /// Whether calls run with the fsuid of the caller
+    switch_ids: bool,
+    /// Group and supplementary groups of the server
+    gid: libc::gid_t,
+    groups: Vec<libc::gid_t>,
+    verifier: Verifier3,
+}
+
+impl Export {
+    /// Runs the filesystem calls made until the returned guard is
+    /// dropped as `cred`
+    fn as_caller(&self, cred: &Identity) -> Option<Caller<'_>> {
+        if !self.switch_ids || cred.uid == 0 {
+            return None;
+        }
+
+        // glibc's setgroups changes every thread, the system call only
+        // the current one
+        unsafe {
+            libc::syscall(libc::SYS_setgroups, cred.gids.len(), cred.gids.as_ptr());
+            libc::setfsgid(cred.gid);
+            libc::setfsuid(cred.uid);
+        }
+        Some(Caller { export: self })
+    }
+
+    /// Returns the handle of `name` in `dirfd`, or of `dirfd` itself if
+    /// `name` is empty
+    fn handle_at(&self, dirfd: RawFd, name: &CStr) -> Result<NfsFh3> {
+        let mut handle = RawHandle {
+            handle_bytes: MAX_HANDLE_SZ as libc::c_uint,
+            handle_type: 0,
+            f_handle: [0; MAX_HANDLE_SZ],
+        };
+        let mut mount_id = 0;
+        let ret = unsafe {
+            libc::name_to_handle_at(
+                dirfd,
+                name.as_ptr(),
+                &mut handle as *mut RawHandle as *mut libc::file_handle,
+                &mut mount_id,
+                libc::AT_EMPTY_PATH,
+            )
+        };
+        check(ret)?;
+
+        let len = handle.handle_bytes as usize;
+        if len + HANDLE_TYPE_SIZE > NFS3_FHSIZE {
+            return Err(NFS3ERR_NOTSUPP.into());
+        }
+        let mut data = handle.handle_type.to_be_bytes().to_vec();
+        data.extend_from_slice(&handle.f_handle[..len]);
+        Ok(NfsFh3 { data })
+    }
+
+    /// Opens the object of `fh`, usually with `O_PATH`
+    fn open(&self, fh: &NfsFh3, flags: libc::c_int) -> Result<OwnedFd> {
+        let len = fh.data.len();
+        if len <= HANDLE_TYPE_SIZE || len > NFS3_FHSIZE {
+            return Err(NFS3ERR_BADHANDLE.into());
+        }
+
+        let (handle_type, bytes) = fh.data.split_at(HANDLE_TYPE_SIZE);
+        let mut handle = RawHandle {
+            handle_bytes: bytes.len() as libc::c_uint,
+            handle_type: libc::c_int::from_be_bytes(handle_type.try_into()?),
+            f_handle: [0; MAX_HANDLE_SZ],
+        };
+        handle.f_handle[..bytes.len()].copy_from_slice(bytes);
+        let fd = unsafe {
+            libc::open_by_handle_at(
+                self.root_fd.as_raw_fd(),
+                &mut handle as *mut RawHandle as *mut libc::file_handle,
+                flags | libc::O_CLOEXEC,
+            )
+        };
+        match check(fd) {
+            Ok(fd) => Ok(unsafe { OwnedFd::from_raw_fd(fd) }),
+            // not a handle of this filesystem
+            Err(err) if err.get() == NFS3ERR_INVAL => Err(NFS3ERR_BADHANDLE.into()),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn open_dir(&self, dir: &NfsFh3) -> Result<OwnedFd> {
+        self.open(dir, libc::O_PATH | libc::O_DIRECTORY)
+    }
+
+    fn getattr(&self, object: &NfsFh3) -> Result<FileAttributes> {
+        let fd = self.open(object, libc::O_PATH)?;
+        Ok(attributes(&stat_at(fd.as_raw_fd(), c"")?))
+    }
+
+    /// Changes the attributes of `fd`, an `O_PATH` descriptor of an
+    /// object with `st`
+    fn set_attributes(
+        &self,
+        fd: &OwnedFd,
+        st: &libc::stat,
+        attributes: &SetAttributes,
+    ) -> Result<()> {
+        let is_symlink = st.st_mode & libc::S_IFMT == libc::S_IFLNK;
+        if attributes.uid.is_some() || attributes.gid.is_some() {
+            let uid = attributes.uid.unwrap_or(u32::MAX);
+            let gid = attributes.gid.unwrap_or(u32::MAX);
+            let flags = libc::AT_EMPTY_PATH | libc::AT_SYMLINK_NOFOLLOW;
+            check(unsafe { libc::fchownat(fd.as_raw_fd(), c"".as_ptr(), uid, gid, flags) })?;
+        }
+        if is_symlink {
+            return Ok(());
+        }
+
+        let path = proc_path(fd);
+        if let Some(mode) = attributes.mode {
+            check(unsafe { libc::chmod(path.as_ptr(), mode & 0o7777) })?;
+        }
+        if let Some(size) = attributes.size {
+            if st.st_mode & libc::S_IFMT != libc::S_IFREG {
+                return Err(NFS3ERR_INVAL.into());
+            }
+            reopen(fd, libc::O_WRONLY)?
+                .set_len(size)
+                .map_err(nfs_error)?;
+        }
+        let times = [timespec(&attributes.atime), timespec(&attributes.mtime)];
+        if times.iter().any(|time| time.tv_nsec != libc::UTIME_OMIT) {
+            let ret = unsafe { libc::utimensat(libc::AT_FDCWD, path.as_ptr(), times.as_ptr(), 0) };
+            check(ret)?;
+        }
+
+        Ok(())
+    }
+
+    /// Applies `attributes` to the new object `name` in `dirfd` and
+    /// returns its handle
+    fn created(&self, dirfd: &OwnedFd, name: &CStr, attributes: &SetAttributes) -> Result<NfsFh3> {
+        let flags = libc::O_PATH | libc::O_NOFOLLOW | libc::O_CLOEXEC;
+        let fd = check(unsafe { libc::openat(dirfd.as_raw_fd(), name.as_ptr(), flags) })?;
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        let st = stat_at(fd.as_raw_fd(), c"")?;
+        self.set_attributes(&fd, &st, attributes)?;
+        self.handle_at(fd.as_raw_fd(), c"")
+    }
+
+    fn lookup(&self, cred: &Identity, dir: &NfsFh3, name: &str) -> Result<NfsFh3> {
+        let dirfd = self.open_dir(dir)?;
+        if name == ".." && *dir == self.root {
+            return Ok(self.root.clone());
+        }
+
+        let name = c_name(name)?;
+        let _caller = self.as_caller(cred);
+        let st = stat_at(dirfd.as_raw_fd(), &name)?;
+        if st.st_dev != self.dev {
+            return Err(NFS3ERR_XDEV.into());
+        }
+        self.handle_at(dirfd.as_raw_fd(), &name)
+    }
+
+    fn readlink(&self, symlink: &NfsFh3) -> Result<NfsPath3> {
+        let fd = self.open(symlink, libc::O_PATH | libc::O_NOFOLLOW)?;
+        let mut buf = vec![0u8; libc::PATH_MAX as usize];
+        let len = unsafe {
+            libc::readlinkat(
+                fd.as_raw_fd(),
+                c"".as_ptr(),
+                buf.as_mut_ptr() as *mut libc::c_char,
+                buf.len(),
+            )
+        };
+        buf.truncate(check(len)? as usize);
+        String::from_utf8(buf).map_err(|_| NFS3ERR_IO.into())
+    }
+
+    /// Opens regular file `file` as `cred` with `flags`
+    fn open_file(&self, cred: &Identity, file: &NfsFh3, flags: libc::c_int) -> Result<File> {
+        let fd = self.open(file, libc::O_PATH)?;
+        match file_type(stat_at(fd.as_raw_fd(), c"")?.st_mode) {
+            FileType3::Reg => (),
+            FileType3::Dir => return Err(NFS3ERR_ISDIR.into()),
+            _ => return Err(NFS3ERR_INVAL.into()),
+        }
+
+        let _caller = self.as_caller(cred);
+        reopen(&fd, flags)
+    }
+
+    fn read(
+        &self,
+        cred: &Identity,
+        file: &NfsFh3,
+        offset: u64,
+        count: u32,
+    ) -> Result<(Bytes, bool)> {
+        let file = self.open_file(cred, file, libc::O_RDONLY)?;
+        let size = file.metadata().map_err(nfs_error)?.len();
+        // the buffer is bounded by the advertised rtmax and the data
+        // left in the file, whatever count the client asked for
+        let count = count.min(DEFAULT_MAX_TRANSFER) as u64;
+        let mut data = vec![0; count.min(size.saturating_sub(offset)) as usize];
+        let mut len = 0;
+        while len < data.len() {
+            match file.read_at(&mut data[len..], offset + len as u64) {
+                Ok(0) => break,
+                Ok(n) => len += n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
+                Err(err) => return Err(nfs_error(err)),
+            }
+        }
+        data.truncate(len);
+
+        Ok((data.into(), offset + len as u64 >= size))
+    }
+
+    fn write(
+        &self,
+        cred: &Identity,
+        file: &NfsFh3,
+        offset: u64,
+        data: &[u8],
+        stable: StableHow,
+    ) -> Result<StableHow> {
+        let file = self.open_file(cred, file, libc::O_WRONLY)?;
+        file.write_all_at(data, offset).map_err(nfs_error)?;
+        match stable {
+            StableHow::Unstable => (),
+            StableHow::DataSync => file.sync_data().map_err(nfs_error)?,
+            StableHow::FileSync => file.sync_all().map_err(nfs_error)?,
+        }
+
+        Ok(stable)
+    }
+
+    /// Flushes `file` as the server, callers may not be able to read it
+    fn commit(&self, file: &NfsFh3) -> Result<()> {
+        let server = Identity::default();
+        let file = self.open_file(&server, file, libc::O_RDONLY)?;
+        file.sync_all().map_err(nfs_error)
+    }
+
+    fn create(
+        &self,
+        cred: &Identity,
+        dir: &NfsFh3,
+        name: &str,
+        how: &CreateHow3,
+    ) -> Result<NfsFh3> {
+        let dirfd = self.open_dir(dir)?;
+        let name = c_name(name)?;
+        let _caller = self.as_caller(cred);
+        let (mode, attributes) = match how {
+            CreateHow3::Unchecked(attributes) | CreateHow3::Guarded(attributes) => {
+                (attributes.mode.unwrap_or(0o644), attributes.clone())
+            }
+            CreateHow3::Exclusive(verifier) => {
+                let (atime, mtime) = verifier_times(*verifier);
+                let attributes = SetAttributes {
+                    atime,
+                    mtime,
+                    ..Default::default()
+                };
+                (0o600, attributes)
+            }
+        };
+
+        let mode = libc::S_IFREG | (mode & 0o7777);
+        let ret = unsafe { libc::mknodat(dirfd.as_raw_fd(), name.as_ptr(), mode, 0) };
+        match (check(ret), how) {
+            (Ok(_), _) => self.created(&dirfd, &name, &attributes),
+            (Err(err), CreateHow3::Unchecked(attributes)) if err.get() == NFS3ERR_EXIST => {
+                // only truncate an existing file
+                let truncate = SetAttributes {
+                    size: attributes.size,
+                    atime: TimeHow::DontChange,
+                    mtime: TimeHow::DontChange,
+                    ..Default::default()
+                };
+                self.created(&dirfd, &name, &truncate)
+            }
+            (Err(err), CreateHow3::Exclusive(verifier)) if err.get() == NFS3ERR_EXIST => {
+                // a retransmission finds the verifier
+                let st = stat_at(dirfd.as_raw_fd(), &name)?;
+                let stored = ((st.st_atime as u64) << 32) | st.st_mtime as u32 as u64;
+                if file_type(st.st_mode) == FileType3::Reg && stored == *verifier {
+                    self.handle_at(dirfd.as_raw_fd(), &name)
+                } else {
+                    Err(err)
+                }
+            }
+            (Err(err), _) => Err(err),
+        }
+    }
+
+    fn mkdir(
+        &self,
+        cred: &Identity,
+        dir: &NfsFh3,
+        name: &str,
+        attributes: &SetAttributes,
+    ) -> Result<NfsFh3> {
+        let dirfd = self.open_dir(dir)?;
+        let name = c_name(name)?;
+        let _caller = self.as_caller(cred);
+        let mode = attributes.mode.unwrap_or(0o755) & 0o7777;
+        check(unsafe { libc::mkdirat(dirfd.as_raw_fd(), name.as_ptr(), mode) })?;
+        self.created(&dirfd, &name, attributes)
+    }
+
+    fn symlink(
+        &self,
+        cred: &Identity,
+        dir: &NfsFh3,
+        name: &str,
+        target: &str,
+        attributes: &SetAttributes,
+    ) -> Result<NfsFh3> {
+        let dirfd = self.open_dir(dir)?;
+        let (name, target) = (c_name(name)?, c_name(target)?);
+        let _caller = self.as_caller(cred);
+        check(unsafe { libc::symlinkat(target.as_ptr(), dirfd.as_raw_fd(), name.as_ptr()) })?;
+        self.created(&dirfd, &name, attributes)
+    }
+
+    fn mknod(
+        &self,
+        cred: &Identity,
+        dir: &NfsFh3,
+        name: &str,
+        what: &MknodData3,
+    ) -> Result<NfsFh3> {
+        let dirfd = self.open_dir(dir)?;
+        let name = c_name(name)?;
+        let (file_type, attributes, rdev) = match what {
+            MknodData3::Chr(device) => (
+                libc::S_IFCHR,
+                &device.attributes,
+                libc::makedev(device.spec.data1, device.spec.data2),
+            ),
+            MknodData3::Blk(device) => (
+                libc::S_IFBLK,
+                &device.attributes,
+                libc::makedev(device.spec.data1, device.spec.data2),
+            ),
+            MknodData3::Sock(attributes) => (libc::S_IFSOCK, attributes, 0),
+            MknodData3::Fifo(attributes) => (libc::S_IFIFO, attributes, 0),
+        };
+
+        let _caller = self.as_caller(cred);
+        let mode = file_type | (attributes.mode.unwrap_or(0o644) & 0o7777);
+        check(unsafe { libc::mknodat(dirfd.as_raw_fd(), name.as_ptr(), mode, rdev) })?;
+        self.created(&dirfd, &name, attributes)
+    }
+
+    fn unlink(&self, cred: &Identity, dir: &NfsFh3, name: &str, flags: libc::c_int) -> Result<()> {
+        let dirfd = self.open_dir(dir)?;
+        let name = c_name(name)?;
+        let _caller = self.as_caller(cred);
+        check(unsafe { libc::unlinkat(dirfd.as_raw_fd(), name.as_ptr(), flags) })?;
+        Ok(())
+    }
+
+    fn rename(
+        &self,
+        cred: &Identity,
+        from_dir: &NfsFh3,
+        from_name: &str,
+        to_dir: &NfsFh3,
+        to_name: &str,
+    ) -> Result<()> {
+        let from_fd = self.open_dir(from_dir)?;
+        let to_fd = self.open_dir(to_dir)?;
+        let (from_name, to_name) = (c_name(from_name)?, c_name(to_name)?);
+        let _caller = self.as_caller(cred);
+        let ret = unsafe {
+            libc::renameat(
+                from_fd.as_raw_fd(),
+                from_name.as_ptr(),
+                to_fd.as_raw_fd(),
+                to_name.as_ptr(),
+            )
+        };
+        check(ret)?;
+        Ok(())
+    }
+
+    fn link(&self, cred: &Identity, file: &NfsFh3, dir: &NfsFh3, name: &str) -> Result<()> {
+        let fd = self.open(file, libc::O_PATH)?;
+        let dirfd = self.open_dir(dir)?;
+        let name = c_name(name)?;
+        let path = proc_path(&fd);
+        let _caller = self.as_caller(cred);
+        let ret = unsafe {
+            libc::linkat(
+                libc::AT_FDCWD,
+                path.as_ptr(),
+                dirfd.as_raw_fd(),
+                name.as_ptr(),
+                libc::AT_SYMLINK_FOLLOW,
+            )
+        };
+        check(ret)?;
+        Ok(())
+    }
+
+    /// Reads entries after `cookie`, the offsets from `readdir` are the
+    /// cookies and stay valid while the directory changes
+    fn readdir(
+        &self,
+        cred: &Identity,
+        dir: &NfsFh3,
+        cookie: Cookie3,
+        count: Count3,
+    ) -> Result<DirPage> {
+        let dirfd = self.open_dir(dir)?;
+        let _caller = self.as_caller(cred);
+        let flags = libc::O_RDONLY | libc::O_DIRECTORY | libc::O_CLOEXEC;
+        let fd = check(unsafe { libc::openat(dirfd.as_raw_fd(), c".".as_ptr(), flags) })?;
+        let stream = unsafe { libc::fdopendir(fd) };
+        if stream.is_null() {
+            let err = last_error();
+            unsafe { libc::close(fd) };
+            return Err(err);
+        }
+
+        if cookie != 0 {
+            unsafe { libc::seekdir(stream, cookie as libc::c_long) };
+        }
+        let mut entries = Vec::new();
+        let mut size = 0;
+        let eof = loop {
+            if size >= count as usize {
+                break false;
+            }
+            let entry = unsafe { libc::readdir64(stream) };
+            if entry.is_null() {
+                break true;
+            }
+
+            let entry = unsafe { &*entry };
+            let name = unsafe { CStr::from_ptr(entry.d_name.as_ptr()) };
+            let name = String::from_utf8_lossy(name.to_bytes()).into_owned();
+            // fileid, name length, cookie and the next entry flag
+            size += 24 + name.len().next_multiple_of(4);
+            entries.push(DirEntry {
+                fileid: entry.d_ino,
+                name,
+                cookie: entry.d_off as Cookie3,
+                handle: None,
+            });
+        };
+        unsafe { libc::closedir(stream) };
+
+        Ok(DirPage {
+            entries,
+            verifier: 0,
+            eof,
+        })
+    }
+
+    fn fsstat(&self, root: &NfsFh3) -> Result<Fsstat3ResOk> {
+        let fd = self.open(root, libc::O_PATH)?;
+        let mut st: libc::statvfs = unsafe { std::mem::zeroed() };
+        check(unsafe { libc::fstatvfs(fd.as_raw_fd(), &mut st) })?;
+        let block = st.f_frsize as u64;
+        Ok(Fsstat3ResOk {
+            obj_attributes: None,
+            tbytes: st.f_blocks as u64 * block,
+            fbytes: st.f_bfree as u64 * block,
+            abytes: st.f_bavail as u64 * block,
+            tfiles: st.f_files as u64,
+            ffiles: st.f_ffree as u64,
+            afiles: st.f_favail as u64,
+            invarsec: 0,
+        })
+    }
+}
+
+/// Exports a local directory
+pub struct PassthroughFs {
+    export: Arc<Export>,
+}
+
+impl PassthroughFs {
+    /// Exports directory `path`, checks that its filesystem supports
+    /// file handles and that they can be opened
+    pub fn new(path: impl AsRef<Path>) -> Result<PassthroughFs> {
+        let path = CString::new(path.as_ref().as_os_str().as_bytes())
+            .map_err(|_| ErrorCode::from(NFS3ERR_INVAL))?;
+        // open_by_handle_at does not take O_PATH descriptors
+        let flags = libc::O_RDONLY | libc::O_DIRECTORY | libc::O_CLOEXEC;
+        let fd = check(unsafe { libc::open(path.as_ptr(), flags) })?;
+        let root_fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        let dev = stat_at(root_fd.as_raw_fd(), c"")?.st_dev;
+
+        let mut groups =
+            vec![0; check(unsafe { libc::getgroups(0, std::ptr::null_mut()) })? as usize];
+        let len =
+            check(unsafe { libc::getgroups(groups.len() as libc::c_int, groups.as_mut_ptr()) })?;
+        groups.truncate(len as usize);
+        let verifier = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_nanos() as u64);
+
+        let mut export = Export {
+            root_fd,
+            root: NfsFh3 { data: vec![] },
+            dev,
+            switch_ids: unsafe { libc::geteuid() } == 0,
+            gid: unsafe { libc::getegid() },
+            groups,
+            verifier,
+        };
+        export.root = export.handle_at(export.root_fd.as_raw_fd(), c"")?;
+        export.open(&export.root, libc::O_PATH)?;
+
+        Ok(PassthroughFs {
+            export: Arc::new(export),
+        })
+    }
+
+    /// Returns the handle of the exported directory
+    pub fn root(&self) -> NfsFh3 {
+        self.export.root.clone()
+    }
+
+    /// Runs `f` on the blocking thread pool
+    fn run<T, F>(&self, f: F) -> BackendFuture<'_, T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Export) -> Result<T> + Send + 'static,
+    {
+        let export = self.export.clone();
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || f(&export))
+                .await
+                .map_err(|_| ErrorCode::from(INTERNAL_ERROR))?
+        })
+    }
+}
+
+impl Nfs3Backend for PassthroughFs {
+    fn getattr(&self, object: NfsFh3) -> BackendFuture<'_, FileAttributes> {
+        self.run(move |export| export.getattr(&object))
+    }
+
+    fn setattr(
+        &self,
+        cred: Identity,
+        object: NfsFh3,
+        attributes: SetAttributes,
+    ) -> BackendFuture<'_, ()> {
+        self.run(move |export| {
+            let fd = export.open(&object, libc::O_PATH | libc::O_NOFOLLOW)?;
+            let st = stat_at(fd.as_raw_fd(), c"")?;
+            let _caller = export.as_caller(&cred);
+            export.set_attributes(&fd, &st, &attributes)
+        })
+    }
+
+    fn lookup(&self, cred: Identity, dir: NfsFh3, name: Filename3) -> BackendFuture<'_, NfsFh3> {
+        self.run(move |export| export.lookup(&cred, &dir, &name))
+    }
+
+    fn readlink(&self, _cred: Identity, symlink: NfsFh3) -> BackendFuture<'_, NfsPath3> {
+        self.run(move |export| export.readlink(&symlink))
+    }
+
+    fn read(
+        &self,
+        cred: Identity,
+        file: NfsFh3,
+        offset: Offset3,
+        count: Count3,
+    ) -> BackendFuture<'_, (Bytes, bool)> {
+        self.run(move |export| export.read(&cred, &file, offset, count))
+    }
+
+    fn write(
+        &self,
+        cred: Identity,
+        file: NfsFh3,
+        offset: Offset3,
+        data: Bytes,
+        stable: StableHow,
+    ) -> BackendFuture<'_, StableHow> {
+        self.run(move |export| export.write(&cred, &file, offset, &data, stable))
+    }
+
+    fn create(
+        &self,
+        cred: Identity,
+        dir: NfsFh3,
+        name: Filename3,
+        how: CreateHow3,
+    ) -> BackendFuture<'_, NfsFh3> {
+        self.run(move |export| export.create(&cred, &dir, &name, &how))
+    }
+
+    fn mkdir(
+        &self,
+        cred: Identity,
+        dir: NfsFh3,
+        name: Filename3,
+        attributes: SetAttributes,
+    ) -> BackendFuture<'_, NfsFh3> {
+        self.run(move |export| export.mkdir(&cred, &dir, &name, &attributes))
+    }
+
+    fn symlink(
+        &self,
+        cred: Identity,
+        dir: NfsFh3,
+        name: Filename3,
+        target: NfsPath3,
+        attributes: SetAttributes,
+    ) -> BackendFuture<'_, NfsFh3> {
+        self.run(move |export| export.symlink(&cred, &dir, &name, &target, &attributes))
+    }
+
+    fn mknod(
+        &self,
+        cred: Identity,
+        dir: NfsFh3,
+        name: Filename3,
+        what: MknodData3,
+    ) -> BackendFuture<'_, NfsFh3> {
+        self.run(move |export| export.mknod(&cred, &dir, &name, &what))
+    }
+
+    fn remove(&self, cred: Identity, dir: NfsFh3, name: Filename3) -> BackendFuture<'_, ()> {
+        self.run(move |export| export.unlink(&cred, &dir, &name, 0))
+    }
+
+    fn rmdir(&self, cred: Identity, dir: NfsFh3, name: Filename3) -> BackendFuture<'_, ()> {
+        self.run(move |export| export.unlink(&cred, &dir, &name, libc::AT_REMOVEDIR))
+    }
+
+    fn rename(
+        &self,
+        cred: Identity,
+        from_dir: NfsFh3,
+        from_name: Filename3,
+        to_dir: NfsFh3,
+        to_name: Filename3,
+    ) -> BackendFuture<'_, ()> {
+        self.run(move |export| export.rename(&cred, &from_dir, &from_name, &to_dir, &to_name))
+    }
+
+    fn link(
+        &self,
+        cred: Identity,
+        file: NfsFh3,
+        dir: NfsFh3,
+        name: Filename3,
+    ) -> BackendFuture<'_, ()> {
+        self.run(move |export| export.link(&cred, &file, &dir, &name))
+    }
+
+    fn readdir(
+        &self,
+        cred: Identity,
+        dir: NfsFh3,
+        cookie: Cookie3,
+        _verifier: Verifier3,
+        count: Count3,
+    ) -> BackendFuture<'_, DirPage> {
+        self.run(move |export| export.readdir(&cred, &dir, cookie, count))
+    }
+
+    fn fsstat(&self, root: NfsFh3) -> BackendFuture<'_, Fsstat3ResOk> {
+        self.run(move |export| export.fsstat(&root))
+    }
+
+    fn commit(
+        &self,
+        _cred: Identity,
+        file: NfsFh3,
+        _offset: Offset3,
+        _count: Count3,
+    ) -> BackendFuture<'_, ()> {
+        self.run(move |export| export.commit(&file))
+    }
+
+    fn write_verifier(&self) -> Verifier3 {
+        self.export.verifier
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// Scratch directory removed when dropped
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> TempDir {
+            let path =
+                std::env::temp_dir().join(format!("pinfish-{}-{}", name, std::process::id()));
+            let _ = std::fs::remove_dir_all(&path);
+            std::fs::create_dir(&path).unwrap();
+            TempDir(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Exports `dir`.  Needs CAP_DAC_READ_SEARCH and handle support,
+    /// so the tests using it are ignored, run them with `cargo test --
+    /// --ignored`.
+    fn export(dir: &TempDir) -> PassthroughFs {
+        match PassthroughFs::new(&dir.0) {
+            Ok(fs) => fs,
+            Err(err) => panic!("cannot export {:?}: {}", dir.0, err),
+        }
+    }
+
+    fn root() -> Identity {
+        Identity::new(0, 0, vec![])
+    }
+
+    #[tokio::test]
+    #[ignore = "needs CAP_DAC_READ_SEARCH and a filesystem with file handles"]
+    async fn files() {
+        let dir = TempDir::new("files");
+        let fs = export(&dir);
+        let root_fh = fs.root();
+
+        let attributes = SetAttributes {
+            mode: Some(0o640),
+            ..Default::default()
+        };
+        let how = CreateHow3::Guarded(attributes);
+        let file = fs
+            .create(root(), root_fh.clone(), "file".into(), how)
+            .await
+            .unwrap();
+        let stable = fs
+            .write(
+                root(),
+                file.clone(),
+                0,
+                Bytes::from("hello"),
+                StableHow::FileSync,
+            )
+            .await
+            .unwrap();
+        assert_eq!(stable, StableHow::FileSync);
+        let (data, eof) = fs.read(root(), file.clone(), 1, 100).await.unwrap();
+        assert_eq!((&data[..], eof), (&b"ello"[..], true));
+        let (data, eof) = fs.read(root(), file.clone(), 0, u32::MAX).await.unwrap();
+        assert_eq!((&data[..], eof), (&b"hello"[..], true));
+        let (data, eof) = fs.read(root(), file.clone(), 9, u32::MAX).await.unwrap();
+        assert_eq!((&data[..], eof), (&b""[..], true));
+        let attributes = fs.getattr(file.clone()).await.unwrap();
+        assert_eq!(
+            (attributes.file_type, attributes.mode),
+            (FileType3::Reg, 0o640)
+        );
+        assert_eq!(attributes.size, 5);
+        let how = CreateHow3::Guarded(Default::default());
+        let err = fs
+            .create(root(), root_fh.clone(), "file".into(), how)
+            .await
+            .unwrap_err();
+        assert_eq!(err.get(), NFS3ERR_EXIST);
+
+        let sub = fs
+            .mkdir(root(), root_fh.clone(), "sub".into(), Default::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            fs.lookup(root(), sub.clone(), "..".into()).await.unwrap(),
+            root_fh
+        );
+        assert_eq!(
+            fs.lookup(root(), root_fh.clone(), "..".into())
+                .await
+                .unwrap(),
+            root_fh
+        );
+        fs.link(root(), file.clone(), sub.clone(), "link".into())
+            .await
+            .unwrap();
+        assert_eq!(fs.getattr(file.clone()).await.unwrap().num_links, 2);
+        let symlink = fs
+            .symlink(
+                root(),
+                sub.clone(),
+                "symlink".into(),
+                "../file".into(),
+                Default::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(fs.readlink(root(), symlink).await.unwrap(), "../file");
+        let err = fs
+            .rmdir(root(), root_fh.clone(), "sub".into())
+            .await
+            .unwrap_err();
+        assert_eq!(err.get(), NFS3ERR_NOTEMPTY);
+
+        fs.rename(
+            root(),
+            sub.clone(),
+            "link".into(),
+            root_fh.clone(),
+            "moved".into(),
+        )
+        .await
+        .unwrap();
+        let moved = fs
+            .lookup(root(), root_fh.clone(), "moved".into())
+            .await
+            .unwrap();
+        assert_eq!(moved, file);
+        let err = fs
+            .lookup(root(), sub.clone(), "link".into())
+            .await
+            .unwrap_err();
+        assert_eq!(err.get(), NFS3ERR_NOENT);
+
+        // handles stay valid in another instance
+        let again = PassthroughFs::new(&dir.0).unwrap();
+        assert_eq!(again.root(), root_fh);
+        assert_eq!(again.getattr(file.clone()).await.unwrap().size, 5);
+
+        fs.remove(root(), root_fh.clone(), "file".into())
+            .await
+            .unwrap();
+        fs.remove(root(), root_fh.clone(), "moved".into())
+            .await
+            .unwrap();
+        let err = fs.getattr(file).await.unwrap_err();
+        assert_eq!(err.get(), NFS3ERR_STALE);
+        let err = fs.getattr(NfsFh3 { data: vec![1] }).await.unwrap_err();
+        assert_eq!(err.get(), NFS3ERR_BADHANDLE);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs CAP_DAC_READ_SEARCH and a filesystem with file handles"]
+    async fn readdir_and_create_modes() {
+        let dir = TempDir::new("readdir");
+        let fs = export(&dir);
+        for name in ["a", "b", "c"] {
+            std::fs::write(dir.0.join(name), name).unwrap();
+        }
+
+        let page = fs.readdir(root(), fs.root(), 0, 0, 4096).await.unwrap();
+        assert!(page.eof);
+        let mut names: Vec<_> = page.entries.iter().map(|e| e.name.as_str()).collect();
+        names.sort();
+        assert_eq!(names, [".", "..", "a", "b", "c"]);
+        let cookie = page.entries[1].cookie;
+        let rest = fs
+            .readdir(root(), fs.root(), cookie, 0, 4096)
+            .await
+            .unwrap();
+        assert_eq!(rest.entries.len(), 3);
+        let first = fs.readdir(root(), fs.root(), 0, 0, 1).await.unwrap();
+        assert_eq!((first.entries.len(), first.eof), (1, false));
+
+        // unchecked create of an existing file only truncates
+        let attributes = SetAttributes {
+            mode: Some(0o600),
+            size: Some(0),
+            ..Default::default()
+        };
+        let how = CreateHow3::Unchecked(attributes);
+        let a = fs.create(root(), fs.root(), "a".into(), how).await.unwrap();
+        let attributes = fs.getattr(a).await.unwrap();
+        assert_eq!(attributes.size, 0);
+        assert_ne!(attributes.mode, 0o600);
+
+        let how = || CreateHow3::Exclusive(0x1234_5678_9abc_def0);
+        let file = fs
+            .create(root(), fs.root(), "x".into(), how())
+            .await
+            .unwrap();
+        let again = fs
+            .create(root(), fs.root(), "x".into(), how())
+            .await
+            .unwrap();
+        assert_eq!(again, file);
+        let err = fs
+            .create(root(), fs.root(), "x".into(), CreateHow3::Exclusive(1))
+            .await
+            .unwrap_err();
+        assert_eq!(err.get(), NFS3ERR_EXIST);
+
+        let fifo = MknodData3::Fifo(Default::default());
+        let fifo = fs
+            .mknod(root(), fs.root(), "fifo".into(), fifo)
+            .await
+            .unwrap();
+        let attributes = fs.getattr(fifo.clone()).await.unwrap();
+        assert_eq!(attributes.file_type, FileType3::Fifo);
+        let err = fs.read(root(), fifo, 0, 1).await.unwrap_err();
+        assert_eq!(err.get(), NFS3ERR_INVAL);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs CAP_DAC_READ_SEARCH and a filesystem with file handles"]
+    async fn caller_ids() {
+        let dir = TempDir::new("ids");
+        let fs = export(&dir);
+        if !fs.export.switch_ids {
+            return;
+        }
+        let user = Identity::new(1000, 1000, vec![]);
+        let secret = dir.0.join("secret");
+        std::fs::write(&secret, "s").unwrap();
+        std::fs::set_permissions(&secret, std::os::unix::fs::PermissionsExt::from_mode(0o600))
+            .unwrap();
+
+        let secret = fs
+            .lookup(user.clone(), fs.root(), "secret".into())
+            .await
+            .unwrap();
+        let err = fs.read(user.clone(), secret, 0, 1).await.unwrap_err();
+        assert_eq!(err.get(), NFS3ERR_ACCES);
+        let err = fs
+            .mkdir(user.clone(), fs.root(), "d".into(), Default::default())
+            .await
+            .unwrap_err();
+        assert_eq!(err.get(), NFS3ERR_ACCES);
+
+        let public = SetAttributes {
+            mode: Some(0o777),
+            ..Default::default()
+        };
+        let public = fs
+            .mkdir(root(), fs.root(), "public".into(), public)
+            .await
+            .unwrap();
+        let how = CreateHow3::Unchecked(Default::default());
+        let file = fs
+            .create(user.clone(), public, "mine".into(), how)
+            .await
+            .unwrap();
+        let attributes = fs.getattr(file.clone()).await.unwrap();
+        assert_eq!((attributes.uid, attributes.gid), (1000, 1000));
+        fs.write(
+            user.clone(),
+            file.clone(),
+            0,
+            Bytes::from("x"),
+            StableHow::Unstable,
+        )
+        .await
+        .unwrap();
+        let mode = SetAttributes {
+            mode: Some(0o604),
+            ..Default::default()
+        };
+        fs.setattr(user.clone(), file.clone(), mode).await.unwrap();
+        let (data, _) = fs.read(user.clone(), file.clone(), 0, 1).await.unwrap();
+        assert_eq!(&data[..], b"x");
+        // the ids of the thread are restored
+        let attributes = fs.getattr(fs.root()).await.unwrap();
+        assert_eq!(attributes.uid, 0);
+        fs.mkdir(root(), fs.root(), "d".into(), Default::default())
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,396 @@
+//! Port mapper (version 2) and rpcbind (versions 3 and 4) client.
+//!
+//! Version 2 maps a program to a port per transport protocol, versions
+//! 3 and 4 map it to a universal address per network identifier.
+use super::{
+    format_uaddr, net_id, CallArgs, CallResult, Mapping, RpcBind, PMAPPROC_CALLIT, PMAPPROC_DUMP,
+    PMAPPROC_GETPORT, PMAP_PROG, PMAP_VERS, PORT, RPCBPROC_DUMP, RPCBPROC_GETADDR,
+    RPCBPROC_GETTIME, RPCBPROC_SET, RPCBPROC_UNSET, RPCBVERS_3, RPCBVERS_4,
+};
+use crate::{
+    result::{Result, INVALID_DATA, NOT_CONNECTED, RPC_REGISTRATION_FAILED},
+    rpc::{self, auth::CredentialProvider, RpcClient},
+    xdr::{List, PackTo, Packer, UnpackFrom},
+};
+use bytes::{Bytes, BytesMut};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::{lookup_host, TcpStream, UdpSocket};
+
+/// rpcbind version used by the version 3/4 procedures
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RpcbVersion {
+    V3,
+    #[default]
+    V4,
+}
+
+impl RpcbVersion {
+    pub const fn vers(&self) -> u32 {
+        match self {
+            RpcbVersion::V3 => RPCBVERS_3,
+            RpcbVersion::V4 => RPCBVERS_4,
+        }
+    }
+}
+
+pub struct PortmapClient {
+    rpc: RpcClient,
+
+    /// rpcbind version used by the version 3/4 procedures
+    vers: u32,
+
+    credentials: Arc<dyn CredentialProvider>,
+}
+
+impl PortmapClient {
+    /// Constructs a client over `rpc`, which must be connected to the
+    /// port mapper of the server
+    pub fn new(rpc: RpcClient) -> PortmapClient {
+        PortmapClient {
+            rpc,
+            vers: RPCBVERS_4,
+            credentials: Arc::new(rpc::auth::NoCredentials),
+        }
+    }
+
+    /// Connects to the port mapper on `host` over TCP
+    pub async fn connect(host: &str) -> Result<PortmapClient> {
+        let connection = TcpStream::connect((host, PORT)).await?;
+        connection.set_nodelay(true)?;
+        Ok(PortmapClient::new(RpcClient::new(connection)))
+    }
+
+    /// Connects to the port mapper on `host` over UDP, as needed by
+    /// `callit`
+    pub async fn connect_udp(host: &str, retransmit: rpc::Retransmit) -> Result<PortmapClient> {
+        let addr = match lookup_host((host, PORT)).await?.next() {
+            Some(addr) => addr,
+            None => return Err(NOT_CONNECTED.into()),
+        };
+        let local: SocketAddr = if addr.is_ipv4() {
+            ([0, 0, 0, 0], 0).into()
+        } else {
+            ([0u16; 8], 0).into()
+        };
+        let socket = UdpSocket::bind(local).await?;
+        socket.connect(addr).await?;
+        Ok(PortmapClient::new(RpcClient::new_udp(socket, retransmit)))
+    }
+
+    /// Selects rpcbind version 3 or 4 (the default) for `getaddr`,
+    /// `dump_rpcb` and `gettime`
+    pub fn set_version(&mut self, version: RpcbVersion) {
+        self.vers = version.vers();
+    }
+
+    /// Sets the credentials sent with each call, AUTH_NONE by default
+    pub fn set_credentials(&mut self, credentials: Arc<dyn CredentialProvider>) {
+        self.credentials = credentials;
+    }
+
+    pub fn rpc(&self) -> &RpcClient {
+        &self.rpc
+    }
+
+    fn new_buf_with_call_header(&self, xid: u32, vers: u32, proc: u32) -> BytesMut {
+        let mut buf = BytesMut::new();
+        buf.pack_uint(xid);
+        rpc::CallHeader {
+            prog: PMAP_PROG,
+            vers,
+            proc,
+            cred: self.credentials.credential(),
+            verf: rpc::OpaqueAuth::new_none(),
+        }
+        .pack_to(&mut buf);
+
+        buf
+    }
+
+    async fn call(&self, xid: u32, buf: BytesMut) -> Result<Bytes> {
+        let mut response_buf = self.rpc.call(buf, xid).await?;
+        self.rpc.check_header(&mut response_buf)?;
+        Ok(response_buf)
+    }
+
+    /// PMAPPROC_GETPORT: returns the port of `prog`/`vers` over `prot`
+    /// (`IPPROTO_TCP` or `IPPROTO_UDP`), 0 if not registered.  Fails
+    /// with `INVALID_DATA` if the reply is not a valid port.
+    pub async fn getport(&self, prog: u32, vers: u32, prot: u32) -> Result<u16> {
+        let xid = RpcClient::next_xid();
+        let mut buf = self.new_buf_with_call_header(xid, PMAP_VERS, PMAPPROC_GETPORT);
+        Mapping {
+            prog,
+            vers,
+            prot,
+            port: 0,
+        }
+        .pack_to(&mut buf);
+
+        let mut response_buf = self.call(xid, buf).await?;
+        let port = u32::unpack_from(&mut response_buf)?;
+        u16::try_from(port).map_err(|_| INVALID_DATA.into())
+    }
+
+    /// PMAPPROC_DUMP: lists all registrations, like `rpcinfo -p`
+    pub async fn dump(&self) -> Result<Vec<Mapping>> {
+        let xid = RpcClient::next_xid();
+        let buf = self.new_buf_with_call_header(xid, PMAP_VERS, PMAPPROC_DUMP);
+
+        let mut response_buf = self.call(xid, buf).await?;
+        Ok(List::unpack_from(&mut response_buf)?.0)
+    }
+
+    /// PMAPPROC_CALLIT: has the port mapper call `proc` of
+    /// `prog`/`vers` with XDR encoded `args`.  Returns the port of the
+    /// program and its XDR encoded results.  Servers only forward
+    /// calls received over UDP and stay silent if the call fails.
+    pub async fn callit(&self, prog: u32, vers: u32, proc: u32, args: Bytes) -> Result<CallResult> {
+        let xid = RpcClient::next_xid();
+        let mut buf = self.new_buf_with_call_header(xid, PMAP_VERS, PMAPPROC_CALLIT);
+        CallArgs {
+            prog,
+            vers,
+            proc,
+            args,
+        }
+        .pack_to(&mut buf);
+
+        let mut response_buf = self.call(xid, buf).await?;
+        CallResult::unpack_from(&mut response_buf)
+    }
+
+    /// RPCBPROC_GETADDR: returns the universal address of
+    /// `prog`/`vers` over `net_id` (e.g. `NETID_TCP`), `None` if not
+    /// registered.  See `parse_uaddr`.
+    pub async fn getaddr(&self, prog: u32, vers: u32, net_id: &str) -> Result<Option<String>> {
+        let xid = RpcClient::next_xid();
+        let mut buf = self.new_buf_with_call_header(xid, self.vers, RPCBPROC_GETADDR);
+        RpcBind {
+            prog,
+            vers,
+            net_id: net_id.into(),
+            addr: String::new(),
+            owner: String::new(),
+        }
+        .pack_to(&mut buf);
+
+        let mut response_buf = self.call(xid, buf).await?;
+        let addr = String::unpack_from(&mut response_buf)?;
+        Ok(if addr.is_empty() { None } else { Some(addr) })
+    }
+
+    /// RPCBPROC_SET: registers `entry`, returns false if its program,
+    /// version and network identifier are already registered.  Servers
+    /// only accept registrations from local callers.
+    pub async fn set(&self, entry: &RpcBind) -> Result<bool> {
+        let xid = RpcClient::next_xid();
+        let mut buf = self.new_buf_with_call_header(xid, self.vers, RPCBPROC_SET);
+        entry.pack_to(&mut buf);
+
+        let mut response_buf = self.call(xid, buf).await?;
+        bool::unpack_from(&mut response_buf)
+    }
+
+    /// Registers version `vers` of `prog` as reachable at `addr` over
+    /// TCP or UDP (`IPPROTO_TCP` or `IPPROTO_UDP`), replacing any
+    /// previous registration
+    pub async fn register(&self, prog: u32, vers: u32, prot: u32, addr: &SocketAddr) -> Result<()> {
+        let net_id = net_id(prot, addr);
+        self.unset(prog, vers, net_id).await?;
+        let entry = RpcBind {
+            prog,
+            vers,
+            net_id: net_id.into(),
+            addr: format_uaddr(addr),
+            owner: String::new(),
+        };
+        if self.set(&entry).await? {
+            Ok(())
+        } else {
+            Err(RPC_REGISTRATION_FAILED.into())
+        }
+    }
+
+    /// RPCBPROC_UNSET: removes the registrations of `prog`/`vers` over
+    /// `net_id`, or over all network identifiers if `net_id` is empty
+    pub async fn unset(&self, prog: u32, vers: u32, net_id: &str) -> Result<bool> {
+        let xid = RpcClient::next_xid();
+        let mut buf = self.new_buf_with_call_header(xid, self.vers, RPCBPROC_UNSET);
+        RpcBind {
+            prog,
+            vers,
+            net_id: net_id.into(),
+            addr: String::new(),
+            owner: String::new(),
+        }
+        .pack_to(&mut buf);
+
+        let mut response_buf = self.call(xid, buf).await?;
+        bool::unpack_from(&mut response_buf)
+    }
+
+    /// RPCBPROC_DUMP: lists all registrations with their universal
+    /// addresses and owners
+    pub async fn dump_rpcb(&self) -> Result<Vec<RpcBind>> {
+        let xid = RpcClient::next_xid();
+        let buf = self.new_buf_with_call_header(xid, self.vers, RPCBPROC_DUMP);
+
+        let mut response_buf = self.call(xid, buf).await?;
+        Ok(List::unpack_from(&mut response_buf)?.0)
+    }
+
+    /// RPCBPROC_GETTIME: returns the server time in seconds since the
+    /// epoch
+    pub async fn gettime(&self) -> Result<u32> {
+        let xid = RpcClient::next_xid();
+        let buf = self.new_buf_with_call_header(xid, self.vers, RPCBPROC_GETTIME);
+
+        let mut response_buf = self.call(xid, buf).await?;
+        u32::unpack_from(&mut response_buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::portmap::{parse_uaddr, IPPROTO_TCP, NETID_TCP, NETID_UDP};
+    use crate::result::RPC_PROC_UNAVAIL;
+    use crate::rpc::server::{tests::serve_duplex, Call, DispatchFuture, Dispatcher, RpcServer};
+
+    /// Registered with a port that does not fit 16 bits
+    const BAD_PORT_PROG: u32 = 100099;
+
+    /// Serves a fixed table of registrations, CALLIT echoes the
+    /// arguments as the results of a program on port 2049
+    struct FakeRpcbind {
+        table: Vec<RpcBind>,
+    }
+
+    impl FakeRpcbind {
+        fn find(&self, prog: u32, vers: u32, net_id: &str) -> Option<&RpcBind> {
+            self.table
+                .iter()
+                .find(|entry| entry.prog == prog && entry.vers == vers && entry.net_id == net_id)
+        }
+
+        fn mappings(&self) -> Vec<Mapping> {
+            self.table
+                .iter()
+                .map(|entry| Mapping {
+                    prog: entry.prog,
+                    vers: entry.vers,
+                    prot: IPPROTO_TCP,
+                    port: parse_uaddr(&entry.addr).unwrap().port() as u32,
+                })
+                .collect()
+        }
+    }
+
+    impl Dispatcher for Arc<FakeRpcbind> {
+        fn dispatch(&self, mut call: Call) -> DispatchFuture {
+            let rpcbind = self.clone();
+            Box::pin(async move {
+                let mut results = BytesMut::new();
+                match (call.vers, call.proc) {
+                    (PMAP_VERS, PMAPPROC_GETPORT) => {
+                        let mapping = Mapping::unpack_from(&mut call.args)?;
+                        let port = rpcbind
+                            .find(mapping.prog, mapping.vers, NETID_TCP)
+                            .map_or(0, |entry| parse_uaddr(&entry.addr).unwrap().port());
+                        if mapping.prog == BAD_PORT_PROG {
+                            results.pack_uint(0x10000);
+                        } else {
+                            results.pack_uint(port as u32);
+                        }
+                    }
+                    (PMAP_VERS, PMAPPROC_CALLIT) => {
+                        let args = CallArgs::unpack_from(&mut call.args)?;
+                        CallResult {
+                            port: 2049,
+                            results: args.args,
+                        }
+                        .pack_to(&mut results);
+                    }
+                    (PMAP_VERS, PMAPPROC_DUMP) => List(rpcbind.mappings()).pack_to(&mut results),
+                    (_, RPCBPROC_GETADDR) => {
+                        let query = RpcBind::unpack_from(&mut call.args)?;
+                        let addr = rpcbind
+                            .find(query.prog, query.vers, &query.net_id)
+                            .map_or(String::new(), |entry| entry.addr.clone());
+                        addr.pack_to(&mut results);
+                    }
+                    (_, RPCBPROC_DUMP) => List(rpcbind.table.clone()).pack_to(&mut results),
+                    (_, RPCBPROC_GETTIME) => results.pack_uint(1234),
+                    _ => return Err(RPC_PROC_UNAVAIL.into()),
+                }
+                Ok(results.freeze())
+            })
+        }
+    }
+
+    fn new_client() -> PortmapClient {
+        let rpcbind = Arc::new(FakeRpcbind {
+            table: vec![
+                RpcBind {
+                    prog: PMAP_PROG,
+                    vers: RPCBVERS_4,
+                    net_id: NETID_TCP.into(),
+                    addr: "0.0.0.0.0.111".into(),
+                    owner: "superuser".into(),
+                },
+                RpcBind {
+                    prog: 100003,
+                    vers: 3,
+                    net_id: NETID_TCP.into(),
+                    addr: "0.0.0.0.8.1".into(),
+                    owner: "superuser".into(),
+                },
+            ],
+        });
+
+        let mut server = RpcServer::new();
+        for vers in [PMAP_VERS, RPCBVERS_3, RPCBVERS_4] {
+            server.register(PMAP_PROG, vers, Arc::new(rpcbind.clone()));
+        }
+        PortmapClient::new(serve_duplex(server, None))
+    }
+
+    #[tokio::test]
+    async fn portmap() {
+        let client = new_client();
+        assert_eq!(client.getport(100003, 3, IPPROTO_TCP).await.unwrap(), 2049);
+        assert_eq!(client.getport(100005, 3, IPPROTO_TCP).await.unwrap(), 0);
+        let err = client.getport(BAD_PORT_PROG, 1, IPPROTO_TCP).await;
+        assert_eq!(err.unwrap_err().get(), INVALID_DATA);
+
+        let mappings = client.dump().await.unwrap();
+        assert_eq!(mappings.len(), 2);
+        assert_eq!(mappings[1].port, 2049);
+    }
+
+    #[tokio::test]
+    async fn rpcbind() {
+        let mut client = new_client();
+        client.set_version(RpcbVersion::V3);
+        let addr = client.getaddr(100003, 3, NETID_TCP).await.unwrap();
+        assert_eq!(addr.as_deref(), Some("0.0.0.0.8.1"));
+        assert_eq!(client.getaddr(100003, 3, NETID_UDP).await.unwrap(), None);
+
+        client.set_version(RpcbVersion::V4);
+        let table = client.dump_rpcb().await.unwrap();
+        assert_eq!(table.len(), 2);
+        assert_eq!(table[0].owner, "superuser");
+        assert_eq!(client.gettime().await.unwrap(), 1234);
+    }
+
+    #[tokio::test]
+    async fn callit() {
+        let client = new_client();
+        let args = Bytes::from_static(&[0, 0, 0, 42]);
+        let result = client.callit(100003, 3, 0, args.clone()).await.unwrap();
+        assert_eq!(result.port, 2049);
+        assert_eq!(result.results, args);
+    }
+}
@@ -0,0 +1,157 @@
+//! This modules defines the constants and structures for encoding and
+//! decoding RPC port mapper and bind protocols.
+use crate::xdr;
+use bytes::Bytes;
+use pinfish_macros::{PackTo, UnpackFrom};
+use std::net::{IpAddr, SocketAddr};
+
+pub mod client;
+pub mod server;
+
+/// TCP/UDP Port number for the RPC Port Mapper service and RPC bind
+pub const PORT: u16 = 111;
+
+pub const PMAP_VERS: u32 = 2;
+pub const PMAP_PROG: u32 = 100000;
+
+/// rpcbind versions, same program number as the port mapper
+pub const RPCBVERS_3: u32 = 3;
+pub const RPCBVERS_4: u32 = 4;
+
+#[derive(PackTo, UnpackFrom, Debug, Clone, PartialEq, Eq)]
+pub struct Mapping {
+    pub prog: u32,
+    pub vers: u32,
+    pub prot: u32,
+    pub port: u32,
+}
+
+#[derive(PackTo, UnpackFrom, Debug, Clone, PartialEq, Eq)]
+pub struct RpcBind {
+    pub prog: u32,
+    pub vers: u32,
+    pub net_id: String,
+    pub addr: String,
+    pub owner: String,
+}
+
+pub const IPPROTO_TCP: u32 = 6; /* protocol number for TCP/IP */
+pub const IPPROTO_UDP: u32 = 17; /* protocol number for UDP/IP */
+
+pub const PMAPPROC_NULL: u32 = 0;
+pub const PMAPPROC_SET: u32 = 1;
+pub const PMAPPROC_UNSET: u32 = 2;
+pub const PMAPPROC_GETPORT: u32 = 3;
+pub const PMAPPROC_DUMP: u32 = 4;
+pub const PMAPPROC_CALLIT: u32 = 5;
+
+pub const RPCBPROC_NULL: u32 = 0;
+pub const RPCBPROC_SET: u32 = 1;
+pub const RPCBPROC_UNSET: u32 = 2;
+pub const RPCBPROC_GETADDR: u32 = 3;
+pub const RPCBPROC_DUMP: u32 = 4;
+/// RPCBPROC_BCAST in version 4
+pub const RPCBPROC_CALLIT: u32 = 5;
+pub const RPCBPROC_GETTIME: u32 = 6;
+pub const RPCBPROC_UADDR2TADDR: u32 = 7;
+pub const RPCBPROC_TADDR2UADDR: u32 = 8;
+pub const RPCBPROC_GETVERSADDR: u32 = 9;
+pub const RPCBPROC_INDIRECT: u32 = 10;
+pub const RPCBPROC_GETADDRLIST: u32 = 11;
+pub const RPCBPROC_GETSTAT: u32 = 12;
+
+/// Network identifiers used in rpcbind registrations
+pub const NETID_TCP: &str = "tcp";
+pub const NETID_UDP: &str = "udp";
+pub const NETID_TCP6: &str = "tcp6";
+pub const NETID_UDP6: &str = "udp6";
+
+/// PMAPPROC_CALLIT arguments
+#[derive(PackTo, UnpackFrom, Debug)]
+pub struct CallArgs {
+    pub prog: u32,
+    pub vers: u32,
+    pub proc: u32,
+    pub args: Bytes,
+}
+
+/// PMAPPROC_CALLIT results, `port` is the port of the called program
+#[derive(PackTo, UnpackFrom, Debug)]
+pub struct CallResult {
+    pub port: u32,
+    pub results: Bytes,
+}
+
+/// Parses an RFC5665 universal address, "h1.h2.h3.h4.p1.p2" for IPv4
+/// or an IPv6 address followed by ".p1.p2"
+pub fn parse_uaddr(uaddr: &str) -> Option<SocketAddr> {
+    let mut parts = uaddr.rsplitn(3, '.');
+    let low: u8 = parts.next()?.parse().ok()?;
+    let high: u8 = parts.next()?.parse().ok()?;
+    let ip: IpAddr = parts.next()?.parse().ok()?;
+
+    Some((ip, u16::from_be_bytes([high, low])).into())
+}
+
+/// Formats `addr` as an RFC5665 universal address
+pub fn format_uaddr(addr: &SocketAddr) -> String {
+    let [high, low] = addr.port().to_be_bytes();
+    std::format!("{}.{}.{}", addr.ip(), high, low)
+}
+
+/// Network identifier for `prot` over the address family of `addr`
+pub fn net_id(prot: u32, addr: &SocketAddr) -> &'static str {
+    match (prot, addr.is_ipv4()) {
+        (IPPROTO_UDP, true) => NETID_UDP,
+        (IPPROTO_UDP, false) => NETID_UDP6,
+        (_, true) => NETID_TCP,
+        (_, false) => NETID_TCP6,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::xdr::{List, PackTo as _, UnpackFrom as _};
+    use bytes::BytesMut;
+
+    #[test]
+    fn uaddr() {
+        let addr = parse_uaddr("192.168.1.20.8.1").unwrap();
+        assert_eq!(addr, "192.168.1.20:2049".parse().unwrap());
+        assert_eq!(format_uaddr(&addr), "192.168.1.20.8.1");
+
+        let addr = parse_uaddr("fe80::1.0.111").unwrap();
+        assert_eq!(addr, "[fe80::1]:111".parse().unwrap());
+        assert_eq!(format_uaddr(&addr), "fe80::1.0.111");
+
+        assert!(parse_uaddr("").is_none());
+        assert!(parse_uaddr("10.0.0.1.256.1").is_none());
+    }
+
+    #[test]
+    fn list() {
+        let mappings = List(vec![
+            Mapping {
+                prog: PMAP_PROG,
+                vers: PMAP_VERS,
+                prot: IPPROTO_TCP,
+                port: PORT as u32,
+            },
+            Mapping {
+                prog: 100003,
+                vers: 3,
+                prot: IPPROTO_UDP,
+                port: 2049,
+            },
+        ]);
+        let mut buf = BytesMut::new();
+        mappings.pack_to(&mut buf);
+        assert_eq!(buf.len(), 2 * (4 + 16) + 4);
+
+        let mut buf = buf.freeze();
+        let List(mappings) = List::<Mapping>::unpack_from(&mut buf).unwrap();
+        assert_eq!(mappings[1].port, 2049);
+        assert!(buf.is_empty());
+    }
+}
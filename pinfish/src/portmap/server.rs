@@ -0,0 +1,349 @@
+//! Port mapper (version 2) and rpcbind (versions 3 and 4) server.
+//!
+//! `Rpcbind` keeps the registration table and can be registered with
+//! an `RpcServer` listening on any port, so tests can run their own
+//! instance instead of the system rpcbind.  Servers in the same
+//! process register directly with `Rpcbind::register`.
+//!
+//! Registrations are returned as registered: a wildcard address such as
+//! "0.0.0.0.8.1" is not replaced by the address the caller used to
+//! reach the server.
+use super::{
+    format_uaddr, net_id, parse_uaddr, Mapping, RpcBind, IPPROTO_TCP, IPPROTO_UDP, NETID_TCP,
+    NETID_UDP, PMAPPROC_DUMP, PMAPPROC_GETPORT, PMAPPROC_NULL, PMAPPROC_SET, PMAPPROC_UNSET,
+    PMAP_PROG, PMAP_VERS, RPCBPROC_DUMP, RPCBPROC_GETADDR, RPCBPROC_GETTIME, RPCBPROC_GETVERSADDR,
+    RPCBPROC_NULL, RPCBPROC_SET, RPCBPROC_UNSET, RPCBVERS_3, RPCBVERS_4,
+};
+use crate::{
+    result::{Result, RPC_PROC_UNAVAIL},
+    rpc::server::{Call, DispatchFuture, Dispatcher, RpcServer},
+    xdr::{List, PackTo, Packer, UnpackFrom},
+};
+use bytes::BytesMut;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// Owner recorded for registrations made through `Rpcbind::register`
+/// and version 2 calls without AUTH_SYS credentials
+pub const OWNER_UNKNOWN: &str = "unknown";
+
+/// Registration table shared by all versions of the protocol
+#[derive(Default)]
+pub struct Rpcbind {
+    table: Mutex<Vec<RpcBind>>,
+}
+
+impl Rpcbind {
+    pub fn new() -> Rpcbind {
+        Default::default()
+    }
+
+    /// Adds `entry`, returns false if `prog`, `vers` and `net_id` are
+    /// already registered
+    pub fn set(&self, entry: RpcBind) -> bool {
+        let mut table = self.table.lock().unwrap();
+        if table
+            .iter()
+            .any(|e| same_key(e, entry.prog, entry.vers, &entry.net_id))
+        {
+            return false;
+        }
+
+        table.push(entry);
+        true
+    }
+
+    /// Removes the registrations of `prog` and `vers` over `net_id`,
+    /// or over all network identifiers if `net_id` is empty.  Returns
+    /// false if nothing was registered.
+    pub fn unset(&self, prog: u32, vers: u32, net_id: &str) -> bool {
+        let mut table = self.table.lock().unwrap();
+        let len = table.len();
+        table.retain(|e| {
+            !(e.prog == prog && e.vers == vers && (net_id.is_empty() || e.net_id == net_id))
+        });
+
+        table.len() != len
+    }
+
+    /// Registers version `vers` of `prog` as reachable at `addr` over
+    /// TCP or UDP (`IPPROTO_TCP` or `IPPROTO_UDP`), replacing any
+    /// previous registration
+    pub fn register(&self, prog: u32, vers: u32, prot: u32, addr: &SocketAddr) {
+        let net_id = net_id(prot, addr);
+        self.unset(prog, vers, net_id);
+        self.set(RpcBind {
+            prog,
+            vers,
+            net_id: net_id.into(),
+            addr: format_uaddr(addr),
+            owner: OWNER_UNKNOWN.into(),
+        });
+    }
+
+    /// Registers all programs of `server` as reachable at `addr`
+    pub fn register_server(&self, server: &RpcServer, prot: u32, addr: &SocketAddr) {
+        for (prog, vers) in server.programs() {
+            self.register(prog, vers, prot, addr);
+        }
+    }
+
+    /// Returns the universal address of `prog` and `vers` over
+    /// `net_id`, empty if not registered
+    pub fn getaddr(&self, prog: u32, vers: u32, net_id: &str) -> String {
+        let table = self.table.lock().unwrap();
+        table
+            .iter()
+            .find(|e| same_key(e, prog, vers, net_id))
+            .map_or(String::new(), |e| e.addr.clone())
+    }
+
+    /// Returns the IPv4 port of `prog` and `vers` over `prot`, 0 if not
+    /// registered
+    pub fn getport(&self, prog: u32, vers: u32, prot: u32) -> u16 {
+        let net_id = match prot {
+            IPPROTO_TCP => NETID_TCP,
+            IPPROTO_UDP => NETID_UDP,
+            _ => return 0,
+        };
+
+        parse_uaddr(&self.getaddr(prog, vers, net_id)).map_or(0, |addr| addr.port())
+    }
+
+    pub fn dump(&self) -> Vec<RpcBind> {
+        self.table.lock().unwrap().clone()
+    }
+
+    /// Returns the IPv4 TCP and UDP registrations as version 2 mappings
+    pub fn mappings(&self) -> Vec<Mapping> {
+        let table = self.table.lock().unwrap();
+        table
+            .iter()
+            .filter_map(|e| {
+                let prot = match e.net_id.as_str() {
+                    NETID_TCP => IPPROTO_TCP,
+                    NETID_UDP => IPPROTO_UDP,
+                    _ => return None,
+                };
+                let addr = parse_uaddr(&e.addr)?;
+                Some(Mapping {
+                    prog: e.prog,
+                    vers: e.vers,
+                    prot,
+                    port: addr.port() as u32,
+                })
+            })
+            .collect()
+    }
+
+    /// Registers the port mapper and rpcbind versions with `server`
+    pub fn register_with(self: &Arc<Self>, server: &mut RpcServer) {
+        for vers in [PMAP_VERS, RPCBVERS_3, RPCBVERS_4] {
+            server.register(
+                PMAP_PROG,
+                vers,
+                Arc::new(RpcbindDispatcher {
+                    rpcbind: self.clone(),
+                    vers,
+                }),
+            );
+        }
+    }
+}
+
+fn same_key(entry: &RpcBind, prog: u32, vers: u32, net_id: &str) -> bool {
+    entry.prog == prog && entry.vers == vers && entry.net_id == net_id
+}
+
+/// Only local callers may change registrations, calls from the same
+/// process or a Unix domain socket have no peer address
+fn may_modify(call: &Call) -> bool {
+    call.peer.is_none_or(|peer| peer.ip().is_loopback())
+}
+
+struct RpcbindDispatcher {
+    rpcbind: Arc<Rpcbind>,
+    vers: u32,
+}
+
+impl RpcbindDispatcher {
+    fn dispatch_pmap(&self, mut call: Call, results: &mut BytesMut) -> Result<()> {
+        match call.proc {
+            PMAPPROC_NULL => (),
+            PMAPPROC_SET => {
+                let mapping = Mapping::unpack_from(&mut call.args)?;
+                let net_id = match mapping.prot {
+                    IPPROTO_TCP => Some(NETID_TCP),
+                    IPPROTO_UDP => Some(NETID_UDP),
+                    _ => None,
+                };
+                let done = match net_id {
+                    Some(net_id) if may_modify(&call) && mapping.port <= u16::MAX as u32 => {
+                        let addr = SocketAddr::from(([0, 0, 0, 0], mapping.port as u16));
+                        self.rpcbind.set(RpcBind {
+                            prog: mapping.prog,
+                            vers: mapping.vers,
+                            net_id: net_id.into(),
+                            addr: format_uaddr(&addr),
+                            owner: owner(&call),
+                        })
+                    }
+                    _ => false,
+                };
+                results.pack_bool(done);
+            }
+            PMAPPROC_UNSET => {
+                // the protocol and port are ignored
+                let mapping = Mapping::unpack_from(&mut call.args)?;
+                let done = may_modify(&call) && {
+                    let tcp = self.rpcbind.unset(mapping.prog, mapping.vers, NETID_TCP);
+                    let udp = self.rpcbind.unset(mapping.prog, mapping.vers, NETID_UDP);
+                    tcp || udp
+                };
+                results.pack_bool(done);
+            }
+            PMAPPROC_GETPORT => {
+                let mapping = Mapping::unpack_from(&mut call.args)?;
+                let port = self
+                    .rpcbind
+                    .getport(mapping.prog, mapping.vers, mapping.prot);
+                results.pack_uint(port as u32);
+            }
+            PMAPPROC_DUMP => List(self.rpcbind.mappings()).pack_to(results),
+            _ => return Err(RPC_PROC_UNAVAIL.into()),
+        }
+
+        Ok(())
+    }
+
+    fn dispatch_rpcb(&self, mut call: Call, results: &mut BytesMut) -> Result<()> {
+        match call.proc {
+            RPCBPROC_NULL => (),
+            RPCBPROC_SET => {
+                let mut entry = RpcBind::unpack_from(&mut call.args)?;
+                entry.owner = owner(&call);
+                let done = may_modify(&call) && self.rpcbind.set(entry);
+                results.pack_bool(done);
+            }
+            RPCBPROC_UNSET => {
+                let entry = RpcBind::unpack_from(&mut call.args)?;
+                let done =
+                    may_modify(&call) && self.rpcbind.unset(entry.prog, entry.vers, &entry.net_id);
+                results.pack_bool(done);
+            }
+            RPCBPROC_GETADDR => {
+                let entry = RpcBind::unpack_from(&mut call.args)?;
+                let addr = self.rpcbind.getaddr(entry.prog, entry.vers, &entry.net_id);
+                addr.pack_to(results);
+            }
+            // only exact version matches are returned by GETADDR too
+            RPCBPROC_GETVERSADDR if self.vers == RPCBVERS_4 => {
+                let entry = RpcBind::unpack_from(&mut call.args)?;
+                let addr = self.rpcbind.getaddr(entry.prog, entry.vers, &entry.net_id);
+                addr.pack_to(results);
+            }
+            RPCBPROC_DUMP => List(self.rpcbind.dump()).pack_to(results),
+            RPCBPROC_GETTIME => {
+                let now = SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap_or_default();
+                results.pack_uint(now.as_secs() as u32);
+            }
+            _ => return Err(RPC_PROC_UNAVAIL.into()),
+        }
+
+        Ok(())
+    }
+}
+
+/// Owner of registrations made by `call`, the uid of its AUTH_SYS
+/// credential if any
+fn owner(call: &Call) -> String {
+    call.auth_sys()
+        .map_or(OWNER_UNKNOWN.into(), |sys| sys.uid.to_string())
+}
+
+impl Dispatcher for RpcbindDispatcher {
+    fn dispatch(&self, call: Call) -> DispatchFuture {
+        let mut results = BytesMut::new();
+        let result = if self.vers == PMAP_VERS {
+            self.dispatch_pmap(call, &mut results)
+        } else {
+            self.dispatch_rpcb(call, &mut results)
+        };
+
+        Box::pin(async move { result.map(|_| results.freeze()) })
+    }
+
+    fn cacheable(&self, proc: u32) -> bool {
+        // same procedure numbers in all versions
+        proc == RPCBPROC_SET || proc == RPCBPROC_UNSET
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::portmap::{client::PortmapClient, NETID_UDP6};
+    use crate::rpc::server::tests::serve_duplex;
+
+    fn new_client(rpcbind: &Arc<Rpcbind>, peer: Option<SocketAddr>) -> PortmapClient {
+        let mut server = RpcServer::new();
+        rpcbind.register_with(&mut server);
+        PortmapClient::new(serve_duplex(server, peer))
+    }
+
+    #[tokio::test]
+    async fn registrations() {
+        let rpcbind = Arc::new(Rpcbind::new());
+        let nfs: SocketAddr = "127.0.0.1:2049".parse().unwrap();
+        rpcbind.register(100003, 3, IPPROTO_TCP, &nfs);
+        rpcbind.register(100003, 3, IPPROTO_UDP, &"[::1]:2049".parse().unwrap());
+
+        let client = new_client(&rpcbind, None);
+        assert_eq!(client.getport(100003, 3, IPPROTO_TCP).await.unwrap(), 2049);
+        assert_eq!(client.getport(100003, 3, IPPROTO_UDP).await.unwrap(), 0);
+        let addr = client.getaddr(100003, 3, NETID_UDP6).await.unwrap();
+        assert_eq!(addr.as_deref(), Some("::1.8.1"));
+
+        let entry = RpcBind {
+            prog: 100005,
+            vers: 3,
+            net_id: NETID_TCP.into(),
+            addr: "127.0.0.1.3.0".into(),
+            owner: String::new(),
+        };
+        assert!(client.set(&entry).await.unwrap());
+        assert!(!client.set(&entry).await.unwrap());
+        assert_eq!(rpcbind.getport(100005, 3, IPPROTO_TCP), 768);
+        assert_eq!(client.dump().await.unwrap().len(), 2);
+        assert_eq!(client.dump_rpcb().await.unwrap().len(), 3);
+
+        // an empty network identifier removes all of them
+        assert!(client.unset(100003, 3, "").await.unwrap());
+        assert!(!client.unset(100003, 3, "").await.unwrap());
+        assert_eq!(
+            rpcbind.dump(),
+            vec![RpcBind {
+                owner: OWNER_UNKNOWN.into(),
+                ..entry
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn remote_set_denied() {
+        let rpcbind = Arc::new(Rpcbind::new());
+        let client = new_client(&rpcbind, Some("192.0.2.1:700".parse().unwrap()));
+        let entry = RpcBind {
+            prog: 100005,
+            vers: 3,
+            net_id: NETID_TCP.into(),
+            addr: "192.0.2.1.3.0".into(),
+            owner: String::new(),
+        };
+        assert!(!client.set(&entry).await.unwrap());
+        assert!(rpcbind.dump().is_empty());
+    }
+}
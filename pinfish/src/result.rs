@@ -34,6 +34,13 @@ impl std::fmt::Display for ErrorCode {
             ERR_ACCESS => write!(f, "error: ERR_ACCESS"),
             ERR_EXISTS => write!(f, "error: ERR_EXISTS"),
             ERR_BADXDR => write!(f, "error: ERR_BADXDR"),
+            RESERVED_PORT_DENIED => write!(
+                f,
+                "error: binding a reserved port requires root or CAP_NET_BIND_SERVICE"
+            ),
+            RESERVED_PORTS_EXHAUSTED => write!(f, "error: no reserved port available"),
+            RPC_REGISTRATION_FAILED => write!(f, "error: port mapper registration failed"),
+            WRITE_VERIFIER_UNSTABLE => write!(f, "error: server kept losing uncommitted writes"),
             _ => write!(f, "error: {} (0x{:x}", n, n),
         }
     }
@@ -53,6 +60,10 @@ impl From<&u32> for ErrorCode {
 
 impl From<std::io::Error> for ErrorCode {
     fn from(err: std::io::Error) -> ErrorCode {
+        if let Some(code) = err.get_ref().and_then(|e| e.downcast_ref::<ErrorCode>()) {
+            return *code;
+        }
+
         match err.kind() {
             std::io::ErrorKind::ConnectionRefused => CONNECTION_REFUSED,
             std::io::ErrorKind::ConnectionReset => CONNECTION_RESET,
@@ -61,6 +72,7 @@ impl From<std::io::Error> for ErrorCode {
             std::io::ErrorKind::ConnectionAborted => CONNECTION_ABORTED,
             std::io::ErrorKind::NotConnected => NOT_CONNECTED,
             std::io::ErrorKind::InvalidData => INVALID_DATA,
+            std::io::ErrorKind::TimedOut => TIMED_OUT,
             // std::io::ErrorKind::Uncategorized => UNCATEGORIZED_IO_ERROR,
             _ => UNCATEGORIZED_IO_ERROR,
         }
@@ -111,6 +123,27 @@ pub const RPC_SYSTEM_ERR: u32 = CRATE_ERROR_BASE + 12;
 pub const RPC_REJECTED_MISMATCH: u32 = CRATE_ERROR_BASE + 13;
 pub const RPC_REJECTED_AUTH_ERROR: u32 = CRATE_ERROR_BASE + 14;
 pub const UNCATEGORIZED_IO_ERROR: u32 = CRATE_ERROR_BASE + 15;
+/// No reply received before the call deadline
+pub const TIMED_OUT: u32 = CRATE_ERROR_BASE + 16;
+/// Server rejected the RFC9289 AUTH_TLS probe
+pub const TLS_NOT_SUPPORTED: u32 = CRATE_ERROR_BASE + 17;
+/// None of the auth flavors accepted by the server is available
+pub const AUTH_FLAVOR_NOT_SUPPORTED: u32 = CRATE_ERROR_BASE + 18;
+/// RPCSEC_GSS context establishment, checksum or mechanism failure
+pub const GSS_FAILURE: u32 = CRATE_ERROR_BASE + 19;
+/// RPCSEC_GSS context rejected by the server or out of sequence
+/// numbers, it needs to be established again
+pub const GSS_CONTEXT_PROBLEM: u32 = CRATE_ERROR_BASE + 20;
+/// Binding a reserved source port was refused, the process needs root
+/// or CAP_NET_BIND_SERVICE
+pub const RESERVED_PORT_DENIED: u32 = CRATE_ERROR_BASE + 21;
+/// Every port in the reserved range is in use
+pub const RESERVED_PORTS_EXHAUSTED: u32 = CRATE_ERROR_BASE + 22;
+/// The port mapper refused a registration
+pub const RPC_REGISTRATION_FAILED: u32 = CRATE_ERROR_BASE + 23;
+/// The server's write verifier kept changing while uncommitted writes
+/// were sent again
+pub const WRITE_VERIFIER_UNSTABLE: u32 = CRATE_ERROR_BASE + 24;
 
 pub const NFS4ERR_COMPLETE_ALREADY: u32 = 10054;
 
@@ -2,24 +2,45 @@
 //!
 use crate::{
     result::{
-        ErrorCode, Result, INVALID_DATA, RPC_GARBAGE_ARGS, RPC_PROC_UNAVAIL, RPC_PROG_MISMATCH,
-        RPC_PROG_UNAVAIL, RPC_REJECTED_AUTH_ERROR, RPC_REJECTED_MISMATCH, RPC_SYSTEM_ERR,
+        ErrorCode, Result, CONNECTION_RESET, GSS_CONTEXT_PROBLEM, INVALID_DATA, RPC_GARBAGE_ARGS,
+        RPC_PROC_UNAVAIL, RPC_PROG_MISMATCH, RPC_PROG_UNAVAIL, RPC_REJECTED_AUTH_ERROR,
+        RPC_REJECTED_MISMATCH, RPC_SYSTEM_ERR,
     },
     throttle::Throttle,
-    xdr::{self, UnpackFrom, Unpacker as _},
+    xdr::{self, PackTo, Packer as _, UnpackFrom, Unpacker as _},
 };
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 use pinfish_macros::{PackTo, UnpackFrom};
+use record::{Message, RecordWriter, DEFAULT_MAX_FRAGMENT};
 use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
 use std::sync::{
     atomic::{self, AtomicU32},
     Arc, Mutex,
 };
-use tokio::io::{self, AsyncReadExt, AsyncWriteExt, ReadHalf, WriteHalf};
-use tokio::net::TcpStream;
-use tokio::sync::oneshot;
+use std::time::Duration;
+use tokio::io::{self, AsyncRead, AsyncReadExt, AsyncWrite};
+use tokio::net::UdpSocket;
+use tokio::sync::{oneshot, Notify};
 
-const MAX_PACKET_SIZE: u32 = 1024 * 1024;
+pub mod auth;
+pub mod drc;
+pub mod gss;
+pub mod pool;
+pub mod record;
+pub mod resvport;
+pub mod server;
+#[cfg(feature = "tls")]
+pub mod tls;
+
+pub use crate::throttle::{FlowControl, ThrottleStats};
+
+/// Default largest RPC message accepted over a stream transport
+pub const DEFAULT_MAX_PACKET_SIZE: u32 = 1024 * 1024;
+
+/// Largest RPC message that fits in a UDP datagram
+const MAX_DATAGRAM_SIZE: usize = 65536;
 
 // RFC5531  RPC v2
 
@@ -27,8 +48,10 @@ const LAST_FRAGMENT: u32 = 0x80000000;
 const CALL: u32 = 0;
 const REPLY: u32 = 1;
 
-const AUTH_NONE: u32 = 0;
-const AUTH_SYS: u32 = 1;
+/// Auth flavors, e.g. for the `auth_flavors` of a MOUNT reply
+pub const AUTH_NONE: u32 = 0;
+pub const AUTH_SYS: u32 = 1;
+const AUTH_TLS: u32 = 7;
 
 const MSG_ACCEPTED: u32 = 0;
 const MSG_DENIED: u32 = 1;
@@ -42,11 +65,22 @@ pub struct AuthSys {
     pub gids: Vec<u32>,
 }
 
+/// Authentication data of a flavor this crate does not interpret
+#[derive(Debug)]
+pub struct RawAuth {
+    pub flavor: u32,
+    pub body: Bytes,
+}
+
 /// RFC5531 opaque_auth
 #[derive(Debug)]
 pub enum OpaqueAuth {
     None,
     Sys(AuthSys),
+    /// RFC9289 AUTH_TLS, only used to probe for RPC-over-TLS support
+    Tls,
+    /// Unknown flavors, or AUTH_NONE with a non-empty body
+    Raw(RawAuth),
 }
 
 impl<B: Packer> xdr::PackTo<B> for OpaqueAuth {
@@ -103,8 +137,8 @@ pub enum AcceptedReplyStat {
 
 #[derive(PackTo, UnpackFrom, Debug)]
 pub struct MismatchInfo {
-    low: u32,
-    high: u32,
+    pub low: u32,
+    pub high: u32,
 }
 
 #[derive(PackTo, UnpackFrom, Debug)]
@@ -185,6 +219,7 @@ pub trait Packer {
 
 /// Trait for unpacking RPC header
 pub trait Unpacker {
+    fn unpack_call_header(&mut self) -> Result<CallHeader>;
     fn unpack_reply_header(&mut self) -> Result<ReplyHeader>;
     fn unpack_auth(&mut self) -> Result<OpaqueAuth>;
     fn unpack_auth_sys(&mut self) -> Result<AuthSys>;
@@ -211,13 +246,23 @@ impl<T: xdr::Packer> Packer for T {
                 self.pack_uint(AUTH_SYS);
                 self.pack_auth_sys(auth_sys)
             }
+            OpaqueAuth::Tls => {
+                self.pack_uint(AUTH_TLS);
+                self.pack_uint(0)
+            }
+            OpaqueAuth::Raw(raw) => {
+                self.pack_uint(raw.flavor);
+                self.pack_opaque(&raw.body)
+            }
         }
     }
 
     fn pack_auth_sys(&mut self, auth: &AuthSys) {
-        self.pack_uint((auth.gids.len() + auth.machine_name.len()) as u32 + 20);
+        // stamp, name, uid, gid and gids, each opaque padded to 4 bytes
+        let name_len = (auth.machine_name.len() + 3) & !3;
+        self.pack_uint((20 + name_len + 4 * auth.gids.len()) as u32);
         self.pack_uint(auth.stamp);
-        self.pack_opaque(&*auth.machine_name);
+        self.pack_opaque(&auth.machine_name);
         self.pack_uint(auth.uid);
         self.pack_uint(auth.gid);
         self.pack_array(&auth.gids, |packer: &mut Self, item| {
@@ -227,6 +272,23 @@ impl<T: xdr::Packer> Packer for T {
 }
 
 impl<T: xdr::Unpacker> Unpacker for T {
+    /// Unpacks a call header following the message type, fails with
+    /// `RPC_REJECTED_MISMATCH` if the RPC version is not 2 and with
+    /// `RPC_REJECTED_AUTH_ERROR` if the credential or verifier is bad
+    fn unpack_call_header(&mut self) -> Result<CallHeader> {
+        if self.unpack_uint()? != 2 {
+            return Err(RPC_REJECTED_MISMATCH.into());
+        }
+
+        Ok(CallHeader {
+            prog: self.unpack_uint()?,
+            vers: self.unpack_uint()?,
+            proc: self.unpack_uint()?,
+            cred: self.unpack_auth().map_err(|_| RPC_REJECTED_AUTH_ERROR)?,
+            verf: self.unpack_auth().map_err(|_| RPC_REJECTED_AUTH_ERROR)?,
+        })
+    }
+
     fn unpack_reply_header(&mut self) -> Result<ReplyHeader> {
         let reply_stat = self.unpack_uint()?;
         match reply_stat {
@@ -240,18 +302,24 @@ impl<T: xdr::Unpacker> Unpacker for T {
         let n = self.unpack_uint()?;
         match n {
             AUTH_NONE => {
-                let expect_zero = self.unpack_uint()?;
-                if expect_zero != 0 {
-                    // TODO: handle as opaque
-                    // Tenchically this is opaque and undefined but "recommended"
-                    // that length is zero.
-                    Err(INVALID_DATA.into())
-                } else {
+                // Technically this is opaque and undefined but "recommended"
+                // that length is zero.  RFC9289 uses it to carry "STARTTLS".
+                let body = self.unpack_opaque()?;
+                if body.is_empty() {
                     Ok(OpaqueAuth::None)
+                } else {
+                    Ok(OpaqueAuth::Raw(RawAuth { flavor: n, body }))
                 }
             }
             AUTH_SYS => Ok(OpaqueAuth::Sys(self.unpack_auth_sys()?)),
-            _ => Err(INVALID_DATA.into()),
+            AUTH_TLS => {
+                self.unpack_opaque()?;
+                Ok(OpaqueAuth::Tls)
+            }
+            _ => Ok(OpaqueAuth::Raw(RawAuth {
+                flavor: n,
+                body: self.unpack_opaque()?,
+            })),
         }
     }
 
@@ -289,87 +357,609 @@ impl OpaqueAuth {
     }
 }
 
-struct RpcClientReceiver {
-    connection: ReadHalf<TcpStream>,
-    pending: Arc<Mutex<BTreeMap<u32, oneshot::Sender<Bytes>>>>,
-    max_size: u32,
-    throttle: Arc<Throttle>,
+/// A call waiting for its reply
+struct PendingCall {
+    reply: oneshot::Sender<Bytes>,
+    /// The request, kept by reconnecting clients once it was written
+    /// so it can be retransmitted on a new connection
+    request: Option<Message>,
+}
+
+#[derive(Default)]
+struct PendingCalls {
+    calls: BTreeMap<u32, PendingCall>,
+    /// Set once the receiver stopped, no more replies will arrive
+    closed: bool,
+}
+
+impl PendingCalls {
+    /// Drops all waiting callers and rejects new calls
+    fn close(&mut self) {
+        self.closed = true;
+        self.calls.clear();
+    }
+}
+
+type PendingMap = Arc<Mutex<PendingCalls>>;
+
+/// Retransmission policy for datagram transports.  The timeout starts
+/// at `timeout` and doubles after every retransmission, up to
+/// `max_timeout`.
+#[derive(Debug, Clone, Copy)]
+pub struct Retransmit {
+    /// Time to wait for a reply before the first retransmission
+    pub timeout: Duration,
+    /// Upper bound for the backed-off timeout
+    pub max_timeout: Duration,
+    /// Number of retransmissions before the call fails with `TimedOut`
+    pub retries: u32,
+}
+
+impl Default for Retransmit {
+    /// Same defaults as the Linux NFS client over UDP (timeo=11, retrans=3)
+    fn default() -> Self {
+        Retransmit {
+            timeout: Duration::from_millis(1100),
+            max_timeout: Duration::from_secs(60),
+            retries: 3,
+        }
+    }
+}
+
+/// Reconnect policy for stream transports.  After a disconnect the
+/// first attempt is made immediately, then the delay between attempts
+/// starts at `delay` and doubles up to `max_delay`.
+#[derive(Debug, Clone, Copy)]
+pub struct Reconnect {
+    pub delay: Duration,
+    pub max_delay: Duration,
+    /// Number of connection attempts before pending calls fail with
+    /// `ConnectionReset`
+    pub attempts: u32,
+}
+
+impl Default for Reconnect {
+    fn default() -> Self {
+        Reconnect {
+            delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(15),
+            attempts: 10,
+        }
+    }
 }
 
-impl RpcClientReceiver {
+/// Matches a received message with a pending call by xid and hands
+/// the rest of the message to the caller.  Calls from the server are
+/// returned with their xid for the receiver to dispatch.
+fn dispatch_message(pending: &PendingMap, mut buf: Bytes) -> Result<Option<(u32, Bytes)>> {
+    // too short to be an RPC message
+    if buf.remaining() < 8 {
+        return Ok(None);
+    }
+
+    let xid = buf.unpack_uint()?;
+    let msg_type = buf.unpack_uint()?;
+    match msg_type {
+        CALL => return Ok(Some((xid, buf))),
+        REPLY => {
+            let call = {
+                let mut pending = pending.lock().unwrap();
+                pending.calls.remove(&xid)
+            };
+            // an unmatched xid is a duplicate reply to a retransmitted
+            // call or a reply to a call given up on, both are dropped as
+            // is a reply whose caller stopped waiting
+            if let Some(call) = call {
+                let _ = call.reply.send(buf);
+            }
+        }
+        // neither a call nor a reply, dropped
+        _ => (),
+    }
+
+    Ok(None)
+}
+
+/// Reply status and, on success, the XDR encoded results of a callback
+pub type CallbackFuture = Pin<Box<dyn Future<Output = (AcceptedReplyStat, Bytes)> + Send>>;
+
+/// Handles calls made by the server over the client's connection,
+/// e.g. the NFSv4.1 backchannel
+pub trait CallbackDispatcher: Send + Sync {
+    /// Called for every call received, `args` holds the XDR encoded
+    /// arguments following the call header
+    fn dispatch(&self, xid: u32, header: CallHeader, args: Bytes) -> CallbackFuture;
+}
+
+type CallbackSlot = Arc<Mutex<Option<Arc<dyn CallbackDispatcher>>>>;
+
+/// Decodes calls received on a stream transport, runs them through
+/// the registered dispatcher and writes the replies on the same
+/// connection
+#[derive(Clone)]
+struct CallbackHandler {
+    dispatcher: CallbackSlot,
+    connection: Arc<StreamWriter>,
+    broken: Option<Arc<Notify>>,
+}
+
+impl CallbackHandler {
+    fn handle(&self, xid: u32, mut buf: Bytes) {
+        let header = buf.unpack_call_header();
+        let dispatcher = self.dispatcher.lock().unwrap().clone();
+        let handler = self.clone();
+
+        tokio::spawn(async move {
+            let (header, results) = match (header, dispatcher) {
+                (Ok(header), Some(dispatcher)) => {
+                    let (stat, results) = dispatcher.dispatch(xid, header, buf).await;
+                    (accepted(stat), results)
+                }
+                (Ok(_), None) => (accepted(AcceptedReplyStat::ProgUnavail), Bytes::new()),
+                (Err(err), _) => match header_error(err) {
+                    Some(header) => (header, Bytes::new()),
+                    None => return,
+                },
+            };
+
+            handler.reply(xid, header, results).await;
+        });
+    }
+
+    async fn reply(&self, xid: u32, header: ReplyHeader, results: Bytes) {
+        let reply = new_reply(xid, &header, &results);
+        let mut connection = self.connection.lock().await;
+        if connection.broken {
+            return;
+        }
+
+        if connection.writer.write(&reply.into()).await.is_err() {
+            connection.set_broken(self.broken.as_deref());
+        }
+    }
+}
+
+/// Accepted reply header with an AUTH_NONE verifier
+pub(crate) fn accepted(stat: AcceptedReplyStat) -> ReplyHeader {
+    ReplyHeader::Accepted(AcceptedReply {
+        verf: OpaqueAuth::None,
+        stat,
+    })
+}
+
+/// Reply header rejecting a call whose header failed to unpack with
+/// `err`, `None` if the header is garbage and the call is dropped
+pub(crate) fn header_error(err: ErrorCode) -> Option<ReplyHeader> {
+    match err.get() {
+        RPC_REJECTED_MISMATCH => {
+            let mismatch = MismatchInfo { low: 2, high: 2 };
+            Some(ReplyHeader::Denied(RejectedReply::RpcMismatch(mismatch)))
+        }
+        RPC_REJECTED_AUTH_ERROR => Some(ReplyHeader::Denied(RejectedReply::AuthError(
+            AuthStat::BadCred,
+        ))),
+        _ => None,
+    }
+}
+
+/// Builds a reply message to `xid`
+pub(crate) fn new_reply(xid: u32, header: &ReplyHeader, results: &[u8]) -> Bytes {
+    let mut buf = BytesMut::new();
+    buf.pack_uint(xid);
+    buf.pack_uint(REPLY);
+    header.pack_to(&mut buf);
+    buf.put_slice(results);
+
+    buf.freeze()
+}
+
+/// A stream that can be split into read and write halves
+pub trait Stream: AsyncRead + AsyncWrite + Send {}
+
+impl<S: AsyncRead + AsyncWrite + Send> Stream for S {}
+
+type BoxedStream = Pin<Box<dyn Stream>>;
+
+type ConnectFuture = Pin<Box<dyn Future<Output = io::Result<BoxedStream>> + Send>>;
+
+/// Creates a new connection to the server for a reconnecting client
+type Connector = Box<dyn Fn() -> ConnectFuture + Send + Sync>;
+
+/// Write half of a stream transport
+struct StreamConnection {
+    writer: RecordWriter<Pin<Box<dyn AsyncWrite + Send>>>,
+    /// Set by a caller that failed writing, the receiver should
+    /// reconnect
+    broken: bool,
+}
+
+impl StreamConnection {
+    fn new(writer: Pin<Box<dyn AsyncWrite + Send>>, max_fragment: usize) -> StreamConnection {
+        let mut writer = RecordWriter::new(writer);
+        writer.set_max_fragment(max_fragment);
+        StreamConnection {
+            writer,
+            broken: false,
+        }
+    }
+
+    /// Marks the connection broken after a failed write, waking up a
+    /// reconnecting receiver through `broken`
+    fn set_broken(&mut self, broken: Option<&Notify>) {
+        self.broken = true;
+        if let Some(broken) = broken {
+            broken.notify_one();
+        }
+    }
+}
+
+type StreamWriter = tokio::sync::Mutex<StreamConnection>;
+
+struct RpcClientReceiver<R> {
+    connection: R,
+    pending: PendingMap,
+    callbacks: CallbackHandler,
+    max_size: Arc<AtomicU32>,
+}
+
+impl<R: AsyncRead + Unpin> RpcClientReceiver<R> {
     pub async fn run(&mut self) -> Result<()> {
         loop {
-            self.throttle.check().await;
             let mut buf = BytesMut::new();
-            read_packet(&mut self.connection, &mut buf, self.max_size).await?;
-            let mut buf = buf.freeze();
-            if buf.remaining() < 8 {
-                println!("bad packet -- too short");
-                continue;
+            let max_size = self.max_size.load(atomic::Ordering::Relaxed);
+            read_packet(&mut self.connection, &mut buf, max_size).await?;
+            if let Some((xid, call)) = dispatch_message(&self.pending, buf.freeze())? {
+                self.callbacks.handle(xid, call);
             }
+        }
+    }
+}
+
+/// Receive loop for a reconnecting stream client.  When the
+/// connection drops, a new one is established and all calls that were
+/// already written are sent again with their original xids.
+struct RpcReconnectingReceiver {
+    connector: Connector,
+    reconnect: Reconnect,
+    connection: Arc<StreamWriter>,
+    /// Notified by callers that failed writing the request
+    broken: Arc<Notify>,
+    pending: PendingMap,
+    callbacks: CallbackHandler,
+    max_size: Arc<AtomicU32>,
+}
+
+impl RpcReconnectingReceiver {
+    pub async fn run(&mut self, mut read: io::ReadHalf<BoxedStream>) -> Result<()> {
+        loop {
+            let _ = self.receive(&mut read).await;
+            read = self.reconnect().await?;
+        }
+    }
 
-            let xid = buf.unpack_uint()?;
-            let msg_type = buf.unpack_uint()?;
-            match msg_type {
-                CALL => println!("CB not implemented yet"),
-                REPLY => {
-                    let tx = {
-                        let mut pending = self.pending.lock().unwrap();
-                        pending.remove(&xid)
-                    };
-                    match tx {
-                        None => println!("unmatched xid {}", xid),
-                        Some(tx) => {
-                            if let Err(_) = tx.send(buf) {
-                                println!("caller for xid {} dropped", xid);
+    /// Receives replies until the connection breaks
+    async fn receive(&self, read: &mut io::ReadHalf<BoxedStream>) -> Result<()> {
+        loop {
+            let mut buf = BytesMut::new();
+            {
+                // Keep the read going across spurious notifications,
+                // dropping it would lose a partially read packet
+                let max_size = self.max_size.load(atomic::Ordering::Relaxed);
+                let packet = read_packet(read, &mut buf, max_size);
+                tokio::pin!(packet);
+                loop {
+                    tokio::select! {
+                        result = &mut packet => break result?,
+                        _ = self.broken.notified() => {
+                            if self.connection.lock().await.broken {
+                                return Err(CONNECTION_RESET.into());
                             }
                         }
                     }
                 }
-                _ => println!("corrupt packet msg_type={}", msg_type),
             }
+            if let Some((xid, call)) = dispatch_message(&self.pending, buf.freeze())? {
+                self.callbacks.handle(xid, call);
+            }
+        }
+    }
+
+    /// Establishes a new connection and retransmits pending calls,
+    /// writers are blocked until done
+    async fn reconnect(&self) -> Result<io::ReadHalf<BoxedStream>> {
+        let mut connection = self.connection.lock().await;
+        let mut delay = self.reconnect.delay;
+        for attempt in 0..self.reconnect.attempts {
+            if attempt > 0 {
+                tokio::time::sleep(delay).await;
+                delay = std::cmp::min(delay * 2, self.reconnect.max_delay);
+            }
+
+            let (read, write) = match (self.connector)().await {
+                Ok(stream) => tokio::io::split(stream),
+                Err(_) => continue,
+            };
+            *connection = StreamConnection::new(Box::pin(write), connection.writer.max_fragment());
+
+            let requests: Vec<Message> = {
+                let pending = self.pending.lock().unwrap();
+                pending
+                    .calls
+                    .values()
+                    .filter_map(|call| call.request.clone())
+                    .collect()
+            };
+
+            let mut replayed = true;
+            for request in requests {
+                if connection.writer.write(&request).await.is_err() {
+                    replayed = false;
+                    break;
+                }
+            }
+
+            if replayed {
+                return Ok(read);
+            }
+        }
+
+        Err(CONNECTION_RESET.into())
+    }
+}
+
+/// Receive loop for datagram transports, every datagram holds exactly
+/// one RPC message without record marking
+struct RpcDatagramReceiver {
+    socket: Arc<UdpSocket>,
+    pending: PendingMap,
+}
+
+impl RpcDatagramReceiver {
+    pub async fn run(&mut self) -> Result<()> {
+        let mut buf = vec![0; MAX_DATAGRAM_SIZE];
+        loop {
+            let len = match self.socket.recv(&mut buf).await {
+                Ok(len) => len,
+                // ICMP errors for earlier datagrams, e.g. port
+                // unreachable while the server restarts, are reported
+                // by the next receive, the call is retransmitted
+                Err(err) if is_transient(&err) => continue,
+                Err(err) => return Err(err.into()),
+            };
+            // calls from the server are only served on stream
+            // connections, over datagrams they are dropped and the
+            // server retransmits or gives up; malformed datagrams are
+            // dropped as well
+            let _ = dispatch_message(&self.pending, Bytes::copy_from_slice(&buf[..len]));
         }
     }
 }
 
+/// Whether a receive on a datagram socket can be retried
+fn is_transient(err: &std::io::Error) -> bool {
+    use std::io::ErrorKind::*;
+    matches!(
+        err.kind(),
+        ConnectionRefused | ConnectionReset | Interrupted | WouldBlock
+    )
+}
+
+/// Removes a call from the pending map when the caller stops waiting,
+/// whether it completed, timed out or was cancelled
+struct PendingGuard<'a> {
+    pending: &'a PendingMap,
+    xid: u32,
+}
+
+impl Drop for PendingGuard<'_> {
+    fn drop(&mut self) {
+        self.pending.lock().unwrap().calls.remove(&self.xid);
+    }
+}
+
+enum Transport {
+    Stream {
+        connection: Arc<StreamWriter>,
+        /// Set for reconnecting clients, notifies the receiver about
+        /// a failed write
+        broken: Option<Arc<Notify>>,
+    },
+    Datagram {
+        socket: Arc<UdpSocket>,
+        retransmit: Retransmit,
+    },
+}
+
 pub struct RpcClient {
-    connection: tokio::sync::Mutex<WriteHalf<TcpStream>>,
-    pending: Arc<Mutex<BTreeMap<u32, oneshot::Sender<Bytes>>>>,
+    transport: Transport,
+    pending: PendingMap,
+    callbacks: CallbackSlot,
+    /// Default deadline for calls
+    timeout: Option<Duration>,
+    /// Largest reply accepted over a stream transport
+    max_size: Arc<AtomicU32>,
     receiver: tokio::task::JoinHandle<()>,
-    throttle: Arc<Throttle>,
+    /// Flow control for calls
+    throttle: Throttle,
 }
 
 impl RpcClient {
-    pub fn new(connection: TcpStream) -> RpcClient {
+    /// Constructs a client over a stream transport using record
+    /// marking, e.g. a `TcpStream`, a `UnixStream` or an in-memory
+    /// `DuplexStream`
+    pub fn new<S>(connection: S) -> RpcClient
+    where
+        S: AsyncRead + AsyncWrite + Send + 'static,
+    {
         let (read, write) = tokio::io::split(connection);
-        let pending = Arc::new(Mutex::new(BTreeMap::new()));
+        let connection = Arc::new(tokio::sync::Mutex::new(StreamConnection::new(
+            Box::pin(write),
+            DEFAULT_MAX_FRAGMENT,
+        )));
+        let pending: PendingMap = Default::default();
+        let callbacks: CallbackSlot = Default::default();
 
-        let throttle = Arc::new(Throttle::new());
+        let max_size = Arc::new(AtomicU32::new(DEFAULT_MAX_PACKET_SIZE));
         let mut reader = RpcClientReceiver {
             connection: read,
             pending: pending.clone(),
-            max_size: MAX_PACKET_SIZE,
-            throttle: throttle.clone(),
+            callbacks: CallbackHandler {
+                dispatcher: callbacks.clone(),
+                connection: connection.clone(),
+                broken: None,
+            },
+            max_size: max_size.clone(),
+        };
+
+        let receiver = tokio::spawn(async move {
+            let _ = reader.run().await;
+            reader.pending.lock().unwrap().close();
+        });
+
+        RpcClient {
+            transport: Transport::Stream {
+                connection,
+                broken: None,
+            },
+            pending,
+            callbacks,
+            timeout: None,
+            max_size,
+            receiver,
+            throttle: Throttle::new(Default::default()),
+        }
+    }
+
+    /// Constructs a client over a stream returned by `connect`.  When
+    /// the connection drops, `connect` is called again according to
+    /// `reconnect` and calls still waiting for a reply are
+    /// retransmitted with their original xids.  Once the attempts are
+    /// used up, waiting calls fail with `ConnectionReset`.
+    pub async fn new_reconnecting<F, Fut, S>(
+        connect: F,
+        reconnect: Reconnect,
+    ) -> io::Result<RpcClient>
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = io::Result<S>> + Send + 'static,
+        S: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        let connector: Connector = Box::new(move || {
+            let connecting = connect();
+            Box::pin(async move { Ok(Box::pin(connecting.await?) as BoxedStream) })
+        });
+
+        let (read, write) = tokio::io::split(connector().await?);
+        let connection = Arc::new(tokio::sync::Mutex::new(StreamConnection::new(
+            Box::pin(write),
+            DEFAULT_MAX_FRAGMENT,
+        )));
+        let pending: PendingMap = Default::default();
+        let callbacks: CallbackSlot = Default::default();
+        let broken = Arc::new(Notify::new());
+
+        let max_size = Arc::new(AtomicU32::new(DEFAULT_MAX_PACKET_SIZE));
+        let mut reader = RpcReconnectingReceiver {
+            connector,
+            reconnect,
+            connection: connection.clone(),
+            broken: broken.clone(),
+            pending: pending.clone(),
+            callbacks: CallbackHandler {
+                dispatcher: callbacks.clone(),
+                connection: connection.clone(),
+                broken: Some(broken.clone()),
+            },
+            max_size: max_size.clone(),
+        };
+
+        let receiver = tokio::spawn(async move {
+            let _ = reader.run(read).await;
+            reader.pending.lock().unwrap().close();
+        });
+
+        Ok(RpcClient {
+            transport: Transport::Stream {
+                connection,
+                broken: Some(broken),
+            },
+            pending,
+            callbacks,
+            timeout: None,
+            max_size,
+            receiver,
+            throttle: Throttle::new(Default::default()),
+        })
+    }
+
+    /// Constructs a client over UDP.  `socket` must already be
+    /// connected to the server address.  Calls that do not receive a
+    /// reply are retransmitted with the same xid according to
+    /// `retransmit`.
+    pub fn new_udp(socket: UdpSocket, retransmit: Retransmit) -> RpcClient {
+        let socket = Arc::new(socket);
+        let pending: PendingMap = Default::default();
+
+        let mut reader = RpcDatagramReceiver {
+            socket: socket.clone(),
+            pending: pending.clone(),
         };
 
         let receiver = tokio::spawn(async move {
             let _ = reader.run().await;
+            reader.pending.lock().unwrap().close();
         });
 
         RpcClient {
-            connection: tokio::sync::Mutex::new(write),
+            transport: Transport::Datagram { socket, retransmit },
             pending,
+            callbacks: Default::default(),
+            timeout: None,
+            max_size: Arc::new(AtomicU32::new(MAX_DATAGRAM_SIZE as u32)),
             receiver,
-            throttle,
+            throttle: Throttle::new(Default::default()),
         }
     }
 
-    pub fn close_throttle(&self) {
-        self.throttle.close();
+    /// Registers `dispatcher` to handle calls the server makes over
+    /// this client's connection.  Without a dispatcher such calls are
+    /// answered with `PROG_UNAVAIL`.  Datagram transports ignore calls
+    /// from the server.
+    pub fn set_callback_dispatcher(&self, dispatcher: Option<Arc<dyn CallbackDispatcher>>) {
+        *self.callbacks.lock().unwrap() = dispatcher;
+    }
+
+    /// Sets the largest reply accepted over a stream transport, a
+    /// larger reply breaks the connection.  Datagram transports are
+    /// limited by the datagram size instead.
+    pub fn set_max_packet_size(&self, max_size: u32) {
+        if let Transport::Stream { .. } = self.transport {
+            self.max_size.store(max_size, atomic::Ordering::Relaxed);
+        }
+    }
+
+    pub fn max_packet_size(&self) -> u32 {
+        self.max_size.load(atomic::Ordering::Relaxed)
+    }
+
+    /// Limits the calls waiting for a reply and the reply bytes they
+    /// expect, calls wait for capacity before being sent
+    pub fn set_flow_control(&mut self, limits: FlowControl) {
+        self.throttle = Throttle::new(limits);
+    }
+
+    pub fn flow_control(&self) -> FlowControl {
+        self.throttle.limits()
+    }
+
+    /// Returns the number of calls waiting for a reply
+    pub fn outstanding(&self) -> usize {
+        self.pending.lock().unwrap().calls.len()
     }
 
-    pub fn open_throttle(&self) {
-        self.throttle.open();
+    /// Returns how often and how long calls waited for capacity
+    pub fn throttle_stats(&self) -> ThrottleStats {
+        self.throttle.stats()
     }
 
     /// Returns a new xid (RPC transaction ID).
@@ -384,45 +974,200 @@ impl RpcClient {
         XID.fetch_add(1, atomic::Ordering::Relaxed)
     }
 
-    pub async fn call(&self, buf: impl Buf, xid: u32) -> io::Result<Bytes> {
+    /// Sets the default deadline for calls made with `call`, `None`
+    /// waits for a reply indefinitely
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Registers `xid` as waiting for a reply.  The registration is
+    /// removed when the returned guard is dropped.
+    fn register(&self, xid: u32) -> io::Result<(PendingGuard<'_>, oneshot::Receiver<Bytes>)> {
         let (tx, rx) = oneshot::channel();
-        {
+        let mut pending = self.pending.lock().unwrap();
+        if pending.closed {
+            return Err(io::ErrorKind::NotConnected.into());
+        }
+
+        let call = PendingCall {
+            reply: tx,
+            request: None,
+        };
+        pending.calls.insert(xid, call);
+
+        let guard = PendingGuard {
+            pending: &self.pending,
+            xid,
+        };
+
+        Ok((guard, rx))
+    }
+
+    /// Sends the call in `message` and waits for the matching reply, up
+    /// to the client's default deadline.  Record marking is added when
+    /// sending over a stream transport.
+    pub async fn call(&self, message: impl Into<Message>, xid: u32) -> io::Result<Bytes> {
+        self.call_with_timeout(message, xid, self.timeout).await
+    }
+
+    /// Same as `call` for a call expecting a reply of up to
+    /// `reply_size` bytes, which counts against the flow control
+    /// `max_reply_bytes` until the reply is received
+    pub async fn call_sized(
+        &self,
+        message: impl Into<Message>,
+        xid: u32,
+        reply_size: u32,
+    ) -> io::Result<Bytes> {
+        self.call_limited(message.into(), xid, reply_size, self.timeout)
+            .await
+    }
+
+    /// Same as `call` with a deadline of `timeout` for this call
+    /// instead of the client default.  A call that times out or is
+    /// dropped stops waiting and a late reply is discarded.
+    pub async fn call_with_timeout(
+        &self,
+        message: impl Into<Message>,
+        xid: u32,
+        timeout: Option<Duration>,
+    ) -> io::Result<Bytes> {
+        self.call_limited(message.into(), xid, 0, timeout).await
+    }
+
+    /// Waits for flow control capacity, then sends the call.  The
+    /// deadline starts once the call is allowed out.
+    async fn call_limited(
+        &self,
+        message: Message,
+        xid: u32,
+        reply_size: u32,
+        timeout: Option<Duration>,
+    ) -> io::Result<Bytes> {
+        let _permit = self.throttle.acquire(reply_size).await;
+        match timeout {
+            None => self.call_inner(message, xid).await,
+            Some(timeout) => {
+                match tokio::time::timeout(timeout, self.call_inner(message, xid)).await {
+                    Ok(result) => result,
+                    Err(_) => Err(io::ErrorKind::TimedOut.into()),
+                }
+            }
+        }
+    }
+
+    async fn call_inner(&self, request: Message, xid: u32) -> io::Result<Bytes> {
+        let (_guard, rx) = self.register(xid)?;
+
+        match &self.transport {
+            Transport::Stream { connection, broken } => {
+                self.send(connection, broken.as_deref(), request, xid)
+                    .await?;
+                rx.await.map_err(|_| io::ErrorKind::ConnectionReset.into())
+            }
+            Transport::Datagram { socket, retransmit } => {
+                Self::call_datagram(socket, retransmit, request.to_bytes(), rx).await
+            }
+        }
+    }
+
+    /// Sets the largest record fragment sent over a stream transport.
+    /// Has no effect on a datagram transport.
+    pub async fn set_max_fragment(&self, max_fragment: usize) {
+        if let Transport::Stream { connection, .. } = &self.transport {
+            connection
+                .lock()
+                .await
+                .writer
+                .set_max_fragment(max_fragment);
+        }
+    }
+
+    /// Writes `request` to the stream.  On a reconnecting client
+    /// (`broken` is set) the request is recorded for replay before it
+    /// is written, so write errors are left to the receiver to recover
+    /// from.
+    async fn send(
+        &self,
+        connection: &StreamWriter,
+        broken: Option<&Notify>,
+        request: Message,
+        xid: u32,
+    ) -> io::Result<()> {
+        let mut connection = connection.lock().await;
+        if broken.is_some() {
             let mut pending = self.pending.lock().unwrap();
-            pending.insert(xid, tx);
+            match pending.calls.get_mut(&xid) {
+                Some(call) => call.request = Some(request.clone()),
+                None => return Ok(()),
+            }
         }
 
-        self.send(buf).await?;
+        if connection.broken {
+            return match broken {
+                Some(_) => Ok(()),
+                None => Err(io::ErrorKind::NotConnected.into()),
+            };
+        }
 
-        rx.await.map_err(|_| io::ErrorKind::Other.into())
+        match connection.writer.write(&request).await {
+            Ok(()) => Ok(()),
+            Err(err) => {
+                connection.set_broken(broken);
+                match broken {
+                    Some(_) => Ok(()),
+                    None => Err(err),
+                }
+            }
+        }
     }
 
-    async fn send(&self, mut buf: impl Buf) -> io::Result<()> {
-        let mut connection = self.connection.lock().await;
-        while buf.has_remaining() {
-            connection.write_all_buf(&mut buf).await?;
+    /// Sends `buf` as a single datagram, retransmitting with
+    /// exponential backoff until a reply arrives or retries run out
+    async fn call_datagram(
+        socket: &UdpSocket,
+        retransmit: &Retransmit,
+        buf: Bytes,
+        mut rx: oneshot::Receiver<Bytes>,
+    ) -> io::Result<Bytes> {
+        let mut timeout = retransmit.timeout;
+        for _ in 0..=retransmit.retries {
+            socket.send(&buf).await?;
+            match tokio::time::timeout(timeout, &mut rx).await {
+                Ok(reply) => return reply.map_err(|_| io::ErrorKind::ConnectionReset.into()),
+                Err(_) => timeout = std::cmp::min(timeout * 2, retransmit.max_timeout),
+            }
         }
 
-        Ok(())
+        Err(io::ErrorKind::TimedOut.into())
     }
 
     pub fn check_header<B: Buf>(&self, buf: &mut B) -> Result<()> {
-        let header = ReplyHeader::unpack_from(buf)?;
-        match header {
-            ReplyHeader::Accepted(AcceptedReply { stat, .. }) => match stat {
-                AcceptedReplyStat::Success => Ok(()),
-                AcceptedReplyStat::ProgUnavail => Err(RPC_PROG_UNAVAIL.into()),
-                AcceptedReplyStat::ProgMismatch(_) => Err(RPC_PROG_MISMATCH.into()),
-                AcceptedReplyStat::ProcUnavail => Err(RPC_PROC_UNAVAIL.into()),
-                AcceptedReplyStat::GarbageArgs => Err(RPC_GARBAGE_ARGS.into()),
-                AcceptedReplyStat::SystemErr => Err(RPC_SYSTEM_ERR.into()),
-            },
+        reply_verf(ReplyHeader::unpack_from(buf)?).map(|_| ())
+    }
+}
+
+/// Maps `header` to an error unless the call was accepted and
+/// succeeded, returns the reply verifier
+pub(crate) fn reply_verf(header: ReplyHeader) -> Result<OpaqueAuth> {
+    match header {
+        ReplyHeader::Accepted(AcceptedReply { verf, stat }) => match stat {
+            AcceptedReplyStat::Success => Ok(verf),
+            AcceptedReplyStat::ProgUnavail => Err(RPC_PROG_UNAVAIL.into()),
+            AcceptedReplyStat::ProgMismatch(_) => Err(RPC_PROG_MISMATCH.into()),
+            AcceptedReplyStat::ProcUnavail => Err(RPC_PROC_UNAVAIL.into()),
+            AcceptedReplyStat::GarbageArgs => Err(RPC_GARBAGE_ARGS.into()),
+            AcceptedReplyStat::SystemErr => Err(RPC_SYSTEM_ERR.into()),
+        },
 
-            ReplyHeader::Denied(denied) => {
-                match denied {
-                    RejectedReply::RpcMismatch(_) => Err(RPC_REJECTED_MISMATCH.into()),
-                    // TODO: unpack auth error
-                    RejectedReply::AuthError(_) => Err(RPC_REJECTED_AUTH_ERROR.into()),
+        ReplyHeader::Denied(denied) => {
+            match denied {
+                RejectedReply::RpcMismatch(_) => Err(RPC_REJECTED_MISMATCH.into()),
+                RejectedReply::AuthError(AuthStat::CredProblem | AuthStat::CtxProblem) => {
+                    Err(GSS_CONTEXT_PROBLEM.into())
                 }
+                // TODO: unpack auth error
+                RejectedReply::AuthError(_) => Err(RPC_REJECTED_AUTH_ERROR.into()),
             }
         }
     }
@@ -433,3 +1178,413 @@ impl Drop for RpcClient {
         self.receiver.abort();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::{TcpListener, TcpStream};
+
+    #[test]
+    fn auth_sys_length() {
+        let mut buf = BytesMut::new();
+        buf.pack_auth(&OpaqueAuth::new_sys(
+            7,
+            Bytes::from_static(b"host1"),
+            1000,
+            100,
+            vec![4, 24],
+        ));
+
+        // flavor and length, then stamp, 5 byte name padded to 8, uid,
+        // gid, count and 2 gids
+        assert_eq!(buf.len(), 8 + 4 + 4 + 8 + 4 + 4 + 4 + 8);
+        assert_eq!(u32::from_be_bytes(buf[4..8].try_into().unwrap()), 36);
+        let mut buf = buf.freeze();
+        match buf.unpack_auth().unwrap() {
+            OpaqueAuth::Sys(sys) => assert_eq!(sys.gids, vec![4, 24]),
+            auth => panic!("unexpected {:?}", auth),
+        }
+        assert!(buf.is_empty());
+    }
+
+    fn new_call(xid: u32) -> Bytes {
+        let mut buf = BytesMut::new();
+        buf.pack_uint(xid);
+        buf.pack_uint(CALL);
+        buf.freeze()
+    }
+
+    async fn new_udp_pair(retransmit: Retransmit) -> (UdpSocket, RpcClient) {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        socket.connect(server.local_addr().unwrap()).await.unwrap();
+
+        (server, RpcClient::new_udp(socket, retransmit))
+    }
+
+    #[tokio::test]
+    async fn duplex_stream() {
+        let (client_end, mut server_end) = tokio::io::duplex(4096);
+        let client = RpcClient::new(client_end);
+
+        let server_task = tokio::spawn(async move {
+            let mut buf = BytesMut::with_capacity(4096);
+            read_packet(&mut server_end, &mut buf, 4096).await.unwrap();
+            let xid = buf.unpack_uint().unwrap();
+            assert_eq!(buf.unpack_uint().unwrap(), CALL);
+
+            let mut reply = BytesMut::new();
+            reply.pack_uint(LAST_FRAGMENT | 12);
+            reply.pack_uint(xid);
+            reply.pack_uint(REPLY);
+            reply.pack_uint(0x5678);
+            server_end.write_all(&reply).await.unwrap();
+        });
+
+        let xid = RpcClient::next_xid();
+        let mut call = BytesMut::new();
+        call.pack_uint(xid);
+        call.pack_uint(CALL);
+        let mut reply = client.call(call.freeze(), xid).await.unwrap();
+        assert_eq!(reply.unpack_uint().unwrap(), 0x5678);
+        server_task.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn fragmented_call() {
+        let (client_end, mut server_end) = tokio::io::duplex(4096);
+        let client = RpcClient::new(client_end);
+        client.set_max_fragment(4).await;
+
+        let server_task = tokio::spawn(async move {
+            let mut buf = BytesMut::with_capacity(4096);
+            read_packet(&mut server_end, &mut buf, 4096).await.unwrap();
+            let xid = buf.unpack_uint().unwrap();
+            assert_eq!(buf.unpack_uint().unwrap(), CALL);
+            assert_eq!(buf.unpack_opaque().unwrap().as_ref(), b"large");
+
+            let mut reply = BytesMut::new();
+            reply.pack_uint(LAST_FRAGMENT | 8);
+            reply.pack_uint(xid);
+            reply.pack_uint(REPLY);
+            server_end.write_all(&reply).await.unwrap();
+        });
+
+        let xid = RpcClient::next_xid();
+        let mut call = Message::from(new_call(xid));
+        call.push_opaque(Bytes::from_static(b"large"));
+        client.call(call, xid).await.unwrap();
+        server_task.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn max_packet_size() {
+        const LARGE: usize = 3 * 1024 * 1024 / 2;
+
+        let (client_end, mut server_end) = tokio::io::duplex(65536);
+        let client = RpcClient::new(client_end);
+        assert_eq!(client.max_packet_size(), DEFAULT_MAX_PACKET_SIZE);
+        client.set_max_packet_size(2 * 1024 * 1024);
+
+        let server_task = tokio::spawn(async move {
+            let xid = read_call(&mut server_end).await;
+            let mut reply = BytesMut::new();
+            reply.pack_uint(LAST_FRAGMENT | (8 + LARGE as u32));
+            reply.pack_uint(xid);
+            reply.pack_uint(REPLY);
+            reply.resize(reply.len() + LARGE, 0);
+            server_end.write_all(&reply).await.unwrap();
+            server_end
+        });
+
+        let xid = RpcClient::next_xid();
+        let reply = client.call(new_call(xid), xid).await.unwrap();
+        assert_eq!(reply.len(), LARGE);
+        server_task.await.unwrap();
+    }
+
+    struct Doubler;
+
+    impl CallbackDispatcher for Doubler {
+        fn dispatch(&self, _xid: u32, header: CallHeader, mut args: Bytes) -> CallbackFuture {
+            Box::pin(async move {
+                if header.proc != 1 {
+                    return (AcceptedReplyStat::ProcUnavail, Bytes::new());
+                }
+                let mut results = BytesMut::new();
+                results.pack_uint(args.unpack_uint().unwrap() * 2);
+                (AcceptedReplyStat::Success, results.freeze())
+            })
+        }
+    }
+
+    /// Sends a callback from the server end and returns the reply
+    /// following the reply header
+    async fn callback(server_end: &mut io::DuplexStream, xid: u32, arg: u32) -> Result<Bytes> {
+        let mut call = BytesMut::new();
+        call.pack_uint(0);
+        call.pack_uint(xid);
+        CallHeader {
+            prog: 0x40000000,
+            vers: 1,
+            proc: 1,
+            cred: OpaqueAuth::None,
+            verf: OpaqueAuth::None,
+        }
+        .pack_to(&mut call);
+        call.pack_uint(arg);
+        let frag_size = (call.len() - 4) as u32 | LAST_FRAGMENT;
+        (&mut call[0..4]).put_u32(frag_size);
+        server_end.write_all(&call).await.unwrap();
+
+        let mut buf = BytesMut::with_capacity(4096);
+        read_packet(server_end, &mut buf, 4096).await.unwrap();
+        assert_eq!(buf.unpack_uint().unwrap(), xid);
+        assert_eq!(buf.unpack_uint().unwrap(), REPLY);
+        let mut buf = buf.freeze();
+        reply_verf(ReplyHeader::unpack_from(&mut buf)?)?;
+        Ok(buf)
+    }
+
+    #[tokio::test]
+    async fn callback_dispatch() {
+        let (client_end, mut server_end) = tokio::io::duplex(4096);
+        let client = RpcClient::new(client_end);
+
+        let err = callback(&mut server_end, 1, 21).await.unwrap_err();
+        assert_eq!(err.get(), RPC_PROG_UNAVAIL);
+
+        client.set_callback_dispatcher(Some(Arc::new(Doubler)));
+        let mut results = callback(&mut server_end, 2, 21).await.unwrap();
+        assert_eq!(results.unpack_uint().unwrap(), 42);
+    }
+
+    /// Reads one call from `stream` and returns its xid
+    async fn read_call<S: AsyncRead + Unpin>(stream: &mut S) -> u32 {
+        let mut buf = BytesMut::with_capacity(4096);
+        read_packet(stream, &mut buf, 4096).await.unwrap();
+        buf.unpack_uint().unwrap()
+    }
+
+    #[tokio::test]
+    async fn reconnect_replay() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server_task = tokio::spawn(async move {
+            // drop the first connection after receiving the call
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let xid = read_call(&mut stream).await;
+            drop(stream);
+
+            let (mut stream, _) = listener.accept().await.unwrap();
+            assert_eq!(read_call(&mut stream).await, xid);
+
+            let mut reply = BytesMut::new();
+            reply.pack_uint(LAST_FRAGMENT | 12);
+            reply.pack_uint(xid);
+            reply.pack_uint(REPLY);
+            reply.pack_uint(0x9abc);
+            stream.write_all(&reply).await.unwrap();
+            stream
+        });
+
+        let client =
+            RpcClient::new_reconnecting(move || TcpStream::connect(addr), Reconnect::default())
+                .await
+                .unwrap();
+
+        let xid = RpcClient::next_xid();
+        let mut call = BytesMut::new();
+        call.pack_uint(xid);
+        call.pack_uint(CALL);
+        let mut reply = client.call(call.freeze(), xid).await.unwrap();
+        assert_eq!(reply.unpack_uint().unwrap(), 0x9abc);
+        server_task.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn reconnect_give_up() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let reconnect = Reconnect {
+            delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(5),
+            attempts: 3,
+        };
+
+        let server_task = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            read_call(&mut stream).await;
+            // closing the listener makes every reconnect attempt fail
+        });
+
+        let client = RpcClient::new_reconnecting(move || TcpStream::connect(addr), reconnect)
+            .await
+            .unwrap();
+
+        let xid = RpcClient::next_xid();
+        let mut call = BytesMut::new();
+        call.pack_uint(xid);
+        call.pack_uint(CALL);
+        let err = client.call(call.freeze(), xid).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionReset);
+        server_task.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn call_timeout() {
+        let (client_end, mut server_end) = tokio::io::duplex(4096);
+        let mut client = RpcClient::new(client_end);
+        client.set_timeout(Some(Duration::from_millis(10)));
+
+        let xid = RpcClient::next_xid();
+        let mut call = BytesMut::new();
+        call.pack_uint(xid);
+        call.pack_uint(CALL);
+        let err = client.call(call.freeze(), xid).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(client.pending.lock().unwrap().calls.is_empty());
+
+        // a late reply is discarded and does not disturb later calls
+        assert_eq!(read_call(&mut server_end).await, xid);
+        let mut reply = BytesMut::new();
+        reply.pack_uint(LAST_FRAGMENT | 8);
+        reply.pack_uint(xid);
+        reply.pack_uint(REPLY);
+        server_end.write_all(&reply).await.unwrap();
+
+        let xid = RpcClient::next_xid();
+        let mut call = BytesMut::new();
+        call.pack_uint(xid);
+        call.pack_uint(CALL);
+        let server_task = tokio::spawn(async move {
+            assert_eq!(read_call(&mut server_end).await, xid);
+            let mut reply = BytesMut::new();
+            reply.pack_uint(LAST_FRAGMENT | 12);
+            reply.pack_uint(xid);
+            reply.pack_uint(REPLY);
+            reply.pack_uint(0x4321);
+            server_end.write_all(&reply).await.unwrap();
+            server_end
+        });
+
+        let timeout = Some(Duration::from_secs(10));
+        let mut reply = client
+            .call_with_timeout(call.freeze(), xid, timeout)
+            .await
+            .unwrap();
+        assert_eq!(reply.unpack_uint().unwrap(), 0x4321);
+        server_task.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn call_cancelled() {
+        let (client_end, _server_end) = tokio::io::duplex(4096);
+        let client = RpcClient::new(client_end);
+
+        let xid = RpcClient::next_xid();
+        let mut call = BytesMut::new();
+        call.pack_uint(xid);
+        call.pack_uint(CALL);
+        {
+            let call = client.call(call.freeze(), xid);
+            tokio::pin!(call);
+            assert!(futures_poll_once(call.as_mut()).await.is_none());
+            assert!(client.pending.lock().unwrap().calls.contains_key(&xid));
+        }
+
+        assert!(client.pending.lock().unwrap().calls.is_empty());
+    }
+
+    /// Polls `future` once, returns its output if it completed
+    async fn futures_poll_once<F: Future + Unpin>(mut future: F) -> Option<F::Output> {
+        std::future::poll_fn(|cx| match Pin::new(&mut future).poll(cx) {
+            std::task::Poll::Ready(output) => std::task::Poll::Ready(Some(output)),
+            std::task::Poll::Pending => std::task::Poll::Ready(None),
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn udp_retransmit() {
+        let retransmit = Retransmit {
+            timeout: Duration::from_millis(20),
+            max_timeout: Duration::from_millis(100),
+            retries: 3,
+        };
+        let (server, client) = new_udp_pair(retransmit).await;
+
+        let server_task = tokio::spawn(async move {
+            let mut buf = [0u8; 64];
+            // drop the first transmission, answer the retransmission
+            let (first_len, _) = server.recv_from(&mut buf).await.unwrap();
+            let (len, peer) = server.recv_from(&mut buf).await.unwrap();
+            assert_eq!(first_len, len);
+            let mut request = &buf[..len];
+            let xid = request.unpack_uint().unwrap();
+
+            let mut reply = BytesMut::new();
+            reply.pack_uint(xid);
+            reply.pack_uint(REPLY);
+            reply.pack_uint(0x1234);
+            server.send_to(&reply, peer).await.unwrap();
+        });
+
+        let xid = RpcClient::next_xid();
+        let mut reply = client.call(new_call(xid), xid).await.unwrap();
+        assert_eq!(reply.unpack_uint().unwrap(), 0x1234);
+        server_task.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn udp_garbage_and_duplicates() {
+        let (server, client) = new_udp_pair(Retransmit::default()).await;
+
+        let server_task = tokio::spawn(async move {
+            let mut buf = [0u8; 64];
+            for value in [1u32, 2] {
+                let (len, peer) = server.recv_from(&mut buf).await.unwrap();
+                let mut request = &buf[..len];
+                let xid = request.unpack_uint().unwrap();
+
+                let mut reply = BytesMut::new();
+                reply.pack_uint(xid);
+                reply.pack_uint(REPLY);
+                reply.pack_uint(value);
+                // a short datagram, a duplicate and an unknown message
+                // type must not stop the receiver
+                server.send_to(b"abc", peer).await.unwrap();
+                server.send_to(&reply, peer).await.unwrap();
+                server.send_to(&reply, peer).await.unwrap();
+                let mut garbage = BytesMut::new();
+                garbage.pack_uint(xid);
+                garbage.pack_uint(7);
+                server.send_to(&garbage, peer).await.unwrap();
+            }
+        });
+
+        for value in [1u32, 2] {
+            let xid = RpcClient::next_xid();
+            let mut reply = client.call(new_call(xid), xid).await.unwrap();
+            assert_eq!(reply.unpack_uint().unwrap(), value);
+        }
+        server_task.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn udp_timeout() {
+        let retransmit = Retransmit {
+            timeout: Duration::from_millis(10),
+            max_timeout: Duration::from_millis(20),
+            retries: 2,
+        };
+        let (_server, client) = new_udp_pair(retransmit).await;
+
+        let xid = RpcClient::next_xid();
+        let err = client.call(new_call(xid), xid).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(client.pending.lock().unwrap().calls.is_empty());
+    }
+}
@@ -0,0 +1,171 @@
+//! Credential providers supplying the `cred` of each RPC call.
+//!
+//! A client holds an `Arc<dyn CredentialProvider>`.  `SysCredentials`
+//! sends AUTH_SYS with a default identity which can be overridden for
+//! the calls made inside `with_identity`, e.g. by a gateway serving
+//! several users over one client.
+use super::{OpaqueAuth, AUTH_NONE, AUTH_SYS};
+use crate::result::{Result, AUTH_FLAVOR_NOT_SUPPORTED};
+use bytes::Bytes;
+use std::future::Future;
+use std::sync::Arc;
+
+/// Maximum number of supplementary groups in an AUTH_SYS credential
+pub const MAX_GIDS: usize = 16;
+
+/// Supplies the credential sent with each call
+pub trait CredentialProvider: Send + Sync {
+    /// Auth flavor of the credentials returned by `credential`
+    fn flavor(&self) -> u32;
+
+    /// Returns the credential for the next call
+    fn credential(&self) -> OpaqueAuth;
+}
+
+/// User identity carried in an AUTH_SYS credential
+#[derive(Debug, Clone, Default)]
+pub struct Identity {
+    pub uid: u32,
+    pub gid: u32,
+    /// Supplementary groups, only the first `MAX_GIDS` are sent
+    pub gids: Vec<u32>,
+}
+
+impl Identity {
+    pub fn new(uid: u32, gid: u32, gids: Vec<u32>) -> Identity {
+        Identity { uid, gid, gids }
+    }
+}
+
+tokio::task_local! {
+    static IDENTITY: Identity;
+}
+
+/// Runs `f` with `identity` replacing the default identity of any
+/// `SysCredentials` used by calls made from within `f`
+pub async fn with_identity<F: Future>(identity: Identity, f: F) -> F::Output {
+    IDENTITY.scope(identity, f).await
+}
+
+/// AUTH_NONE
+pub struct NoCredentials;
+
+impl CredentialProvider for NoCredentials {
+    fn flavor(&self) -> u32 {
+        AUTH_NONE
+    }
+
+    fn credential(&self) -> OpaqueAuth {
+        OpaqueAuth::None
+    }
+}
+
+/// AUTH_SYS credentials for `machine_name`
+pub struct SysCredentials {
+    stamp: u32,
+    machine_name: Bytes,
+    identity: Identity,
+}
+
+impl SysCredentials {
+    /// Constructs AUTH_SYS credentials sending `identity` unless
+    /// overridden by `with_identity`
+    pub fn new(machine_name: &str, identity: Identity) -> SysCredentials {
+        let stamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs() as u32)
+            .unwrap_or(0);
+
+        SysCredentials {
+            stamp,
+            machine_name: Bytes::copy_from_slice(machine_name.as_bytes()),
+            identity,
+        }
+    }
+
+    fn credential_for(&self, identity: &Identity) -> OpaqueAuth {
+        let gids = &identity.gids[..identity.gids.len().min(MAX_GIDS)];
+        OpaqueAuth::new_sys(
+            self.stamp,
+            self.machine_name.clone(),
+            identity.uid,
+            identity.gid,
+            gids.to_vec(),
+        )
+    }
+}
+
+impl Default for SysCredentials {
+    /// root on "localhost"
+    fn default() -> Self {
+        SysCredentials::new("localhost", Identity::default())
+    }
+}
+
+impl CredentialProvider for SysCredentials {
+    fn flavor(&self) -> u32 {
+        AUTH_SYS
+    }
+
+    fn credential(&self) -> OpaqueAuth {
+        IDENTITY
+            .try_with(|identity| self.credential_for(identity))
+            .unwrap_or_else(|_| self.credential_for(&self.identity))
+    }
+}
+
+/// Picks the credentials to use with a server accepting `flavors`,
+/// e.g. the `auth_flavors` of a MOUNT reply.  `provider` is kept if its
+/// flavor is accepted or the list is empty, otherwise falls back to
+/// AUTH_NONE when accepted.
+pub fn select(
+    provider: &Arc<dyn CredentialProvider>,
+    flavors: &[u32],
+) -> Result<Arc<dyn CredentialProvider>> {
+    if flavors.is_empty() || flavors.contains(&provider.flavor()) {
+        Ok(provider.clone())
+    } else if flavors.contains(&AUTH_NONE) {
+        Ok(Arc::new(NoCredentials))
+    } else {
+        Err(AUTH_FLAVOR_NOT_SUPPORTED.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uid_and_gids(auth: OpaqueAuth) -> (u32, usize) {
+        match auth {
+            OpaqueAuth::Sys(sys) => (sys.uid, sys.gids.len()),
+            auth => panic!("unexpected {:?}", auth),
+        }
+    }
+
+    #[tokio::test]
+    async fn identity_override() {
+        let creds = SysCredentials::new("host", Identity::new(1000, 1000, (0..20).collect()));
+        assert_eq!(uid_and_gids(creds.credential()), (1000, MAX_GIDS));
+
+        let uid = with_identity(Identity::new(42, 42, vec![]), async {
+            uid_and_gids(creds.credential())
+        })
+        .await;
+        assert_eq!(uid, (42, 0));
+    }
+
+    #[test]
+    fn select_flavor() {
+        let sys: Arc<dyn CredentialProvider> = Arc::new(SysCredentials::default());
+        assert_eq!(select(&sys, &[]).unwrap().flavor(), AUTH_SYS);
+        assert_eq!(
+            select(&sys, &[AUTH_NONE, AUTH_SYS]).unwrap().flavor(),
+            AUTH_SYS
+        );
+        assert_eq!(select(&sys, &[6, AUTH_NONE]).unwrap().flavor(), AUTH_NONE);
+        assert_eq!(
+            select(&sys, &[6]).err().unwrap().get(),
+            AUTH_FLAVOR_NOT_SUPPORTED
+        );
+    }
+}
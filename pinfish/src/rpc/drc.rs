@@ -0,0 +1,235 @@
+//! Duplicate request cache for RPC servers.
+//!
+//! Replies to non-idempotent calls are remembered so a retransmitted
+//! call gets the original reply instead of being executed again.  A
+//! retransmission arriving while the original call is still executing
+//! is dropped, the client will retransmit again later.  Calls in
+//! progress count against the capacity, once it is taken by them
+//! further calls are executed without caching their replies.
+use super::server::Call;
+use bytes::Bytes;
+use std::collections::{BTreeMap, HashMap};
+use std::net::SocketAddr;
+use std::sync::Mutex;
+
+/// Number of argument bytes covered by the checksum
+const CHECKSUM_LEN: usize = 256;
+
+/// Identifies a call across retransmissions
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    xid: u32,
+    peer: Option<SocketAddr>,
+    prog: u32,
+    vers: u32,
+    proc: u32,
+    args_len: usize,
+    /// Guards against xid reuse with different arguments
+    checksum: u32,
+}
+
+impl CacheKey {
+    pub fn new(call: &Call) -> CacheKey {
+        // FNV-1a over the start of the arguments
+        let len = call.args.len().min(CHECKSUM_LEN);
+        let checksum = call.args[..len].iter().fold(0x811c9dc5u32, |hash, b| {
+            (hash ^ *b as u32).wrapping_mul(0x01000193)
+        });
+
+        CacheKey {
+            xid: call.xid,
+            peer: call.peer,
+            prog: call.prog,
+            vers: call.vers,
+            proc: call.proc,
+            args_len: call.args.len(),
+            checksum,
+        }
+    }
+}
+
+enum Entry {
+    InProgress,
+    /// The reply and its position in the LRU order
+    Done(Bytes, u64),
+}
+
+#[derive(Default)]
+struct Entries {
+    entries: HashMap<CacheKey, Entry>,
+    /// Completed entries, least recently used first
+    lru: BTreeMap<u64, CacheKey>,
+    next_use: u64,
+}
+
+impl Entries {
+    fn touch(&mut self, key: &CacheKey) -> u64 {
+        let used = self.next_use;
+        self.next_use += 1;
+        self.lru.insert(used, key.clone());
+        used
+    }
+}
+
+/// Tracks up to `capacity` calls in progress or completed, evicting the
+/// least recently used completed call to make room
+pub struct DuplicateRequestCache {
+    capacity: usize,
+    entries: Mutex<Entries>,
+}
+
+/// Result of looking up a call in the cache
+pub enum Lookup<'a> {
+    /// First time the call is seen, it should be executed and the
+    /// reply passed to `InProgress::finish`
+    New(InProgress<'a>),
+    /// The call is still executing, the retransmission should be
+    /// dropped
+    InProgress,
+    /// The call completed with this reply
+    Done(Bytes),
+    /// The cache is taken by calls in progress, the call should be
+    /// executed without caching its reply
+    Full,
+}
+
+/// A call being executed.  The entry is removed if dropped without
+/// calling `finish`, so a later retransmission executes the call again.
+pub struct InProgress<'a> {
+    cache: &'a DuplicateRequestCache,
+    key: Option<CacheKey>,
+}
+
+impl InProgress<'_> {
+    /// Stores the reply for retransmissions of the call
+    pub fn finish(mut self, reply: Bytes) {
+        if let Some(key) = self.key.take() {
+            self.cache.finish(key, reply);
+        }
+    }
+}
+
+impl Drop for InProgress<'_> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.cache.entries.lock().unwrap().entries.remove(&key);
+        }
+    }
+}
+
+impl DuplicateRequestCache {
+    pub fn new(capacity: usize) -> DuplicateRequestCache {
+        DuplicateRequestCache {
+            capacity,
+            entries: Default::default(),
+        }
+    }
+
+    /// Looks up `key`, registering it as in progress if not found
+    pub fn lookup(&self, key: CacheKey) -> Lookup<'_> {
+        let mut entries = self.entries.lock().unwrap();
+        let used = match entries.entries.get(&key) {
+            None => {
+                if entries.entries.len() >= self.capacity {
+                    match entries.lru.pop_first() {
+                        Some((_, old)) => entries.entries.remove(&old),
+                        None => return Lookup::Full,
+                    };
+                }
+                entries.entries.insert(key.clone(), Entry::InProgress);
+                return Lookup::New(InProgress {
+                    cache: self,
+                    key: Some(key),
+                });
+            }
+            Some(Entry::InProgress) => return Lookup::InProgress,
+            Some(Entry::Done(_, used)) => *used,
+        };
+
+        entries.lru.remove(&used);
+        let used = entries.touch(&key);
+        match entries.entries.get_mut(&key) {
+            Some(Entry::Done(reply, entry_used)) => {
+                *entry_used = used;
+                Lookup::Done(reply.clone())
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn finish(&self, key: CacheKey, reply: Bytes) {
+        let mut entries = self.entries.lock().unwrap();
+        let used = entries.touch(&key);
+        entries.entries.insert(key, Entry::Done(reply, used));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc::OpaqueAuth;
+
+    fn key(xid: u32, args: &'static [u8]) -> CacheKey {
+        CacheKey::new(&Call {
+            xid,
+            prog: 100003,
+            vers: 3,
+            proc: 8,
+            cred: OpaqueAuth::None,
+            verf: OpaqueAuth::None,
+            args: Bytes::from_static(args),
+            peer: Some(([127, 0, 0, 1], 700).into()),
+        })
+    }
+
+    #[test]
+    fn lookup_states() {
+        let cache = DuplicateRequestCache::new(2);
+        let Lookup::New(call) = cache.lookup(key(1, b"a")) else {
+            panic!("expected new call");
+        };
+        assert!(matches!(cache.lookup(key(1, b"a")), Lookup::InProgress));
+        // same xid with other arguments is a different call
+        assert!(matches!(cache.lookup(key(1, b"b")), Lookup::New(_)));
+
+        call.finish(Bytes::from_static(b"reply"));
+        match cache.lookup(key(1, b"a")) {
+            Lookup::Done(reply) => assert_eq!(reply.as_ref(), b"reply"),
+            _ => panic!("expected cached reply"),
+        }
+
+        // dropped without finishing, executed again
+        drop(cache.lookup(key(2, b"a")));
+        assert!(matches!(cache.lookup(key(2, b"a")), Lookup::New(_)));
+    }
+
+    #[test]
+    fn lru_eviction() {
+        let cache = DuplicateRequestCache::new(2);
+        for xid in 1..=3 {
+            if let Lookup::New(call) = cache.lookup(key(xid, b"")) {
+                call.finish(Bytes::new());
+            }
+            // keep xid 1 recently used
+            assert!(matches!(cache.lookup(key(1, b"")), Lookup::Done(_)));
+        }
+
+        assert!(matches!(cache.lookup(key(3, b"")), Lookup::Done(_)));
+        assert!(matches!(cache.lookup(key(2, b"")), Lookup::New(_)));
+    }
+
+    #[test]
+    fn full_of_calls_in_progress() {
+        let cache = DuplicateRequestCache::new(2);
+        let Lookup::New(first) = cache.lookup(key(1, b"")) else {
+            panic!("expected new call");
+        };
+        let _second = cache.lookup(key(2, b""));
+        assert!(matches!(cache.lookup(key(3, b"")), Lookup::Full));
+
+        // a completed call makes room again
+        first.finish(Bytes::new());
+        assert!(matches!(cache.lookup(key(3, b"")), Lookup::New(_)));
+        assert!(matches!(cache.lookup(key(1, b"")), Lookup::New(_)));
+    }
+}
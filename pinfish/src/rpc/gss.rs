@@ -0,0 +1,605 @@
+//! RPCSEC_GSS (RFC2203) security flavor.
+//!
+//! The GSS-API mechanism (e.g. Kerberos V5) is abstracted behind the
+//! `Mechanism` trait.  `GssContext` runs the context creation exchange
+//! with the server over the NULL procedure and then protects calls at
+//! the negotiated `Service` level: authentication only (krb5),
+//! integrity (krb5i) or privacy (krb5p).
+use super::{
+    reply_verf, CallHeader, OpaqueAuth, Packer as _, RawAuth, ReplyHeader, RpcClient, CALL,
+};
+use crate::{
+    result::{Result, GSS_CONTEXT_PROBLEM, GSS_FAILURE, INVALID_DATA},
+    xdr::{self, PackTo, Packer, UnpackFrom, Unpacker},
+};
+use bytes::{BufMut, Bytes, BytesMut};
+use pinfish_macros::{PackTo, UnpackFrom};
+use std::sync::atomic::{AtomicU32, Ordering};
+use tokio::sync::Semaphore;
+
+/// Auth flavor number of RPCSEC_GSS
+pub const RPCSEC_GSS: u32 = 6;
+const RPCSEC_GSS_VERS_1: u32 = 1;
+
+/// Sequence numbers must stay below this value, after which the
+/// context has to be established again
+pub const MAXSEQ: u32 = 0x80000000;
+
+const GSS_S_COMPLETE: u32 = 0;
+const GSS_S_CONTINUE_NEEDED: u32 = 1;
+
+/// RFC2203 rpc_gss_proc_t
+#[derive(PackTo, UnpackFrom, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GssProc {
+    Data,
+    Init,
+    ContinueInit,
+    Destroy,
+}
+
+/// RFC2203 rpc_gss_service_t
+#[derive(PackTo, UnpackFrom, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Service {
+    /// Authentication only (krb5)
+    #[xdr(1)]
+    None,
+    /// Checksummed arguments and results (krb5i)
+    Integrity,
+    /// Encrypted arguments and results (krb5p)
+    Privacy,
+}
+
+/// RFC2203 rpc_gss_cred_vers_1_t
+#[derive(PackTo, UnpackFrom, Debug, Clone)]
+pub struct GssCred {
+    pub proc: GssProc,
+    pub seq_num: u32,
+    pub service: Service,
+    pub handle: Bytes,
+}
+
+impl GssCred {
+    /// Returns the credential as a RPCSEC_GSS `OpaqueAuth`
+    pub fn to_auth(&self) -> OpaqueAuth {
+        let mut body = BytesMut::new();
+        body.pack_uint(RPCSEC_GSS_VERS_1);
+        self.pack_to(&mut body);
+        OpaqueAuth::Raw(RawAuth {
+            flavor: RPCSEC_GSS,
+            body: body.freeze(),
+        })
+    }
+
+    /// Extracts the credential from a RPCSEC_GSS `OpaqueAuth`
+    pub fn from_auth(auth: &OpaqueAuth) -> Result<GssCred> {
+        match auth {
+            OpaqueAuth::Raw(RawAuth { flavor, body }) if *flavor == RPCSEC_GSS => {
+                let mut body = body.clone();
+                if body.unpack_uint()? != RPCSEC_GSS_VERS_1 {
+                    return Err(INVALID_DATA.into());
+                }
+                GssCred::unpack_from(&mut body)
+            }
+            _ => Err(INVALID_DATA.into()),
+        }
+    }
+}
+
+/// RFC2203 rpc_gss_init_res
+#[derive(PackTo, UnpackFrom, Debug)]
+pub struct InitRes {
+    pub handle: Bytes,
+    pub gss_major: u32,
+    pub gss_minor: u32,
+    pub seq_window: u32,
+    pub gss_token: Bytes,
+}
+
+/// Outcome of one `Mechanism::init_sec_context` step
+pub enum InitStatus {
+    /// The token must be sent to the server and its reply token fed
+    /// back to `init_sec_context`
+    Continue(Bytes),
+    /// The context is established on the client side, with a last
+    /// token for the server if any
+    Complete(Option<Bytes>),
+}
+
+/// Client side of a GSS-API mechanism with a single security context
+pub trait Mechanism: Send + Sync {
+    /// Advances context establishment, `input` is the token returned
+    /// by the server or `None` for the first step
+    fn init_sec_context(&mut self, input: Option<&[u8]>) -> Result<InitStatus>;
+
+    /// Returns a checksum of `message`
+    fn get_mic(&self, message: &[u8]) -> Result<Bytes>;
+
+    /// Checks `mic` is a checksum of `message` produced by the peer
+    fn verify_mic(&self, message: &[u8], mic: &[u8]) -> Result<()>;
+
+    /// Encrypts and checksums `message`
+    fn wrap(&self, message: &[u8]) -> Result<Bytes>;
+
+    /// Reverses `wrap` applied by the peer
+    fn unwrap(&self, token: &[u8]) -> Result<Bytes>;
+}
+
+/// Sequence window for detecting replayed or stale calls on the
+/// server side of a context, at most 128 sequence numbers wide
+pub struct SeqWindow {
+    size: u32,
+    highest: u32,
+    /// Bit n is set if `highest - n` was seen
+    seen: u128,
+}
+
+impl SeqWindow {
+    pub fn new(size: u32) -> SeqWindow {
+        assert!(size > 0 && size <= 128);
+        SeqWindow {
+            size,
+            highest: 0,
+            seen: 0,
+        }
+    }
+
+    /// Records `seq_num`, returns false if it was already seen, is
+    /// below the window or out of range, in which case the call must
+    /// be silently discarded
+    pub fn accept(&mut self, seq_num: u32) -> bool {
+        if seq_num >= MAXSEQ {
+            return false;
+        }
+
+        if seq_num > self.highest {
+            let shift = seq_num - self.highest;
+            self.seen = if shift >= 128 { 0 } else { self.seen << shift };
+            self.seen |= 1;
+            self.highest = seq_num;
+            return true;
+        }
+
+        let age = self.highest - seq_num;
+        if age >= self.size || self.seen & (1 << age) != 0 {
+            return false;
+        }
+        self.seen |= 1 << age;
+
+        true
+    }
+}
+
+/// An established RPCSEC_GSS context for one program and version
+pub struct GssContext {
+    mech: Box<dyn Mechanism>,
+    prog: u32,
+    vers: u32,
+    service: Service,
+    handle: Bytes,
+    seq_num: AtomicU32,
+    /// Keeps the calls in flight within the server's sequence window
+    window: Semaphore,
+}
+
+impl GssContext {
+    /// Establishes a context with the server for `prog`/`vers` over
+    /// `rpc` using `mech`, to protect calls at the `service` level
+    pub async fn establish(
+        rpc: &RpcClient,
+        prog: u32,
+        vers: u32,
+        mut mech: Box<dyn Mechanism>,
+        service: Service,
+    ) -> Result<GssContext> {
+        let mut cred = GssCred {
+            proc: GssProc::Init,
+            seq_num: 0,
+            service,
+            handle: Bytes::new(),
+        };
+        let mut input = None;
+        // seq_window and reply verifier once the server completes
+        let mut server_complete = None;
+
+        loop {
+            let (token, complete) = match mech.init_sec_context(input.as_deref())? {
+                InitStatus::Continue(token) => (Some(token), false),
+                InitStatus::Complete(token) => (token, true),
+            };
+
+            if let Some(token) = token {
+                if server_complete.is_some() {
+                    return Err(GSS_FAILURE.into());
+                }
+
+                let (res, verf) = Self::init_call(rpc, prog, vers, &cred, &token).await?;
+                match res.gss_major {
+                    GSS_S_COMPLETE => server_complete = Some((res.seq_window, verf)),
+                    GSS_S_CONTINUE_NEEDED => (),
+                    _ => return Err(GSS_FAILURE.into()),
+                }
+                cred.proc = GssProc::ContinueInit;
+                cred.handle = res.handle;
+                input = Some(res.gss_token);
+            }
+
+            if complete {
+                break;
+            }
+        }
+
+        let (seq_window, verf) = server_complete.ok_or(GSS_FAILURE)?;
+        let context = GssContext {
+            mech,
+            prog,
+            vers,
+            service,
+            handle: cred.handle,
+            seq_num: AtomicU32::new(0),
+            window: Semaphore::new((seq_window.max(1) as usize).min(Semaphore::MAX_PERMITS)),
+        };
+        context.verify(&seq_window.to_be_bytes(), &verf)?;
+
+        Ok(context)
+    }
+
+    /// Sends one context creation token to the server
+    async fn init_call(
+        rpc: &RpcClient,
+        prog: u32,
+        vers: u32,
+        cred: &GssCred,
+        token: &[u8],
+    ) -> Result<(InitRes, OpaqueAuth)> {
+        let xid = RpcClient::next_xid();
+        let mut buf = BytesMut::new();
+        buf.pack_uint(xid);
+        CallHeader {
+            prog,
+            vers,
+            proc: 0,
+            cred: cred.to_auth(),
+            verf: OpaqueAuth::None,
+        }
+        .pack_to(&mut buf);
+        buf.pack_opaque(token);
+
+        let mut reply = rpc.call(buf, xid).await?;
+        let verf = reply_verf(ReplyHeader::unpack_from(&mut reply)?)?;
+        Ok((InitRes::unpack_from(&mut reply)?, verf))
+    }
+
+    pub fn service(&self) -> Service {
+        self.service
+    }
+
+    /// Calls `proc` with XDR encoded `args`, returns the XDR encoded
+    /// results once the reply has been verified and unwrapped
+    pub async fn call(&self, rpc: &RpcClient, proc: u32, args: &[u8]) -> Result<Bytes> {
+        let _permit = self.window.acquire().await.map_err(|_| GSS_FAILURE)?;
+        let seq_num = self.next_seq_num()?;
+        let xid = RpcClient::next_xid();
+        let buf = self.new_call(xid, GssProc::Data, seq_num, proc, args)?;
+
+        let mut reply = rpc.call(buf, xid).await?;
+        let verf = reply_verf(ReplyHeader::unpack_from(&mut reply)?)?;
+        self.verify(&seq_num.to_be_bytes(), &verf)?;
+
+        match self.service {
+            Service::None => Ok(reply),
+            Service::Integrity => {
+                let body = reply.unpack_opaque()?;
+                let mic = reply.unpack_opaque()?;
+                self.mech.verify_mic(&body, &mic)?;
+                strip_seq_num(body, seq_num)
+            }
+            Service::Privacy => {
+                let body = self.mech.unwrap(&reply.unpack_opaque()?)?;
+                strip_seq_num(body, seq_num)
+            }
+        }
+    }
+
+    /// Asks the server to discard the context
+    pub async fn destroy(self, rpc: &RpcClient) -> Result<()> {
+        let seq_num = self.next_seq_num()?;
+        let xid = RpcClient::next_xid();
+        let buf = self.new_call(xid, GssProc::Destroy, seq_num, 0, &[])?;
+
+        let mut reply = rpc.call(buf, xid).await?;
+        let verf = reply_verf(ReplyHeader::unpack_from(&mut reply)?)?;
+        self.verify(&seq_num.to_be_bytes(), &verf)
+    }
+
+    fn next_seq_num(&self) -> Result<u32> {
+        let seq_num = self.seq_num.fetch_add(1, Ordering::Relaxed);
+        if seq_num >= MAXSEQ {
+            return Err(GSS_CONTEXT_PROBLEM.into());
+        }
+
+        Ok(seq_num)
+    }
+
+    /// Builds a call message with the verifier computed over the header
+    /// and `args` protected according to the service level
+    fn new_call(
+        &self,
+        xid: u32,
+        gss_proc: GssProc,
+        seq_num: u32,
+        proc: u32,
+        args: &[u8],
+    ) -> Result<Bytes> {
+        let cred = GssCred {
+            proc: gss_proc,
+            seq_num,
+            service: self.service,
+            handle: self.handle.clone(),
+        };
+
+        let mut buf = BytesMut::new();
+        buf.pack_uint(xid);
+        buf.pack_uint(CALL);
+        buf.pack_uint(2); // rpcvers
+        buf.pack_uint(self.prog);
+        buf.pack_uint(self.vers);
+        buf.pack_uint(proc);
+        buf.pack_auth(&cred.to_auth());
+
+        let mic = self.mech.get_mic(&buf)?;
+        buf.pack_auth(&OpaqueAuth::Raw(RawAuth {
+            flavor: RPCSEC_GSS,
+            body: mic,
+        }));
+
+        if gss_proc != GssProc::Data || self.service == Service::None {
+            buf.put_slice(args);
+            return Ok(buf.freeze());
+        }
+
+        let mut body = BytesMut::with_capacity(args.len() + 4);
+        body.pack_uint(seq_num);
+        body.put_slice(args);
+        if self.service == Service::Integrity {
+            buf.pack_opaque(&body);
+            buf.pack_opaque(&self.mech.get_mic(&body)?);
+        } else {
+            buf.pack_opaque(&self.mech.wrap(&body)?);
+        }
+
+        Ok(buf.freeze())
+    }
+
+    /// Checks `verf` is a RPCSEC_GSS checksum of `message`
+    fn verify(&self, message: &[u8], verf: &OpaqueAuth) -> Result<()> {
+        match verf {
+            OpaqueAuth::Raw(RawAuth { flavor, body }) if *flavor == RPCSEC_GSS => {
+                self.mech.verify_mic(message, body)
+            }
+            _ => Err(GSS_FAILURE.into()),
+        }
+    }
+}
+
+/// Checks and removes the sequence number preceding protected
+/// arguments or results
+fn strip_seq_num(mut body: Bytes, seq_num: u32) -> Result<Bytes> {
+    if body.unpack_uint()? != seq_num {
+        return Err(GSS_FAILURE.into());
+    }
+
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc::{read_packet, record::RecordWriter, Unpacker as _, MSG_ACCEPTED, REPLY};
+    use tokio::io::DuplexStream;
+
+    /// Fake mechanism: a one byte key agreed in two round trips, a
+    /// keyed hash for checksums and XOR for wrapping
+    #[derive(Default)]
+    struct FakeMech {
+        key: u8,
+    }
+
+    impl FakeMech {
+        fn mic(&self, message: &[u8]) -> Bytes {
+            let hash = message.iter().fold(self.key as u32, |hash, b| {
+                hash.wrapping_mul(31).wrapping_add(*b as u32)
+            });
+            Bytes::copy_from_slice(&hash.to_be_bytes())
+        }
+
+        fn xor(&self, message: &[u8]) -> Bytes {
+            message.iter().map(|b| b ^ self.key).collect()
+        }
+    }
+
+    impl Mechanism for FakeMech {
+        fn init_sec_context(&mut self, input: Option<&[u8]>) -> Result<InitStatus> {
+            match input {
+                None => Ok(InitStatus::Continue(Bytes::from_static(b"hello"))),
+                Some([key]) => {
+                    self.key = *key;
+                    Ok(InitStatus::Complete(Some(Bytes::from_static(b"ack"))))
+                }
+                Some(_) => Err(GSS_FAILURE.into()),
+            }
+        }
+
+        fn get_mic(&self, message: &[u8]) -> Result<Bytes> {
+            Ok(self.mic(message))
+        }
+
+        fn verify_mic(&self, message: &[u8], mic: &[u8]) -> Result<()> {
+            if self.mic(message).as_ref() == mic {
+                Ok(())
+            } else {
+                Err(GSS_FAILURE.into())
+            }
+        }
+
+        fn wrap(&self, message: &[u8]) -> Result<Bytes> {
+            Ok(self.xor(message))
+        }
+
+        fn unwrap(&self, token: &[u8]) -> Result<Bytes> {
+            Ok(self.xor(token))
+        }
+    }
+
+    const KEY: u8 = 0x5a;
+
+    async fn send_reply(stream: &mut DuplexStream, xid: u32, verf: OpaqueAuth, body: &[u8]) {
+        let mut reply = BytesMut::new();
+        reply.pack_uint(xid);
+        reply.pack_uint(REPLY);
+        reply.pack_uint(MSG_ACCEPTED);
+        reply.pack_auth(&verf);
+        reply.pack_uint(0); // SUCCESS
+        reply.put_slice(body);
+        RecordWriter::new(stream)
+            .write(&reply.into())
+            .await
+            .unwrap();
+    }
+
+    /// Serves context creation and calls adding one to a u32 argument
+    /// until the context is destroyed
+    async fn serve(mut stream: DuplexStream) {
+        let mech = FakeMech { key: KEY };
+        let mut window = SeqWindow::new(4);
+        loop {
+            let mut buf = BytesMut::with_capacity(4096);
+            read_packet(&mut stream, &mut buf, 4096).await.unwrap();
+            let packet = buf.freeze();
+            let mut buf = packet.clone();
+            let xid = buf.unpack_uint().unwrap();
+            assert_eq!(buf.unpack_uint().unwrap(), CALL);
+            for _ in 0..4 {
+                buf.unpack_uint().unwrap(); // rpcvers, prog, vers, proc
+            }
+            let cred = GssCred::from_auth(&buf.unpack_auth().unwrap()).unwrap();
+            let header_len = packet.len() - buf.len();
+            let verf = buf.unpack_auth().unwrap();
+
+            let verf_mic = |message: &[u8]| {
+                OpaqueAuth::Raw(RawAuth {
+                    flavor: RPCSEC_GSS,
+                    body: mech.mic(message),
+                })
+            };
+
+            let token = match cred.proc {
+                GssProc::Init | GssProc::ContinueInit => buf.unpack_opaque().unwrap(),
+                _ => {
+                    let OpaqueAuth::Raw(RawAuth { body, .. }) = verf else {
+                        panic!("unexpected verifier");
+                    };
+                    mech.verify_mic(&packet[..header_len], &body).unwrap();
+                    assert!(window.accept(cred.seq_num));
+                    Bytes::new()
+                }
+            };
+
+            let mut res = BytesMut::new();
+            match cred.proc {
+                GssProc::Init => {
+                    assert_eq!(token.as_ref(), b"hello");
+                    InitRes {
+                        handle: Bytes::from_static(b"ctx"),
+                        gss_major: GSS_S_CONTINUE_NEEDED,
+                        gss_minor: 0,
+                        seq_window: 0,
+                        gss_token: Bytes::copy_from_slice(&[KEY]),
+                    }
+                    .pack_to(&mut res);
+                    send_reply(&mut stream, xid, OpaqueAuth::None, &res).await;
+                }
+                GssProc::ContinueInit => {
+                    assert_eq!(token.as_ref(), b"ack");
+                    assert_eq!(cred.handle.as_ref(), b"ctx");
+                    InitRes {
+                        handle: cred.handle,
+                        gss_major: GSS_S_COMPLETE,
+                        gss_minor: 0,
+                        seq_window: 4,
+                        gss_token: Bytes::new(),
+                    }
+                    .pack_to(&mut res);
+                    let verf = verf_mic(&4u32.to_be_bytes());
+                    send_reply(&mut stream, xid, verf, &res).await;
+                }
+                GssProc::Data => {
+                    let mut args = match cred.service {
+                        Service::None => buf,
+                        Service::Integrity => {
+                            let body = buf.unpack_opaque().unwrap();
+                            let mic = buf.unpack_opaque().unwrap();
+                            mech.verify_mic(&body, &mic).unwrap();
+                            strip_seq_num(body, cred.seq_num).unwrap()
+                        }
+                        Service::Privacy => {
+                            let body = mech.unwrap(&buf.unpack_opaque().unwrap()).unwrap();
+                            strip_seq_num(body, cred.seq_num).unwrap()
+                        }
+                    };
+
+                    let mut body = BytesMut::new();
+                    body.pack_uint(cred.seq_num);
+                    body.pack_uint(args.unpack_uint().unwrap() + 1);
+                    match cred.service {
+                        Service::None => res.put_slice(&body[4..]),
+                        Service::Integrity => {
+                            res.pack_opaque(&body);
+                            res.pack_opaque(&mech.mic(&body));
+                        }
+                        Service::Privacy => res.pack_opaque(&mech.xor(&body)),
+                    }
+                    let verf = verf_mic(&cred.seq_num.to_be_bytes());
+                    send_reply(&mut stream, xid, verf, &res).await;
+                }
+                GssProc::Destroy => {
+                    let verf = verf_mic(&cred.seq_num.to_be_bytes());
+                    send_reply(&mut stream, xid, verf, &[]).await;
+                    return;
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn gss_services() {
+        for service in [Service::None, Service::Integrity, Service::Privacy] {
+            let (client_end, server_end) = tokio::io::duplex(4096);
+            let server_task = tokio::spawn(serve(server_end));
+            let rpc = RpcClient::new(client_end);
+
+            let mech = Box::<FakeMech>::default();
+            let context = GssContext::establish(&rpc, 100003, 3, mech, service)
+                .await
+                .unwrap();
+            for n in 0..6u32 {
+                let mut res = context.call(&rpc, 1, &n.to_be_bytes()).await.unwrap();
+                assert_eq!(res.unpack_uint().unwrap(), n + 1);
+            }
+            context.destroy(&rpc).await.unwrap();
+            server_task.await.unwrap();
+        }
+    }
+
+    #[test]
+    fn seq_window() {
+        let mut window = SeqWindow::new(4);
+        assert!(window.accept(0));
+        assert!(window.accept(2));
+        assert!(window.accept(1));
+        assert!(!window.accept(1));
+        assert!(window.accept(10));
+        assert!(window.accept(7));
+        assert!(!window.accept(6));
+        assert!(!window.accept(MAXSEQ));
+    }
+}
@@ -0,0 +1,201 @@
+//! Several connections to one server used as one client (nconnect).
+//!
+//! A single connection serializes all writes, spreading calls over
+//! several connections lets large transfers use more of the link.
+//! Each call goes out on one connection picked according to the
+//! pool's `Balance` policy.
+use super::{record::Message, CallbackDispatcher, FlowControl, RpcClient, ThrottleStats};
+use bytes::Bytes;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io;
+
+/// How calls are spread over the connections of a pool
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Balance {
+    /// Each connection in turn
+    #[default]
+    RoundRobin,
+    /// The connection with the fewest calls waiting for a reply
+    LeastOutstanding,
+}
+
+/// Connections to one server used as one client
+pub struct RpcPool {
+    clients: Vec<RpcClient>,
+    balance: Balance,
+    /// Next connection for round robin
+    next: AtomicUsize,
+}
+
+impl RpcPool {
+    /// Constructs a pool of `clients`, which should all be connected to
+    /// the same server
+    pub fn new(clients: Vec<RpcClient>, balance: Balance) -> RpcPool {
+        assert!(!clients.is_empty());
+        RpcPool {
+            clients,
+            balance,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn clients(&self) -> &[RpcClient] {
+        &self.clients
+    }
+
+    pub fn balance(&self) -> Balance {
+        self.balance
+    }
+
+    /// Returns the connection for the next call
+    pub fn pick(&self) -> &RpcClient {
+        match self.balance {
+            Balance::RoundRobin => {
+                let next = self.next.fetch_add(1, Ordering::Relaxed);
+                &self.clients[next % self.clients.len()]
+            }
+            Balance::LeastOutstanding => {
+                // start at a rotating index so ties are spread out
+                let start = self.next.fetch_add(1, Ordering::Relaxed);
+                let len = self.clients.len();
+                (0..len)
+                    .map(|i| &self.clients[(start + i) % len])
+                    .min_by_key(|client| client.outstanding())
+                    .unwrap()
+            }
+        }
+    }
+
+    /// Sends the call on the connection picked by `pick`
+    pub async fn call(&self, message: impl Into<Message>, xid: u32) -> io::Result<Bytes> {
+        self.pick().call(message, xid).await
+    }
+
+    /// Sets the default deadline for calls on all connections
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        for client in &mut self.clients {
+            client.set_timeout(timeout);
+        }
+    }
+
+    /// Sets flow control limits for each connection
+    pub fn set_flow_control(&mut self, limits: FlowControl) {
+        for client in &mut self.clients {
+            client.set_flow_control(limits);
+        }
+    }
+
+    pub fn set_max_packet_size(&self, max_size: u32) {
+        for client in &self.clients {
+            client.set_max_packet_size(max_size);
+        }
+    }
+
+    pub fn set_callback_dispatcher(&self, dispatcher: Option<Arc<dyn CallbackDispatcher>>) {
+        for client in &self.clients {
+            client.set_callback_dispatcher(dispatcher.clone());
+        }
+    }
+
+    /// Returns the flow control waits summed over all connections
+    pub fn throttle_stats(&self) -> ThrottleStats {
+        self.clients
+            .iter()
+            .map(|client| client.throttle_stats())
+            .fold(ThrottleStats::default(), |total, stats| ThrottleStats {
+                immediate: total.immediate + stats.immediate,
+                waited: total.waited + stats.waited,
+                wait_time: total.wait_time + stats.wait_time,
+                max_wait: total.max_wait.max(stats.max_wait),
+            })
+    }
+}
+
+impl From<RpcClient> for RpcPool {
+    fn from(client: RpcClient) -> RpcPool {
+        RpcPool::new(vec![client], Default::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc::{read_packet, CALL, LAST_FRAGMENT, REPLY};
+    use crate::xdr::{Packer as _, Unpacker as _};
+    use bytes::BytesMut;
+    use tokio::io::{AsyncWriteExt, DuplexStream};
+
+    /// Answers calls on `stream` with the index of the connection
+    async fn answer(mut stream: DuplexStream, index: u32) {
+        loop {
+            let mut buf = BytesMut::with_capacity(4096);
+            if read_packet(&mut stream, &mut buf, 4096).await.is_err() {
+                return;
+            }
+            let xid = buf.unpack_uint().unwrap();
+            let mut reply = BytesMut::new();
+            reply.pack_uint(LAST_FRAGMENT | 12);
+            reply.pack_uint(xid);
+            reply.pack_uint(REPLY);
+            reply.pack_uint(index);
+            stream.write_all(&reply).await.unwrap();
+        }
+    }
+
+    fn new_pool(balance: Balance, connections: u32) -> RpcPool {
+        let clients = (0..connections)
+            .map(|index| {
+                let (client_end, server_end) = tokio::io::duplex(4096);
+                tokio::spawn(answer(server_end, index));
+                RpcClient::new(client_end)
+            })
+            .collect();
+        RpcPool::new(clients, balance)
+    }
+
+    async fn call(pool: &RpcPool) -> u32 {
+        let xid = RpcClient::next_xid();
+        let mut call = BytesMut::new();
+        call.pack_uint(xid);
+        call.pack_uint(CALL);
+        pool.call(call, xid).await.unwrap().unpack_uint().unwrap()
+    }
+
+    #[tokio::test]
+    async fn round_robin() {
+        let pool = new_pool(Balance::RoundRobin, 3);
+        let mut used = Vec::new();
+        for _ in 0..6 {
+            used.push(call(&pool).await);
+        }
+        assert_eq!(used, vec![0, 1, 2, 0, 1, 2]);
+    }
+
+    #[tokio::test]
+    async fn least_outstanding() {
+        let (client_end, _server_end) = tokio::io::duplex(4096);
+        let (idle_end, server_end) = tokio::io::duplex(4096);
+        tokio::spawn(answer(server_end, 1));
+        let pool = RpcPool::new(
+            vec![RpcClient::new(client_end), RpcClient::new(idle_end)],
+            Balance::LeastOutstanding,
+        );
+
+        // a call that never completes keeps the first connection busy
+        let xid = RpcClient::next_xid();
+        let mut stuck = BytesMut::new();
+        stuck.pack_uint(xid);
+        stuck.pack_uint(CALL);
+        let stuck = pool.clients()[0].call(stuck, xid);
+        tokio::pin!(stuck);
+        assert!(tokio::time::timeout(Duration::from_millis(10), &mut stuck)
+            .await
+            .is_err());
+
+        for _ in 0..4 {
+            assert_eq!(call(&pool).await, 1);
+        }
+    }
+}
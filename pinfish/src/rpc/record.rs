@@ -0,0 +1,239 @@
+//! Record marking (RFC5531 section 11) for stream transports.
+//!
+//! A `Message` is an RPC message without record marking, kept as a
+//! list of buffers so large payloads are written without copying.
+//! `RecordWriter` splits messages into fragments and writes them with
+//! vectored writes.
+use super::LAST_FRAGMENT;
+use crate::xdr::Packer as _;
+use bytes::{Buf, Bytes, BytesMut};
+use std::collections::VecDeque;
+use std::io::IoSlice;
+use tokio::io::{self, AsyncWrite, AsyncWriteExt};
+
+/// Default largest fragment written by `RecordWriter`
+pub const DEFAULT_MAX_FRAGMENT: usize = 1024 * 1024;
+
+/// Upper bound on buffers passed to a single vectored write
+const MAX_IOV: usize = 64;
+
+/// An RPC message made of one or more buffers
+#[derive(Debug, Clone, Default)]
+pub struct Message {
+    parts: Vec<Bytes>,
+    len: usize,
+}
+
+impl Message {
+    pub fn new() -> Message {
+        Default::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn parts(&self) -> &[Bytes] {
+        &self.parts
+    }
+
+    /// Appends `part` without copying it
+    pub fn push(&mut self, part: Bytes) {
+        if !part.is_empty() {
+            self.len += part.len();
+            self.parts.push(part);
+        }
+    }
+
+    /// Appends XDR variable-length opaque data without copying `data`
+    pub fn push_opaque(&mut self, data: Bytes) {
+        static PADDING: [u8; 3] = [0; 3];
+        let pad = (4 - data.len() % 4) % 4;
+
+        let mut len = BytesMut::with_capacity(4);
+        len.pack_uint(data.len() as u32);
+        self.push(len.freeze());
+        self.push(data);
+        self.push(Bytes::from_static(&PADDING[..pad]));
+    }
+
+    /// Returns the message in one buffer, copying only if it is made
+    /// of several parts
+    pub fn to_bytes(&self) -> Bytes {
+        match self.parts.as_slice() {
+            [] => Bytes::new(),
+            [part] => part.clone(),
+            parts => {
+                let mut buf = BytesMut::with_capacity(self.len);
+                for part in parts {
+                    buf.extend_from_slice(part);
+                }
+                buf.freeze()
+            }
+        }
+    }
+}
+
+impl From<Bytes> for Message {
+    fn from(part: Bytes) -> Message {
+        let mut message = Message::new();
+        message.push(part);
+        message
+    }
+}
+
+impl From<BytesMut> for Message {
+    fn from(part: BytesMut) -> Message {
+        part.freeze().into()
+    }
+}
+
+/// Writes messages to a stream as records of one or more fragments.
+///
+/// Writing is cancellation safe: if a write is dropped after part of a
+/// record went out, the next write completes that record first so the
+/// peer never sees a truncated record.
+pub struct RecordWriter<W> {
+    writer: W,
+    max_fragment: usize,
+    /// Buffers of the record being written, including fragment headers
+    pending: VecDeque<Bytes>,
+    /// Bytes of the record being written, and of those still pending
+    record_len: usize,
+    remaining: usize,
+}
+
+impl<W: AsyncWrite + Unpin> RecordWriter<W> {
+    pub fn new(writer: W) -> RecordWriter<W> {
+        RecordWriter {
+            writer,
+            max_fragment: DEFAULT_MAX_FRAGMENT,
+            pending: VecDeque::new(),
+            record_len: 0,
+            remaining: 0,
+        }
+    }
+
+    /// Sets the largest fragment size, messages bigger than this are
+    /// split into several fragments
+    pub fn set_max_fragment(&mut self, max_fragment: usize) {
+        assert!(max_fragment > 0 && max_fragment <= !LAST_FRAGMENT as usize);
+        self.max_fragment = max_fragment;
+    }
+
+    pub fn max_fragment(&self) -> usize {
+        self.max_fragment
+    }
+
+    /// Writes `message` as one record
+    pub async fn write(&mut self, message: &Message) -> io::Result<()> {
+        if self.remaining != self.record_len {
+            self.write_pending().await?;
+        }
+
+        self.pending.clear();
+        self.record_len = 0;
+        let mut fragment_len = 0;
+        let mut parts = message.parts().iter().cloned();
+        let mut part = parts.next();
+        loop {
+            let len = std::cmp::min(message.len() - fragment_len, self.max_fragment);
+            fragment_len += len;
+            let mut header = BytesMut::with_capacity(4);
+            if fragment_len == message.len() {
+                header.pack_uint(len as u32 | LAST_FRAGMENT);
+            } else {
+                header.pack_uint(len as u32);
+            }
+            self.pending.push_back(header.freeze());
+
+            let mut needed = len;
+            while needed > 0 {
+                let current = part.as_mut().expect("message length matches its parts");
+                let chunk = current.split_to(std::cmp::min(needed, current.len()));
+                needed -= chunk.len();
+                self.pending.push_back(chunk);
+                if current.is_empty() {
+                    part = parts.next();
+                }
+            }
+
+            if fragment_len == message.len() {
+                break;
+            }
+        }
+
+        self.record_len = self.pending.iter().map(|chunk| chunk.len()).sum();
+        self.remaining = self.record_len;
+        self.write_pending().await
+    }
+
+    async fn write_pending(&mut self) -> io::Result<()> {
+        while !self.pending.is_empty() {
+            let slices: Vec<IoSlice> = self
+                .pending
+                .iter()
+                .take(MAX_IOV)
+                .map(|chunk| IoSlice::new(chunk))
+                .collect();
+            let mut written = self.writer.write_vectored(&slices).await?;
+            if written == 0 {
+                return Err(io::ErrorKind::WriteZero.into());
+            }
+
+            self.remaining -= written;
+            while written > 0 {
+                let chunk = self.pending.front_mut().unwrap();
+                if chunk.len() <= written {
+                    written -= chunk.len();
+                    self.pending.pop_front();
+                } else {
+                    chunk.advance(written);
+                    written = 0;
+                }
+            }
+        }
+
+        self.writer.flush().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc::read_packet;
+    use tokio::io::AsyncReadExt;
+
+    #[tokio::test]
+    async fn fragments() {
+        let (client_end, mut server_end) = tokio::io::duplex(4096);
+        let mut writer = RecordWriter::new(client_end);
+        writer.set_max_fragment(8);
+
+        let mut message = Message::from(Bytes::from_static(b"header"));
+        message.push_opaque(Bytes::from_static(b"0123456789"));
+        assert_eq!(message.len(), 6 + 4 + 12);
+        writer.write(&message).await.unwrap();
+        writer
+            .write(&Bytes::from_static(b"next").into())
+            .await
+            .unwrap();
+
+        // 22 bytes in fragments of 8, 8 and 6
+        let mut raw = [0u8; 4 + 8];
+        server_end.read_exact(&mut raw).await.unwrap();
+        assert_eq!(&raw, b"\x00\x00\x00\x08header\x00\x00");
+
+        let mut buf = BytesMut::new();
+        read_packet(&mut server_end, &mut buf, 4096).await.unwrap();
+        assert_eq!(&buf[..], b"\x00\x0a0123456789\x00\x00");
+
+        let mut buf = BytesMut::new();
+        read_packet(&mut server_end, &mut buf, 4096).await.unwrap();
+        assert_eq!(&buf[..], b"next");
+    }
+}
@@ -0,0 +1,117 @@
+//! Reserved source ports.
+//!
+//! Servers exporting with the `secure` option only accept calls from
+//! source ports below 1024, which only a privileged process can bind.
+//! Ports are tried from the top of the range down, like the Linux
+//! client does, skipping those already in use.
+use crate::result::{
+    ErrorCode, Result, NOT_CONNECTED, RESERVED_PORTS_EXHAUSTED, RESERVED_PORT_DENIED,
+};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use tokio::io;
+use tokio::net::{lookup_host, TcpSocket, TcpStream, ToSocketAddrs, UdpSocket};
+
+/// Lowest and highest reserved port tried, the Linux defaults
+pub const MIN_RESVPORT: u16 = 665;
+pub const MAX_RESVPORT: u16 = 1023;
+
+/// Returns the unspecified address of the family of `peer` on `port`
+fn local_addr(peer: &SocketAddr, port: u16) -> SocketAddr {
+    if peer.is_ipv4() {
+        (Ipv4Addr::UNSPECIFIED, port).into()
+    } else {
+        (Ipv6Addr::UNSPECIFIED, port).into()
+    }
+}
+
+/// Maps a bind error, `None` if the next port should be tried
+fn bind_error(err: io::Error) -> Option<ErrorCode> {
+    match err.kind() {
+        io::ErrorKind::AddrInUse => None,
+        io::ErrorKind::PermissionDenied => Some(RESERVED_PORT_DENIED.into()),
+        _ => Some(err.into()),
+    }
+}
+
+async fn resolve<A: ToSocketAddrs>(addr: A) -> Result<SocketAddr> {
+    match lookup_host(addr).await?.next() {
+        Some(addr) => Ok(addr),
+        None => Err(NOT_CONNECTED.into()),
+    }
+}
+
+/// Connects a TCP stream to `addr` from a reserved port
+pub async fn connect_tcp<A: ToSocketAddrs>(addr: A) -> Result<TcpStream> {
+    let addr = resolve(addr).await?;
+    for port in (MIN_RESVPORT..=MAX_RESVPORT).rev() {
+        let socket = if addr.is_ipv4() {
+            TcpSocket::new_v4()?
+        } else {
+            TcpSocket::new_v6()?
+        };
+        // ports in TIME_WAIT from earlier connections can be reused
+        socket.set_reuseaddr(true)?;
+        if let Err(err) = socket.bind(local_addr(&addr, port)) {
+            match bind_error(err) {
+                None => continue,
+                Some(err) => return Err(err),
+            }
+        }
+
+        match socket.connect(addr).await {
+            Ok(stream) => return Ok(stream),
+            // the same port is connected to this server already
+            Err(err)
+                if err.kind() == io::ErrorKind::AddrInUse
+                    || err.kind() == io::ErrorKind::AddrNotAvailable =>
+            {
+                continue
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    Err(RESERVED_PORTS_EXHAUSTED.into())
+}
+
+/// Binds a UDP socket to a reserved port and connects it to `addr`
+pub async fn bind_udp<A: ToSocketAddrs>(addr: A) -> Result<UdpSocket> {
+    let addr = resolve(addr).await?;
+    for port in (MIN_RESVPORT..=MAX_RESVPORT).rev() {
+        match UdpSocket::bind(local_addr(&addr, port)).await {
+            Ok(socket) => {
+                socket.connect(addr).await?;
+                return Ok(socket);
+            }
+            Err(err) => match bind_error(err) {
+                None => continue,
+                Some(err) => return Err(err),
+            },
+        }
+    }
+
+    Err(RESERVED_PORTS_EXHAUSTED.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn reserved_source_port() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // only a privileged process gets a reserved port
+        match connect_tcp(addr).await {
+            Ok(stream) => {
+                let port = stream.local_addr().unwrap().port();
+                assert!((MIN_RESVPORT..=MAX_RESVPORT).contains(&port));
+                let (_, peer) = listener.accept().await.unwrap();
+                assert_eq!(peer.port(), port);
+            }
+            Err(err) => assert_eq!(err.get(), RESERVED_PORT_DENIED),
+        }
+    }
+}
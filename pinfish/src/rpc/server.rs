@@ -0,0 +1,536 @@
+//! Server side of ONC RPC.
+//!
+//! `RpcServer` accepts calls over TCP, UDP or any stream, and hands
+//! them to the `Dispatcher` registered for the called program and
+//! version.  Dispatchers return the XDR encoded results, errors are
+//! turned into the matching RPC reply status.
+use super::{
+    accepted, auth,
+    drc::{CacheKey, DuplicateRequestCache, Lookup},
+    header_error, new_reply, read_packet,
+    record::RecordWriter,
+    AcceptedReplyStat, AuthStat, AuthSys, MismatchInfo, OpaqueAuth, RejectedReply, ReplyHeader,
+    Unpacker as _, CALL, DEFAULT_MAX_PACKET_SIZE, MAX_DATAGRAM_SIZE,
+};
+use crate::{
+    result::{
+        ErrorCode, Result, INVALID_DATA, NOT_ENOUGH_DATA, RPC_GARBAGE_ARGS, RPC_PROC_UNAVAIL,
+        RPC_REJECTED_AUTH_ERROR,
+    },
+    xdr::Unpacker as _,
+};
+use bytes::{Bytes, BytesMut};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::io::{self, AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, UdpSocket};
+use tokio::sync::Semaphore;
+
+/// A call received by the server
+#[derive(Debug)]
+pub struct Call {
+    pub xid: u32,
+    pub prog: u32,
+    pub vers: u32,
+    pub proc: u32,
+    pub cred: OpaqueAuth,
+    pub verf: OpaqueAuth,
+    /// XDR encoded arguments
+    pub args: Bytes,
+    /// Address of the client, if known
+    pub peer: Option<SocketAddr>,
+}
+
+impl Call {
+    /// Returns the AUTH_SYS credential of the call, if any
+    pub fn auth_sys(&self) -> Option<&AuthSys> {
+        match &self.cred {
+            OpaqueAuth::Sys(sys) => Some(sys),
+            _ => None,
+        }
+    }
+
+    /// Returns the identity carried by an AUTH_SYS credential
+    pub fn identity(&self) -> Option<auth::Identity> {
+        self.auth_sys()
+            .map(|sys| auth::Identity::new(sys.uid, sys.gid, sys.gids.clone()))
+    }
+}
+
+/// XDR encoded results of a call.  `RPC_PROC_UNAVAIL` is replied as
+/// `PROC_UNAVAIL`, unpacking errors and `RPC_GARBAGE_ARGS` as
+/// `GARBAGE_ARGS`, `RPC_REJECTED_AUTH_ERROR` as `AUTH_TOOWEAK` and any
+/// other error as `SYSTEM_ERR`.
+pub type DispatchFuture = Pin<Box<dyn Future<Output = Result<Bytes>> + Send>>;
+
+/// Handles the calls to one version of a program
+pub trait Dispatcher: Send + Sync {
+    fn dispatch(&self, call: Call) -> DispatchFuture;
+
+    /// Whether replies to `proc` go through the duplicate request
+    /// cache, true for non-idempotent procedures
+    fn cacheable(&self, _proc: u32) -> bool {
+        false
+    }
+}
+
+/// Calls of one connection or UDP socket handled at the same time by
+/// default
+pub const DEFAULT_MAX_IN_FLIGHT: usize = 64;
+
+/// Dispatches calls to the registered programs
+pub struct RpcServer {
+    programs: BTreeMap<(u32, u32), Arc<dyn Dispatcher>>,
+    drc: Option<DuplicateRequestCache>,
+    max_in_flight: usize,
+}
+
+impl Default for RpcServer {
+    fn default() -> Self {
+        RpcServer {
+            programs: BTreeMap::new(),
+            drc: None,
+            max_in_flight: DEFAULT_MAX_IN_FLIGHT,
+        }
+    }
+}
+
+impl RpcServer {
+    pub fn new() -> RpcServer {
+        Default::default()
+    }
+
+    /// Registers `dispatcher` for calls to version `vers` of `prog`
+    pub fn register(&mut self, prog: u32, vers: u32, dispatcher: Arc<dyn Dispatcher>) {
+        self.programs.insert((prog, vers), dispatcher);
+    }
+
+    /// Returns the registered programs and versions
+    pub fn programs(&self) -> impl Iterator<Item = (u32, u32)> + '_ {
+        self.programs.keys().copied()
+    }
+
+    /// Enables replaying cached replies to retransmitted calls of the
+    /// procedures dispatchers mark as cacheable
+    pub fn set_duplicate_cache(&mut self, drc: Option<DuplicateRequestCache>) {
+        self.drc = drc;
+    }
+
+    /// Sets how many calls of one connection, or of one UDP socket, are
+    /// handled at the same time.  No more calls are read from it until
+    /// one of them completes.
+    pub fn set_max_in_flight(&mut self, max_in_flight: usize) {
+        self.max_in_flight = max_in_flight.max(1);
+    }
+
+    /// Accepts connections on `listener` and serves each of them on a
+    /// new task.  Only returns if accepting fails.
+    pub async fn serve_tcp(self: Arc<Self>, listener: TcpListener) -> io::Result<()> {
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            let _ = stream.set_nodelay(true);
+            let server = self.clone();
+            tokio::spawn(async move {
+                let _ = server.serve_stream(stream, Some(peer)).await;
+            });
+        }
+    }
+
+    /// Serves calls on a record marked stream, e.g. a `TcpStream`, a
+    /// `UnixStream` or a TLS stream, until the peer disconnects.
+    /// Calls are handled concurrently and replies may be sent out of
+    /// order.
+    pub async fn serve_stream<S>(self: Arc<Self>, stream: S, peer: Option<SocketAddr>) -> Result<()>
+    where
+        S: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        let (mut read, write) = tokio::io::split(stream);
+        let write = Arc::new(tokio::sync::Mutex::new(RecordWriter::new(write)));
+        let in_flight = Arc::new(Semaphore::new(self.max_in_flight));
+        loop {
+            let permit = in_flight.clone().acquire_owned().await;
+            let mut buf = BytesMut::new();
+            read_packet(&mut read, &mut buf, DEFAULT_MAX_PACKET_SIZE).await?;
+
+            let server = self.clone();
+            let write = write.clone();
+            tokio::spawn(async move {
+                let _permit = permit;
+                if let Some(reply) = server.handle(buf.freeze(), peer).await {
+                    let _ = write.lock().await.write(&reply.into()).await;
+                }
+            });
+        }
+    }
+
+    /// Serves calls received on `socket`, one call per datagram.  Only
+    /// returns if receiving fails.
+    pub async fn serve_udp(self: Arc<Self>, socket: UdpSocket) -> io::Result<()> {
+        let socket = Arc::new(socket);
+        let in_flight = Arc::new(Semaphore::new(self.max_in_flight));
+        loop {
+            let permit = in_flight.clone().acquire_owned().await;
+            let mut buf = vec![0; MAX_DATAGRAM_SIZE];
+            let (len, peer) = socket.recv_from(&mut buf).await?;
+            buf.truncate(len);
+
+            let server = self.clone();
+            let socket = socket.clone();
+            tokio::spawn(async move {
+                let _permit = permit;
+                if let Some(reply) = server.handle(buf.into(), Some(peer)).await {
+                    let _ = socket.send_to(&reply, peer).await;
+                }
+            });
+        }
+    }
+
+    /// Handles one message, returns the reply if any
+    async fn handle(&self, mut buf: Bytes, peer: Option<SocketAddr>) -> Option<Bytes> {
+        let xid = buf.unpack_uint().ok()?;
+        if buf.unpack_uint().ok()? != CALL {
+            return None;
+        }
+
+        let call = match buf.unpack_call_header() {
+            Ok(header) => Call {
+                xid,
+                prog: header.prog,
+                vers: header.vers,
+                proc: header.proc,
+                cred: header.cred,
+                verf: header.verf,
+                args: buf,
+                peer,
+            },
+            Err(err) => return Some(new_reply(xid, &header_error(err)?, &[])),
+        };
+
+        let cacheable = self
+            .programs
+            .get(&(call.prog, call.vers))
+            .is_some_and(|dispatcher| dispatcher.cacheable(call.proc));
+        let in_progress = match &self.drc {
+            Some(drc) if cacheable => match drc.lookup(CacheKey::new(&call)) {
+                Lookup::New(in_progress) => Some(in_progress),
+                Lookup::InProgress => return None,
+                Lookup::Done(reply) => return Some(reply),
+                Lookup::Full => None,
+            },
+            _ => None,
+        };
+
+        let (header, results) = self.dispatch(call).await;
+        let reply = new_reply(xid, &header, &results);
+        if let Some(in_progress) = in_progress {
+            in_progress.finish(reply.clone());
+        }
+
+        Some(reply)
+    }
+
+    async fn dispatch(&self, call: Call) -> (ReplyHeader, Bytes) {
+        if let Some(sys) = call.auth_sys() {
+            if sys.gids.len() > auth::MAX_GIDS || sys.machine_name.len() > 255 {
+                return (bad_cred(), Bytes::new());
+            }
+        }
+
+        let dispatcher = match self.programs.get(&(call.prog, call.vers)) {
+            Some(dispatcher) => dispatcher.clone(),
+            None => return (self.unavailable(call.prog), Bytes::new()),
+        };
+
+        match dispatcher.dispatch(call).await {
+            Ok(results) => (accepted(AcceptedReplyStat::Success), results),
+            Err(err) => (error_reply(err), Bytes::new()),
+        }
+    }
+
+    /// Reply header for a call to an unregistered program or version
+    fn unavailable(&self, prog: u32) -> ReplyHeader {
+        let mut versions = self
+            .programs
+            .range((prog, 0)..=(prog, u32::MAX))
+            .map(|((_, vers), _)| *vers);
+
+        match versions.next() {
+            None => accepted(AcceptedReplyStat::ProgUnavail),
+            Some(low) => {
+                let high = versions.next_back().unwrap_or(low);
+                accepted(AcceptedReplyStat::ProgMismatch(MismatchInfo { low, high }))
+            }
+        }
+    }
+}
+
+fn bad_cred() -> ReplyHeader {
+    ReplyHeader::Denied(RejectedReply::AuthError(AuthStat::BadCred))
+}
+
+/// Maps a dispatcher error to a reply header
+fn error_reply(err: ErrorCode) -> ReplyHeader {
+    match err.get() {
+        RPC_PROC_UNAVAIL => accepted(AcceptedReplyStat::ProcUnavail),
+        RPC_GARBAGE_ARGS | INVALID_DATA | NOT_ENOUGH_DATA => {
+            accepted(AcceptedReplyStat::GarbageArgs)
+        }
+        RPC_REJECTED_AUTH_ERROR => ReplyHeader::Denied(RejectedReply::AuthError(AuthStat::TooWeak)),
+        _ => accepted(AcceptedReplyStat::SystemErr),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::result::{RPC_PROG_MISMATCH, RPC_PROG_UNAVAIL};
+    use crate::rpc::{reply_verf, AcceptedReply, CallHeader, Retransmit, RpcClient};
+    use crate::xdr::{PackTo as _, Packer as _, UnpackFrom as _};
+    use bytes::Buf as _;
+    use std::time::Duration;
+
+    const PROG: u32 = 0x20000000;
+
+    /// Version 1 of PROG: 0 is NULL, 1 adds two numbers, 2 returns the
+    /// AUTH_SYS uid of the caller
+    struct Adder;
+
+    impl Dispatcher for Adder {
+        fn dispatch(&self, mut call: Call) -> DispatchFuture {
+            Box::pin(async move {
+                let mut results = BytesMut::new();
+                match call.proc {
+                    0 => (),
+                    1 => {
+                        let sum = call.args.unpack_uint()? + call.args.unpack_uint()?;
+                        results.pack_uint(sum);
+                    }
+                    2 => results.pack_uint(call.identity().ok_or(RPC_REJECTED_AUTH_ERROR)?.uid),
+                    _ => return Err(RPC_PROC_UNAVAIL.into()),
+                }
+                Ok(results.freeze())
+            })
+        }
+    }
+
+    /// Serves `server` over an in-memory stream as if connected from
+    /// `peer`, returns a client of it
+    pub(crate) fn serve_duplex(
+        server: impl Into<Arc<RpcServer>>,
+        peer: Option<SocketAddr>,
+    ) -> RpcClient {
+        let (client_end, server_end) = tokio::io::duplex(1 << 16);
+        tokio::spawn(server.into().serve_stream(server_end, peer));
+        RpcClient::new(client_end)
+    }
+
+    fn new_server() -> Arc<RpcServer> {
+        let mut server = RpcServer::new();
+        server.register(PROG, 1, Arc::new(Adder));
+        server.register(PROG, 3, Arc::new(Adder));
+        Arc::new(server)
+    }
+
+    async fn call(rpc: &RpcClient, prog: u32, vers: u32, proc: u32, args: &[u32]) -> Result<Bytes> {
+        call_xid(rpc, RpcClient::next_xid(), prog, vers, proc, args).await
+    }
+
+    async fn call_xid(
+        rpc: &RpcClient,
+        xid: u32,
+        prog: u32,
+        vers: u32,
+        proc: u32,
+        args: &[u32],
+    ) -> Result<Bytes> {
+        let mut buf = BytesMut::new();
+        buf.pack_uint(xid);
+        CallHeader {
+            prog,
+            vers,
+            proc,
+            cred: OpaqueAuth::new_sys(0, Bytes::from_static(b"test"), 1000, 100, vec![]),
+            verf: OpaqueAuth::None,
+        }
+        .pack_to(&mut buf);
+        for arg in args {
+            buf.pack_uint(*arg);
+        }
+
+        let mut reply = rpc.call(buf.freeze(), xid).await?;
+        reply_verf(ReplyHeader::unpack_from(&mut reply)?)?;
+        Ok(reply)
+    }
+
+    #[tokio::test]
+    async fn stream_replies() {
+        let rpc = serve_duplex(new_server(), None);
+
+        assert!(call(&rpc, PROG, 1, 0, &[]).await.unwrap().is_empty());
+        let mut sum = call(&rpc, PROG, 1, 1, &[20, 22]).await.unwrap();
+        assert_eq!(sum.unpack_uint().unwrap(), 42);
+        let mut uid = call(&rpc, PROG, 3, 2, &[]).await.unwrap();
+        assert_eq!(uid.unpack_uint().unwrap(), 1000);
+
+        let err = |result: Result<Bytes>| result.unwrap_err().get();
+        assert_eq!(err(call(&rpc, PROG, 1, 1, &[20]).await), RPC_GARBAGE_ARGS);
+        assert_eq!(err(call(&rpc, PROG, 1, 9, &[]).await), RPC_PROC_UNAVAIL);
+        assert_eq!(err(call(&rpc, PROG, 2, 0, &[]).await), RPC_PROG_MISMATCH);
+        assert_eq!(err(call(&rpc, PROG + 1, 1, 0, &[]).await), RPC_PROG_UNAVAIL);
+    }
+
+    #[test]
+    fn prog_mismatch_range() {
+        match new_server().unavailable(PROG) {
+            ReplyHeader::Accepted(AcceptedReply {
+                stat: AcceptedReplyStat::ProgMismatch(info),
+                ..
+            }) => assert_eq!((info.low, info.high), (1, 3)),
+            header => panic!("unexpected {:?}", header),
+        }
+    }
+
+    /// Returns how many times it was called, procedure 1 is cacheable
+    #[derive(Default)]
+    struct Counter {
+        count: std::sync::atomic::AtomicU32,
+    }
+
+    impl Dispatcher for Counter {
+        fn dispatch(&self, _call: Call) -> DispatchFuture {
+            let count = self
+                .count
+                .fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+                + 1;
+            let mut results = BytesMut::new();
+            results.pack_uint(count);
+            Box::pin(async move { Ok(results.freeze()) })
+        }
+
+        fn cacheable(&self, proc: u32) -> bool {
+            proc == 1
+        }
+    }
+
+    /// Calls wait for a permit of `release` once started
+    struct Blocker {
+        started: Arc<std::sync::atomic::AtomicU32>,
+        release: Arc<Semaphore>,
+    }
+
+    impl Dispatcher for Blocker {
+        fn dispatch(&self, _call: Call) -> DispatchFuture {
+            self.started
+                .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            let release = self.release.clone();
+            Box::pin(async move {
+                release.acquire().await.unwrap().forget();
+                Ok(Bytes::new())
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn max_in_flight() {
+        let blocker = Blocker {
+            started: Default::default(),
+            release: Arc::new(Semaphore::new(0)),
+        };
+        let started = blocker.started.clone();
+        let release = blocker.release.clone();
+        let mut server = RpcServer::new();
+        server.register(PROG, 1, Arc::new(blocker));
+        server.set_max_in_flight(2);
+        let rpc = Arc::new(serve_duplex(server, None));
+
+        let calls: Vec<_> = (0..3)
+            .map(|_| {
+                let rpc = rpc.clone();
+                tokio::spawn(async move { call(&rpc, PROG, 1, 0, &[]).await })
+            })
+            .collect();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(started.load(std::sync::atomic::Ordering::Relaxed), 2);
+        release.add_permits(3);
+        for call in calls {
+            call.await.unwrap().unwrap();
+        }
+        assert_eq!(started.load(std::sync::atomic::Ordering::Relaxed), 3);
+    }
+
+    #[tokio::test]
+    async fn header_errors() {
+        let server = new_server();
+        let header = |rpcvers: u32| {
+            let mut buf = BytesMut::new();
+            buf.pack_uint(7);
+            buf.pack_uint(CALL);
+            buf.pack_uint(rpcvers);
+            buf.pack_uint(PROG);
+            buf
+        };
+        let reply = |reply: Option<Bytes>| {
+            let mut reply = reply.unwrap();
+            reply.advance(8);
+            ReplyHeader::unpack_from(&mut reply).unwrap()
+        };
+
+        // truncated after the program number
+        assert!(server.handle(header(2).freeze(), None).await.is_none());
+
+        assert!(matches!(
+            reply(server.handle(header(3).freeze(), None).await),
+            ReplyHeader::Denied(RejectedReply::RpcMismatch(_))
+        ));
+
+        // AUTH_SYS credential longer than the message
+        let mut buf = header(2);
+        buf.pack_uint(1);
+        buf.pack_uint(0);
+        buf.pack_uint(crate::rpc::AUTH_SYS);
+        buf.pack_uint(100);
+        buf.pack_uint(0);
+        assert!(matches!(
+            reply(server.handle(buf.freeze(), None).await),
+            ReplyHeader::Denied(RejectedReply::AuthError(AuthStat::BadCred))
+        ));
+    }
+
+    #[tokio::test]
+    async fn duplicate_replay() {
+        let mut server = RpcServer::new();
+        server.register(PROG, 1, Arc::new(Counter::default()));
+        server.set_duplicate_cache(Some(DuplicateRequestCache::new(16)));
+
+        let rpc = serve_duplex(server, None);
+
+        let xid = RpcClient::next_xid();
+        for _ in 0..2 {
+            let mut count = call_xid(&rpc, xid, PROG, 1, 1, &[]).await.unwrap();
+            assert_eq!(count.unpack_uint().unwrap(), 1);
+        }
+
+        // not cacheable, executed again
+        for expected in 2..4 {
+            let mut count = call_xid(&rpc, xid, PROG, 1, 0, &[]).await.unwrap();
+            assert_eq!(count.unpack_uint().unwrap(), expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn udp_replies() {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        tokio::spawn(new_server().serve_udp(socket));
+
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        socket.connect(addr).await.unwrap();
+        let rpc = RpcClient::new_udp(socket, Retransmit::default());
+
+        let mut sum = call(&rpc, PROG, 1, 1, &[40, 2]).await.unwrap();
+        assert_eq!(sum.unpack_uint().unwrap(), 42);
+    }
+}
@@ -0,0 +1,224 @@
+//! RPC-over-TLS (RFC 9289).
+//!
+//! The client probes the server with a NULL call carrying an AUTH_TLS
+//! credential.  A server that supports RPC-over-TLS answers with an
+//! AUTH_NONE verifier containing "STARTTLS", after which the client
+//! starts a TLS handshake on the same connection and carries on with
+//! record-marked RPC on top of TLS.
+//!
+//! Only built with the `tls` feature, which is off by default, e.g.
+//! `cargo test --features tls` also runs the tests of this module.
+use super::{
+    read_packet, record::RecordWriter, AcceptedReply, AcceptedReplyStat, CallHeader, OpaqueAuth,
+    RawAuth, ReplyHeader, RpcClient, AUTH_NONE, DEFAULT_MAX_PACKET_SIZE, REPLY,
+};
+use crate::{
+    result::{Result, INVALID_DATA, TLS_NOT_SUPPORTED},
+    xdr::{PackTo, Packer, UnpackFrom, Unpacker},
+};
+use bytes::BytesMut;
+use rustls::{pki_types::ServerName, ClientConfig, RootCertStore};
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_rustls::{client::TlsStream, TlsConnector};
+
+/// ALPN protocol identifier required by RFC9289
+pub const ALPN_SUNRPC: &[u8] = b"sunrpc";
+
+/// Verifier body of a successful AUTH_TLS probe reply
+const STARTTLS: &[u8] = b"STARTTLS";
+
+/// TLS parameters for connecting to an RPC server
+#[derive(Clone)]
+pub struct TlsConfig {
+    pub client_config: Arc<ClientConfig>,
+    /// Name the server certificate is checked against
+    pub server_name: ServerName<'static>,
+}
+
+impl TlsConfig {
+    /// Constructs a config trusting the certificates in `roots`
+    pub fn new(roots: RootCertStore, server_name: ServerName<'static>) -> TlsConfig {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let mut client_config = ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .expect("ring supports the default protocol versions")
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        client_config.alpn_protocols = vec![ALPN_SUNRPC.to_vec()];
+
+        TlsConfig {
+            client_config: Arc::new(client_config),
+            server_name,
+        }
+    }
+}
+
+/// Sends the AUTH_TLS probe for `prog`/`vers` on `stream` and checks
+/// the server is willing to start TLS
+pub async fn probe<S>(stream: &mut S, prog: u32, vers: u32) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let xid = RpcClient::next_xid();
+    let mut buf = BytesMut::new();
+    buf.pack_uint(xid);
+    CallHeader {
+        prog,
+        vers,
+        proc: 0,
+        cred: OpaqueAuth::Tls,
+        verf: OpaqueAuth::None,
+    }
+    .pack_to(&mut buf);
+    RecordWriter::new(&mut *stream).write(&buf.into()).await?;
+
+    let mut reply = BytesMut::new();
+    read_packet(stream, &mut reply, DEFAULT_MAX_PACKET_SIZE).await?;
+    if reply.unpack_uint()? != xid || reply.unpack_uint()? != REPLY {
+        return Err(INVALID_DATA.into());
+    }
+
+    match ReplyHeader::unpack_from(&mut reply)? {
+        ReplyHeader::Accepted(AcceptedReply {
+            verf: OpaqueAuth::Raw(RawAuth { flavor, body }),
+            stat: AcceptedReplyStat::Success,
+        }) if flavor == AUTH_NONE && body.as_ref() == STARTTLS => Ok(()),
+        _ => Err(TLS_NOT_SUPPORTED.into()),
+    }
+}
+
+/// Probes the server for RPC-over-TLS support and upgrades `stream`
+/// to TLS.  `prog` and `vers` identify the program to be used over the
+/// connection.
+pub async fn start_tls<S>(
+    mut stream: S,
+    prog: u32,
+    vers: u32,
+    config: &TlsConfig,
+) -> Result<TlsStream<S>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    probe(&mut stream, prog, vers).await?;
+
+    let connector = TlsConnector::from(config.client_config.clone());
+    Ok(connector
+        .connect(config.server_name.clone(), stream)
+        .await?)
+}
+
+impl RpcClient {
+    /// Constructs a client over `connection` upgraded to TLS after a
+    /// successful AUTH_TLS probe for `prog`/`vers`
+    pub async fn new_tls<S>(
+        connection: S,
+        prog: u32,
+        vers: u32,
+        config: &TlsConfig,
+    ) -> Result<RpcClient>
+    where
+        S: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    {
+        let connection = start_tls(connection, prog, vers, config).await?;
+        Ok(RpcClient::new(connection))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc::{Unpacker as _, CALL, LAST_FRAGMENT, MSG_ACCEPTED};
+    use rustls::pki_types::{PrivateKeyDer, PrivatePkcs8KeyDer};
+    use rustls::ServerConfig;
+    use tokio::io::{AsyncWriteExt, DuplexStream};
+    use tokio_rustls::TlsAcceptor;
+
+    /// Reads the AUTH_TLS probe from `stream` and answers it with `verf`
+    async fn answer_probe(stream: &mut DuplexStream, verf: &[u8]) {
+        let mut buf = BytesMut::with_capacity(4096);
+        read_packet(stream, &mut buf, 4096).await.unwrap();
+        let xid = buf.unpack_uint().unwrap();
+        assert_eq!(buf.unpack_uint().unwrap(), CALL);
+        assert_eq!(buf.unpack_uint().unwrap(), 2); // rpcvers
+        buf.unpack_uint().unwrap(); // prog
+        buf.unpack_uint().unwrap(); // vers
+        assert_eq!(buf.unpack_uint().unwrap(), 0); // NULL
+        assert!(matches!(buf.unpack_auth().unwrap(), OpaqueAuth::Tls));
+
+        let mut reply = BytesMut::new();
+        reply.pack_uint(xid);
+        reply.pack_uint(REPLY);
+        reply.pack_uint(MSG_ACCEPTED);
+        reply.pack_uint(AUTH_NONE);
+        reply.pack_opaque(verf);
+        reply.pack_uint(0); // SUCCESS
+        RecordWriter::new(stream)
+            .write(&reply.into())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn tls_call() {
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+        let mut roots = RootCertStore::empty();
+        roots.add(cert.cert.der().clone()).unwrap();
+        let config = TlsConfig::new(roots, ServerName::try_from("localhost").unwrap());
+
+        let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(cert.key_pair.serialize_der()));
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let mut server_config = ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert.cert.der().clone()], key)
+            .unwrap();
+        server_config.alpn_protocols = vec![ALPN_SUNRPC.to_vec()];
+
+        let (client_end, mut server_end) = tokio::io::duplex(4096);
+        let server_task = tokio::spawn(async move {
+            answer_probe(&mut server_end, STARTTLS).await;
+
+            let acceptor = TlsAcceptor::from(Arc::new(server_config));
+            let mut stream = acceptor.accept(server_end).await.unwrap();
+            assert_eq!(stream.get_ref().1.alpn_protocol(), Some(ALPN_SUNRPC));
+
+            let mut buf = BytesMut::with_capacity(4096);
+            read_packet(&mut stream, &mut buf, 4096).await.unwrap();
+            let xid = buf.unpack_uint().unwrap();
+
+            let mut reply = BytesMut::new();
+            reply.pack_uint(LAST_FRAGMENT | 12);
+            reply.pack_uint(xid);
+            reply.pack_uint(REPLY);
+            reply.pack_uint(0x5678);
+            stream.write_all(&reply).await.unwrap();
+            stream.flush().await.unwrap();
+        });
+
+        let client = RpcClient::new_tls(client_end, 100003, 3, &config)
+            .await
+            .unwrap();
+
+        let xid = RpcClient::next_xid();
+        let mut call = BytesMut::new();
+        call.pack_uint(xid);
+        call.pack_uint(CALL);
+        let mut reply = client.call(call.freeze(), xid).await.unwrap();
+        assert_eq!(reply.unpack_uint().unwrap(), 0x5678);
+        server_task.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn tls_not_supported() {
+        let (mut client_end, mut server_end) = tokio::io::duplex(4096);
+        let server_task = tokio::spawn(async move {
+            answer_probe(&mut server_end, b"").await;
+        });
+
+        let err = probe(&mut client_end, 100003, 3).await.unwrap_err();
+        assert_eq!(err.get(), TLS_NOT_SUPPORTED);
+        server_task.await.unwrap();
+    }
+}
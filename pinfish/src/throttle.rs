@@ -1,39 +1,148 @@
-//! Synchronization object for throttling receives on client/server
-use std::sync::atomic::{AtomicBool, Ordering};
-use tokio::sync::Notify;
+//! Flow control for RPC clients.
+//!
+//! A `Throttle` bounds the calls waiting for a reply and the reply
+//! bytes they expect.  Callers wait for capacity before sending, the
+//! receive loop keeps running so replies are never held back.
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::{Semaphore, SemaphorePermit};
 
-/// A Synchronization object for throttling receives on client/server
+/// Limits on the calls waiting for a reply over one client
+#[derive(Debug, Clone, Copy)]
+pub struct FlowControl {
+    /// Calls sent and waiting for a reply
+    pub max_requests: u32,
+    /// Sum of the reply sizes expected by calls waiting for a reply
+    pub max_reply_bytes: u32,
+}
+
+impl Default for FlowControl {
+    fn default() -> Self {
+        FlowControl {
+            max_requests: 256,
+            max_reply_bytes: 64 * 1024 * 1024,
+        }
+    }
+}
+
+/// Time callers spent waiting for capacity
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ThrottleStats {
+    /// Calls that sent without waiting
+    pub immediate: u64,
+    /// Calls that had to wait
+    pub waited: u64,
+    /// Total time spent waiting
+    pub wait_time: Duration,
+    /// Longest single wait
+    pub max_wait: Duration,
+}
+
+/// Capacity held by a call until its reply is received or the call is
+/// dropped
+pub struct Permit<'a> {
+    _request: SemaphorePermit<'a>,
+    _reply_bytes: SemaphorePermit<'a>,
+}
+
+/// Flow control state of one client
 pub struct Throttle {
-    /// Receives continue when the throttle is open
-    open: AtomicBool,
-    /// Notifies the receive task it can resume
-    notify: Notify,
+    limits: FlowControl,
+    requests: Semaphore,
+    reply_bytes: Semaphore,
+    stats: Mutex<ThrottleStats>,
 }
 
 impl Throttle {
-    pub fn new() -> Throttle {
+    pub fn new(limits: FlowControl) -> Throttle {
+        assert!(limits.max_requests > 0);
         Throttle {
-            open: AtomicBool::new(true),
-            notify: Notify::new(),
+            limits,
+            requests: Semaphore::new(limits.max_requests as usize),
+            reply_bytes: Semaphore::new(limits.max_reply_bytes as usize),
+            stats: Default::default(),
         }
     }
 
-    /// Open the throttle, wakes the receive thread if throttle was closed
-    pub fn open(&self) {
-        if self.open.swap(true, Ordering::AcqRel) == false {
-            self.notify.notify_one();
+    pub fn limits(&self) -> FlowControl {
+        self.limits
+    }
+
+    /// Waits for room for one more call expecting a reply of up to
+    /// `reply_size` bytes.  A reply larger than `max_reply_bytes` waits
+    /// for all of the reply bytes to be available.
+    pub async fn acquire(&self, reply_size: u32) -> Permit<'_> {
+        let reply_size = reply_size.min(self.limits.max_reply_bytes);
+        if let Ok(request) = self.requests.try_acquire() {
+            if let Ok(reply_bytes) = self.reply_bytes.try_acquire_many(reply_size) {
+                self.stats.lock().unwrap().immediate += 1;
+                return Permit {
+                    _request: request,
+                    _reply_bytes: reply_bytes,
+                };
+            }
+        }
+
+        let start = Instant::now();
+        // the semaphores are never closed
+        let request = self.requests.acquire().await.unwrap();
+        let reply_bytes = self.reply_bytes.acquire_many(reply_size).await.unwrap();
+        let waited = start.elapsed();
+
+        let mut stats = self.stats.lock().unwrap();
+        stats.waited += 1;
+        stats.wait_time += waited;
+        stats.max_wait = stats.max_wait.max(waited);
+
+        Permit {
+            _request: request,
+            _reply_bytes: reply_bytes,
         }
     }
 
-    /// Close the throttle, receiver will block until open
-    pub fn close(&self) {
-        self.open.swap(false, Ordering::Release);
+    pub fn stats(&self) -> ThrottleStats {
+        *self.stats.lock().unwrap()
     }
+}
 
-    /// Check and wait for the throttle to be opened
-    pub async fn check(&self) -> () {
-        while !self.open.load(Ordering::Acquire) {
-            self.notify.notified().await;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn waits_for_capacity() {
+        let throttle = Throttle::new(FlowControl {
+            max_requests: 2,
+            max_reply_bytes: 100,
+        });
+
+        let first = throttle.acquire(60).await;
+        let second = throttle.acquire(10).await;
+        assert_eq!(throttle.stats().immediate, 2);
+
+        // out of requests, then out of reply bytes
+        {
+            let third = throttle.acquire(0);
+            tokio::pin!(third);
+            assert!(tokio::time::timeout(Duration::from_millis(10), &mut third)
+                .await
+                .is_err());
+            drop(second);
+            let _third = third.await;
         }
+        {
+            let large = throttle.acquire(1000);
+            tokio::pin!(large);
+            assert!(tokio::time::timeout(Duration::from_millis(10), &mut large)
+                .await
+                .is_err());
+            drop(first);
+            let _large = large.await;
+        }
+
+        let stats = throttle.stats();
+        assert_eq!(stats.waited, 2);
+        assert!(stats.max_wait >= Duration::from_millis(10));
+        assert!(stats.wait_time >= stats.max_wait);
     }
 }
@@ -31,7 +31,7 @@ pub trait Packer {
 
     fn pack_array<I, F>(&mut self, array: &[I], pack_fn: F)
     where
-        F: Fn(&mut Self, &I) -> (),
+        F: Fn(&mut Self, &I),
     {
         self.pack_uint(array.len() as u32);
         for item in array {
@@ -201,8 +201,8 @@ mod tests {
         assert_eq!(buf.unpack_uhyper().unwrap(), 0x0506070809101112);
         assert_eq!(buf.unpack_int().unwrap(), -1234567);
         assert_eq!(buf.unpack_hyper().unwrap(), -1234567890111213);
-        assert_eq!(buf.unpack_bool().unwrap(), true);
-        assert_eq!(buf.unpack_bool().unwrap(), false);
+        assert!(buf.unpack_bool().unwrap());
+        assert!(!buf.unpack_bool().unwrap());
         assert_eq!(buf.unpack_float().unwrap(), 0.1234);
         assert_eq!(buf.unpack_double().unwrap(), 0.5678);
         assert_eq!(
@@ -306,6 +306,21 @@ impl<T: PackTo<B>, B: Packer> PackTo<B> for Option<T> {
     }
 }
 
+/// XDR optional-data list (e.g. `pmaplist`, `exports`), each item is
+/// preceded by TRUE and the list is terminated by FALSE
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct List<T>(pub Vec<T>);
+
+impl<T: PackTo<B>, B: Packer> PackTo<B> for List<T> {
+    fn pack_to(&self, buf: &mut B) {
+        for item in &self.0 {
+            buf.pack_bool(true);
+            item.pack_to(buf);
+        }
+        buf.pack_bool(false);
+    }
+}
+
 impl<T: PackTo<B>, B: Packer> PackTo<B> for Box<T> {
     fn pack_to(&self, buf: &mut B) {
         self.as_ref().pack_to(buf)
@@ -343,7 +358,7 @@ impl<T: std::fmt::Debug + VecPackUnpack + PackTo<B>, B: Packer> PackTo<B> for Ve
 impl<T: std::fmt::Debug + VecPackUnpack + UnpackFrom<B>, B: Unpacker> UnpackFrom<B> for Vec<T> {
     fn unpack_from(buf: &mut B) -> Result<Self> {
         let len = buf.unpack_uint()? as usize;
-        let mut result = Vec::with_capacity(len.into());
+        let mut result = Vec::with_capacity(len);
         for _ in 0..len {
             result.push(T::unpack_from(buf)?)
         }
@@ -363,6 +378,17 @@ impl<T: UnpackFrom<B>, B: Unpacker> UnpackFrom<B> for Option<T> {
     }
 }
 
+impl<T: UnpackFrom<B>, B: Unpacker> UnpackFrom<B> for List<T> {
+    fn unpack_from(buf: &mut B) -> Result<Self> {
+        let mut items = Vec::new();
+        while buf.unpack_bool()? {
+            items.push(T::unpack_from(buf)?);
+        }
+
+        Ok(List(items))
+    }
+}
+
 impl<T: UnpackFrom<B>, B: Unpacker> UnpackFrom<B> for Box<T> {
     fn unpack_from(buf: &mut B) -> Result<Self> {
         Ok(Box::new(T::unpack_from(buf)?))
@@ -394,3 +420,7 @@ impl<B: Unpacker> UnpackFrom<B> for () {
         Ok(())
     }
 }
+
+impl<B: Packer> PackTo<B> for () {
+    fn pack_to(&self, _buf: &mut B) {}
+}